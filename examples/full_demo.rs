@@ -69,10 +69,7 @@ async fn demo_playlist(_render: &Render) -> Result<(), Error> {
 
     playlist.set_loop(true);
 
-    println!("   Playlist created with {} files:", playlist.len());
-    for (i, file) in playlist.files().iter().enumerate() {
-        println!("     {}: {}", i + 1, file.display());
-    }
+    println!("   Playlist created: {playlist}");
 
     println!("   Testing playlist navigation...");
     let mut count = 0;
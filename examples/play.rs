@@ -7,7 +7,7 @@ use std::path::PathBuf;
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let discover_timeout_secs = 5;
-    let render_spec = RenderSpec::Query(discover_timeout_secs, "Kodi".to_string());
+    let render_spec = RenderSpec::Query(discover_timeout_secs, "Kodi".parse()?);
     let render = Render::new(render_spec).await?;
     let host_ip = get_local_ip().await?;
     let host_port = STREAMING_PORT_DEFAULT;
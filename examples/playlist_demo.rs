@@ -46,10 +46,7 @@ async fn main() -> Result<(), Error> {
     // Set playlist to loop
     playlist.set_loop(true);
 
-    println!("Playlist created with {} files", playlist.len());
-    for (i, file) in playlist.files().iter().enumerate() {
-        println!("  {}: {}", i + 1, file.display());
-    }
+    println!("Playlist created: {playlist}");
 
     // Discover render device
     println!("Discovering DLNA devices...");
@@ -62,7 +59,8 @@ async fn main() -> Result<(), Error> {
     let host_port = STREAMING_PORT_DEFAULT;
     let config = Config::default();
 
-    // Play each file in the playlist
+    // Play each file in the playlist; `next_file` advances the playback
+    // cursor, unlike `iter`, and wraps around as the playlist loops
     let total_files = playlist.len();
     let mut file_count = 0;
     while let Some(current_file) = playlist.next_file() {
@@ -43,13 +43,7 @@ async fn main() -> Result<(), Error> {
     }
 
     playlist.set_loop(true);
-    println!("   Playlist created with {} files", playlist.len());
-
-    // Display playlist contents
-    println!("   Files in playlist:");
-    for (i, file) in playlist.files().iter().enumerate() {
-        println!("     {}: {}", i + 1, file.display());
-    }
+    println!("   Playlist created: {playlist}");
 
     println!("\n3. Starting TUI...");
     println!("   Use the following controls in the TUI:");
@@ -5,15 +5,26 @@
 
 mod args;
 mod commands;
+mod output;
 
-pub use args::{Cli, List, Play};
+pub use args::{AfterPlayback, Cli, Interfaces, List, Play, Probe};
 pub use commands::Commands;
+pub use output::Printer;
+#[cfg(feature = "qrcode")]
+pub use output::qr_code;
 
 use crate::error::Result;
-use clap::Parser;
+use clap::{CommandFactory, Parser, error::ErrorKind};
 
 /// Run the CLI application
 pub async fn run() -> Result<()> {
     let cli = Cli::parse();
+    if let Commands::Play(play) = &cli.command
+        && let Err(message) = play.validate()
+    {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, message)
+            .exit();
+    }
     cli.command.run(&cli).await
 }
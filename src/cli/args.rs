@@ -3,24 +3,40 @@
 //! This module contains the CLI argument definitions and parsing logic
 //! using the clap crate.
 
-use crate::config::{Config, DEFAULT_DISCOVERY_TIMEOUT};
+use crate::config::{
+    Config, DEFAULT_ACTION_TIMEOUT_SECS, DEFAULT_DISCOVERY_TIMEOUT, DEFAULT_MAX_PLAYLIST_FILES,
+    DEFAULT_SUBTITLE_WARN_BYTES, DEFAULT_SUBTITLE_WARN_CUES, DEFAULT_TUI_POLL_INTERVAL_MS,
+    DevicePreset, MAX_STREAMING_CHUNK_SIZE, MAX_STREAMING_READ_AHEAD, MIN_STREAMING_CHUNK_SIZE,
+};
+use crate::devices::DeviceMatcher;
 use crate::media::STREAMING_PORT_DEFAULT;
-use clap::{Args, Parser};
+use crate::utils::{format_dlna_time, try_time_str_to_milliseconds};
+use clap::{Args, Parser, ValueEnum};
+use encoding_rs::Encoding;
 use log::LevelFilter;
+use rupnp::ssdp::URN;
 use std::path::PathBuf;
 
 /// A minimal UPnP/DLNA media streamer
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Time in seconds to search and discover streamer hosts
+    /// Time in seconds to search and discover streamer hosts (0 searches until a device is found, up to 60 seconds)
     #[arg(short, long, default_value_t = DEFAULT_DISCOVERY_TIMEOUT)]
     pub timeout: u64,
 
+    /// Time in seconds to wait for a device to answer a command before giving up
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_ACTION_TIMEOUT_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub action_timeout: u64,
+
     /// Log level
     #[arg(long, value_name = "LEVEL", global = true, default_value_t = LevelFilter::Info)]
     pub log_level: LevelFilter,
 
+    /// Only print results, warnings and errors
+    #[arg(long, global = true)]
+    pub quiet: bool,
+
     /// Subtitle synchronization interval in milliseconds
     #[arg(long, default_value_t = 500)]
     pub subtitle_sync_interval: u64,
@@ -33,13 +49,56 @@ pub struct Cli {
 impl Cli {
     /// Build a Config from CLI arguments and Play command
     pub fn build_config(&self, play_cmd: Option<&super::Play>) -> Config {
-        let mut config = Config::new()
+        let preset = play_cmd.and_then(|play| play.preset);
+        let mut config = preset
+            .map_or_else(Config::new, DevicePreset::config)
             .with_discovery_timeout(self.timeout)
+            .with_action_timeout(self.action_timeout)
             .with_log_level(self.log_level)
+            .with_quiet(self.quiet)
             .with_subtitle_sync_interval(self.subtitle_sync_interval);
 
         if let Some(play) = play_cmd {
-            config = config.with_streaming_port(play.port);
+            config = config
+                .with_streaming_port(play.port)
+                .with_wait_for_device(play.wait_for_device)
+                .with_recursive_scan(play.recursive)
+                .with_follow_symlinks(play.follow_symlinks)
+                .with_max_files(play.max_files)
+                .with_events_json(play.events_json)
+                .with_title(play.title.clone())
+                .with_throttle_when_paused(play.throttle_when_paused)
+                .with_keep_serving(play.keep_serving)
+                .with_force_recast(play.force)
+                .with_output_metadata(play.output_metadata.clone())
+                .with_template_dir(play.template_dir.clone())
+                .with_clip(play.start, play.end)
+                .with_subtitle_warn_limits(play.subtitle_warn_bytes, play.subtitle_warn_cues)
+                .with_tui_poll_interval((!play.tui_no_poll).then_some(play.tui_poll))
+                .with_tui_confirm_quit(!play.no_confirm_quit);
+
+            // Flags given explicitly override the preset
+            if let Some(server_header) = &play.server_header {
+                config = config.with_server_header(server_header.clone());
+            }
+            if let Some(chunk_size) = play.chunk_size {
+                config = config.with_chunk_size(chunk_size);
+            }
+            if let Some(read_ahead) = play.read_ahead {
+                config = config.with_read_ahead(read_ahead);
+            }
+            if let Some(play_speed) = &play.play_speed {
+                config = config.with_play_speed(Some(play_speed.clone()));
+            }
+            if play.omit_play_speed {
+                config = config.with_play_speed(None);
+            }
+            if play.clean_title {
+                config = config.with_clean_title(true);
+            }
+            if play.no_clean_title {
+                config = config.with_clean_title(false);
+            }
         }
 
         config
@@ -48,7 +107,88 @@ impl Cli {
 
 /// List command arguments
 #[derive(Args)]
-pub struct List;
+pub struct List {
+    /// Only list devices matching this query, as used by `play --query-device`
+    #[arg(short = 'q', long = "query", value_parser = parse_device_query)]
+    pub query: Option<DeviceMatcher>,
+
+    /// List devices of this UPnP type (e.g. urn:schemas-upnp-org:device:MediaServer:1) instead of renders
+    #[arg(long, value_name = "URN")]
+    pub device_type: Option<URN>,
+
+    /// Only search through the default network interface (faster, but misses devices on other subnets)
+    #[arg(long)]
+    pub single_interface: bool,
+
+    /// Report how discovery went instead of listing renders: SSDP responses, description fetch times and AVTransport support of every device, through the default interface
+    #[arg(long, conflicts_with_all = ["query", "device_type"])]
+    pub stats: bool,
+
+    /// Also show the storage media each device reports it can play from (GetDeviceCapabilities)
+    #[arg(long, conflicts_with_all = ["stats", "device_type"])]
+    pub capabilities: bool,
+
+    /// Print the devices as a JSON array of their name, location and UDN
+    #[arg(long, conflicts_with_all = ["stats", "capabilities"])]
+    pub json: bool,
+}
+
+/// What the play command does once a file has played to its end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AfterPlayback {
+    /// Exit, leaving the device on the finished media
+    Stop,
+    /// Play the same file again
+    Loop,
+    /// Play the next file, exiting after the last one
+    Next,
+    /// Stop the device and clear its media, then exit
+    Quit,
+}
+
+impl Play {
+    /// Checks the arguments that depend on each other
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(start), Some(end)) = (self.start, self.end)
+            && start >= end
+        {
+            return Err(format!(
+                "--start ({}) must be before --end ({})",
+                format_dlna_time(start),
+                format_dlna_time(end)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Interfaces command arguments
+#[derive(Args)]
+pub struct Interfaces {
+    /// Only list IPv4 addresses, which renderers support best
+    #[arg(long)]
+    pub ipv4: bool,
+}
+
+/// Probe command arguments
+#[derive(Args)]
+pub struct Probe {
+    /// Specify the device to probe through a query (scan devices first)
+    #[arg(short = 'q', long = "query-device", value_parser = parse_device_query)]
+    pub device_query: Option<DeviceMatcher>,
+
+    /// Specify the device to probe through its exact location (no scan, faster)
+    #[arg(short, long = "device")]
+    pub device_url: Option<String>,
+
+    /// Print the report as JSON
+    #[arg(long, conflicts_with = "scpd")]
+    pub json: bool,
+
+    /// Print the raw AVTransport service description (SCPD) XML instead of the report
+    #[arg(long)]
+    pub scpd: bool,
+}
 
 /// Play command arguments
 #[derive(Args)]
@@ -57,43 +197,631 @@ pub struct Play {
     #[arg(short = 'H', long = "host")]
     pub host: Option<String>,
 
+    /// The network interface (e.g. eth0, en0) whose address is used to host and serve the files
+    #[arg(long, value_name = "NAME", conflicts_with = "host")]
+    pub interface: Option<String>,
+
     /// The port to be used to host and serve the files
     #[arg(short = 'P', long = "port", default_value_t=STREAMING_PORT_DEFAULT)]
-    pub port: u32,
+    pub port: u16,
 
-    /// Specify the device where to play through a query (scan devices before playing)
-    #[arg(short = 'q', long = "query-device")]
-    pub device_query: Option<String>,
+    /// Specify the device where to play through a query, e.g. `Kodi`, `name=Living Room TV` or `udn:<UDN>` (scan devices before playing)
+    #[arg(short = 'q', long = "query-device", value_parser = parse_device_query)]
+    pub device_query: Option<DeviceMatcher>,
 
     /// Specify the device where to play through its exact location (no scan, faster)
     #[arg(short, long = "device")]
     pub device_url: Option<String>,
 
-    /// The file of the subtitle (if not provided, we derive it from <FILE_VIDEO>)
+    /// Keep retrying the --device or --query-device device for up to this many seconds until it answers, e.g. while a TV is booting
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub wait_for_device: Option<u64>,
+
+    /// The file of the subtitle, repeat for multiple tracks; relative paths are looked up next to the video first (if not provided, we derive them from <FILE_VIDEO>, e.g. movie.srt and movie.en.srt)
     #[arg(short, long, value_name = "FILE_SUBTITLE")]
-    pub subtitle: Option<PathBuf>,
+    pub subtitle: Vec<PathBuf>,
 
     /// Disable subtitles
     #[arg(short, long)]
     pub no_subtitle: bool,
 
-    /// Enable subtitle synchronization to clipboard
+    /// Show the title and elapsed time on screen every few minutes, through the subtitle track, instead of subtitles found next to the media (not of --subtitle files, see --osd-replace)
+    #[arg(long)]
+    pub osd: bool,
+
+    /// Show the on-screen display even if it replaces a subtitle given with --subtitle
+    #[arg(long, requires = "osd")]
+    pub osd_replace: bool,
+
+    /// Warn about subtitle files larger than this, as some renderers drop them (0 never warns)
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_SUBTITLE_WARN_BYTES)]
+    pub subtitle_warn_bytes: u64,
+
+    /// Warn about subtitles with more cues than this, as some renderers truncate them (0 never warns)
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_SUBTITLE_WARN_CUES)]
+    pub subtitle_warn_cues: usize,
+
+    /// Enable subtitle synchronization to clipboard (optional when --subtitle-sync-file is given too)
     #[arg(long)]
     pub subtitle_sync: bool,
 
-    /// Enable interactive keyboard control (space to pause/resume, q to quit)
+    /// Copy the current subtitle to the clipboard, failing without one
+    #[arg(long)]
+    pub subtitle_sync_clipboard: bool,
+
+    /// Write the current subtitle to a file, e.g. for streaming overlays (clipboard is only used with --subtitle-sync or --subtitle-sync-clipboard)
+    #[arg(long, value_name = "PATH")]
+    pub subtitle_sync_file: Option<PathBuf>,
+
+    /// Encoding of the subtitle file used by --subtitle-sync, e.g. gbk, shift_jis or windows-1252 (detected by default)
+    #[arg(long, value_name = "LABEL", value_parser = parse_subtitle_encoding)]
+    pub subtitle_encoding: Option<&'static Encoding>,
+
+    /// Enable interactive keyboard control (space to pause/resume, h for help, q to quit)
     #[arg(short, long)]
     pub interactive: bool,
 
+    /// Emit newline-delimited JSON playback events on stdout
+    #[arg(long)]
+    pub events_json: bool,
+
     /// Enable Terminal User Interface (TUI) mode
     #[arg(long)]
     pub tui: bool,
 
+    /// Interval in milliseconds between TUI status queries
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_TUI_POLL_INTERVAL_MS, requires = "tui")]
+    pub tui_poll: u64,
+
+    /// Never query the status in the TUI on its own, only on manual refresh (r)
+    #[arg(long, requires = "tui", conflicts_with = "tui_poll")]
+    pub tui_no_poll: bool,
+
+    /// Quit the TUI right away, without asking for confirmation while something is playing
+    #[arg(long, requires = "tui")]
+    pub no_confirm_quit: bool,
+
     /// Enable playlist mode (loop through all files)
     #[arg(long)]
     pub playlist: bool,
 
-    /// The file or directory to be played
+    /// What to do once a file has played to its end [default: next with --playlist or --watch, stop otherwise]
+    ///
+    /// Only applies when the device reaches the end of the media (or of the clip); stopping the device early still ends playback of the file as usual.
+    #[arg(long, value_name = "POLICY", conflicts_with_all = ["tui", "serve_only", "keep_serving"])]
+    pub after_playback: Option<AfterPlayback>,
+
+    /// Play the files in random order
     #[arg(long)]
-    pub path: PathBuf,
+    pub shuffle: bool,
+
+    /// Play the files in a random order that is reproducible with the same seed (implies --shuffle)
+    #[arg(long, value_name = "N")]
+    pub shuffle_seed: Option<u64>,
+
+    /// Start playback at this position (HH:MM:SS, MM:SS or seconds)
+    #[arg(long, value_name = "TIME", value_parser = parse_clip_time)]
+    pub start: Option<u64>,
+
+    /// Stop playback at this position (HH:MM:SS, MM:SS or seconds)
+    #[arg(long, value_name = "TIME", value_parser = parse_clip_time)]
+    pub end: Option<u64>,
+
+    /// Write the metadata and SetAVTransportURI payload sent to the device to this file, for bug reports
+    #[arg(long, value_name = "PATH")]
+    pub output_metadata: Option<PathBuf>,
+
+    /// Load `didl_lite.xml` and `set_av_transport_uri.xml` from this directory instead of the built-in metadata templates
+    #[arg(long, value_name = "DIR")]
+    pub template_dir: Option<PathBuf>,
+
+    /// Only serve the file and print its URL, for devices or apps that play from a URL
+    #[arg(long, conflicts_with_all = ["device_url", "device_query", "tui", "interactive", "playlist"])]
+    pub serve_only: bool,
+
+    /// Keep serving the file after playback has finished, until interrupted (for device-side repeat or re-buffering)
+    #[arg(long, conflicts_with = "playlist")]
+    pub keep_serving: bool,
+
+    /// Cast the file again even if the device is already playing it, restarting playback
+    #[arg(long)]
+    pub force: bool,
+
+    /// Check that media files are non-empty and have a valid header before playing, skipping bad files of a directory
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Scan subdirectories when <PATH> is a directory
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Follow symlinked files and directories when <PATH> is a directory, instead of skipping them
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Maximum number of files to play, across all the given paths and directories
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_PLAYLIST_FILES)]
+    pub max_files: usize,
+
+    /// Apply settings known to work with a family of renderers (individual flags still override them)
+    #[arg(long, value_name = "NAME")]
+    pub preset: Option<DevicePreset>,
+
+    /// Value of the `Server` header sent by the streaming server [default: crab-dlna/<VERSION>]
+    ///
+    /// Some renderers only accept streams from specific servers.
+    #[arg(long, value_name = "STR")]
+    pub server_header: Option<String>,
+
+    /// Size of the chunks read from disk when streaming, in bytes [default: 65536]
+    ///
+    /// Smaller chunks can help slow renderers, larger ones help high-bitrate files.
+    #[arg(long, value_name = "BYTES", value_parser = parse_chunk_size)]
+    pub chunk_size: Option<usize>,
+
+    /// Bytes to read from disk ahead of the renderer when streaming, 0 disables read-ahead [default: 0]
+    ///
+    /// Smooths playback of high-bitrate files from slow storage, at the cost of memory
+    /// for each open stream.
+    #[arg(long, value_name = "BYTES", value_parser = parse_read_ahead)]
+    pub read_ahead: Option<usize>,
+
+    /// Stop reading the file from disk while playback is paused
+    ///
+    /// Reduces disk activity during long pauses on renderers that keep
+    /// buffering, e.g. for media on network mounts.
+    #[arg(long)]
+    pub throttle_when_paused: bool,
+
+    /// Warn about videos many renderers can't play, above 1080p or in HDR
+    ///
+    /// Reads the header of MP4 and Matroska files before casting them.
+    #[cfg(feature = "probe")]
+    #[arg(long)]
+    pub probe_video: bool,
+
+    /// Speed sent with the Play action starting playback [default: 1]
+    ///
+    /// For renderers expecting another format, e.g. `1.0`.
+    #[arg(long, value_name = "SPEED", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    pub play_speed: Option<String>,
+
+    /// Leave the Speed element out of the Play action, for renderers faulting on it
+    #[arg(long, conflicts_with = "play_speed")]
+    pub omit_play_speed: bool,
+
+    /// Title shown on the device (defaults to one derived from the file name)
+    #[arg(long, value_name = "STR")]
+    pub title: Option<String>,
+
+    /// Remove release tags (resolution, codec, group, ...) from titles derived from file names
+    #[arg(long, overrides_with = "no_clean_title")]
+    pub clean_title: bool,
+
+    /// Keep release tags in titles derived from file names, e.g. when a preset removes them
+    #[arg(long, overrides_with = "clean_title")]
+    pub no_clean_title: bool,
+
+    /// Append each file played to a history log of JSON lines, by default history.jsonl in the crab-dlna configuration directory
+    #[arg(long, value_name = "FILE", num_args = 0..=1)]
+    pub history: Option<Option<PathBuf>>,
+
+    /// Also play the media files created in this directory, waiting for new ones once the playlist is done
+    #[cfg(feature = "watch")]
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["serve_only", "tui"])]
+    pub watch: Option<PathBuf>,
+
+    /// The file or directory to be played, repeat to play several in order
+    #[cfg_attr(feature = "watch", arg(long, required_unless_present = "watch"))]
+    #[cfg_attr(not(feature = "watch"), arg(long, required = true))]
+    pub path: Vec<PathBuf>,
+}
+
+/// Parses and validates the streaming chunk size
+fn parse_chunk_size(value: &str) -> Result<usize, String> {
+    let chunk_size: usize = value
+        .parse()
+        .map_err(|_| format!("'{value}' is not a number of bytes"))?;
+
+    if !(MIN_STREAMING_CHUNK_SIZE..=MAX_STREAMING_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(format!(
+            "chunk size must be between {MIN_STREAMING_CHUNK_SIZE} and {MAX_STREAMING_CHUNK_SIZE} bytes"
+        ));
+    }
+    Ok(chunk_size)
+}
+
+/// Parses and validates the streaming read-ahead size
+fn parse_read_ahead(value: &str) -> Result<usize, String> {
+    let read_ahead: usize = value
+        .parse()
+        .map_err(|_| format!("'{value}' is not a number of bytes"))?;
+
+    if read_ahead > MAX_STREAMING_READ_AHEAD {
+        return Err(format!(
+            "read-ahead must be at most {MAX_STREAMING_READ_AHEAD} bytes"
+        ));
+    }
+    Ok(read_ahead)
+}
+
+/// Encodings listed when an unknown subtitle encoding is given
+const SUPPORTED_SUBTITLE_ENCODINGS: &[&str] = &[
+    "UTF-8",
+    "UTF-16LE",
+    "UTF-16BE",
+    "GBK",
+    "gb18030",
+    "Big5",
+    "Shift_JIS",
+    "EUC-JP",
+    "ISO-2022-JP",
+    "EUC-KR",
+    "windows-874",
+    "windows-1250",
+    "windows-1251",
+    "windows-1252",
+    "windows-1253",
+    "windows-1254",
+    "windows-1255",
+    "windows-1256",
+    "windows-1257",
+    "windows-1258",
+    "ISO-8859-2",
+    "ISO-8859-5",
+    "ISO-8859-7",
+    "ISO-8859-15",
+    "KOI8-R",
+    "KOI8-U",
+    "IBM866",
+    "macintosh",
+];
+
+/// Parses a position given as HH:MM:SS, MM:SS or seconds, into milliseconds
+fn parse_clip_time(value: &str) -> Result<u64, String> {
+    let time = value.trim();
+    let milliseconds = match time.matches(':').count() {
+        0 => time
+            .parse::<f64>()
+            .ok()
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(|seconds| (seconds * 1000.0) as u64),
+        1 => try_time_str_to_milliseconds(&format!("0:{time}")),
+        _ => try_time_str_to_milliseconds(time),
+    };
+    milliseconds
+        .ok_or_else(|| format!("'{value}' is not a position, use HH:MM:SS, MM:SS or seconds"))
+}
+
+/// Parses a device query (e.g. `Kodi` or `udn:4d696e69-...`)
+fn parse_device_query(value: &str) -> Result<DeviceMatcher, String> {
+    value.parse().map_err(|e: crate::Error| e.to_string())
+}
+
+/// Parses a subtitle encoding label (e.g. `gbk` or `shift_jis`)
+fn parse_subtitle_encoding(value: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(value.trim().as_bytes()).ok_or_else(|| {
+        format!(
+            "unknown encoding '{value}', supported encodings include: {}",
+            SUPPORTED_SUBTITLE_ENCODINGS.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_interface_conflicts_with_host() {
+        let result = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--host",
+            "192.168.1.2",
+            "--interface",
+            "eth0",
+            "--path",
+            "video.mp4",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_port_out_of_range_is_rejected() {
+        let result = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--port",
+            "70000",
+            "--path",
+            "video.mp4",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_list_device_type() {
+        let cli = Cli::try_parse_from([
+            "crab-dlna",
+            "list",
+            "--device-type",
+            "urn:schemas-upnp-org:device:MediaServer:1",
+        ])
+        .unwrap();
+        let super::super::Commands::List(list) = cli.command else {
+            panic!("expected the list command");
+        };
+        assert_eq!(
+            list.device_type,
+            Some(URN::device("schemas-upnp-org", "MediaServer", 1))
+        );
+
+        let result = Cli::try_parse_from(["crab-dlna", "list", "--device-type", "MediaServer"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_list_query() {
+        let cli = Cli::try_parse_from(["crab-dlna", "list", "--query", "name=Kodi"]).unwrap();
+        let super::super::Commands::List(list) = cli.command else {
+            panic!("expected the list command");
+        };
+        assert!(matches!(list.query, Some(DeviceMatcher::NameIs(name)) if name == "Kodi"));
+
+        let result = Cli::try_parse_from(["crab-dlna", "list", "--query", "name~("]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_list_json() {
+        let cli = Cli::try_parse_from(["crab-dlna", "list", "-q", "Kodi", "--json"]).unwrap();
+        let super::super::Commands::List(list) = cli.command else {
+            panic!("expected the list command");
+        };
+        assert!(list.json);
+
+        for flag in ["--stats", "--capabilities"] {
+            let result = Cli::try_parse_from(["crab-dlna", "list", "--json", flag]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_query_device_is_parsed() {
+        let cli =
+            Cli::try_parse_from(["crab-dlna", "play", "--path", "a.mkv", "-q", "udn:4d696e69"])
+                .unwrap();
+        let super::super::Commands::Play(play) = cli.command else {
+            panic!("expected the play command");
+        };
+        assert!(matches!(play.device_query, Some(DeviceMatcher::Udn(udn)) if udn == "4d696e69"));
+
+        for command in [vec!["play", "--path", "a.mkv"], vec!["probe"]] {
+            let args = [&["crab-dlna"], &command[..], &["--query-device", "name~("]].concat();
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_after_playback() {
+        let cli = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--path",
+            "a.mkv",
+            "--after-playback",
+            "loop",
+        ])
+        .unwrap();
+        let super::super::Commands::Play(play) = cli.command else {
+            panic!("expected the play command");
+        };
+        assert_eq!(play.after_playback, Some(AfterPlayback::Loop));
+
+        let result = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--path",
+            "a.mkv",
+            "--after-playback",
+            "quit",
+            "--keep-serving",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_flags_override_preset() {
+        let cli = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--preset",
+            "lg-webos",
+            "--chunk-size",
+            "65536",
+            "--path",
+            "video.mp4",
+        ])
+        .unwrap();
+        let super::super::Commands::Play(play) = &cli.command else {
+            panic!("expected the play command");
+        };
+        let config = cli.build_config(Some(play));
+        assert_eq!(play.preset, Some(DevicePreset::LgWebos));
+        assert_eq!(config.chunk_size, 65536);
+        assert!(config.clean_title);
+        assert_eq!(config.server_header, crate::config::DLNA_SERVER_HEADER);
+    }
+
+    #[test]
+    fn test_no_clean_title_overrides_preset() {
+        let parse = |args: &[&str]| {
+            let cli = Cli::try_parse_from(
+                [
+                    "crab-dlna",
+                    "play",
+                    "--preset",
+                    "lg-webos",
+                    "--path",
+                    "video.mp4",
+                ]
+                .iter()
+                .chain(args),
+            )
+            .unwrap();
+            let super::super::Commands::Play(play) = &cli.command else {
+                panic!("expected the play command");
+            };
+            cli.build_config(Some(play)).clean_title
+        };
+        assert!(parse(&[]));
+        assert!(!parse(&["--no-clean-title"]));
+        // The last of the two flags wins
+        assert!(parse(&["--no-clean-title", "--clean-title"]));
+        assert!(!parse(&["--clean-title", "--no-clean-title"]));
+    }
+
+    #[test]
+    fn test_multiple_paths_keep_order() {
+        let cli = Cli::try_parse_from([
+            "crab-dlna",
+            "play",
+            "--path",
+            "Season 1",
+            "--path",
+            "extra.mp4",
+        ])
+        .unwrap();
+        let super::super::Commands::Play(play) = cli.command else {
+            panic!("expected the play command");
+        };
+        assert_eq!(play.path, ["Season 1", "extra.mp4"].map(PathBuf::from));
+
+        let result = Cli::try_parse_from(["crab-dlna", "play"]);
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_watch_makes_path_optional() {
+        let cli = Cli::try_parse_from(["crab-dlna", "play", "--watch", "Downloads"]).unwrap();
+        let super::super::Commands::Play(play) = cli.command else {
+            panic!("expected the play command");
+        };
+        assert_eq!(play.watch, Some(PathBuf::from("Downloads")));
+        assert!(play.path.is_empty());
+    }
+
+    #[test]
+    fn test_history_file_is_optional() {
+        let history = |args: &[&str]| {
+            let cli = Cli::try_parse_from(
+                ["crab-dlna", "play", "--path", "video.mp4"]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap();
+            let super::super::Commands::Play(play) = cli.command else {
+                panic!("expected the play command");
+            };
+            play.history
+        };
+        assert_eq!(history(&[]), None);
+        assert_eq!(history(&["--history"]), Some(None));
+        assert_eq!(
+            history(&["--history", "watched.jsonl"]),
+            Some(Some(PathBuf::from("watched.jsonl")))
+        );
+    }
+
+    #[test]
+    fn test_play_speed_flags() {
+        let parse = |args: &[&str]| {
+            let cli = Cli::try_parse_from(
+                ["crab-dlna", "play", "--path", "video.mp4"]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap();
+            let super::super::Commands::Play(play) = &cli.command else {
+                panic!("expected the play command");
+            };
+            cli.build_config(Some(play)).play_speed
+        };
+        assert_eq!(parse(&[]).as_deref(), Some("1"));
+        assert_eq!(parse(&["--play-speed", "1.0"]).as_deref(), Some("1.0"));
+        assert_eq!(parse(&["--omit-play-speed"]), None);
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(parse_chunk_size("65536"), Ok(65536));
+        assert!(parse_chunk_size("1024").is_err());
+        assert!(parse_chunk_size("1073741824").is_err());
+        assert!(parse_chunk_size("64k").is_err());
+    }
+
+    #[test]
+    fn test_parse_clip_time() {
+        assert_eq!(parse_clip_time("01:02:03"), Ok(3_723_000));
+        assert_eq!(parse_clip_time("02:03"), Ok(123_000));
+        assert_eq!(parse_clip_time("90.5"), Ok(90_500));
+        assert!(parse_clip_time("-5").is_err());
+        assert!(parse_clip_time("soon").is_err());
+    }
+
+    #[test]
+    fn test_clip_start_before_end() {
+        let parse = |start: &str, end: &str| {
+            let cli = Cli::try_parse_from([
+                "crab-dlna",
+                "play",
+                "--start",
+                start,
+                "--end",
+                end,
+                "--path",
+                "video.mp4",
+            ])
+            .unwrap();
+            let super::super::Commands::Play(play) = cli.command else {
+                panic!("expected the play command");
+            };
+            play.validate()
+        };
+        assert!(parse("00:01:00", "00:02:00").is_ok());
+        assert!(parse("00:02:00", "00:01:00").is_err());
+        assert!(parse("60", "1:00").is_err());
+    }
+
+    #[test]
+    fn test_parse_read_ahead() {
+        assert_eq!(parse_read_ahead("0"), Ok(0));
+        assert_eq!(parse_read_ahead("8388608"), Ok(8 * 1024 * 1024));
+        assert!(parse_read_ahead("1073741824").is_err());
+        assert!(parse_read_ahead("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_subtitle_encoding() {
+        assert_eq!(parse_subtitle_encoding("gbk"), Ok(encoding_rs::GBK));
+        assert_eq!(
+            parse_subtitle_encoding("shift_jis"),
+            Ok(encoding_rs::SHIFT_JIS)
+        );
+        assert_eq!(
+            parse_subtitle_encoding("windows-1252"),
+            Ok(encoding_rs::WINDOWS_1252)
+        );
+        let error = parse_subtitle_encoding("klingon").unwrap_err();
+        assert!(error.contains("klingon"));
+        assert!(error.contains("Shift_JIS"));
+    }
 }
@@ -0,0 +1,33 @@
+//! Interfaces command implementation for crab-dlna
+//!
+//! This module implements the interfaces command which lists the local
+//! network interfaces files can be served from.
+
+use super::super::Printer;
+use crate::{config::Config, error::Result, media::list_interface_addresses};
+
+/// Interfaces command implementation
+pub struct InterfacesCommand<'a> {
+    args: &'a super::super::Interfaces,
+}
+
+impl<'a> InterfacesCommand<'a> {
+    /// Create a new interfaces command
+    pub fn new(args: &'a super::super::Interfaces) -> Self {
+        Self { args }
+    }
+
+    /// Execute the interfaces command
+    ///
+    /// The address files are served from by default is marked with `*`.
+    pub fn run(&self, config: &Config) -> Result<()> {
+        let printer = Printer::new(config.quiet);
+        let addresses = list_interface_addresses()?
+            .into_iter()
+            .filter(|address| !self.args.ipv4 || address.address.is_ipv4());
+        for address in addresses {
+            printer.result(address);
+        }
+        Ok(())
+    }
+}
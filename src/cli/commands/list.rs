@@ -3,30 +3,125 @@
 //! This module implements the list command which discovers and displays
 //! available DLNA devices on the network.
 
+use super::super::Printer;
 use crate::{
-    config::{Config, LOG_MSG_LIST_DEVICES},
-    devices::Render,
+    config::{Config, DEFAULT_DISCOVERY_TIMEOUT, LOG_MSG_LIST_DEVICES},
+    devices::{
+        DeviceIdentity, Render,
+        discovery::{discover_by_target, discover_with_stats, is_device_type},
+    },
     error::Result,
+    utils::format_device_description,
 };
 use log::info;
+use rupnp::ssdp::{SearchTarget, URN};
+use serde_json::{Value, json};
 
 /// List command implementation
 pub struct ListCommand<'a> {
-    _args: &'a super::super::List,
+    args: &'a super::super::List,
 }
 
 impl<'a> ListCommand<'a> {
     /// Create a new list command
     pub fn new(args: &'a super::super::List) -> Self {
-        Self { _args: args }
+        Self { args }
     }
 
     /// Execute the list command
     pub async fn run(&self, config: &Config) -> Result<()> {
         info!("{LOG_MSG_LIST_DEVICES}");
-        for render in Render::discover(config.discovery_timeout).await? {
-            println!("{render}");
+        if let Some(device_type) = &self.args.device_type {
+            return self.list_device_type(config, device_type).await;
+        }
+        if self.args.stats {
+            return self.report_stats(config).await;
+        }
+
+        let renders = match self.args.single_interface {
+            true => Render::discover(config.discovery_timeout).await?,
+            false => Render::discover_on_all_interfaces(config.discovery_timeout).await?,
+        };
+        let printer = Printer::new(config.quiet);
+        let matching = renders.into_iter().filter(|render| match &self.args.query {
+            Some(matcher) => matcher.matches(&render.identity()),
+            None => true,
+        });
+        if self.args.json {
+            let devices: Vec<Value> = matching.map(|render| device_json(&render.device)).collect();
+            printer.result(Value::Array(devices));
+            return Ok(());
+        }
+        for render in matching {
+            printer.result(&render);
+            if self.args.capabilities {
+                printer.result(format_capabilities(&render).await);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a discovery and reports how it went, for triaging devices not found
+    async fn report_stats(&self, config: &Config) -> Result<()> {
+        // Searching until a device answers would hide the timings of a failed search
+        let duration_secs = match config.discovery_timeout {
+            0 => DEFAULT_DISCOVERY_TIMEOUT,
+            duration_secs => duration_secs,
+        };
+        let stats =
+            discover_with_stats(duration_secs, config.ssdp_search_attempts, config.ssdp_ttl)
+                .await?;
+        Printer::new(config.quiet).result(stats);
+        Ok(())
+    }
+
+    /// Lists the devices of an arbitrary UPnP device type
+    async fn list_device_type(&self, config: &Config, device_type: &URN) -> Result<()> {
+        let search_target = SearchTarget::URN(device_type.clone());
+        let devices = discover_by_target(&search_target, config.discovery_timeout).await?;
+        let printer = Printer::new(config.quiet);
+        let matching = devices
+            .iter()
+            .filter(|device| is_device_type(device, device_type))
+            .filter(|device| match &self.args.query {
+                Some(matcher) => matcher.matches(&DeviceIdentity::from(&***device)),
+                None => true,
+            });
+        if self.args.json {
+            let devices: Vec<Value> = matching.map(device_json).collect();
+            printer.result(Value::Array(devices));
+            return Ok(());
+        }
+        let descriptions = matching.map(|device| {
+            format_device_description(
+                &device.device_type().to_string(),
+                device.friendly_name(),
+                &device.url().to_string(),
+            )
+        });
+        for description in descriptions {
+            printer.result(description);
         }
         Ok(())
     }
 }
+
+/// Describes a device for `--json`, with what `play` needs to select it
+fn device_json(device: &rupnp::Device) -> Value {
+    json!({
+        "name": device.friendly_name(),
+        "location": device.url().to_string(),
+        "udn": device.udn(),
+    })
+}
+
+/// Describes the capabilities a device reports, as an indented line below it
+async fn format_capabilities(render: &Render) -> String {
+    match render.get_device_capabilities().await {
+        Ok(capabilities) if capabilities.play_media.is_empty() => {
+            "  Plays from: (none reported)".to_string()
+        }
+        Ok(capabilities) => format!("  Plays from: {}", capabilities.play_media.join(", ")),
+        Err(e) => format!("  Capabilities unavailable: {e}"),
+    }
+}
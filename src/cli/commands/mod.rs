@@ -1,16 +1,26 @@
 //! CLI command implementations for crab-dlna
 //!
 //! This module contains the implementation of CLI commands including
-//! list and play functionality.
+//! list, interfaces, probe and play functionality.
 
+mod interfaces;
 mod list;
 mod play;
+mod probe;
 
+pub use interfaces::InterfacesCommand;
 pub use list::ListCommand;
 pub use play::PlayCommand;
+pub use probe::ProbeCommand;
 
-use crate::{config::Config, error::Result};
+use crate::{
+    config::Config,
+    devices::{DeviceMatcher, Render, RenderSpec},
+    error::Result,
+};
 use clap::Subcommand;
+use log::{LevelFilter, info, warn};
+use std::time::Duration;
 
 /// Available CLI commands
 #[derive(Subcommand)]
@@ -18,48 +28,146 @@ pub enum Commands {
     /// Scan and list devices in the network capable of playing media
     List(super::List),
 
+    /// List the local network interfaces and their addresses, to pick a --host or --interface value
+    Interfaces(super::Interfaces),
+
+    /// Report everything a device tells about itself, to attach to bug reports
+    Probe(super::Probe),
+
     /// Play a video file
-    Play(super::Play),
+    Play(Box<super::Play>),
 }
 
 impl Commands {
     /// Execute the command
     pub async fn run(&self, cli: &super::Cli) -> Result<()> {
+        // Logging comes first, so that warnings about the configuration are shown
+        self.setup_log(cli);
         let config = match self {
-            Self::List(_) => cli.build_config(None),
+            Self::List(_) | Self::Interfaces(_) | Self::Probe(_) => cli.build_config(None),
             Self::Play(play) => cli.build_config(Some(play)),
         };
-        self.setup_log(&config);
         match self {
             Self::List(list) => ListCommand::new(list).run(&config).await?,
+            Self::Interfaces(interfaces) => InterfacesCommand::new(interfaces).run(&config)?,
+            Self::Probe(probe) => ProbeCommand::new(probe).run(&config).await?,
             Self::Play(play) => PlayCommand::new(play).run(&config).await?,
         }
         Ok(())
     }
 
     /// Setup logging configuration
-    fn setup_log(&self, _config: &Config) {
+    fn setup_log(&self, cli: &super::Cli) {
         use crate::config::LOG_LEVEL_ENV_VAR;
-        use log::LevelFilter;
         use simple_logger::SimpleLogger;
         use std::env;
 
-        let log_level = if let Ok(crabldna_log) = env::var(LOG_LEVEL_ENV_VAR) {
-            match crabldna_log.as_str() {
-                "trace" => LevelFilter::Trace,
-                "debug" => LevelFilter::Debug,
-                "info" => LevelFilter::Info,
-                "warn" => LevelFilter::Warn,
-                "error" => LevelFilter::Error,
-                _ => LevelFilter::Info,
-            }
-        } else {
-            LevelFilter::Info
-        };
-
+        let env_level = env::var(LOG_LEVEL_ENV_VAR).ok();
         SimpleLogger::new()
-            .with_level(log_level)
+            .with_level(log_level(env_level.as_deref(), cli.log_level, cli.quiet))
             .init()
             .unwrap_or_else(|_| eprintln!("Warning: Logger already initialized"));
     }
 }
+
+/// Builds the render specification from `--device` and `--query-device`
+///
+/// An exact location wins over a query; without either, the first device found is used.
+pub(crate) fn render_spec(
+    device_url: Option<&str>,
+    device_query: Option<&DeviceMatcher>,
+    timeout: u64,
+) -> RenderSpec {
+    match (device_url, device_query) {
+        (Some(device_url), _) => RenderSpec::Location(device_url.to_owned()),
+        (None, Some(device_query)) => RenderSpec::Query(timeout, device_query.clone()),
+        (None, None) => RenderSpec::First(timeout),
+    }
+}
+
+/// Selects the render device matching a specification
+pub(crate) async fn select_render(render_spec: RenderSpec, config: &Config) -> Result<Render> {
+    info!("Selecting render");
+    let render = match (config.wait_for_device, &render_spec) {
+        (Some(_), RenderSpec::First(_)) => {
+            warn!("--wait-for-device only applies to --device and --query-device");
+            Render::new(render_spec).await?
+        }
+        (Some(wait_secs), _) => {
+            Render::wait_for(render_spec, Duration::from_secs(wait_secs)).await?
+        }
+        (None, _) => Render::new(render_spec).await?,
+    };
+    Ok(render
+        .with_action_timeout(Duration::from_secs(config.action_timeout))
+        .with_play_speed(config.play_speed.clone()))
+}
+
+/// Resolves the log level from the environment and the command line
+///
+/// The environment variable takes precedence over `--log-level`, and `--quiet`
+/// caps the result at warnings.
+fn log_level(env_level: Option<&str>, cli_level: LevelFilter, quiet: bool) -> LevelFilter {
+    let log_level = match env_level {
+        Some("trace") => LevelFilter::Trace,
+        Some("debug") => LevelFilter::Debug,
+        Some("info") => LevelFilter::Info,
+        Some("warn") => LevelFilter::Warn,
+        Some("error") => LevelFilter::Error,
+        Some(_) => LevelFilter::Info,
+        None => cli_level,
+    };
+
+    match quiet {
+        true => log_level.min(LevelFilter::Warn),
+        false => log_level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_spec_precedence() {
+        let tv: DeviceMatcher = "tv".parse().unwrap();
+        assert!(matches!(
+            render_spec(Some("http://tv/desc.xml"), Some(&tv), 5),
+            RenderSpec::Location(url) if url == "http://tv/desc.xml"
+        ));
+        assert!(matches!(
+            render_spec(None, Some(&tv), 5),
+            RenderSpec::Query(5, DeviceMatcher::NameContains(name)) if name == "tv"
+        ));
+        assert!(matches!(render_spec(None, None, 0), RenderSpec::First(0)));
+    }
+
+    #[test]
+    fn test_log_level_precedence() {
+        assert_eq!(
+            log_level(None, LevelFilter::Debug, false),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            log_level(Some("trace"), LevelFilter::Debug, false),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            log_level(Some("bogus"), LevelFilter::Debug, false),
+            LevelFilter::Info
+        );
+    }
+
+    #[test]
+    fn test_log_level_quiet() {
+        assert_eq!(log_level(None, LevelFilter::Info, true), LevelFilter::Warn);
+        assert_eq!(
+            log_level(Some("debug"), LevelFilter::Info, true),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            log_level(Some("error"), LevelFilter::Info, true),
+            LevelFilter::Error
+        );
+    }
+}
@@ -3,54 +3,101 @@
 //! This module implements the play command which handles media playback
 //! including playlist management, TUI mode, and interactive control.
 
+use super::{
+    super::{AfterPlayback, Printer},
+    render_spec, select_render,
+};
+#[cfg(feature = "watch")]
+use crate::media::DirectoryWatcher;
+#[cfg(feature = "probe")]
+use crate::utils::probe_video;
 use crate::{
-    config::Config,
-    devices::{Render, RenderSpec},
-    dlna,
+    config::{Config, HISTORY_MAX_ENTRIES, LOG_MSG_NO_SUBTITLE_FILE},
+    devices::Render,
+    dlna::{self, EndReason, FailedFile, PlaybackEvent, metadata::media_title},
     error::{Error, Result},
-    infer_subtitle_from_video,
     keyboard::start_interactive_control,
-    media::{MediaStreamingServer, Playlist, SubtitleSyncer, get_local_ip},
-    start_tui,
-    utils::is_supported_media_file,
+    media::{
+        ClipboardMode, MediaStreamingServer, Playlist, ReadThrottle, SubtitleSyncer,
+        get_interface_ip, get_local_ip, osd::OsdDirectory,
+    },
+    start_tui_with_config,
+    utils::{
+        HistoryEntry, HistoryOutcome, append_history, compute_file_uri, default_history_path,
+        format_dlna_time, infer_subtitles_from_video, is_supported_media_file,
+        resolve_subtitle_path, subtitle_size_warning, unix_time_secs, verify_media_file,
+    },
+};
+use log::{debug, error, info, warn};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
-use log::info;
-use std::path::Path;
+
+/// Stands in for the directory watcher when built without the `watch` feature
+#[cfg(not(feature = "watch"))]
+type DirectoryWatcher = std::convert::Infallible;
 
 /// Play command implementation
 pub struct PlayCommand<'a> {
     args: &'a super::super::Play,
+    /// Holds the on-screen display files, removed once the command is done
+    osd_directory: Option<OsdDirectory>,
 }
 
 impl<'a> PlayCommand<'a> {
     /// Create a new play command
     pub fn new(args: &'a super::super::Play) -> Self {
-        Self { args }
+        Self {
+            args,
+            osd_directory: args.osd.then(|| OsdDirectory::new(&std::env::temp_dir())),
+        }
     }
 
     /// Execute the play command
     pub async fn run(&self, config: &Config) -> Result<()> {
-        let render = self.select_render(config).await?;
+        let render_spec = render_spec(
+            self.args.device_url.as_deref(),
+            self.args.device_query.as_ref(),
+            config.discovery_timeout,
+        );
 
-        // Create playlist from path
-        let mut playlist = if self.args.path.is_dir() {
-            info!(
-                "Creating playlist from directory: {}",
-                self.args.path.display()
+        // Create playlist from the paths in order, before spending time on discovery
+        let mut playlist = Playlist::default();
+        for path in &self.args.path {
+            playlist.append(self.build_playlist_for_path(path, config)?);
+        }
+        // The limit applies to all the paths together, not to each of them
+        let skipped = playlist.truncate(config.max_files);
+        if skipped > 0 {
+            warn!(
+                "Only playing the first {} files, {skipped} more were skipped (use --max-files to raise the limit)",
+                config.max_files
             );
-            Playlist::from_directory(&self.args.path)?
-        } else {
-            info!("Creating playlist from file: {}", self.args.path.display());
-            Playlist::from_file(&self.args.path)?
-        };
+        }
+
+        if self.args.serve_only {
+            return self.serve_only(&playlist, config).await;
+        }
+
+        // Watch before discovery, so files dropped meanwhile are played too
+        let mut watcher = self.watch_directory()?;
+
+        let render = select_render(render_spec.clone(), config).await?;
 
         // Set playlist options
         playlist.set_loop(self.args.playlist);
+        match self.args.shuffle_seed {
+            Some(seed) => playlist.set_shuffle_seeded(seed),
+            None => playlist.set_shuffle(self.args.shuffle),
+        }
+        debug!("Playlist: {playlist}");
 
         // Handle TUI mode
         if self.args.tui {
             info!("Starting TUI mode");
-            return start_tui(render, playlist).await;
+            return start_tui_with_config(render, render_spec, playlist, config).await;
         }
 
         // Start interactive control if requested
@@ -58,65 +105,172 @@ impl<'a> PlayCommand<'a> {
             let render_clone = render.clone();
             Some(tokio::spawn(async move {
                 if let Err(e) = start_interactive_control(render_clone).await {
-                    eprintln!("Interactive control error: {e}");
+                    warn!("Interactive control error: {e}");
                 }
             }))
         } else {
             None
         };
 
+        // Status lines would interleave with JSON events on stdout
+        let printer = Printer::new(config.quiet || config.events_json);
+
+        let history_path = self.history_path();
+        let after_playback = self.after_playback(watcher.is_some());
+
         // Play all files in the playlist
         let mut play_result = Ok(());
-        while let Some(current_file) = playlist.next_file() {
-            info!("Playing: {}", current_file.display());
+        let mut summary = PlaylistSummary::default();
+        let started = Instant::now();
+        let mut replay = None;
+        loop {
+            let current_file = match replay.take() {
+                Some(file) => file,
+                None => {
+                    let Some(file) = next_file(&mut playlist, &mut watcher, &printer).await else {
+                        break;
+                    };
+                    if playlist.current_index() == Some(0) && !summary.results.is_empty() {
+                        summary.loops += 1;
+                    }
+                    file
+                }
+            };
+            printer.status(format_args!("Playing: {}", current_file.display()));
 
-            let media_streaming_server = self
-                .build_media_streaming_server_for_file(current_file, config)
+            let mut media_streaming_server = self
+                .build_media_streaming_server_for_file(&current_file, config)
                 .await?;
 
+            // Serve the following file too, so gapless devices can pre-load it
+            if self.args.playlist
+                && after_playback == AfterPlayback::Next
+                && let Some(next_file) = playlist.peek_next_file()
+                && *next_file != current_file
+            {
+                media_streaming_server = media_streaming_server.with_next_file(next_file);
+            }
+
             // Create subtitle syncer if subtitle synchronization is enabled and subtitle file exists
-            let subtitle_syncer = if self.args.subtitle_sync {
+            let subtitle_syncer = if self.args.subtitle_sync
+                || self.args.subtitle_sync_clipboard
+                || self.args.subtitle_sync_file.is_some()
+            {
                 if let Some(subtitle_path) = media_streaming_server.subtitle_file_path() {
-                    match SubtitleSyncer::new(subtitle_path) {
+                    let clipboard_mode = match (
+                        self.args.subtitle_sync_clipboard,
+                        self.args.subtitle_sync,
+                        &self.args.subtitle_sync_file,
+                    ) {
+                        (true, _, _) | (false, true, None) => ClipboardMode::Required,
+                        (false, true, Some(_)) => ClipboardMode::Optional,
+                        (false, false, _) => ClipboardMode::Disabled,
+                    };
+                    let syncer = SubtitleSyncer::open(
+                        subtitle_path,
+                        clipboard_mode,
+                        self.args.subtitle_encoding,
+                    );
+                    match syncer {
                         Ok(syncer) => {
                             info!("Subtitle synchronization enabled");
-                            Some(syncer)
+                            Some(match &self.args.subtitle_sync_file {
+                                Some(output_file) => syncer.with_output_file(output_file),
+                                None => syncer,
+                            })
                         }
+                        Err(e @ Error::ClipboardUnavailable { .. }) => return Err(e),
                         Err(e) => {
-                            eprintln!("Failed to create subtitle syncer: {e}");
+                            warn!("Failed to create subtitle syncer: {e}");
                             None
                         }
                     }
                 } else {
-                    eprintln!("Subtitle synchronization requires a subtitle file");
+                    warn!("Subtitle synchronization requires a subtitle file");
                     None
                 }
             } else {
                 None
             };
 
-            // Play the current file
-            play_result = dlna::play(
+            // Play the current file, releasing the device if interrupted
+            let started_at = unix_time_secs();
+            let playback = dlna::play_until_stopped(
                 render.clone(),
                 media_streaming_server,
                 subtitle_syncer,
                 config,
-            )
-            .await;
-
-            if play_result.is_err() {
-                eprintln!(
-                    "Failed to play {}: {:?}",
-                    current_file.display(),
-                    play_result
+            );
+            let mut end_reason = None;
+            let interrupted = tokio::select! {
+                result = playback => {
+                    end_reason = result.as_ref().ok().copied();
+                    play_result = result.map(|_| ());
+                    false
+                }
+                _ = tokio::signal::ctrl_c() => true,
+            };
+            if interrupted {
+                info!("Interrupted, releasing the device");
+                render.stop_and_clear().await;
+                record_history(
+                    &history_path,
+                    &current_file,
+                    &render,
+                    started_at,
+                    HistoryOutcome::Interrupted,
+                    None,
                 );
-                if !self.args.playlist {
-                    break; // Stop on error if not in playlist mode
+                play_result = Err(Error::Interrupted {
+                    file: current_file.display().to_string(),
+                });
+                break;
+            }
+            summary.record(current_file.clone(), &play_result);
+            let (outcome, error) = match (&play_result, end_reason) {
+                (Err(e), _) => (HistoryOutcome::Failed, Some(e.to_string())),
+                (Ok(()), Some(EndReason::Stopped)) => (HistoryOutcome::Stopped, None),
+                (Ok(()), _) => (HistoryOutcome::Completed, None),
+            };
+            record_history(
+                &history_path,
+                &current_file,
+                &render,
+                started_at,
+                outcome,
+                error,
+            );
+
+            if let Err(e) = &play_result
+                && config.events_json
+            {
+                PlaybackEvent::Error {
+                    file: current_file.display().to_string(),
+                    message: e.to_string(),
+                }
+                .emit();
+            }
+
+            if let Err(e) = &play_result {
+                error!("Failed to play {}: {e}", current_file.display());
+                if !self.args.playlist && watcher.is_none() {
+                    break; // Stop on error if not in playlist or watch mode
                 }
             }
 
-            // If not in playlist mode, play only one file
-            if !self.args.playlist {
+            if end_reason == Some(EndReason::EndOfMedia) {
+                match after_playback {
+                    AfterPlayback::Stop => break,
+                    AfterPlayback::Loop => replay = Some(current_file),
+                    AfterPlayback::Next => {}
+                    AfterPlayback::Quit => {
+                        info!("Playback finished, releasing the device");
+                        render.stop_and_clear().await;
+                        break;
+                    }
+                }
+            } else if !self.args.playlist && watcher.is_none() {
+                // Stopped early: if not in playlist or watch mode, play only one file
                 break;
             }
         }
@@ -126,20 +280,116 @@ impl<'a> PlayCommand<'a> {
             handle.abort();
         }
 
+        if self.args.playlist || watcher.is_some() {
+            summary.duration = started.elapsed();
+            if config.events_json {
+                summary.to_event().emit();
+            } else {
+                printer.status(&summary);
+            }
+        }
+
         play_result
     }
 
-    /// Select the render device based on command arguments
-    async fn select_render(&self, config: &Config) -> Result<Render> {
-        info!("Selecting render");
-        Render::new(if let Some(device_url) = &self.args.device_url {
-            RenderSpec::Location(device_url.to_owned())
-        } else if let Some(device_query) = &self.args.device_query {
-            RenderSpec::Query(config.discovery_timeout, device_query.to_owned())
+    /// Build the playlist of a single `--path`, scanning it if it is a directory
+    fn build_playlist_for_path(&self, path: &Path, config: &Config) -> Result<Playlist> {
+        if path.is_dir() {
+            info!("Creating playlist from directory: {}", path.display());
+            let mut playlist = Playlist::from_directory_with_config(path, config)?;
+            if self.args.verify {
+                remove_unplayable_files(&mut playlist, path)?;
+            }
+            Ok(playlist)
         } else {
-            RenderSpec::First(config.discovery_timeout)
-        })
-        .await
+            info!("Creating playlist from file: {}", path.display());
+            let playlist = Playlist::from_file(path)?;
+            if self.args.verify {
+                verify_media_file(path)?;
+            }
+            Ok(playlist)
+        }
+    }
+
+    /// Resolves the address the files are served from
+    async fn host_ip(&self) -> Result<String> {
+        match (&self.args.host, &self.args.interface) {
+            (Some(host), _) => Ok(host.clone()),
+            (None, Some(interface)) => get_interface_ip(interface),
+            (None, None) => get_local_ip().await,
+        }
+    }
+
+    /// What to do once a file has played to its end, `--after-playback` or its default
+    ///
+    /// Playlist and watch modes go on to the next file, while a single file is
+    /// only played once.
+    fn after_playback(&self, watching: bool) -> AfterPlayback {
+        match self.args.after_playback {
+            Some(after_playback) => after_playback,
+            None if self.args.playlist || watching => AfterPlayback::Next,
+            None => AfterPlayback::Stop,
+        }
+    }
+
+    /// Location of the `--history` log, `None` when not saving history
+    fn history_path(&self) -> Option<PathBuf> {
+        match &self.args.history {
+            None => None,
+            Some(Some(path)) => Some(path.clone()),
+            Some(None) => {
+                let path = default_history_path();
+                if path.is_none() {
+                    warn!(
+                        "No configuration directory found to save play history in, give --history a file"
+                    );
+                }
+                path
+            }
+        }
+    }
+
+    /// Starts watching the `--watch` directory, if any
+    #[cfg(feature = "watch")]
+    fn watch_directory(&self) -> Result<Option<DirectoryWatcher>> {
+        let Some(dir) = &self.args.watch else {
+            return Ok(None);
+        };
+        info!("Watching {} for new media files", dir.display());
+        DirectoryWatcher::new(dir).map(Some)
+    }
+
+    #[cfg(not(feature = "watch"))]
+    fn watch_directory(&self) -> Result<Option<DirectoryWatcher>> {
+        Ok(None)
+    }
+
+    /// Serves the first file of the playlist without a device, until interrupted
+    async fn serve_only(&self, playlist: &Playlist, config: &Config) -> Result<()> {
+        let Some(file) = playlist.get_file(0) else {
+            return Ok(());
+        };
+        if playlist.len() > 1 {
+            warn!("Only serving the first of {} files", playlist.len());
+        }
+        let server = self
+            .build_media_streaming_server_for_file(file, config)
+            .await?;
+        let uri = compute_file_uri(&self.host_ip().await?, config.streaming_port, file);
+        let listener = server.bind().await?;
+
+        let printer = Printer::new(config.quiet);
+        printer.result(&uri);
+        #[cfg(feature = "qrcode")]
+        if let Some(qr_code) = super::super::qr_code(&uri) {
+            printer.status(qr_code);
+        }
+        printer.status("Serving until interrupted (Ctrl+C)");
+
+        tokio::select! {
+            result = server.serve(listener) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        }
     }
 
     /// Build media streaming server for a specific file
@@ -163,19 +413,262 @@ impl<'a> PlayCommand<'a> {
             });
         }
 
-        let local_host_ip = get_local_ip().await?;
-        let host_ip = self.args.host.as_ref().unwrap_or(&local_host_ip);
+        // Renderers often fail silently on files they can't decode
+        #[cfg(feature = "probe")]
+        if self.args.probe_video
+            && let Some(warning) =
+                probe_video(file_path).and_then(|video| video.compatibility_warning())
+        {
+            warn!("{}: {warning}", file_path.display());
+        }
+
+        let host_ip = self.host_ip().await?;
         let host_port = config.streaming_port;
 
-        let subtitle = match &self.args.no_subtitle {
-            false => self
+        let subtitles = match (self.args.no_subtitle, self.args.subtitle.is_empty()) {
+            (true, _) => Vec::new(),
+            (false, true) => infer_subtitles_from_video(file_path),
+            (false, false) => self
                 .args
                 .subtitle
-                .clone()
-                .or_else(|| infer_subtitle_from_video(file_path)),
-            true => None,
+                .iter()
+                .map(|subtitle| resolve_subtitle_path(subtitle, file_path))
+                .collect(),
+        };
+
+        // Only subtitles given with --subtitle are kept over the on-screen display
+        let subtitles = match (&self.osd_directory, self.args.subtitle.first()) {
+            (Some(_), Some(subtitle)) if !self.args.osd_replace && !self.args.no_subtitle => {
+                warn!(
+                    "Not showing the on-screen display, as it would replace subtitle {} (use --osd-replace)",
+                    subtitle.display()
+                );
+                subtitles
+            }
+            (Some(osd_directory), _) => {
+                if let Some(subtitle) = subtitles.first() {
+                    info!(
+                        "Showing the on-screen display instead of subtitle {}",
+                        subtitle.display()
+                    );
+                }
+                vec![osd_directory.write_subtitle(file_path, &media_title(file_path, config))?]
+            }
+            (None, _) => subtitles,
         };
 
-        MediaStreamingServer::new(file_path, &subtitle, host_ip, &host_port)
+        if subtitles.is_empty() {
+            debug!("{LOG_MSG_NO_SUBTITLE_FILE}");
+        }
+        for subtitle in &subtitles {
+            if let Some(warning) = subtitle_size_warning(
+                subtitle,
+                config.subtitle_warn_bytes,
+                config.subtitle_warn_cues,
+            ) {
+                warn!("{warning}");
+            }
+        }
+
+        let first_subtitle = subtitles.first().cloned();
+        let media_streaming_server =
+            MediaStreamingServer::new(file_path, &first_subtitle, &host_ip, &host_port)?;
+        let media_streaming_server = subtitles
+            .iter()
+            .skip(1)
+            .try_fold(media_streaming_server, |server, subtitle| {
+                server.with_subtitle(subtitle)
+            })?;
+
+        Ok(media_streaming_server
+            .with_server_header(&config.server_header)
+            .with_chunk_size(config.chunk_size)
+            .with_read_ahead(config.read_ahead)
+            .with_read_throttle(config.throttle_when_paused.then(ReadThrottle::new)))
+    }
+}
+
+/// Moves to the next file of the playlist, queueing the new files of the watched directory
+///
+/// Once the playlist is done, waits for a new file to arrive until interrupted.
+#[cfg_attr(not(feature = "watch"), allow(unused_variables))]
+async fn next_file(
+    playlist: &mut Playlist,
+    watcher: &mut Option<DirectoryWatcher>,
+    printer: &Printer,
+) -> Option<PathBuf> {
+    #[cfg(feature = "watch")]
+    if let Some(watcher) = watcher {
+        let queue = |playlist: &mut Playlist, file: PathBuf| {
+            let display = file.display().to_string();
+            let added = playlist.add_file_unique(file);
+            if added {
+                printer.status(format_args!("Queued: {display}"));
+            }
+            added
+        };
+        while let Some(file) = watcher.try_next_file() {
+            queue(playlist, file);
+        }
+        if playlist.peek_next_file().is_none() {
+            printer.status("Waiting for new files...");
+            loop {
+                let file = tokio::select! {
+                    file = watcher.next_file() => file?,
+                    _ = tokio::signal::ctrl_c() => return None,
+                };
+                if queue(playlist, file) {
+                    break;
+                }
+            }
+        }
+    }
+    playlist.next_file().cloned()
+}
+
+/// Appends a file played to the `--history` log, if saving history
+fn record_history(
+    history_path: &Option<PathBuf>,
+    file: &Path,
+    render: &Render,
+    started_at: u64,
+    outcome: HistoryOutcome,
+    error: Option<String>,
+) {
+    let Some(history_path) = history_path else {
+        return;
+    };
+    let entry = HistoryEntry {
+        file: file.display().to_string(),
+        device: render.friendly_name().to_string(),
+        started_at,
+        outcome,
+        error,
+    };
+    if let Err(e) = append_history(history_path, &entry, HISTORY_MAX_ENTRIES) {
+        warn!(
+            "Failed to save play history to {}: {e}",
+            history_path.display()
+        );
+    }
+}
+
+/// Outcome of the files played during a playlist run
+#[derive(Debug, Default)]
+struct PlaylistSummary {
+    /// Each file played, with the error it failed with, in order
+    results: Vec<(PathBuf, Option<String>)>,
+    /// Time spent playing the files
+    duration: Duration,
+    /// Number of times the whole playlist was played
+    loops: usize,
+}
+
+impl PlaylistSummary {
+    /// Records the outcome of playing a file
+    fn record(&mut self, file: PathBuf, result: &Result<()>) {
+        let error = result.as_ref().err().map(ToString::to_string);
+        self.results.push((file, error));
+    }
+
+    /// Builds the JSON event reporting the summary
+    fn to_event(&self) -> PlaybackEvent {
+        let mut played = Vec::new();
+        let mut failed = Vec::new();
+        for (file, error) in &self.results {
+            let file = file.display().to_string();
+            match error {
+                Some(message) => failed.push(FailedFile {
+                    file,
+                    message: message.clone(),
+                }),
+                None => played.push(file),
+            }
+        }
+        PlaybackEvent::Summary {
+            played,
+            failed,
+            duration_secs: self.duration.as_secs(),
+            loops: self.loops,
+        }
+    }
+}
+
+impl fmt::Display for PlaylistSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.results.iter().filter(|(_, e)| e.is_some()).count();
+        write!(
+            f,
+            "Played {} of {} files in {}",
+            self.results.len() - failed,
+            self.results.len(),
+            format_dlna_time(self.duration.as_millis() as u64)
+        )?;
+        match self.loops {
+            0 => {}
+            1 => write!(f, ", 1 loop completed")?,
+            loops => write!(f, ", {loops} loops completed")?,
+        }
+        for (file, error) in &self.results {
+            match error {
+                Some(message) => write!(f, "\n  failed: {} ({message})", file.display())?,
+                None => write!(f, "\n  played: {}", file.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the files that fail verification from a playlist, warning about each
+fn remove_unplayable_files(playlist: &mut Playlist, dir_path: &Path) -> Result<()> {
+    for index in (0..playlist.len()).rev() {
+        let Some(file) = playlist.get_file(index) else {
+            continue;
+        };
+        if let Err(e) = verify_media_file(file) {
+            warn!("Skipping file: {e}");
+            playlist.remove(index);
+        }
+    }
+
+    if playlist.is_empty() {
+        return Err(Error::MediaFileNotFound {
+            path: dir_path.display().to_string(),
+            context: "No playable media files found in directory".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playlist_summary() {
+        let mut summary = PlaylistSummary {
+            duration: Duration::from_secs(3723),
+            loops: 1,
+            ..Default::default()
+        };
+        summary.record(PathBuf::from("a.mp4"), &Ok(()));
+        summary.record(
+            PathBuf::from("b.mp4"),
+            &Err(Error::MediaFileNotFound {
+                path: "b.mp4".to_string(),
+                context: "gone".to_string(),
+            }),
+        );
+
+        let text = summary.to_string();
+        assert!(text.starts_with("Played 1 of 2 files in 01:02:03, 1 loop completed\n"));
+        assert!(text.contains("\n  played: a.mp4"));
+        assert!(text.contains("\n  failed: b.mp4 ("));
+
+        let PlaybackEvent::Summary { played, failed, .. } = summary.to_event() else {
+            panic!("expected a summary event");
+        };
+        assert_eq!(played, ["a.mp4"]);
+        assert_eq!(failed[0].file, "b.mp4");
     }
 }
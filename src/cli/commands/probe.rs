@@ -0,0 +1,270 @@
+//! Probe command implementation for crab-dlna
+//!
+//! This module implements the probe command which queries everything a
+//! device reports about itself, to attach to interoperability bug reports.
+
+use super::{super::Printer, render_spec, select_render};
+use crate::{
+    config::Config,
+    devices::{
+        DeviceCapabilities, MediaInfo, PositionInfo, Render, TransportInfo, TransportSettings,
+    },
+    dlna::{get_mute, get_volume},
+    error::Result,
+};
+use serde_json::{Value, json};
+use std::fmt;
+
+/// Probe command implementation
+pub struct ProbeCommand<'a> {
+    args: &'a super::super::Probe,
+}
+
+impl<'a> ProbeCommand<'a> {
+    /// Create a new probe command
+    pub fn new(args: &'a super::super::Probe) -> Self {
+        Self { args }
+    }
+
+    /// Execute the probe command
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        let render_spec = render_spec(
+            self.args.device_url.as_deref(),
+            self.args.device_query.as_ref(),
+            config.discovery_timeout,
+        );
+        let render = select_render(render_spec, config).await?;
+        let printer = Printer::new(config.quiet);
+        if self.args.scpd {
+            printer.result(render.service_scpd().await?);
+            return Ok(());
+        }
+
+        let report = ProbeReport::query(&render).await;
+        match self.args.json {
+            true => printer.result(report.to_json()),
+            false => printer.result(&report),
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one query of the device, with the error message if it failed
+type Probed<T> = std::result::Result<T, String>;
+
+/// Everything a device reports about itself
+struct ProbeReport {
+    friendly_name: String,
+    description_url: String,
+    device_type: String,
+    service_type: String,
+    service_id: String,
+    actions: Probed<Vec<String>>,
+    capabilities: Probed<DeviceCapabilities>,
+    transport_info: Probed<TransportInfo>,
+    position_info: Probed<PositionInfo>,
+    media_info: Probed<MediaInfo>,
+    transport_settings: Probed<TransportSettings>,
+    volume: Probed<u8>,
+    mute: Probed<bool>,
+}
+
+impl ProbeReport {
+    /// Queries the device, carrying on past the queries it fails to answer
+    async fn query(render: &Render) -> Self {
+        Self {
+            friendly_name: render.friendly_name().to_string(),
+            description_url: render.description_url().to_string(),
+            device_type: render.device_type().to_string(),
+            service_type: render.service_type().to_string(),
+            service_id: render.service_id().to_string(),
+            actions: probed(render.supported_actions().await),
+            capabilities: probed(render.get_device_capabilities().await),
+            transport_info: probed(render.get_transport_info().await),
+            position_info: probed(render.get_position_info().await),
+            media_info: probed(render.get_media_info().await),
+            transport_settings: probed(render.get_transport_settings().await),
+            volume: probed(get_volume(render).await),
+            mute: probed(get_mute(render).await),
+        }
+    }
+
+    /// Builds the JSON form of the report
+    ///
+    /// Failed queries are reported as `{"error": message}`.
+    fn to_json(&self) -> Value {
+        json!({
+            "friendly_name": self.friendly_name,
+            "description_url": self.description_url,
+            "device_type": self.device_type,
+            "service_type": self.service_type,
+            "service_id": self.service_id,
+            "actions": probed_json(&self.actions, |actions| json!(actions)),
+            "capabilities": probed_json(&self.capabilities, |capabilities| json!({
+                "play_media": capabilities.play_media,
+                "rec_media": capabilities.rec_media,
+                "rec_quality_modes": capabilities.rec_quality_modes,
+            })),
+            "transport_info": probed_json(&self.transport_info, |info| json!({
+                "transport_state": info.transport_state,
+                "transport_status": info.transport_status,
+                "speed": info.speed,
+            })),
+            "position_info": probed_json(&self.position_info, |info| json!({
+                "track": info.track,
+                "track_duration": info.track_duration,
+                "track_meta_data": info.track_meta_data,
+                "track_uri": info.track_uri,
+                "rel_time": info.rel_time,
+                "abs_time": info.abs_time,
+            })),
+            "media_info": probed_json(&self.media_info, |info| json!({
+                "nr_tracks": info.nr_tracks,
+                "media_duration": info.media_duration,
+                "current_uri": info.current_uri,
+                "play_medium": info.play_medium,
+            })),
+            "transport_settings": probed_json(&self.transport_settings, |settings| json!({
+                "play_mode": settings.play_mode.to_string(),
+                "rec_quality_mode": settings.rec_quality_mode,
+            })),
+            "volume": probed_json(&self.volume, |volume| json!(volume)),
+            "mute": probed_json(&self.mute, |mute| json!(mute)),
+        })
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = [
+            format!("Device type: {}", self.device_type),
+            format!("Description: {}", self.description_url),
+            format!("Service: {} ({})", self.service_type, self.service_id),
+            probed_line("Actions", &self.actions, |actions| actions.join(", ")),
+            probed_line("Plays from", &self.capabilities, |capabilities| {
+                capabilities.play_media.join(", ")
+            }),
+            probed_line("Transport", &self.transport_info, |info| {
+                format!(
+                    "{} ({}), speed {}",
+                    info.transport_state, info.transport_status, info.speed
+                )
+            }),
+            probed_line("Position", &self.position_info, |info| {
+                format!(
+                    "{} / {}, track {} {}",
+                    info.rel_time, info.track_duration, info.track, info.track_uri
+                )
+            }),
+            probed_line("Media", &self.media_info, |info| {
+                format!(
+                    "{} track(s), {} from {} {}",
+                    info.nr_tracks, info.media_duration, info.play_medium, info.current_uri
+                )
+            }),
+            probed_line("Play mode", &self.transport_settings, |settings| {
+                settings.play_mode.to_string()
+            }),
+            probed_line("Volume", &self.volume, u8::to_string),
+            probed_line("Muted", &self.mute, |mute| {
+                if *mute { "yes" } else { "no" }.to_string()
+            }),
+        ];
+
+        write!(f, "{}", self.friendly_name)?;
+        for line in lines {
+            write!(f, "\n  {}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the message of a failed query
+fn probed<T>(result: Result<T>) -> Probed<T> {
+    result.map_err(|e| e.to_string())
+}
+
+/// Serializes the outcome of a query
+fn probed_json<T>(probed: &Probed<T>, to_json: impl FnOnce(&T) -> Value) -> Value {
+    match probed {
+        Ok(value) => to_json(value),
+        Err(message) => json!({ "error": message }),
+    }
+}
+
+/// Formats the outcome of a query as a labelled line
+fn probed_line<T>(label: &str, probed: &Probed<T>, format: impl FnOnce(&T) -> String) -> String {
+    match probed {
+        Ok(value) => format!("{label}: {}", format(value)),
+        Err(message) => format!("{label}: unavailable ({message})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> ProbeReport {
+        ProbeReport {
+            friendly_name: "Living Room TV".to_string(),
+            description_url: "http://192.168.1.20:1400/description.xml".to_string(),
+            device_type: "urn:schemas-upnp-org:device:MediaRenderer:1".to_string(),
+            service_type: "urn:schemas-upnp-org:service:AVTransport:1".to_string(),
+            service_id: "urn:upnp-org:serviceId:AVTransport".to_string(),
+            actions: Ok(vec!["Play".to_string(), "Stop".to_string()]),
+            capabilities: Ok(DeviceCapabilities {
+                play_media: vec!["NETWORK".to_string()],
+                ..Default::default()
+            }),
+            transport_info: Ok(TransportInfo {
+                transport_state: "STOPPED".to_string(),
+                transport_status: "OK".to_string(),
+                speed: "1".to_string(),
+            }),
+            position_info: Ok(PositionInfo::default()),
+            media_info: Ok(MediaInfo {
+                nr_tracks: 1,
+                media_duration: "01:30:00".to_string(),
+                current_uri: "http://192.168.1.5:9000/video.mkv".to_string(),
+                play_medium: "NETWORK".to_string(),
+            }),
+            transport_settings: Err("Invalid Action".to_string()),
+            volume: Ok(12),
+            mute: Ok(false),
+        }
+    }
+
+    #[test]
+    fn test_probe_report_display() {
+        let text = report().to_string();
+
+        assert!(text.starts_with("Living Room TV\n  Device type: "));
+        assert!(text.contains("\n  Actions: Play, Stop\n"));
+        assert!(text.contains("\n  Plays from: NETWORK\n"));
+        assert!(text.contains("\n  Transport: STOPPED (OK), speed 1\n"));
+        assert!(text.contains("\n  Play mode: unavailable (Invalid Action)\n"));
+        assert!(text.contains(
+            "\n  Media: 1 track(s), 01:30:00 from NETWORK http://192.168.1.5:9000/video.mkv\n"
+        ));
+        assert!(text.contains("\n  Volume: 12\n"));
+        assert!(text.ends_with("\n  Muted: no"));
+    }
+
+    #[test]
+    fn test_probe_report_json() {
+        let json = report().to_json();
+
+        assert_eq!(json["friendly_name"], "Living Room TV");
+        assert_eq!(json["actions"], json!(["Play", "Stop"]));
+        assert_eq!(json["capabilities"]["play_media"], json!(["NETWORK"]));
+        assert_eq!(json["transport_info"]["transport_state"], "STOPPED");
+        assert_eq!(json["transport_settings"]["error"], "Invalid Action");
+        assert_eq!(json["media_info"]["nr_tracks"], 1);
+        assert_eq!(
+            json["media_info"]["current_uri"],
+            "http://192.168.1.5:9000/video.mkv"
+        );
+        assert_eq!(json["volume"], 12);
+        assert_eq!(json["mute"], false);
+    }
+}
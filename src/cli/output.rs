@@ -0,0 +1,93 @@
+//! User-facing output for crab-dlna commands
+//!
+//! Results such as device listings are written to stdout so they can be piped
+//! into other tools, while diagnostics go through `log`, which writes to stderr.
+
+use std::fmt::Display;
+
+/// Writes user-facing output to stdout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Printer {
+    /// Whether status lines are suppressed
+    quiet: bool,
+}
+
+impl Printer {
+    /// Creates a printer, suppressing status lines when `quiet` is set
+    pub fn new(quiet: bool) -> Self {
+        Self { quiet }
+    }
+
+    /// Prints a result line, regardless of `--quiet`
+    pub fn result(&self, line: impl Display) {
+        println!("{line}");
+    }
+
+    /// Prints a status line, unless `--quiet` is set
+    pub fn status(&self, line: impl Display) {
+        if !self.quiet {
+            println!("{line}");
+        }
+    }
+}
+
+/// Renders a QR code of `text` for the terminal, None if it doesn't fit in one
+///
+/// Half-block characters are used on UTF-8 terminals and `#` otherwise. Colors
+/// are inverted, so the code reads as dark on light on a dark terminal.
+#[cfg(feature = "qrcode")]
+pub fn qr_code(text: &str) -> Option<String> {
+    render_qr_code(text, terminal_supports_utf8())
+}
+
+/// Renders a QR code of `text` with half blocks, or `#` without `utf8`
+#[cfg(feature = "qrcode")]
+fn render_qr_code(text: &str, utf8: bool) -> Option<String> {
+    use qrcode::{QrCode, render::unicode::Dense1x2};
+
+    let code = QrCode::new(text.as_bytes()).ok()?;
+    Some(if utf8 {
+        code.render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build()
+    } else {
+        code.render::<char>()
+            .module_dimensions(2, 1)
+            .dark_color(' ')
+            .light_color('#')
+            .build()
+    })
+}
+
+/// Checks whether the locale of the terminal uses UTF-8
+#[cfg(feature = "qrcode")]
+fn terminal_supports_utf8() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+#[cfg(all(test, feature = "qrcode"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qr_code_falls_back_to_ascii() {
+        let url = "http://192.168.1.100:9000/0123456789abcdef/movie.mp4";
+        let ascii = render_qr_code(url, false).unwrap();
+        assert!(ascii.chars().all(|c| matches!(c, '#' | ' ' | '\n')));
+        let unicode = render_qr_code(url, true).unwrap();
+        assert!(!unicode.is_ascii());
+        // Half blocks pack two rows of modules into one line
+        assert_eq!(ascii.lines().count().div_ceil(2), unicode.lines().count());
+    }
+}
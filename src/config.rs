@@ -4,8 +4,10 @@
 //! for the crab-dlna media streaming application.
 
 mod constants;
+mod presets;
 mod types;
 
 // Re-export all constants and types
 pub use constants::*;
+pub use presets::*;
 pub use types::*;
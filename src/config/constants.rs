@@ -8,20 +8,59 @@
 // =============================================================================
 
 /// Default port for the media streaming server
-pub const DEFAULT_STREAMING_PORT: u32 = 9000;
+pub const DEFAULT_STREAMING_PORT: u16 = 9000;
 
 /// Default timeout for device discovery in seconds
 pub const DEFAULT_DISCOVERY_TIMEOUT: u64 = 5;
 
+/// Default time to wait for a device to answer a UPnP action, in seconds
+pub const DEFAULT_ACTION_TIMEOUT_SECS: u64 = 10;
+
+/// Time to wait for an HTTP server already listening on the streaming port to answer, in milliseconds
+pub const SERVER_PROBE_TIMEOUT_MS: u64 = 1000;
+
 /// Maximum number of retries for network operations
 pub const MAX_NETWORK_RETRIES: u32 = 3;
 
+/// UPnP error code renders return while still busy with a previous transport change
+pub const UPNP_ERROR_TRANSITION_NOT_AVAILABLE: u16 = 701;
+
+/// Maximum number of attempts for an action rejected as "transition not available"
+pub const MAX_TRANSITION_RETRIES: u32 = 5;
+
+/// Delay between attempts for an action rejected as "transition not available", in milliseconds
+pub const TRANSITION_RETRY_DELAY_MS: u64 = 300;
+
+/// Default size of the chunks read from disk when streaming a file, in bytes
+pub const DEFAULT_STREAMING_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Smallest accepted streaming chunk size, in bytes
+pub const MIN_STREAMING_CHUNK_SIZE: usize = 4 * 1024;
+
+/// Largest accepted streaming chunk size, in bytes
+pub const MAX_STREAMING_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest accepted read-ahead buffer per streamed response, in bytes
+pub const MAX_STREAMING_READ_AHEAD: usize = 256 * 1024 * 1024;
+
+/// Size above which subtitle responses are compressed for clients accepting it, in bytes
+pub const SUBTITLE_COMPRESSION_MIN_BYTES: u16 = 1024;
+
+/// Number of idle file handles the streaming server keeps open for reuse
+pub const STREAMING_FILE_HANDLE_CACHE_SIZE: usize = 8;
+
 /// TTL (Time To Live) for SSDP multicast packets
 pub const SSDP_TTL: Option<u32> = Some(3);
 
 /// User agent string for HTTP requests
 pub const USER_AGENT: &str = concat!("crab-dlna/", env!("CARGO_PKG_VERSION"));
 
+/// `Server` header in the style of DLNA certified media servers, expected by some renderers
+pub const DLNA_SERVER_HEADER: &str = concat!(
+    "Linux/3.10 UPnP/1.0 DLNADOC/1.50 crab-dlna/",
+    env!("CARGO_PKG_VERSION")
+);
+
 // =============================================================================
 // DLNA Protocol Constants
 // =============================================================================
@@ -32,12 +71,33 @@ pub const DLNA_POSITION_INFO_PAYLOAD: &str = r#"<InstanceID>0</InstanceID>"#;
 /// DLNA payload template for transport info action
 pub const DLNA_TRANSPORT_INFO_PAYLOAD: &str = r#"<InstanceID>0</InstanceID>"#;
 
+/// DLNA payload template for transport settings action
+pub const DLNA_TRANSPORT_SETTINGS_PAYLOAD: &str = r#"<InstanceID>0</InstanceID>"#;
+
+/// DLNA payload template for device capabilities action
+pub const DLNA_DEVICE_CAPABILITIES_PAYLOAD: &str = r#"<InstanceID>0</InstanceID>"#;
+
+/// DLNA payload template for media info action
+pub const DLNA_MEDIA_INFO_PAYLOAD: &str = r#"<InstanceID>0</InstanceID>"#;
+
 /// DLNA instance ID used in payloads
 pub const DLNA_INSTANCE_ID: u32 = 0;
 
 /// DLNA default playback speed
 pub const DLNA_DEFAULT_SPEED: u32 = 1;
 
+/// Playback speeds cycled through by trick-play controls, slowest first
+pub const DLNA_PLAYBACK_SPEEDS: &[&str] = &["-4", "-2", "1/2", "1", "2", "4"];
+
+/// Seconds skipped forward or backward by a single seek key press
+pub const SEEK_STEP_SECONDS: u64 = 10;
+
+/// Volume points added or removed by a single volume key press
+pub const VOLUME_STEP: u8 = 5;
+
+/// Highest volume accepted by the RenderingControl service
+pub const MAX_VOLUME: u8 = 100;
+
 // =============================================================================
 // Media File Support Constants
 // =============================================================================
@@ -47,6 +107,15 @@ pub const SUPPORTED_VIDEO_EXTENSIONS: &[&str] = &[
     "mp4", "avi", "mkv", "mov", "wmv", "flv", "webm", "m4v", "3gp", "ogv",
 ];
 
+/// Default maximum number of files collected when scanning a directory
+pub const DEFAULT_MAX_PLAYLIST_FILES: usize = 10_000;
+
+/// Name of the play history log in the crab-dlna configuration directory
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// Number of entries the play history log is kept to, dropping the oldest ones
+pub const HISTORY_MAX_ENTRIES: usize = 1000;
+
 /// Supported audio file extensions
 pub const SUPPORTED_AUDIO_EXTENSIONS: &[&str] =
     &["mp3", "wav", "flac", "aac", "ogg", "wma", "m4a", "opus"];
@@ -58,6 +127,46 @@ pub const SUPPORTED_AUDIO_EXTENSIONS: &[&str] =
 /// Default interval for subtitle synchronization checks in milliseconds
 pub const DEFAULT_SUBTITLE_SYNC_INTERVAL_MS: u64 = 500;
 
+/// Subtitle file size above which a warning is logged, as some renderers drop large subtitles
+pub const DEFAULT_SUBTITLE_WARN_BYTES: u64 = 512 * 1024;
+
+/// Number of subtitle cues above which a warning is logged, as some renderers truncate them
+pub const DEFAULT_SUBTITLE_WARN_CUES: usize = 5000;
+
+/// Interval between "now playing" on-screen display cues, in seconds
+pub const OSD_INTERVAL_SECS: u64 = 300;
+
+/// Time each "now playing" on-screen display cue stays visible, in seconds
+pub const OSD_CUE_DURATION_SECS: u64 = 5;
+
+/// Length of media covered by the on-screen display cues, in seconds
+pub const OSD_HORIZON_SECS: u64 = 6 * 3600;
+
+/// Shortest accepted polling interval in milliseconds, so the device isn't flooded with requests
+pub const MIN_POLL_INTERVAL_MS: u64 = 100;
+
+/// Interval for polling playback position when reporting events in milliseconds
+pub const EVENT_POLL_INTERVAL_MS: u64 = 1000;
+
+/// Interval for polling the transport state when throttling reads during pauses in milliseconds
+pub const PAUSE_THROTTLE_POLL_INTERVAL_MS: u64 = 1000;
+
+/// Interval for polling the transport state to detect the end of playback in milliseconds
+pub const PLAYBACK_END_POLL_INTERVAL_MS: u64 = 500;
+
+/// Distance from the end of the media within which a stop counts as playing to the end, in milliseconds
+///
+/// Covers the position going stale between polls and devices stopping just
+/// short of the reported duration.
+pub const END_OF_MEDIA_MARGIN_MS: u64 = 5000;
+
+/// Interval for checking the size of a new file in a watched directory in milliseconds
+///
+/// A file is played once its size stays the same over one interval, so files
+/// still being copied or downloaded aren't cast half-written.
+#[cfg(feature = "watch")]
+pub const WATCH_SETTLE_INTERVAL_MS: u64 = 1000;
+
 // =============================================================================
 // Logging Constants
 // =============================================================================
@@ -69,9 +178,55 @@ pub const LOG_LEVEL_ENV_VAR: &str = "CRABDLNA_LOG";
 // Device Discovery Constants
 // =============================================================================
 
+/// Object ID of the root container of a ContentDirectory
+pub const CONTENT_DIRECTORY_ROOT_ID: &str = "0";
+
 /// SSDP search attempts used in upnp_discover function
 pub const SSDP_SEARCH_ATTEMPTS: usize = 3;
 
+/// IPv4 multicast group SSDP searches are sent to
+pub const SSDP_MULTICAST_ADDR: std::net::Ipv4Addr = std::net::Ipv4Addr::new(239, 255, 255, 250);
+
+/// UDP port SSDP searches are sent to
+pub const SSDP_MULTICAST_PORT: u16 = 1900;
+
+/// Length of each search round when a discovery timeout of 0 scans until found
+pub const DISCOVERY_UNTIL_FOUND_ROUND_SECS: u64 = 2;
+
+/// Upper bound on how long a discovery timeout of 0 keeps scanning
+pub const DISCOVERY_UNTIL_FOUND_MAX_SECS: u64 = 60;
+
+/// Description paths commonly served by renderers, tried when a device URL lacks one
+pub const DEVICE_DESCRIPTION_PATHS: &[&str] = &[
+    "description.xml",
+    "dmr.xml",
+    "rootDesc.xml",
+    "DeviceDescription.xml",
+    "dmr/description.xml",
+];
+
+/// Delay in seconds before retrying a device that isn't answering yet, doubled on each failure
+pub const WAIT_FOR_DEVICE_BASE_DELAY_SECS: u64 = 1;
+
+/// Longest delay in seconds between retries of a device that isn't answering yet
+pub const WAIT_FOR_DEVICE_MAX_DELAY_SECS: u64 = 16;
+
+// =============================================================================
+// TUI Constants
+// =============================================================================
+
+/// Default interval between TUI status queries in milliseconds
+pub const DEFAULT_TUI_POLL_INTERVAL_MS: u64 = 1000;
+
+/// Consecutive failed status queries before the TUI tries to reconnect
+pub const TUI_RECONNECT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Maximum number of reconnect attempts before the TUI gives up
+pub const TUI_MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Base delay in seconds between reconnect attempts, doubled on each failure
+pub const TUI_RECONNECT_BASE_DELAY_SECS: u64 = 2;
+
 // =============================================================================
 // Error and Status Messages
 // =============================================================================
@@ -80,7 +235,7 @@ pub const SSDP_SEARCH_ATTEMPTS: usize = 3;
 pub const NO_DEVICES_DISCOVERED_MSG: &str = "No devices discovered in the network";
 
 /// Error message for render device not found
-pub const RENDER_NOT_FOUND_MSG: &str = "No render specified, selecting first one";
+pub const RENDER_NOT_FOUND_MSG: &str = "No render specified, selecting the first one by name";
 
 /// Error message for invalid socket address format
 pub const INVALID_SOCKET_ADDRESS_MSG: &str = "Invalid socket address format";
@@ -88,6 +243,26 @@ pub const INVALID_SOCKET_ADDRESS_MSG: &str = "Invalid socket address format";
 /// Error message for failed media playback
 pub const MEDIA_PLAYBACK_FAILED_MSG: &str = "Failed to start media playback on render device";
 
+/// Exit code for errors without a more specific code
+pub const EXIT_CODE_FAILURE: i32 = 1;
+
+/// Exit code when no device could be found or reached
+///
+/// The codes start at 3, as clap exits with 2 on invalid arguments.
+pub const EXIT_CODE_DEVICE_NOT_FOUND: i32 = 3;
+
+/// Exit code when a media file is missing or unreadable
+pub const EXIT_CODE_MEDIA_NOT_FOUND: i32 = 4;
+
+/// Exit code when the device fails or rejects playback
+pub const EXIT_CODE_PLAYBACK_FAILED: i32 = 5;
+
+/// Exit code for network and streaming server errors
+pub const EXIT_CODE_NETWORK: i32 = 6;
+
+/// Exit code when playback is interrupted with Ctrl+C, as shells report for SIGINT
+pub const EXIT_CODE_INTERRUPTED: i32 = 130;
+
 // =============================================================================
 // DLNA Action Names
 // =============================================================================
@@ -95,18 +270,54 @@ pub const MEDIA_PLAYBACK_FAILED_MSG: &str = "Failed to start media playback on r
 /// DLNA action name for setting AV transport URI
 pub const DLNA_ACTION_SET_AV_TRANSPORT_URI: &str = "SetAVTransportURI";
 
+/// DLNA action name for pre-loading the next AV transport URI
+pub const DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI: &str = "SetNextAVTransportURI";
+
+/// ContentDirectory action name for browsing the children of an object
+pub const DLNA_ACTION_BROWSE: &str = "Browse";
+
 /// DLNA action name for play
 pub const DLNA_ACTION_PLAY: &str = "Play";
 
 /// DLNA action name for pause
 pub const DLNA_ACTION_PAUSE: &str = "Pause";
 
+/// DLNA action name for stop
+pub const DLNA_ACTION_STOP: &str = "Stop";
+
+/// DLNA action name for seeking within the current track
+pub const DLNA_ACTION_SEEK: &str = "Seek";
+
+/// DLNA action name for skipping to the next track
+pub const DLNA_ACTION_NEXT: &str = "Next";
+
+/// DLNA action name for skipping to the previous track
+pub const DLNA_ACTION_PREVIOUS: &str = "Previous";
+
+/// RenderingControl action name for getting the volume
+pub const DLNA_ACTION_GET_VOLUME: &str = "GetVolume";
+
+/// RenderingControl action name for setting the volume
+pub const DLNA_ACTION_SET_VOLUME: &str = "SetVolume";
+
+/// RenderingControl action name for getting the mute state
+pub const DLNA_ACTION_GET_MUTE: &str = "GetMute";
+
 /// DLNA action name for getting position info
 pub const DLNA_ACTION_GET_POSITION_INFO: &str = "GetPositionInfo";
 
 /// DLNA action name for getting transport info
 pub const DLNA_ACTION_GET_TRANSPORT_INFO: &str = "GetTransportInfo";
 
+/// DLNA action name for getting transport settings (play mode)
+pub const DLNA_ACTION_GET_TRANSPORT_SETTINGS: &str = "GetTransportSettings";
+
+/// DLNA action name for getting device capabilities (playable storage media, etc.)
+pub const DLNA_ACTION_GET_DEVICE_CAPABILITIES: &str = "GetDeviceCapabilities";
+
+/// DLNA action name for getting media info (loaded URI, number of tracks, etc.)
+pub const DLNA_ACTION_GET_MEDIA_INFO: &str = "GetMediaInfo";
+
 // =============================================================================
 // Logging Messages
 // =============================================================================
@@ -129,3 +340,9 @@ pub const LOG_MSG_LIST_DEVICES: &str = "List devices";
 
 /// Default DLNA video title
 pub const DEFAULT_DLNA_VIDEO_TITLE: &str = "crab-dlna Video";
+
+/// File in the template directory overriding the built-in DIDL-Lite metadata template
+pub const DIDL_LITE_TEMPLATE_FILE: &str = "didl_lite.xml";
+
+/// File in the template directory overriding the built-in SetAVTransportURI template
+pub const SET_AV_TRANSPORT_URI_TEMPLATE_FILE: &str = "set_av_transport_uri.xml";
@@ -0,0 +1,59 @@
+//! Device presets for crab-dlna
+//!
+//! This module bundles the compatibility settings known to work with some
+//! renderer families, so they don't have to be found out flag by flag.
+
+use clap::ValueEnum;
+
+use super::{constants::*, types::Config};
+
+/// A bundle of settings for a family of renderers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DevicePreset {
+    /// The default settings, which suit most renderers
+    Generic,
+    /// Samsung TVs: a DLNA-style `Server` header and 8 MiB of read-ahead, as
+    /// they fetch data in large bursts
+    Samsung,
+    /// LG webOS TVs: a DLNA-style `Server` header, 256 KiB chunks and titles
+    /// without release tags, as webOS shows the title in its playback banner
+    LgWebos,
+}
+
+impl DevicePreset {
+    /// Returns the configuration with the settings of this preset
+    pub fn config(self) -> Config {
+        let config = Config::default();
+        match self {
+            DevicePreset::Generic => config,
+            DevicePreset::Samsung => config
+                .with_server_header(DLNA_SERVER_HEADER.to_string())
+                .with_read_ahead(8 * 1024 * 1024),
+            DevicePreset::LgWebos => config
+                .with_server_header(DLNA_SERVER_HEADER.to_string())
+                .with_chunk_size(256 * 1024)
+                .with_clean_title(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_preset_is_default() {
+        let config = DevicePreset::Generic.config();
+        assert_eq!(config.server_header, USER_AGENT);
+        assert_eq!(config.chunk_size, DEFAULT_STREAMING_CHUNK_SIZE);
+        assert_eq!(config.read_ahead, 0);
+    }
+
+    #[test]
+    fn test_presets_set_server_header() {
+        for preset in [DevicePreset::Samsung, DevicePreset::LgWebos] {
+            assert_eq!(preset.config().server_header, DLNA_SERVER_HEADER);
+        }
+        assert!(DevicePreset::LgWebos.config().clean_title);
+    }
+}
@@ -3,7 +3,8 @@
 //! This module contains configuration structures and related types
 //! used throughout the application.
 
-use log::LevelFilter;
+use log::{LevelFilter, warn};
+use std::path::PathBuf;
 
 use super::constants::*;
 
@@ -11,17 +12,65 @@ use super::constants::*;
 #[derive(Debug, Clone)]
 pub struct Config {
     /// Port for the streaming server
-    pub streaming_port: u32,
+    pub streaming_port: u16,
     /// Timeout for device discovery
     pub discovery_timeout: u64,
+    /// Time to wait for a device to answer a UPnP action, in seconds
+    pub action_timeout: u64,
+    /// Time to keep retrying a device given by location or query until it answers, in seconds
+    pub wait_for_device: Option<u64>,
     /// Interval for subtitle synchronization
     pub subtitle_sync_interval_ms: u64,
     /// Log level
     pub log_level: LevelFilter,
+    /// Whether informational logs and status output are suppressed
+    pub quiet: bool,
     /// Number of SSDP search attempts
     pub ssdp_search_attempts: usize,
     /// TTL for SSDP discovery packets
     pub ssdp_ttl: Option<u32>,
+    /// Whether directory scans descend into subdirectories
+    pub recursive_scan: bool,
+    /// Whether directory scans follow symlinked files and directories
+    pub follow_symlinks: bool,
+    /// Maximum number of files collected when scanning a directory
+    pub max_files: usize,
+    /// Whether to emit newline-delimited JSON playback events on stdout
+    pub events_json: bool,
+    /// Value of the `Server` header sent by the streaming server
+    pub server_header: String,
+    /// Title advertised to the device instead of one derived from the file name
+    pub title: Option<String>,
+    /// Whether to remove release tags when deriving the title from the file name
+    pub clean_title: bool,
+    /// Size of the chunks read from disk when streaming a file, in bytes
+    pub chunk_size: usize,
+    /// Bytes read ahead of the connection when streaming a file, 0 to disable
+    pub read_ahead: usize,
+    /// Whether disk reads for streamed files are held back while playback is paused
+    pub throttle_when_paused: bool,
+    /// Whether the streaming server keeps serving after playback has finished, until interrupted
+    pub keep_serving: bool,
+    /// Whether to cast the file again when the device is already playing it
+    pub force_recast: bool,
+    /// `Speed` sent with the `Play` action starting playback, `None` to leave the element out
+    pub play_speed: Option<String>,
+    /// File the metadata sent to the device is written to, for debugging
+    pub output_metadata: Option<PathBuf>,
+    /// Directory with templates overriding the built-in DIDL-Lite and SetAVTransportURI ones
+    pub template_dir: Option<PathBuf>,
+    /// Position playback starts from, in milliseconds
+    pub clip_start_ms: Option<u64>,
+    /// Position playback stops at, in milliseconds
+    pub clip_end_ms: Option<u64>,
+    /// Subtitle file size above which a warning is logged, 0 to never warn
+    pub subtitle_warn_bytes: u64,
+    /// Number of subtitle cues above which a warning is logged, 0 to never warn
+    pub subtitle_warn_cues: usize,
+    /// Interval between TUI status queries, `None` to only query on manual refresh
+    pub tui_poll_interval_ms: Option<u64>,
+    /// Whether quitting the TUI asks for confirmation while something is playing
+    pub tui_confirm_quit: bool,
 }
 
 impl Default for Config {
@@ -29,10 +78,34 @@ impl Default for Config {
         Self {
             streaming_port: DEFAULT_STREAMING_PORT,
             discovery_timeout: DEFAULT_DISCOVERY_TIMEOUT,
+            action_timeout: DEFAULT_ACTION_TIMEOUT_SECS,
+            wait_for_device: None,
             subtitle_sync_interval_ms: DEFAULT_SUBTITLE_SYNC_INTERVAL_MS,
             log_level: LevelFilter::Info,
+            quiet: false,
             ssdp_search_attempts: super::constants::SSDP_SEARCH_ATTEMPTS,
             ssdp_ttl: super::constants::SSDP_TTL,
+            recursive_scan: false,
+            follow_symlinks: false,
+            max_files: DEFAULT_MAX_PLAYLIST_FILES,
+            events_json: false,
+            server_header: USER_AGENT.to_string(),
+            title: None,
+            clean_title: false,
+            chunk_size: DEFAULT_STREAMING_CHUNK_SIZE,
+            read_ahead: 0,
+            throttle_when_paused: false,
+            keep_serving: false,
+            force_recast: false,
+            play_speed: Some(DLNA_DEFAULT_SPEED.to_string()),
+            output_metadata: None,
+            template_dir: None,
+            clip_start_ms: None,
+            clip_end_ms: None,
+            subtitle_warn_bytes: DEFAULT_SUBTITLE_WARN_BYTES,
+            subtitle_warn_cues: DEFAULT_SUBTITLE_WARN_CUES,
+            tui_poll_interval_ms: Some(DEFAULT_TUI_POLL_INTERVAL_MS),
+            tui_confirm_quit: true,
         }
     }
 }
@@ -44,7 +117,7 @@ impl Config {
     }
 
     /// Sets the streaming port
-    pub fn with_streaming_port(mut self, port: u32) -> Self {
+    pub fn with_streaming_port(mut self, port: u16) -> Self {
         self.streaming_port = port;
         self
     }
@@ -55,9 +128,40 @@ impl Config {
         self
     }
 
+    /// Sets the interval between TUI status queries, `None` to disable polling
+    ///
+    /// Intervals shorter than `MIN_POLL_INTERVAL_MS` are raised to it.
+    pub fn with_tui_poll_interval(mut self, interval_ms: Option<u64>) -> Self {
+        self.tui_poll_interval_ms =
+            interval_ms.map(|interval_ms| clamp_poll_interval(interval_ms, "TUI poll interval"));
+        self
+    }
+
+    /// Sets whether quitting the TUI asks for confirmation while something is playing
+    pub fn with_tui_confirm_quit(mut self, confirm_quit: bool) -> Self {
+        self.tui_confirm_quit = confirm_quit;
+        self
+    }
+
+    /// Sets the time to wait for a device to answer a UPnP action, in seconds
+    pub fn with_action_timeout(mut self, timeout: u64) -> Self {
+        self.action_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to keep retrying a device that isn't answering yet, in seconds
+    ///
+    /// Only applies to devices given by location or query, see [`crate::Render::wait_for`].
+    pub fn with_wait_for_device(mut self, wait_secs: Option<u64>) -> Self {
+        self.wait_for_device = wait_secs;
+        self
+    }
+
     /// Sets the subtitle synchronization interval
+    ///
+    /// Intervals shorter than `MIN_POLL_INTERVAL_MS` are raised to it.
     pub fn with_subtitle_sync_interval(mut self, interval_ms: u64) -> Self {
-        self.subtitle_sync_interval_ms = interval_ms;
+        self.subtitle_sync_interval_ms = clamp_poll_interval(interval_ms, "subtitle sync interval");
         self
     }
 
@@ -66,6 +170,139 @@ impl Config {
         self.log_level = level;
         self
     }
+
+    /// Sets whether informational logs and status output are suppressed
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Sets whether directory scans descend into subdirectories
+    pub fn with_recursive_scan(mut self, recursive: bool) -> Self {
+        self.recursive_scan = recursive;
+        self
+    }
+
+    /// Sets whether directory scans follow symlinked files and directories
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Sets the maximum number of files collected when scanning a directory
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Sets whether to emit newline-delimited JSON playback events on stdout
+    pub fn with_events_json(mut self, events_json: bool) -> Self {
+        self.events_json = events_json;
+        self
+    }
+
+    /// Sets the value of the `Server` header sent by the streaming server
+    pub fn with_server_header(mut self, server_header: String) -> Self {
+        self.server_header = server_header;
+        self
+    }
+
+    /// Sets the title advertised to the device
+    pub fn with_title(mut self, title: Option<String>) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// Sets whether to remove release tags when deriving the title from the file name
+    pub fn with_clean_title(mut self, clean_title: bool) -> Self {
+        self.clean_title = clean_title;
+        self
+    }
+
+    /// Sets the `Speed` sent with the `Play` action, `None` to leave the element out
+    ///
+    /// A few renderers fault on the element or expect another format than `1`.
+    pub fn with_play_speed(mut self, play_speed: Option<String>) -> Self {
+        self.play_speed = play_speed;
+        self
+    }
+
+    /// Sets the size of the chunks read from disk when streaming a file
+    ///
+    /// Sizes outside `MIN_STREAMING_CHUNK_SIZE..=MAX_STREAMING_CHUNK_SIZE` are
+    /// brought back within.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = clamp_chunk_size(chunk_size);
+        self
+    }
+
+    /// Sets how many bytes are read ahead of the connection when streaming a file
+    pub fn with_read_ahead(mut self, read_ahead: usize) -> Self {
+        self.read_ahead = read_ahead;
+        self
+    }
+
+    /// Sets whether disk reads for streamed files are held back while playback is paused
+    pub fn with_throttle_when_paused(mut self, throttle_when_paused: bool) -> Self {
+        self.throttle_when_paused = throttle_when_paused;
+        self
+    }
+
+    /// Sets whether the streaming server keeps serving after playback has finished, until interrupted
+    pub fn with_keep_serving(mut self, keep_serving: bool) -> Self {
+        self.keep_serving = keep_serving;
+        self
+    }
+
+    /// Sets whether to cast the file again when the device is already playing it
+    pub fn with_force_recast(mut self, force_recast: bool) -> Self {
+        self.force_recast = force_recast;
+        self
+    }
+
+    /// Sets the file the metadata sent to the device is written to, for debugging
+    pub fn with_output_metadata(mut self, path: Option<PathBuf>) -> Self {
+        self.output_metadata = path;
+        self
+    }
+
+    /// Sets the directory with templates overriding the built-in DIDL-Lite and SetAVTransportURI ones
+    pub fn with_template_dir(mut self, template_dir: Option<PathBuf>) -> Self {
+        self.template_dir = template_dir;
+        self
+    }
+
+    /// Sets the positions playback starts from and stops at, in milliseconds, to only play a clip
+    pub fn with_clip(mut self, start_ms: Option<u64>, end_ms: Option<u64>) -> Self {
+        self.clip_start_ms = start_ms;
+        self.clip_end_ms = end_ms;
+        self
+    }
+
+    /// Sets the subtitle size and cue count above which a warning is logged, 0 to never warn
+    pub fn with_subtitle_warn_limits(mut self, max_bytes: u64, max_cues: usize) -> Self {
+        self.subtitle_warn_bytes = max_bytes;
+        self.subtitle_warn_cues = max_cues;
+        self
+    }
+}
+
+/// Raises a user-provided polling interval to `MIN_POLL_INTERVAL_MS`
+pub(crate) fn clamp_poll_interval(interval_ms: u64, name: &str) -> u64 {
+    if interval_ms < MIN_POLL_INTERVAL_MS {
+        warn!("The {name} of {interval_ms}ms is too short, using {MIN_POLL_INTERVAL_MS}ms");
+        return MIN_POLL_INTERVAL_MS;
+    }
+    interval_ms
+}
+
+/// Brings a user-provided chunk size within the supported range
+pub(crate) fn clamp_chunk_size(chunk_size: usize) -> usize {
+    let clamped = chunk_size.clamp(MIN_STREAMING_CHUNK_SIZE, MAX_STREAMING_CHUNK_SIZE);
+    if clamped != chunk_size {
+        warn!("The chunk size of {chunk_size} bytes is out of range, using {clamped} bytes");
+    }
+    clamped
 }
 
 #[cfg(test)]
@@ -91,6 +328,36 @@ mod tests {
         assert_eq!(config.discovery_timeout, 10);
     }
 
+    #[test]
+    fn test_subtitle_sync_interval_lower_bound() {
+        let config = Config::new().with_subtitle_sync_interval(0);
+        assert_eq!(config.subtitle_sync_interval_ms, MIN_POLL_INTERVAL_MS);
+
+        let config = Config::new().with_subtitle_sync_interval(250);
+        assert_eq!(config.subtitle_sync_interval_ms, 250);
+    }
+
+    #[test]
+    fn test_chunk_size_bounds() {
+        let config = Config::new().with_chunk_size(0);
+        assert_eq!(config.chunk_size, MIN_STREAMING_CHUNK_SIZE);
+
+        let config = Config::new().with_chunk_size(usize::MAX);
+        assert_eq!(config.chunk_size, MAX_STREAMING_CHUNK_SIZE);
+
+        let config = Config::new().with_chunk_size(256 * 1024);
+        assert_eq!(config.chunk_size, 256 * 1024);
+    }
+
+    #[test]
+    fn test_tui_poll_interval() {
+        let config = Config::new().with_tui_poll_interval(Some(10));
+        assert_eq!(config.tui_poll_interval_ms, Some(MIN_POLL_INTERVAL_MS));
+
+        let config = Config::new().with_tui_poll_interval(None);
+        assert_eq!(config.tui_poll_interval_ms, None);
+    }
+
     #[test]
     fn test_constants() {
         assert_eq!(DEFAULT_STREAMING_PORT, 9000);
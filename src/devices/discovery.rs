@@ -4,20 +4,37 @@
 //! using SSDP (Simple Service Discovery Protocol).
 
 use crate::{
-    config::{SSDP_SEARCH_ATTEMPTS, SSDP_TTL},
-    error::Result,
+    config::{
+        DISCOVERY_UNTIL_FOUND_MAX_SECS, DISCOVERY_UNTIL_FOUND_ROUND_SECS, SSDP_MULTICAST_ADDR,
+        SSDP_MULTICAST_PORT, SSDP_SEARCH_ATTEMPTS, SSDP_TTL,
+    },
+    error::{Error, Result},
     utils::format_device_description,
 };
-use futures_util::stream::{Stream, StreamExt, TryStreamExt};
-use log::{debug, info};
+use futures_util::{
+    future::join_all,
+    stream::{Stream, StreamExt, TryStreamExt},
+};
+use local_ip_address::list_afinet_netifas;
+use log::{debug, info, warn};
 use rupnp::ssdp::{SearchTarget, URN};
-use std::{collections::HashSet, time::Duration};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashSet,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, SocketAddrV4},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
 
-use super::render::Render;
+use super::{matcher::DeviceMatcher, render::Render};
 
 /// UPnP service URN for AVTransport
 pub const AV_TRANSPORT: URN = URN::service("schemas-upnp-org", "AVTransport", 1);
 
+/// UPnP service URN for RenderingControl
+pub const RENDERING_CONTROL: URN = URN::service("schemas-upnp-org", "RenderingControl", 1);
+
 /// Macro for formatting device information
 macro_rules! format_device {
     ($device:expr) => {{
@@ -31,45 +48,141 @@ macro_rules! format_device {
 
 impl Render {
     /// Discovers DLNA device with AVTransport on the network.
+    ///
+    /// A duration of 0 keeps searching until at least one device answers, for
+    /// up to `DISCOVERY_UNTIL_FOUND_MAX_SECS`.
     pub async fn discover(duration_secs: u64) -> Result<Vec<Self>> {
         Self::discover_with_config(duration_secs, SSDP_SEARCH_ATTEMPTS, SSDP_TTL).await
     }
 
     /// Discovers DLNA devices with configurable SSDP parameters
+    ///
+    /// A duration of 0 behaves as described in [`Render::discover`].
     pub async fn discover_with_config(
         duration_secs: u64,
         search_attempts: usize,
         ttl: Option<u32>,
+    ) -> Result<Vec<Self>> {
+        match duration_secs {
+            0 => {
+                until_any_found(|| {
+                    Self::search(DISCOVERY_UNTIL_FOUND_ROUND_SECS, search_attempts, ttl)
+                })
+                .await
+            }
+            _ => Self::search(duration_secs, search_attempts, ttl).await,
+        }
+    }
+
+    /// Runs a single SSDP search lasting `duration_secs`
+    async fn search(
+        duration_secs: u64,
+        search_attempts: usize,
+        ttl: Option<u32>,
     ) -> Result<Vec<Self>> {
         info!("Discovering devices in the network, waiting {duration_secs} seconds...");
         let search_target = SearchTarget::URN(AV_TRANSPORT);
-        let devices = upnp_discover_with_config(
-            &search_target,
-            Duration::from_secs(duration_secs),
-            search_attempts,
-            ttl,
-        )
-        .await?;
+        let devices = search_by_target(&search_target, duration_secs, search_attempts, ttl).await?;
+
+        let mut renders = Vec::new();
+        for device in devices {
+            if let Some(render) = Self::from_device(device).await {
+                renders.push(render);
+            }
+        }
+        Ok(renders)
+    }
+
+    /// Discovers DLNA devices with AVTransport on every local IPv4 interface.
+    ///
+    /// [`Render::discover`] only searches through the default interface, so
+    /// devices on secondary subnets (VPNs, Docker bridges, multiple NICs) are
+    /// missed. This runs one search per interface and merges the results.
+    pub async fn discover_on_all_interfaces(duration_secs: u64) -> Result<Vec<Self>> {
+        let interfaces = local_ipv4_interfaces()?;
+        if interfaces.is_empty() {
+            debug!("No local IPv4 interfaces found, using the default interface");
+            return Self::discover(duration_secs).await;
+        }
+        Self::discover_on_interfaces(duration_secs, &interfaces).await
+    }
+
+    /// Discovers DLNA devices with AVTransport through the given local interface addresses.
+    ///
+    /// Devices answering on several interfaces are reported once, based on their UDN.
+    /// A duration of 0 behaves as described in [`Render::discover`].
+    pub async fn discover_on_interfaces(
+        duration_secs: u64,
+        interfaces: &[Ipv4Addr],
+    ) -> Result<Vec<Self>> {
+        match duration_secs {
+            0 => {
+                until_any_found(|| {
+                    Self::search_on_interfaces(DISCOVERY_UNTIL_FOUND_ROUND_SECS, interfaces)
+                })
+                .await
+            }
+            _ => Self::search_on_interfaces(duration_secs, interfaces).await,
+        }
+    }
 
-        let mut devices = std::pin::pin!(devices);
+    /// Runs a single SSDP search lasting `duration_secs` on each interface
+    async fn search_on_interfaces(
+        duration_secs: u64,
+        interfaces: &[Ipv4Addr],
+    ) -> Result<Vec<Self>> {
+        info!(
+            "Discovering devices on {} interface(s), waiting {duration_secs} seconds...",
+            interfaces.len()
+        );
+        let search_target = SearchTarget::URN(AV_TRANSPORT);
+        let timeout = Duration::from_secs(duration_secs);
+
+        let searches = interfaces.iter().map(|interface| {
+            ssdp_search_on_interface(
+                &search_target,
+                *interface,
+                timeout,
+                SSDP_SEARCH_ATTEMPTS,
+                SSDP_TTL,
+            )
+        });
+
+        let mut locations = Vec::new();
+        for (interface, result) in interfaces.iter().zip(join_all(searches).await) {
+            match result {
+                Ok(found) => {
+                    for location in found {
+                        if !locations.contains(&location) {
+                            locations.push(location);
+                        }
+                    }
+                }
+                Err(e) => warn!("SSDP search on interface {interface} failed: {e}"),
+            }
+        }
+
+        let devices = join_all(locations.iter().map(|location| async move {
+            let url = location.parse().map_err(rupnp::Error::from)?;
+            rupnp::Device::from_url(url).await
+        }))
+        .await;
 
         let mut renders = Vec::new();
-        let mut discovered_urls = HashSet::new();
+        let mut discovered_udns = HashSet::new();
 
-        while let Some(result) = devices.next().await {
+        for result in devices {
             match result {
                 Ok(device) => {
-                    let device_url = device.url().to_string();
-                    if discovered_urls.contains(&device_url) {
+                    if !discovered_udns.insert(device.udn().to_string()) {
                         debug!("Skipping duplicate device: {}", format_device!(device));
                         continue;
                     }
 
                     debug!("Found device: {}", format_device!(device));
                     if let Some(render) = Self::from_device(device).await {
-                        discovered_urls.insert(device_url);
                         renders.push(render);
-                    };
+                    }
                 }
                 Err(e) => {
                     debug!("A device returned error while discovering it: {e}");
@@ -80,19 +193,22 @@ impl Render {
         Ok(renders)
     }
 
-    /// Selects a device by query string
+    /// Selects the first device a matcher accepts
     pub(super) async fn select_by_query(
         duration_secs: u64,
-        query: &String,
+        matcher: &DeviceMatcher,
     ) -> Result<Option<Self>> {
-        debug!("Selecting device by query: '{query}'");
-        for render in Self::discover(duration_secs).await? {
-            let render_str = render.to_string();
-            if render_str.contains(query.as_str()) {
-                return Ok(Some(render));
-            }
+        debug!("Selecting device by query: '{matcher}'");
+        if duration_secs == 0 {
+            // Scan until a matching device answers, not just any device
+            let found = until_found(|| async {
+                let renders = Self::discover(DISCOVERY_UNTIL_FOUND_ROUND_SECS).await?;
+                Ok(find_by_query(renders, matcher))
+            })
+            .await?;
+            return Ok(found);
         }
-        Ok(None)
+        Ok(find_by_query(Self::discover(duration_secs).await?, matcher))
     }
 
     /// Creates a Render from a UPnP device if it has AVTransport service
@@ -102,10 +218,7 @@ impl Render {
             format_device!(device)
         );
         match device.find_service(&AV_TRANSPORT) {
-            Some(service) => Some(Self {
-                device: device.clone(),
-                service: service.clone(),
-            }),
+            Some(service) => Some(Self::with_service(device.clone(), service.clone())),
             None => {
                 log::warn!("No AVTransport service found on {}", device.friendly_name());
                 None
@@ -114,8 +227,290 @@ impl Render {
     }
 }
 
+/// A device found by [`discover_with_stats`]
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    /// Location of the device description
+    pub location: String,
+    /// Time from the start of the search to the first SSDP response of the device
+    pub response_latency: Duration,
+    /// Time taken to fetch and parse the device description
+    pub description_latency: Duration,
+    /// Friendly name of the device, or why its description could not be used
+    pub description: std::result::Result<String, String>,
+    /// Whether the device has an AVTransport service, i.e. can play media
+    pub has_av_transport: bool,
+}
+
+impl std::fmt::Display for DiscoveredDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let response_ms = self.response_latency.as_millis();
+        let description_ms = self.description_latency.as_millis();
+        match &self.description {
+            Ok(name) => write!(
+                f,
+                "[{}] {name} ({}): responded after {response_ms} ms, described in {description_ms} ms",
+                if self.has_av_transport {
+                    "AVTransport"
+                } else {
+                    "no AVTransport"
+                },
+                self.location
+            ),
+            Err(error) => write!(
+                f,
+                "[failed] {}: responded after {response_ms} ms, description failed after {description_ms} ms: {error}",
+                self.location
+            ),
+        }
+    }
+}
+
+/// Statistics of a discovery run, for triaging devices that are only found sometimes
+///
+/// They tell apart SSDP responses that never arrive (multicast issues), device
+/// descriptions that are slow or fail, and devices lacking AVTransport.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryStats {
+    /// SSDP responses received, repeated ones included
+    pub ssdp_responses: usize,
+    /// SSDP responses that could not be parsed
+    pub malformed_responses: usize,
+    /// Devices that answered, once per location, in the order they answered
+    pub devices: Vec<DiscoveredDevice>,
+    /// Duration of the whole discovery, description fetches included
+    pub duration: Duration,
+}
+
+impl DiscoveryStats {
+    /// Number of devices whose description was fetched and parsed
+    pub fn described(&self) -> usize {
+        self.devices
+            .iter()
+            .filter(|device| device.description.is_ok())
+            .count()
+    }
+
+    /// Number of devices with an AVTransport service
+    pub fn renders(&self) -> usize {
+        self.devices
+            .iter()
+            .filter(|device| device.has_av_transport)
+            .count()
+    }
+}
+
+impl std::fmt::Display for DiscoveryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for device in &self.devices {
+            writeln!(f, "{device}")?;
+        }
+        writeln!(
+            f,
+            "SSDP responses: {} ({} malformed) from {} device(s)",
+            self.ssdp_responses,
+            self.malformed_responses,
+            self.devices.len()
+        )?;
+        writeln!(
+            f,
+            "Device descriptions: {} fetched, {} failed",
+            self.described(),
+            self.devices.len() - self.described()
+        )?;
+        writeln!(f, "Devices with AVTransport: {}", self.renders())?;
+        write!(f, "Total duration: {:.2}s", self.duration.as_secs_f64())
+    }
+}
+
+/// Runs a discovery through the default interface and measures each step
+///
+/// All root devices are searched for, so that devices lacking AVTransport are
+/// reported too. Every device description is fetched, concurrently, once the
+/// search is over.
+pub async fn discover_with_stats(
+    duration_secs: u64,
+    search_attempts: usize,
+    ttl: Option<u32>,
+) -> Result<DiscoveryStats> {
+    info!("Discovering devices in the network, waiting {duration_secs} seconds...");
+    let start = Instant::now();
+    let responses = ssdp_client::search(
+        &SearchTarget::RootDevice,
+        Duration::from_secs(duration_secs),
+        search_attempts,
+        ttl,
+    )
+    .await?;
+    let mut responses = std::pin::pin!(responses);
+
+    let mut stats = DiscoveryStats::default();
+    let mut locations: Vec<(String, Duration)> = Vec::new();
+    while let Some(response) = responses.next().await {
+        stats.ssdp_responses += 1;
+        match response {
+            Ok(response) => {
+                let location = response.location().to_string();
+                if !locations.iter().any(|(known, _)| *known == location) {
+                    locations.push((location, start.elapsed()));
+                }
+            }
+            Err(e) => {
+                debug!("Malformed SSDP response: {e}");
+                stats.malformed_responses += 1;
+            }
+        }
+    }
+
+    stats.devices = join_all(
+        locations
+            .into_iter()
+            .map(|(location, response_latency)| describe_device(location, response_latency)),
+    )
+    .await;
+    stats.duration = start.elapsed();
+    Ok(stats)
+}
+
+/// Fetches the description of a device answering a discovery, timing it
+async fn describe_device(location: String, response_latency: Duration) -> DiscoveredDevice {
+    let start = Instant::now();
+    let device = match location.parse() {
+        Ok(url) => rupnp::Device::from_url(url).await,
+        Err(e) => Err(rupnp::Error::from(e)),
+    };
+    let description_latency = start.elapsed();
+
+    let (description, has_av_transport) = match device {
+        Ok(device) => (
+            Ok(device.friendly_name().to_string()),
+            device.find_service(&AV_TRANSPORT).is_some(),
+        ),
+        Err(e) => (Err(e.to_string()), false),
+    };
+    DiscoveredDevice {
+        location,
+        response_latency,
+        description_latency,
+        description,
+        has_av_transport,
+    }
+}
+
+/// Discovers UPnP devices answering an SSDP search for `search_target`
+///
+/// Devices are reported once per location. A duration of 0 keeps searching
+/// until at least one device answers, as for [`Render::discover`].
+pub async fn discover_by_target(
+    search_target: &SearchTarget,
+    duration_secs: u64,
+) -> Result<Vec<rupnp::Device>> {
+    match duration_secs {
+        0 => {
+            until_any_found(|| {
+                search_by_target(
+                    search_target,
+                    DISCOVERY_UNTIL_FOUND_ROUND_SECS,
+                    SSDP_SEARCH_ATTEMPTS,
+                    SSDP_TTL,
+                )
+            })
+            .await
+        }
+        _ => search_by_target(search_target, duration_secs, SSDP_SEARCH_ATTEMPTS, SSDP_TTL).await,
+    }
+}
+
+/// Runs a single SSDP search for `search_target` lasting `duration_secs`
+pub(super) async fn search_by_target(
+    search_target: &SearchTarget,
+    duration_secs: u64,
+    search_attempts: usize,
+    ttl: Option<u32>,
+) -> Result<Vec<rupnp::Device>> {
+    let devices = upnp_discover_with_config(
+        search_target,
+        Duration::from_secs(duration_secs),
+        search_attempts,
+        ttl,
+    )
+    .await?;
+    let mut devices = std::pin::pin!(devices);
+
+    let mut found = Vec::new();
+    let mut discovered_urls = HashSet::new();
+
+    while let Some(result) = devices.next().await {
+        match result {
+            Ok(device) => {
+                if !discovered_urls.insert(device.url().to_string()) {
+                    debug!("Skipping duplicate device: {}", format_device!(device));
+                    continue;
+                }
+                debug!("Found device: {}", format_device!(device));
+                found.push(device);
+            }
+            Err(e) => {
+                debug!("A device returned error while discovering it: {e}");
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Checks whether a device, or one of its embedded devices, is of the given type
+pub fn is_device_type(device: &rupnp::Device, device_type: &URN) -> bool {
+    device.device_type() == device_type || device.find_device(device_type).is_some()
+}
+
+/// Returns the first render matching the query
+fn find_by_query(renders: Vec<Render>, matcher: &DeviceMatcher) -> Option<Render> {
+    renders
+        .into_iter()
+        .find(|render| matcher.matches(&render.identity()))
+}
+
+/// Repeats a discovery round until it finds something
+///
+/// Gives up with `None` after `DISCOVERY_UNTIL_FOUND_MAX_SECS`.
+async fn until_found<T, F, Fut>(mut round: F) -> Result<Option<T>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<T>>>,
+{
+    info!(
+        "Searching for devices until one is found (up to {DISCOVERY_UNTIL_FOUND_MAX_SECS} seconds)..."
+    );
+    let deadline = Instant::now() + Duration::from_secs(DISCOVERY_UNTIL_FOUND_MAX_SECS);
+    while Instant::now() < deadline {
+        if let Some(found) = round().await? {
+            return Ok(Some(found));
+        }
+        debug!("Nothing found yet, searching again");
+    }
+    Ok(None)
+}
+
+/// Repeats a discovery round until it finds at least one device
+async fn until_any_found<T, F, Fut>(mut round: F) -> Result<Vec<T>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let found = until_found(|| {
+        let renders = round();
+        async move {
+            let renders = renders.await?;
+            Ok((!renders.is_empty()).then_some(renders))
+        }
+    })
+    .await?;
+    Ok(found.unwrap_or_default())
+}
+
 /// Discovers UPnP devices with configurable parameters
-async fn upnp_discover_with_config(
+pub(super) async fn upnp_discover_with_config(
     search_target: &SearchTarget,
     timeout: Duration,
     search_attempts: usize,
@@ -129,3 +524,164 @@ async fn upnp_discover_with_config(
             .and_then(rupnp::Device::from_url),
     )
 }
+
+/// Lists the addresses of the local IPv4 interfaces usable for SSDP discovery
+fn local_ipv4_interfaces() -> Result<Vec<Ipv4Addr>> {
+    let interfaces = list_afinet_netifas().map_err(|err| Error::LocalAddressResolutionFailed {
+        source: err,
+        context: "Failed to list network interfaces for discovery".to_string(),
+    })?;
+
+    let mut addresses = Vec::new();
+    for (name, address) in interfaces {
+        if let IpAddr::V4(address) = address
+            && !address.is_loopback()
+            && !addresses.contains(&address)
+        {
+            debug!("Using interface '{name}' ({address}) for discovery");
+            addresses.push(address);
+        }
+    }
+    Ok(addresses)
+}
+
+/// Sends an SSDP M-SEARCH through one interface and collects the device locations
+async fn ssdp_search_on_interface(
+    search_target: &SearchTarget,
+    interface: Ipv4Addr,
+    timeout: Duration,
+    mx: usize,
+    ttl: Option<u32>,
+) -> std::io::Result<Vec<String>> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_multicast_if_v4(&interface)?;
+    if let Some(ttl) = ttl {
+        socket.set_multicast_ttl_v4(ttl)?;
+    }
+    socket.bind(&SocketAddrV4::new(interface, 0).into())?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket.into())?;
+
+    let msg = format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         Host:{SSDP_MULTICAST_ADDR}:{SSDP_MULTICAST_PORT}\r\n\
+         Man:\"ssdp:discover\"\r\n\
+         ST: {search_target}\r\n\
+         MX: {mx}\r\n\r\n"
+    );
+    socket
+        .send_to(
+            msg.as_bytes(),
+            SocketAddrV4::new(SSDP_MULTICAST_ADDR, SSDP_MULTICAST_PORT),
+        )
+        .await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut locations = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+        match received {
+            Ok(read) => match parse_ssdp_location(&String::from_utf8_lossy(&buf[..read])) {
+                Some(location) if !locations.contains(&location) => locations.push(location),
+                Some(_) => {}
+                None => debug!("Ignoring malformed SSDP response on interface {interface}"),
+            },
+            Err(e) => debug!("Failed to receive SSDP response on interface {interface}: {e}"),
+        }
+    }
+    Ok(locations)
+}
+
+/// Extracts the `LOCATION` header from a successful SSDP search response
+fn parse_ssdp_location(response: &str) -> Option<String> {
+    let mut lines = response.lines();
+    let status = lines.next()?;
+    if !status.starts_with("HTTP/") || !status.contains(" 200") {
+        return None;
+    }
+
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("location")
+            .then(|| value.trim().to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_until_found_retries() {
+        let mut rounds = 0;
+        let found = until_found(|| {
+            rounds += 1;
+            let attempt = rounds;
+            async move { Ok((attempt == 3).then_some(attempt)) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(found, Some(3));
+        assert_eq!(rounds, 3);
+    }
+
+    #[test]
+    fn test_discovery_stats_display() {
+        let device =
+            |description: std::result::Result<&str, &str>, has_av_transport| DiscoveredDevice {
+                location: "http://192.168.1.13:1082/".to_string(),
+                response_latency: Duration::from_millis(120),
+                description_latency: Duration::from_millis(35),
+                description: description.map(str::to_string).map_err(str::to_string),
+                has_av_transport,
+            };
+        let stats = DiscoveryStats {
+            ssdp_responses: 7,
+            malformed_responses: 1,
+            devices: vec![
+                device(Ok("Living Room TV"), true),
+                device(Ok("NAS"), false),
+                device(Err("timed out"), false),
+            ],
+            duration: Duration::from_millis(5020),
+        };
+
+        let report = stats.to_string();
+        assert!(report.starts_with(
+            "[AVTransport] Living Room TV (http://192.168.1.13:1082/): responded after 120 ms, described in 35 ms\n[no AVTransport] NAS"
+        ));
+        assert!(report.contains("[failed] http://192.168.1.13:1082/: responded after 120 ms, description failed after 35 ms: timed out"));
+        assert!(report.contains("SSDP responses: 7 (1 malformed) from 3 device(s)"));
+        assert!(report.contains("Device descriptions: 2 fetched, 1 failed"));
+        assert!(report.contains("Devices with AVTransport: 1"));
+        assert!(report.ends_with("Total duration: 5.02s"));
+    }
+
+    #[test]
+    fn test_parse_ssdp_location() {
+        let response = "HTTP/1.1 200 OK\r\n\
+                        CACHE-CONTROL: max-age=1800\r\n\
+                        Location: http://192.168.1.13:1082/description.xml\r\n\
+                        ST: urn:schemas-upnp-org:service:AVTransport:1\r\n\r\n";
+        assert_eq!(
+            parse_ssdp_location(response),
+            Some("http://192.168.1.13:1082/description.xml".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_ssdp_location_rejects_invalid_responses() {
+        assert_eq!(parse_ssdp_location(""), None);
+        assert_eq!(
+            parse_ssdp_location("HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n"),
+            None
+        );
+        assert_eq!(
+            parse_ssdp_location(
+                "NOTIFY * HTTP/1.1\r\nLocation: http://192.168.1.13/desc.xml\r\n\r\n"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Device matching for crab-dlna
+//!
+//! This module implements the queries given to `play --query-device` and
+//! `list --query`, which pick devices by name, UDN, type or manufacturer.
+
+use crate::error::{Error, Result};
+use regex::Regex;
+use rupnp::ssdp::URN;
+use std::{fmt, str::FromStr};
+
+/// Prefixes picking the kind of match of a query
+const QUERY_PREFIXES: [&str; 6] = ["name=", "name~", "name:", "udn:", "type:", "manufacturer:"];
+
+/// The properties of a device a [`DeviceMatcher`] looks at
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceIdentity<'a> {
+    /// Friendly name of the device
+    pub friendly_name: &'a str,
+    /// Unique device name, e.g. `uuid:4d696e69-444c-164e-9d41-b827eb54e939`
+    pub udn: &'a str,
+    /// UPnP type of the device
+    pub device_type: &'a URN,
+    /// Manufacturer of the device
+    pub manufacturer: &'a str,
+}
+
+impl<'a> From<&'a rupnp::DeviceSpec> for DeviceIdentity<'a> {
+    fn from(device: &'a rupnp::DeviceSpec) -> Self {
+        Self {
+            friendly_name: device.friendly_name(),
+            udn: device.udn(),
+            device_type: device.device_type(),
+            manufacturer: device.manufacturer(),
+        }
+    }
+}
+
+/// A query selecting devices
+///
+/// Parsed from a query string, where a prefix picks the kind of match:
+///
+/// - `Kodi` or `name:Kodi`: the friendly name contains `Kodi`, in any case
+/// - `name=Kodi`: the friendly name is exactly `Kodi`
+/// - `name~^Kodi \d+$`: the friendly name matches the regular expression
+/// - `udn:4d696e69-...`: the UDN, with or without its `uuid:` prefix
+/// - `type:MediaRenderer:2`: the device type contains `MediaRenderer:2`
+/// - `manufacturer:Sony`: the manufacturer contains `Sony`, in any case
+#[derive(Debug, Clone)]
+pub enum DeviceMatcher {
+    /// The friendly name contains the text, ignoring case
+    NameContains(String),
+    /// The friendly name is exactly the text
+    NameIs(String),
+    /// The friendly name matches the regular expression
+    NameMatches(Regex),
+    /// The unique device name is the text, ignoring the `uuid:` prefix and case
+    Udn(String),
+    /// The device type contains the text
+    DeviceType(String),
+    /// The manufacturer contains the text, ignoring case
+    Manufacturer(String),
+}
+
+impl DeviceMatcher {
+    /// Checks whether a device is selected by the query
+    pub fn matches(&self, device: &DeviceIdentity) -> bool {
+        match self {
+            DeviceMatcher::NameContains(text) => contains_ignoring_case(device.friendly_name, text),
+            DeviceMatcher::NameIs(name) => device.friendly_name == name,
+            DeviceMatcher::NameMatches(regex) => regex.is_match(device.friendly_name),
+            DeviceMatcher::Udn(udn) => strip_uuid(device.udn).eq_ignore_ascii_case(strip_uuid(udn)),
+            DeviceMatcher::DeviceType(text) => device.device_type.to_string().contains(text),
+            DeviceMatcher::Manufacturer(text) => contains_ignoring_case(device.manufacturer, text),
+        }
+    }
+}
+
+impl fmt::Display for DeviceMatcher {
+    /// Writes the query the matcher is parsed from
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceMatcher::NameContains(text)
+                if QUERY_PREFIXES.iter().any(|prefix| text.starts_with(prefix)) =>
+            {
+                write!(f, "name:{text}")
+            }
+            DeviceMatcher::NameContains(text) => f.write_str(text),
+            DeviceMatcher::NameIs(name) => write!(f, "name={name}"),
+            DeviceMatcher::NameMatches(regex) => write!(f, "name~{}", regex.as_str()),
+            DeviceMatcher::Udn(udn) => write!(f, "udn:{udn}"),
+            DeviceMatcher::DeviceType(text) => write!(f, "type:{text}"),
+            DeviceMatcher::Manufacturer(text) => write!(f, "manufacturer:{text}"),
+        }
+    }
+}
+
+impl FromStr for DeviceMatcher {
+    type Err = Error;
+
+    fn from_str(query: &str) -> Result<Self> {
+        let matcher = if let Some(name) = query.strip_prefix("name=") {
+            DeviceMatcher::NameIs(name.to_string())
+        } else if let Some(pattern) = query.strip_prefix("name~") {
+            let regex = Regex::new(pattern).map_err(|source| Error::InvalidDeviceQuery {
+                query: query.to_string(),
+                source,
+            })?;
+            DeviceMatcher::NameMatches(regex)
+        } else if let Some(text) = query.strip_prefix("name:") {
+            DeviceMatcher::NameContains(text.to_string())
+        } else if let Some(udn) = query.strip_prefix("udn:") {
+            DeviceMatcher::Udn(udn.to_string())
+        } else if let Some(text) = query.strip_prefix("type:") {
+            DeviceMatcher::DeviceType(text.to_string())
+        } else if let Some(text) = query.strip_prefix("manufacturer:") {
+            DeviceMatcher::Manufacturer(text.to_string())
+        } else {
+            DeviceMatcher::NameContains(query.to_string())
+        };
+        Ok(matcher)
+    }
+}
+
+/// Checks whether a text contains another, ignoring case
+fn contains_ignoring_case(text: &str, pattern: &str) -> bool {
+    text.to_lowercase().contains(&pattern.to_lowercase())
+}
+
+/// Removes the `uuid:` prefix of a unique device name
+fn strip_uuid(udn: &str) -> &str {
+    udn.strip_prefix("uuid:").unwrap_or(udn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static RENDERER: URN = URN::device("schemas-upnp-org", "MediaRenderer", 1);
+
+    fn device() -> DeviceIdentity<'static> {
+        DeviceIdentity {
+            friendly_name: "Kodi (Living Room)",
+            udn: "uuid:4d696e69-444c-164e-9d41-b827eb54e939",
+            device_type: &RENDERER,
+            manufacturer: "XBMC Foundation",
+        }
+    }
+
+    fn matches(query: &str) -> bool {
+        query.parse::<DeviceMatcher>().unwrap().matches(&device())
+    }
+
+    #[test]
+    fn test_name_contains() {
+        assert!(matches("Kodi"));
+        assert!(matches("living room"));
+        assert!(matches("name:KODI"));
+        assert!(!matches("Bedroom"));
+    }
+
+    #[test]
+    fn test_name_contains_ignores_other_properties() {
+        // Used to match the device type and location shown by `list`
+        assert!(!matches("MediaRenderer"));
+        assert!(!matches("XBMC"));
+        assert!(!matches("http"));
+    }
+
+    #[test]
+    fn test_name_is() {
+        assert!(matches("name=Kodi (Living Room)"));
+        assert!(!matches("name=Kodi"));
+        assert!(!matches("name=kodi (living room)"));
+    }
+
+    #[test]
+    fn test_name_matches() {
+        assert!(matches(r"name~^Kodi \(.+\)$"));
+        assert!(!matches("name~^Living"));
+        assert!(matches!(
+            "name~(".parse::<DeviceMatcher>(),
+            Err(Error::InvalidDeviceQuery { .. })
+        ));
+    }
+
+    #[test]
+    fn test_udn() {
+        assert!(matches("udn:uuid:4d696e69-444c-164e-9d41-b827eb54e939"));
+        assert!(matches("udn:4D696E69-444C-164E-9D41-B827EB54E939"));
+        assert!(!matches("udn:4d696e69"));
+    }
+
+    #[test]
+    fn test_device_type() {
+        assert!(matches("type:MediaRenderer"));
+        assert!(matches("type:urn:schemas-upnp-org:device:MediaRenderer:1"));
+        assert!(!matches("type:MediaServer"));
+    }
+
+    #[test]
+    fn test_manufacturer() {
+        assert!(matches("manufacturer:xbmc"));
+        assert!(!matches("manufacturer:Sony"));
+    }
+
+    #[test]
+    fn test_display_gives_back_the_query() {
+        for query in [
+            "Kodi",
+            "name=Kodi (Living Room)",
+            r"name~^Kodi \(.+\)$",
+            "udn:4d696e69-444c-164e-9d41-b827eb54e939",
+            "type:MediaRenderer",
+            "manufacturer:XBMC",
+        ] {
+            assert_eq!(query.parse::<DeviceMatcher>().unwrap().to_string(), query);
+        }
+        let matcher: DeviceMatcher = "name:udn:living room".parse().unwrap();
+        assert_eq!(matcher.to_string(), "name:udn:living room");
+        assert!(matches!(
+            matcher.to_string().parse(),
+            Ok(DeviceMatcher::NameContains(text)) if text == "udn:living room"
+        ));
+    }
+}
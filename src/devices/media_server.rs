@@ -0,0 +1,271 @@
+//! DLNA media server support for crab-dlna
+//!
+//! This module wraps the ContentDirectory service of DLNA media servers
+//! (NAS, Plex, minidlna, ...), so that their content can be browsed and
+//! the resulting URIs cast to a render.
+
+use crate::{
+    config::{CONTENT_DIRECTORY_ROOT_ID, DLNA_ACTION_BROWSE, SSDP_SEARCH_ATTEMPTS, SSDP_TTL},
+    error::{Error, Result},
+    utils::format_device_with_service_description,
+};
+use askama::Template;
+use http::Uri;
+use log::info;
+use quick_xml::{Reader, events::Event};
+use rupnp::ssdp::{SearchTarget, URN};
+
+use super::discovery::search_by_target;
+
+/// UPnP service URN for ContentDirectory
+pub const CONTENT_DIRECTORY: URN = URN::service("schemas-upnp-org", "ContentDirectory", 1);
+
+/// Template context for Browse payload
+#[derive(Template)]
+#[template(path = "browse.xml")]
+struct BrowseTemplate<'a> {
+    object_id: &'a str,
+    starting_index: u32,
+    requested_count: u32,
+}
+
+/// An item or container listed by a ContentDirectory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DidlObject {
+    /// Object ID, used to browse into containers
+    pub id: String,
+    /// Object ID of the parent container
+    pub parent_id: String,
+    /// Title of the object
+    pub title: String,
+    /// UPnP class of the object (e.g. `object.item.videoItem`)
+    pub class: String,
+    /// URI of the first resource of the object, if it is an item
+    pub res: Option<String>,
+    /// Whether the object is a container that can be browsed
+    pub is_container: bool,
+}
+
+/// A DLNA device which is capable of ContentDirectory actions.
+#[derive(Debug, Clone)]
+pub struct MediaServer {
+    /// The UPnP device
+    pub device: rupnp::Device,
+    /// The ContentDirectory service
+    pub service: rupnp::Service,
+}
+
+impl MediaServer {
+    /// Discovers DLNA media servers with ContentDirectory on the network.
+    pub async fn discover(duration_secs: u64) -> Result<Vec<Self>> {
+        info!("Discovering media servers in the network, waiting {duration_secs} seconds...");
+        let search_target = SearchTarget::URN(CONTENT_DIRECTORY);
+        let devices = search_by_target(
+            &search_target,
+            duration_secs,
+            SSDP_SEARCH_ATTEMPTS,
+            SSDP_TTL,
+        )
+        .await?;
+
+        Ok(devices.into_iter().filter_map(Self::from_device).collect())
+    }
+
+    /// Creates a media server from the URL of its device description
+    pub async fn from_url(url: &str) -> Result<Self> {
+        let uri: Uri = url.parse().map_err(|e| Error::DeviceUrlParseError {
+            url: url.to_owned(),
+            reason: format!("Invalid URL format: {e}"),
+        })?;
+
+        let device =
+            rupnp::Device::from_url(uri)
+                .await
+                .map_err(|err| Error::DeviceCreationError {
+                    url: url.to_owned(),
+                    source: err,
+                })?;
+
+        Self::from_device(device).ok_or_else(|| Error::DlnaResponseParseError {
+            action: "Device description".to_string(),
+            error: format!("No ContentDirectory service found at '{url}'"),
+        })
+    }
+
+    /// Creates a media server from a UPnP device if it has ContentDirectory service
+    fn from_device(device: rupnp::Device) -> Option<Self> {
+        let service = device.find_service(&CONTENT_DIRECTORY)?.clone();
+        Some(Self { device, service })
+    }
+
+    /// Lists the top-level containers and items of the media server
+    pub async fn browse_root(&self) -> Result<Vec<DidlObject>> {
+        self.browse(CONTENT_DIRECTORY_ROOT_ID).await
+    }
+
+    /// Lists the children of a container
+    ///
+    /// Items carry the URI of their first resource, which can be cast to a
+    /// render.
+    pub async fn browse(&self, object_id: &str) -> Result<Vec<DidlObject>> {
+        let payload = BrowseTemplate {
+            object_id,
+            starting_index: 0,
+            requested_count: 0,
+        }
+        .render()
+        .map_err(|e| Error::TemplateRenderError {
+            template_name: "browse.xml".to_string(),
+            source: e.into(),
+        })?;
+
+        let response = self
+            .service
+            .action(self.device.url(), DLNA_ACTION_BROWSE, &payload)
+            .await
+            .map_err(|err| Error::DlnaActionFailed {
+                action: DLNA_ACTION_BROWSE.to_string(),
+                source: err,
+            })?;
+
+        let didl = response
+            .get("Result")
+            .ok_or_else(|| Error::DlnaResponseParseError {
+                action: DLNA_ACTION_BROWSE.to_string(),
+                error: "Missing Result".to_string(),
+            })?;
+
+        parse_didl_objects(didl).map_err(|error| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_BROWSE.to_string(),
+            error,
+        })
+    }
+}
+
+/// Parses the items and containers of a DIDL-Lite document
+fn parse_didl_objects(didl: &str) -> std::result::Result<Vec<DidlObject>, String> {
+    let mut reader = Reader::from_str(didl);
+
+    let mut objects = Vec::new();
+    let mut current: Option<DidlObject> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                text.clear();
+                let name = element.local_name();
+                if name.as_ref() == b"item" || name.as_ref() == b"container" {
+                    let mut object = DidlObject {
+                        is_container: name.as_ref() == b"container",
+                        ..Default::default()
+                    };
+                    for attribute in element.attributes().flatten() {
+                        let value = attribute
+                            .unescape_value()
+                            .map_err(|e| e.to_string())?
+                            .to_string();
+                        match attribute.key.local_name().as_ref() {
+                            b"id" => object.id = value,
+                            b"parentID" => object.parent_id = value,
+                            _ => {}
+                        }
+                    }
+                    current = Some(object);
+                }
+            }
+            Ok(Event::Text(content)) => {
+                text.push_str(&content.decode().map_err(|e| e.to_string())?);
+            }
+            Ok(Event::GeneralRef(reference)) => {
+                match reference.resolve_char_ref().map_err(|e| e.to_string())? {
+                    Some(character) => text.push(character),
+                    None => {
+                        let entity = reference.decode().map_err(|e| e.to_string())?;
+                        let resolved = quick_xml::escape::resolve_predefined_entity(&entity)
+                            .ok_or_else(|| format!("Unknown entity '&{entity};'"))?;
+                        text.push_str(resolved);
+                    }
+                }
+            }
+            Ok(Event::End(element)) => {
+                let value = std::mem::take(&mut text).trim().to_string();
+                let name = element.local_name();
+                if name.as_ref() == b"item" || name.as_ref() == b"container" {
+                    objects.extend(current.take());
+                } else if let Some(object) = &mut current {
+                    match name.as_ref() {
+                        b"title" => object.title = value,
+                        b"class" => object.class = value,
+                        b"res" if object.res.is_none() => object.res = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Event::Eof) => return Ok(objects),
+            Err(e) => return Err(e.to_string()),
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Display for MediaServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            format_device_with_service_description(
+                &self.device.device_type().to_string(),
+                &self.service.service_type().to_string(),
+                self.device.friendly_name(),
+                &self.device.url().to_string()
+            )
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_didl_objects() {
+        let didl = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/"
+            xmlns:dc="http://purl.org/dc/elements/1.1/"
+            xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+  <container id="64" parentID="0" restricted="1" childCount="2">
+    <dc:title>Movies</dc:title>
+    <upnp:class>object.container.storageFolder</upnp:class>
+  </container>
+  <item id="64$1" parentID="64" restricted="1">
+    <dc:title>Tom &amp; Jerry</dc:title>
+    <upnp:class>object.item.videoItem</upnp:class>
+    <res protocolInfo="http-get:*:video/mp4:*">http://192.168.1.5:8200/MediaItems/1.mp4?a=1&amp;b=2</res>
+    <res protocolInfo="http-get:*:image/jpeg:*">http://192.168.1.5:8200/Thumbnails/1.jpg</res>
+  </item>
+</DIDL-Lite>"#;
+
+        let objects = parse_didl_objects(didl).unwrap();
+        assert_eq!(objects.len(), 2);
+
+        assert!(objects[0].is_container);
+        assert_eq!(objects[0].id, "64");
+        assert_eq!(objects[0].title, "Movies");
+        assert_eq!(objects[0].res, None);
+
+        assert!(!objects[1].is_container);
+        assert_eq!(objects[1].id, "64$1");
+        assert_eq!(objects[1].parent_id, "64");
+        assert_eq!(objects[1].title, "Tom & Jerry");
+        assert_eq!(objects[1].class, "object.item.videoItem");
+        assert_eq!(
+            objects[1].res.as_deref(),
+            Some("http://192.168.1.5:8200/MediaItems/1.mp4?a=1&b=2")
+        );
+    }
+
+    #[test]
+    fn test_parse_didl_objects_rejects_malformed_xml() {
+        assert!(parse_didl_objects("<DIDL-Lite><item id=\"1\"></container>").is_err());
+    }
+}
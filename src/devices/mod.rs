@@ -4,9 +4,16 @@
 //! on the network, including device discovery, render device management, and device types.
 
 pub mod discovery;
+pub mod matcher;
+pub mod media_server;
 pub mod render;
 pub mod types;
 
 // Re-export main types and functions for backward compatibility
+pub use matcher::{DeviceIdentity, DeviceMatcher};
+pub use media_server::{DidlObject, MediaServer};
 pub use render::Render;
-pub use types::{PositionInfo, RenderSpec, TransportInfo};
+pub use types::{
+    DeviceCapabilities, MediaInfo, PlayMode, PositionInfo, RenderSpec, TransportInfo,
+    TransportSettings,
+};
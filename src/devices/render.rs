@@ -5,16 +5,31 @@
 
 use crate::{
     config::{
-        DLNA_ACTION_GET_POSITION_INFO, DLNA_ACTION_GET_TRANSPORT_INFO, DLNA_POSITION_INFO_PAYLOAD,
-        DLNA_TRANSPORT_INFO_PAYLOAD, NO_DEVICES_DISCOVERED_MSG, RENDER_NOT_FOUND_MSG,
+        DEFAULT_ACTION_TIMEOUT_SECS, DEVICE_DESCRIPTION_PATHS, DLNA_ACTION_GET_DEVICE_CAPABILITIES,
+        DLNA_ACTION_GET_MEDIA_INFO, DLNA_ACTION_GET_POSITION_INFO, DLNA_ACTION_GET_TRANSPORT_INFO,
+        DLNA_ACTION_GET_TRANSPORT_SETTINGS, DLNA_ACTION_SET_AV_TRANSPORT_URI,
+        DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI, DLNA_DEFAULT_SPEED,
+        DLNA_DEVICE_CAPABILITIES_PAYLOAD, DLNA_MEDIA_INFO_PAYLOAD, DLNA_POSITION_INFO_PAYLOAD,
+        DLNA_TRANSPORT_INFO_PAYLOAD, DLNA_TRANSPORT_SETTINGS_PAYLOAD, NO_DEVICES_DISCOVERED_MSG,
+        RENDER_NOT_FOUND_MSG, WAIT_FOR_DEVICE_BASE_DELAY_SECS, WAIT_FOR_DEVICE_MAX_DELAY_SECS,
     },
+    dlna::metadata::{build_clear_setavtransporturi_payload, build_setnextavtransporturi_payload},
     error::{Error, Result},
-    utils::{format_device_with_service_description, retry_with_backoff},
+    utils::{
+        format_action_response, format_device_with_service_description, http_get_text,
+        retry_with_backoff, upnp_error_code,
+    },
 };
 use http::Uri;
-use log::{debug, info};
+use log::{debug, info, trace, warn};
+use quick_xml::{Reader, events::Event};
+use rupnp::ssdp::URN;
+use std::time::{Duration, Instant};
 
-use super::types::{PositionInfo, RenderSpec, TransportInfo};
+use super::matcher::DeviceIdentity;
+use super::types::{
+    DeviceCapabilities, MediaInfo, PositionInfo, RenderSpec, TransportInfo, TransportSettings,
+};
 
 /// A DLNA device which is capable of AVTransport actions.
 #[derive(Debug, Clone)]
@@ -23,6 +38,10 @@ pub struct Render {
     pub device: rupnp::Device,
     /// The AVTransport service
     pub service: rupnp::Service,
+    /// Longest time to wait for the device to answer an action
+    action_timeout: Duration,
+    /// Speed sent with Play actions at normal speed, `None` to leave it out
+    play_speed: Option<String>,
 }
 
 impl Render {
@@ -49,21 +68,249 @@ impl Render {
             }
             RenderSpec::First(timeout) => {
                 info!("{RENDER_NOT_FOUND_MSG}");
-                Ok(Self::discover(*timeout)
+                // Discovery order depends on response timing, so pick by name
+                // to select the same device from one run to the next
+                Self::discover(*timeout)
                     .await?
-                    .first()
+                    .into_iter()
+                    .min_by_key(|render| {
+                        selection_key(render.device.friendly_name(), render.device.udn())
+                    })
                     .ok_or(Error::RenderNotFound {
                         spec: render_spec.clone(),
                         context: NO_DEVICES_DISCOVERED_MSG.to_string(),
-                    })?
-                    .to_owned())
+                    })
+            }
+        }
+    }
+
+    /// Creates a render like [`Render::new`], retrying until the device answers
+    ///
+    /// Handles devices that exist but aren't responding yet, e.g. a TV still
+    /// booting: resolving the device and a `GetTransportInfo` call are retried
+    /// with a doubling delay for up to `wait`, then the last error is returned.
+    pub async fn wait_for(render_spec: RenderSpec, wait: Duration) -> Result<Self> {
+        let deadline = Instant::now() + wait;
+        let mut delay = Duration::from_secs(WAIT_FOR_DEVICE_BASE_DELAY_SECS);
+        let mut attempt = 1;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let error = match tokio::time::timeout(remaining, Self::answering(&render_spec)).await {
+                Ok(Ok(render)) => return Ok(render),
+                Ok(Err(e)) => e,
+                Err(_) => {
+                    return Err(Error::RenderNotFound {
+                        context: format!("Device did not answer within {} seconds", wait.as_secs()),
+                        spec: render_spec,
+                    });
+                }
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if delay >= remaining {
+                return Err(error);
             }
+            info!(
+                "Device not ready on attempt {attempt} ({error}), retrying in {}s",
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(Duration::from_secs(WAIT_FOR_DEVICE_MAX_DELAY_SECS));
+            attempt += 1;
         }
     }
 
+    /// Creates a render and checks that the device answers actions
+    async fn answering(render_spec: &RenderSpec) -> Result<Self> {
+        let render = Self::new(render_spec.clone()).await?;
+        render.get_transport_info().await?;
+        Ok(render)
+    }
+
+    /// Creates a render from a device and its AVTransport service
+    pub(super) fn with_service(device: rupnp::Device, service: rupnp::Service) -> Self {
+        Self {
+            device,
+            service,
+            action_timeout: Duration::from_secs(DEFAULT_ACTION_TIMEOUT_SECS),
+            play_speed: Some(DLNA_DEFAULT_SPEED.to_string()),
+        }
+    }
+
+    /// Sets the longest time to wait for the device to answer an action
+    pub fn with_action_timeout(mut self, action_timeout: Duration) -> Self {
+        self.action_timeout = action_timeout;
+        self
+    }
+
+    /// Returns the longest time to wait for the device to answer an action
+    pub fn action_timeout(&self) -> Duration {
+        self.action_timeout
+    }
+
+    /// Sets the speed sent with Play actions at normal speed, `None` to leave it out
+    pub fn with_play_speed(mut self, play_speed: Option<String>) -> Self {
+        self.play_speed = play_speed;
+        self
+    }
+
+    /// Returns the speed sent with Play actions at normal speed
+    pub fn play_speed(&self) -> Option<&str> {
+        self.play_speed.as_deref()
+    }
+
+    /// Bounds a call to the device by the action timeout
+    ///
+    /// Devices that stop responding mid-action would otherwise leave the
+    /// call hanging forever, as the UPnP client doesn't time out on its own.
+    pub async fn timed<F: Future>(&self, action: &str, call: F) -> Result<F::Output> {
+        tokio::time::timeout(self.action_timeout, call)
+            .await
+            .map_err(|_| Error::DlnaActionTimeout {
+                action: action.to_string(),
+                timeout: self.action_timeout,
+            })
+    }
+
+    /// Returns the properties of the device matched by a [`DeviceMatcher`](super::DeviceMatcher)
+    pub fn identity(&self) -> DeviceIdentity<'_> {
+        DeviceIdentity::from(&*self.device)
+    }
+
+    /// Returns the friendly name of the device
+    pub fn friendly_name(&self) -> &str {
+        self.device.friendly_name()
+    }
+
+    /// Returns the URL of the device, which is the location of its description document
+    pub fn url(&self) -> &Uri {
+        self.device.url()
+    }
+
+    /// Returns the UPnP type of the device
+    pub fn device_type(&self) -> &URN {
+        self.device.device_type()
+    }
+
+    /// Returns the type of the AVTransport service
+    pub fn service_type(&self) -> &URN {
+        self.service.service_type()
+    }
+
+    /// Returns the identifier of the AVTransport service
+    pub fn service_id(&self) -> &str {
+        self.service.service_id()
+    }
+
     /// Returns the host of the render
     pub fn host(&self) -> String {
-        self.device.url().authority().unwrap().host().to_string()
+        self.url().authority().unwrap().host().to_string()
+    }
+
+    /// Returns the URL of the device description document
+    pub fn description_url(&self) -> &Uri {
+        self.url()
+    }
+
+    /// Fetches the raw device description XML
+    pub async fn device_description(&self) -> Result<String> {
+        let url = self.description_url();
+        http_get_text(url)
+            .await
+            .map_err(|err| Error::DeviceDescriptionFetchFailed {
+                url: url.to_string(),
+                source: err,
+            })
+    }
+
+    /// Fetches the raw SCPD XML of the AVTransport service
+    ///
+    /// The document is returned as-is, without being parsed, so that malformed
+    /// service descriptions can still be attached to interoperability reports.
+    pub async fn service_scpd(&self) -> Result<String> {
+        let description = self.device_description().await?;
+        let service_type = self.service.service_type().to_string();
+        let scpd_path = find_scpd_path(&description, &service_type).ok_or_else(|| {
+            Error::DlnaResponseParseError {
+                action: "SCPD lookup".to_string(),
+                error: format!("No SCPDURL found for service '{service_type}'"),
+            }
+        })?;
+
+        let scpd_url = resolve_device_url(self.description_url(), &scpd_path)?;
+        http_get_text(&scpd_url)
+            .await
+            .map_err(|err| Error::DeviceDescriptionFetchFailed {
+                url: scpd_url.to_string(),
+                source: err,
+            })
+    }
+
+    /// Lists the actions the AVTransport service declares in its SCPD
+    pub async fn supported_actions(&self) -> Result<Vec<String>> {
+        let scpd = self.service_scpd().await?;
+        Ok(find_action_names(&scpd))
+    }
+
+    /// Checks whether the AVTransport service declares the given action
+    ///
+    /// Returns `false` if the service description can't be fetched.
+    pub async fn supports_action(&self, action: &str) -> bool {
+        match self.supported_actions().await {
+            Ok(actions) => actions.iter().any(|name| name == action),
+            Err(e) => {
+                debug!("Failed to probe supported actions: {e}");
+                false
+            }
+        }
+    }
+
+    /// Pre-loads the URI the device plays once the current one ends
+    ///
+    /// Only available on devices declaring `SetNextAVTransportURI`, see
+    /// [`Render::supports_action`].
+    pub async fn set_next_av_transport_uri(&self, uri: &str, metadata: &str) -> Result<()> {
+        let payload = build_setnextavtransporturi_payload(uri, metadata)?;
+        self.timed(
+            DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI,
+            self.service.action(
+                self.device.url(),
+                DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI,
+                &payload,
+            ),
+        )
+        .await?
+        .map_err(|err| Error::DlnaSetTransportUriFailed {
+            source: err,
+            uri: uri.to_string(),
+        })?;
+        Ok(())
+    }
+
+    /// Stops playback and clears the transport URI, releasing the device
+    ///
+    /// Some TVs stay on a black loading screen after a failed cast until their
+    /// URI is cleared. Meant for teardown, so failures are only logged.
+    pub async fn stop_and_clear(&self) {
+        if let Err(e) = crate::dlna::stop(self).await {
+            warn!("Failed to stop playback: {e}");
+        }
+
+        let payload = match build_clear_setavtransporturi_payload() {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to clear the transport URI: {e}");
+                return;
+            }
+        };
+        let clear = self
+            .service
+            .action(self.url(), DLNA_ACTION_SET_AV_TRANSPORT_URI, &payload);
+        match self.timed(DLNA_ACTION_SET_AV_TRANSPORT_URI, clear).await {
+            Ok(Ok(_)) => debug!("Cleared the transport URI of {}", self.friendly_name()),
+            Ok(Err(e)) => warn!("Failed to clear the transport URI: {e}"),
+            Err(e) => warn!("Failed to clear the transport URI: {e}"),
+        }
     }
 
     /// Selects a device by URL
@@ -74,35 +321,55 @@ impl Render {
             reason: format!("Invalid URL format: {e}"),
         })?;
 
-        let device = retry_with_backoff(
+        let error = match retry_with_backoff(
             || async { rupnp::Device::from_url(uri.clone()).await },
             &format!("Device creation from URL {url}"),
         )
         .await
-        .map_err(|err| Error::DeviceCreationError {
-            url: url.to_owned(),
-            source: err,
-        })?;
+        {
+            Ok(device) => return Ok(Self::from_device(device).await),
+            Err(error) => error,
+        };
+
+        // The host answered, so the URL may only lack the description path
+        // or have a stray trailing slash
+        if !is_connection_error(&error) {
+            for variant in location_variants(&uri) {
+                debug!("Trying device description at {variant}");
+                if let Ok(device) = rupnp::Device::from_url(variant.clone()).await {
+                    info!("Found the device description at {variant}");
+                    return Ok(Self::from_device(device).await);
+                }
+            }
+        }
 
-        Ok(Self::from_device(device).await)
+        Err(Error::DeviceCreationError {
+            url: url.to_owned(),
+            source: error,
+        })
     }
 
     /// Gets current playback position information
     ///
     /// This method calls the DLNA AVTransport service's GetPositionInfo operation,
-    /// returning detailed information about the current playback position, including time position and track information
+    /// returning detailed information about the current playback position, including time position and track information.
+    /// Some devices don't implement it and yield [`Error::DlnaActionNotSupported`], although they play fine.
     pub async fn get_position_info(&self) -> Result<PositionInfo> {
         let payload = DLNA_POSITION_INFO_PAYLOAD;
 
         let response = self
-            .service
-            .action(self.device.url(), DLNA_ACTION_GET_POSITION_INFO, payload)
-            .await
-            .map_err(|err| Error::DlnaActionFailed {
-                action: DLNA_ACTION_GET_POSITION_INFO.to_string(),
-                source: err,
-            })?;
+            .timed(
+                DLNA_ACTION_GET_POSITION_INFO,
+                self.service
+                    .action(self.device.url(), DLNA_ACTION_GET_POSITION_INFO, payload),
+            )
+            .await?
+            .map_err(|err| action_error(DLNA_ACTION_GET_POSITION_INFO, err))?;
 
+        trace!(
+            "{DLNA_ACTION_GET_POSITION_INFO} response: {}",
+            format_action_response(&response)
+        );
         PositionInfo::from_map(&response).map_err(|err| Error::DlnaResponseParseError {
             action: DLNA_ACTION_GET_POSITION_INFO.to_string(),
             error: err,
@@ -117,19 +384,271 @@ impl Render {
         let payload = DLNA_TRANSPORT_INFO_PAYLOAD;
 
         let response = self
-            .service
-            .action(self.device.url(), DLNA_ACTION_GET_TRANSPORT_INFO, payload)
-            .await
+            .timed(
+                DLNA_ACTION_GET_TRANSPORT_INFO,
+                self.service
+                    .action(self.device.url(), DLNA_ACTION_GET_TRANSPORT_INFO, payload),
+            )
+            .await?
             .map_err(|err| Error::DlnaActionFailed {
                 action: DLNA_ACTION_GET_TRANSPORT_INFO.to_string(),
                 source: err,
             })?;
 
+        trace!(
+            "{DLNA_ACTION_GET_TRANSPORT_INFO} response: {}",
+            format_action_response(&response)
+        );
         TransportInfo::from_map(&response).map_err(|err| Error::DlnaResponseParseError {
             action: DLNA_ACTION_GET_TRANSPORT_INFO.to_string(),
             error: err,
         })
     }
+
+    /// Gets transport settings (play mode, etc.)
+    ///
+    /// This method calls the DLNA AVTransport service's GetTransportSettings
+    /// operation. Devices that don't implement it yield
+    /// [`Error::DlnaActionNotSupported`].
+    pub async fn get_transport_settings(&self) -> Result<TransportSettings> {
+        let payload = DLNA_TRANSPORT_SETTINGS_PAYLOAD;
+
+        let response = self
+            .timed(
+                DLNA_ACTION_GET_TRANSPORT_SETTINGS,
+                self.service.action(
+                    self.device.url(),
+                    DLNA_ACTION_GET_TRANSPORT_SETTINGS,
+                    payload,
+                ),
+            )
+            .await?
+            .map_err(|err| action_error(DLNA_ACTION_GET_TRANSPORT_SETTINGS, err))?;
+
+        trace!(
+            "{DLNA_ACTION_GET_TRANSPORT_SETTINGS} response: {}",
+            format_action_response(&response)
+        );
+        TransportSettings::from_map(&response).map_err(|err| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_GET_TRANSPORT_SETTINGS.to_string(),
+            error: err,
+        })
+    }
+
+    /// Gets device capabilities (storage media it plays from, etc.)
+    ///
+    /// This method calls the DLNA AVTransport service's GetDeviceCapabilities
+    /// operation. Devices that don't implement it yield
+    /// [`Error::DlnaActionNotSupported`].
+    pub async fn get_device_capabilities(&self) -> Result<DeviceCapabilities> {
+        let payload = DLNA_DEVICE_CAPABILITIES_PAYLOAD;
+
+        let response = self
+            .timed(
+                DLNA_ACTION_GET_DEVICE_CAPABILITIES,
+                self.service.action(
+                    self.device.url(),
+                    DLNA_ACTION_GET_DEVICE_CAPABILITIES,
+                    payload,
+                ),
+            )
+            .await?
+            .map_err(|err| action_error(DLNA_ACTION_GET_DEVICE_CAPABILITIES, err))?;
+
+        trace!(
+            "{DLNA_ACTION_GET_DEVICE_CAPABILITIES} response: {}",
+            format_action_response(&response)
+        );
+        DeviceCapabilities::from_map(&response).map_err(|err| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_GET_DEVICE_CAPABILITIES.to_string(),
+            error: err,
+        })
+    }
+
+    /// Gets information about the loaded media (URI, duration, etc.)
+    ///
+    /// This method calls the DLNA AVTransport service's GetMediaInfo
+    /// operation. Devices that don't implement it yield
+    /// [`Error::DlnaActionNotSupported`].
+    pub async fn get_media_info(&self) -> Result<MediaInfo> {
+        let payload = DLNA_MEDIA_INFO_PAYLOAD;
+
+        let response = self
+            .timed(
+                DLNA_ACTION_GET_MEDIA_INFO,
+                self.service
+                    .action(self.device.url(), DLNA_ACTION_GET_MEDIA_INFO, payload),
+            )
+            .await?
+            .map_err(|err| action_error(DLNA_ACTION_GET_MEDIA_INFO, err))?;
+
+        trace!(
+            "{DLNA_ACTION_GET_MEDIA_INFO} response: {}",
+            format_action_response(&response)
+        );
+        MediaInfo::from_map(&response).map_err(|err| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_GET_MEDIA_INFO.to_string(),
+            error: err,
+        })
+    }
+}
+
+/// Maps a failed optional action, telling apart devices that don't implement it
+///
+/// UPnP error 401 (invalid action) and 602 (optional action not implemented)
+/// both mean the device lacks the action.
+fn action_error(action: &str, err: rupnp::Error) -> Error {
+    match upnp_error_code(&err) {
+        Some(401 | 602) => Error::DlnaActionNotSupported {
+            action: action.to_string(),
+        },
+        _ => Error::DlnaActionFailed {
+            action: action.to_string(),
+            source: err,
+        },
+    }
+}
+
+/// Orders renders for `RenderSpec::First`: by friendly name, ignoring case,
+/// then by UDN to break ties between identically named devices
+fn selection_key(friendly_name: &str, udn: &str) -> (String, String) {
+    (friendly_name.to_lowercase(), udn.to_string())
+}
+
+/// Finds the SCPD URL of a service in a raw device description
+fn find_scpd_path(description: &str, service_type: &str) -> Option<String> {
+    let mut reader = Reader::from_str(description);
+    reader.config_mut().trim_text(true);
+
+    let mut current_element = String::new();
+    let mut in_service = false;
+    let mut current_service_type = None;
+    let mut current_scpd_path = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                current_element =
+                    String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+                if current_element == "service" {
+                    in_service = true;
+                    current_service_type = None;
+                    current_scpd_path = None;
+                }
+            }
+            Ok(Event::Text(text)) if in_service => {
+                let text = text.decode().ok()?.to_string();
+                match current_element.as_str() {
+                    "serviceType" => current_service_type = Some(text),
+                    "SCPDURL" => current_scpd_path = Some(text),
+                    _ => {}
+                }
+            }
+            Ok(Event::End(element)) => {
+                if element.local_name().as_ref() == b"service" {
+                    in_service = false;
+                    if current_service_type.as_deref() == Some(service_type) {
+                        return current_scpd_path;
+                    }
+                }
+                current_element.clear();
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Finds the names of the actions declared in a raw SCPD document
+fn find_action_names(scpd: &str) -> Vec<String> {
+    let mut reader = Reader::from_str(scpd);
+    reader.config_mut().trim_text(true);
+
+    let mut path: Vec<String> = Vec::new();
+    let mut actions = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                path.push(String::from_utf8_lossy(element.local_name().as_ref()).to_string());
+            }
+            Ok(Event::Text(text))
+                if path.ends_with(&["action".to_string(), "name".to_string()]) =>
+            {
+                if let Ok(name) = text.decode() {
+                    actions.push(name.to_string());
+                }
+            }
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Eof) | Err(_) => return actions,
+            _ => {}
+        }
+    }
+}
+
+/// Whether fetching a device description failed before the host answered
+fn is_connection_error(error: &rupnp::Error) -> bool {
+    matches!(
+        error,
+        rupnp::Error::NetworkError(_) | rupnp::Error::NetworkClientError(_) | rupnp::Error::IO(_)
+    )
+}
+
+/// Alternative locations to try when a device URL doesn't lead to a description
+///
+/// Toggles the trailing slash and, unless the URL already names a file,
+/// appends the description paths renderers commonly serve.
+fn location_variants(uri: &Uri) -> Vec<Uri> {
+    let path = uri.path();
+    let mut paths = Vec::new();
+    if path.len() > 1
+        && let Some(stripped) = path.strip_suffix('/')
+    {
+        paths.push(stripped.to_string());
+    }
+    let dir = path.trim_end_matches('/');
+    let last_segment = dir.rsplit('/').next().unwrap_or_default();
+    if !last_segment.contains('.') {
+        if !path.ends_with('/') {
+            paths.push(format!("{path}/"));
+        }
+        paths.extend(
+            DEVICE_DESCRIPTION_PATHS
+                .iter()
+                .map(|description_path| format!("{dir}/{description_path}")),
+        );
+    }
+
+    let scheme = uri.scheme_str().unwrap_or("http");
+    let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+    paths
+        .into_iter()
+        .filter_map(|path| format!("{scheme}://{authority}{path}").parse().ok())
+        .collect()
+}
+
+/// Resolves a URL found in a device description against the description URL
+fn resolve_device_url(base: &Uri, path: &str) -> Result<Uri> {
+    let url = if path.starts_with("http://") || path.starts_with("https://") {
+        path.to_string()
+    } else {
+        let scheme = base.scheme_str().unwrap_or("http");
+        let authority = base.authority().map(|a| a.as_str()).unwrap_or_default();
+        if path.starts_with('/') {
+            format!("{scheme}://{authority}{path}")
+        } else {
+            let base_path = base.path();
+            let base_dir = &base_path[..=base_path.rfind('/').unwrap_or(0)];
+            format!("{scheme}://{authority}{base_dir}{path}")
+        }
+    };
+
+    url.parse().map_err(|e| Error::DeviceUrlParseError {
+        url: url.clone(),
+        reason: format!("Invalid URL format: {e}"),
+    })
 }
 
 impl std::fmt::Display for Render {
@@ -138,11 +657,195 @@ impl std::fmt::Display for Render {
             f,
             "{}",
             format_device_with_service_description(
-                &self.device.device_type().to_string(),
-                &self.service.service_type().to_string(),
-                self.device.friendly_name(),
-                &self.device.url().to_string()
+                &self.device_type().to_string(),
+                &self.service_type().to_string(),
+                self.friendly_name(),
+                &self.url().to_string()
             )
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RENDERER: &str = include_str!("../../tests/fixtures/renderer_description.xml");
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>
+        <SCPDURL>/rc.xml</SCPDURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>
+        <SCPDURL>/avt.xml</SCPDURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+
+    #[test]
+    fn test_find_scpd_path() {
+        assert_eq!(
+            find_scpd_path(DESCRIPTION, "urn:schemas-upnp-org:service:AVTransport:1"),
+            Some("/avt.xml".to_string())
+        );
+        assert_eq!(
+            find_scpd_path(DESCRIPTION, "urn:schemas-upnp-org:service:Unknown:1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_action_names() {
+        let scpd = r#"<?xml version="1.0"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <actionList>
+    <action>
+      <name>Play</name>
+      <argumentList>
+        <argument><name>InstanceID</name></argument>
+        <argument><name>Speed</name></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>SetNextAVTransportURI</name>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable><name>TransportState</name></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+        assert_eq!(
+            find_action_names(scpd),
+            vec!["Play".to_string(), "SetNextAVTransportURI".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_location_variants() {
+        let variants = |url: &str| -> Vec<String> {
+            location_variants(&url.parse().unwrap())
+                .iter()
+                .map(Uri::to_string)
+                .collect()
+        };
+
+        let bare = variants("http://192.168.1.10:1400");
+        assert_eq!(bare[0], "http://192.168.1.10:1400/description.xml");
+        assert!(bare.contains(&"http://192.168.1.10:1400/dmr.xml".to_string()));
+        assert_eq!(bare.len(), DEVICE_DESCRIPTION_PATHS.len());
+
+        assert_eq!(
+            variants("http://192.168.1.10:1400/description.xml/"),
+            ["http://192.168.1.10:1400/description.xml"]
+        );
+        assert!(variants("http://192.168.1.10:1400/description.xml").is_empty());
+
+        let dir = variants("http://192.168.1.10:1400/upnp");
+        assert_eq!(dir[0], "http://192.168.1.10:1400/upnp/");
+        assert_eq!(dir[1], "http://192.168.1.10:1400/upnp/description.xml");
+    }
+
+    #[test]
+    fn test_resolve_device_url() {
+        let base: Uri = "http://192.168.1.10:1400/xml/device.xml".parse().unwrap();
+        assert_eq!(
+            resolve_device_url(&base, "/avt.xml").unwrap().to_string(),
+            "http://192.168.1.10:1400/avt.xml"
+        );
+        assert_eq!(
+            resolve_device_url(&base, "avt.xml").unwrap().to_string(),
+            "http://192.168.1.10:1400/xml/avt.xml"
+        );
+        assert_eq!(
+            resolve_device_url(&base, "http://10.0.0.1/scpd.xml")
+                .unwrap()
+                .to_string(),
+            "http://10.0.0.1/scpd.xml"
+        );
+    }
+
+    #[test]
+    fn test_selection_key_ordering() {
+        let mut keys = vec![
+            selection_key("Living Room TV", "uuid:2"),
+            selection_key("bedroom", "uuid:3"),
+            selection_key("Living Room TV", "uuid:1"),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                ("bedroom".to_string(), "uuid:3".to_string()),
+                ("living room tv".to_string(), "uuid:1".to_string()),
+                ("living room tv".to_string(), "uuid:2".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_device_times_out() {
+        use axum::routing::{get, post};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let location = format!("http://{}/description.xml", listener.local_addr().unwrap());
+        let app = axum::Router::new()
+            .route("/description.xml", get(|| async { RENDERER }))
+            .route("/avt/control", post(std::future::pending::<String>));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let render = Render::new(RenderSpec::Location(location))
+            .await
+            .unwrap()
+            .with_action_timeout(Duration::from_millis(100));
+        assert!(matches!(
+            render.get_transport_info().await,
+            Err(Error::DlnaActionTimeout { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_each_attempt_has_its_own_timeout() {
+        const PAUSE_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+  <s:Body><u:PauseResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1"/></s:Body>
+</s:Envelope>"#;
+        use axum::routing::{get, post};
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        // Hangs on the first call only, like a device dropping a request
+        let calls = Arc::new(AtomicUsize::new(0));
+        let control_calls = Arc::clone(&calls);
+        let control = post(move || {
+            let calls = Arc::clone(&control_calls);
+            async move {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    std::future::pending::<()>().await;
+                }
+                ([(http::header::CONTENT_TYPE, "text/xml")], PAUSE_RESPONSE)
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let location = format!("http://{}/description.xml", listener.local_addr().unwrap());
+        let app = axum::Router::new()
+            .route("/description.xml", get(|| async { RENDERER }))
+            .route("/avt/control", control);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let render = Render::new(RenderSpec::Location(location))
+            .await
+            .unwrap()
+            .with_action_timeout(Duration::from_millis(100));
+        crate::dlna::pause(&render).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
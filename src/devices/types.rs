@@ -3,13 +3,18 @@
 //! This module contains type definitions for DLNA devices,
 //! including render specifications and device information structures.
 
+use super::matcher::DeviceMatcher;
+use crate::utils::try_time_str_to_milliseconds;
+use log::debug;
+use std::{collections::HashMap, str::FromStr};
+
 /// An specification of a DLNA render device.
 #[derive(Debug, Clone)]
 pub enum RenderSpec {
     /// Render specified by a location URL
     Location(String),
-    /// Render specified by a query string
-    Query(u64, String),
+    /// Render matched by a device query, e.g. `"Kodi".parse()?`
+    Query(u64, DeviceMatcher),
     /// The first render found
     First(u64),
 }
@@ -54,37 +59,115 @@ impl Default for PositionInfo {
 
 impl PositionInfo {
     /// Parses PositionInfo from HashMap response
-    pub fn from_map(map: &std::collections::HashMap<String, String>) -> Result<Self, String> {
+    ///
+    /// Some devices send malformed numeric fields, so unparseable `Track`,
+    /// `RelCount` and `AbsCount` values fall back to their defaults instead of
+    /// failing. Only an empty response is an error. See
+    /// [`PositionInfo::from_map_strict`] for the strict variant.
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
+        if map.is_empty() {
+            return Err("Empty GetPositionInfo response".to_string());
+        }
+
+        let defaults = Self::default();
         Ok(PositionInfo {
-            track: map
-                .get("Track")
+            track: parse_lenient(map, "Track", defaults.track),
+            rel_count: parse_lenient(map, "RelCount", defaults.rel_count),
+            abs_count: parse_lenient(map, "AbsCount", defaults.abs_count),
+            ..Self::text_fields(map)
+        })
+    }
+
+    /// Parses PositionInfo from HashMap response, failing on any malformed numeric field
+    pub fn from_map_strict(map: &HashMap<String, String>) -> Result<Self, String> {
+        Ok(PositionInfo {
+            track: field(map, "Track")
                 .unwrap_or(&"0".to_string())
                 .parse()
                 .map_err(|e| format!("Failed to parse Track: {e}"))?,
-            track_duration: map.get("TrackDuration").unwrap_or(&"".to_string()).clone(),
-            track_meta_data: map.get("TrackMetaData").unwrap_or(&"".to_string()).clone(),
-            track_uri: map.get("TrackURI").unwrap_or(&"".to_string()).clone(),
-            rel_time: map.get("RelTime").unwrap_or(&"".to_string()).clone(),
-            abs_time: map.get("AbsTime").unwrap_or(&"".to_string()).clone(),
-            rel_count: map
-                .get("RelCount")
+            rel_count: field(map, "RelCount")
                 .unwrap_or(&"-1".to_string())
                 .parse()
                 .map_err(|e| format!("Failed to parse RelCount: {e}"))?,
-            abs_count: map
-                .get("AbsCount")
+            abs_count: field(map, "AbsCount")
                 .unwrap_or(&"-1".to_string())
                 .parse()
                 .map_err(|e| format!("Failed to parse AbsCount: {e}"))?,
+            ..Self::text_fields(map)
         })
     }
+
+    /// Copies the text fields of a response, leaving numeric fields at their defaults
+    fn text_fields(map: &HashMap<String, String>) -> Self {
+        let text = |key: &str| field(map, key).cloned().unwrap_or_default();
+        PositionInfo {
+            track_duration: text("TrackDuration"),
+            track_meta_data: text("TrackMetaData"),
+            track_uri: text("TrackURI"),
+            rel_time: text("RelTime"),
+            abs_time: text("AbsTime"),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the track duration in milliseconds, if the device reports one
+    ///
+    /// Returns `None` for empty or `NOT_IMPLEMENTED` durations.
+    pub fn duration_ms(&self) -> Option<u64> {
+        try_time_str_to_milliseconds(&self.track_duration)
+    }
+
+    /// Returns the relative time position in milliseconds, if the device reports one
+    ///
+    /// Returns `None` for empty or `NOT_IMPLEMENTED` positions.
+    pub fn rel_time_ms(&self) -> Option<u64> {
+        try_time_str_to_milliseconds(&self.rel_time)
+    }
+
+    /// Returns the remaining time of the current track in milliseconds
+    pub fn remaining_ms(&self) -> Option<u64> {
+        Some(self.duration_ms()?.saturating_sub(self.rel_time_ms()?))
+    }
+}
+
+/// Parses a numeric response field, falling back to `default` if it is missing or malformed
+fn parse_lenient<T: FromStr>(map: &HashMap<String, String>, key: &str, default: T) -> T {
+    match field(map, key) {
+        Some(value) => value.trim().parse().unwrap_or_else(|_| {
+            debug!("Ignoring unparseable {key} value '{value}' in position info");
+            default
+        }),
+        None => default,
+    }
+}
+
+/// Looks up a field of an action response
+///
+/// Some devices send namespaced or differently-cased argument names, e.g.
+/// `u:CurrentTransportState` or `currenttransportstate`, so the lookup falls
+/// back to comparing the names without their prefix and ignoring case.
+fn field<'a>(map: &'a HashMap<String, String>, key: &str) -> Option<&'a String> {
+    if let Some(value) = map.get(key) {
+        return Some(value);
+    }
+    let found = map.iter().find_map(|(name, value)| {
+        let local_name = name
+            .rsplit_once(':')
+            .map_or(name.as_str(), |(_, local)| local);
+        local_name.eq_ignore_ascii_case(key).then_some(value)
+    });
+    if found.is_none() {
+        let mut names: Vec<&str> = map.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        debug!("Missing {key} in action response, which has {names:?}");
+    }
+    found
 }
 
 /// Transport information
 ///
 /// Contains information returned by the GetTransportInfo operation
-#[derive(Debug, Clone)]
-#[derive(Default)]
+#[derive(Debug, Clone, Default)]
 pub struct TransportInfo {
     /// Transport state (e.g., PLAYING, PAUSED_PLAYBACK, STOPPED)
     pub transport_state: String,
@@ -96,17 +179,323 @@ pub struct TransportInfo {
 
 impl TransportInfo {
     /// Parses TransportInfo from HashMap response
-    pub fn from_map(map: &std::collections::HashMap<String, String>) -> Result<Self, String> {
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
         Ok(TransportInfo {
-            transport_state: map
-                .get("CurrentTransportState")
-                .unwrap_or(&"".to_string())
-                .clone(),
-            transport_status: map
-                .get("CurrentTransportStatus")
-                .unwrap_or(&"".to_string())
-                .clone(),
-            speed: map.get("CurrentSpeed").unwrap_or(&"".to_string()).clone(),
+            transport_state: field(map, "CurrentTransportState")
+                .cloned()
+                .unwrap_or_default(),
+            transport_status: field(map, "CurrentTransportStatus")
+                .cloned()
+                .unwrap_or_default(),
+            speed: field(map, "CurrentSpeed").cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Parses the playback speed as a ratio of the normal speed
+    ///
+    /// Devices report speeds such as `1`, `2`, `-2` (rewind) or `1/2`.
+    /// Returns `None` for an empty or malformed speed.
+    pub fn speed_ratio(&self) -> Option<f32> {
+        let speed = self.speed.trim();
+        match speed.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator: f32 = numerator.trim().parse().ok()?;
+                let denominator: f32 = denominator.trim().parse().ok()?;
+                (denominator != 0.0).then(|| numerator / denominator)
+            }
+            None => speed.parse().ok(),
+        }
+    }
+}
+
+/// Play mode of a render, as defined by the AVTransport service
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PlayMode {
+    /// Play the current track once
+    #[default]
+    Normal,
+    /// Play tracks in random order
+    Shuffle,
+    /// Repeat the current track
+    RepeatOne,
+    /// Repeat all tracks
+    RepeatAll,
+    /// Play tracks in random order, possibly repeating them
+    Random,
+    /// Play the current track only, then stop
+    Direct1,
+    /// Play the first seconds of each track
+    Intro,
+    /// A vendor-defined play mode
+    Other(String),
+}
+
+impl FromStr for PlayMode {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "NORMAL" => PlayMode::Normal,
+            "SHUFFLE" => PlayMode::Shuffle,
+            "REPEAT_ONE" => PlayMode::RepeatOne,
+            "REPEAT_ALL" => PlayMode::RepeatAll,
+            "RANDOM" => PlayMode::Random,
+            "DIRECT_1" => PlayMode::Direct1,
+            "INTRO" => PlayMode::Intro,
+            other => PlayMode::Other(other.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for PlayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            PlayMode::Normal => "NORMAL",
+            PlayMode::Shuffle => "SHUFFLE",
+            PlayMode::RepeatOne => "REPEAT_ONE",
+            PlayMode::RepeatAll => "REPEAT_ALL",
+            PlayMode::Random => "RANDOM",
+            PlayMode::Direct1 => "DIRECT_1",
+            PlayMode::Intro => "INTRO",
+            PlayMode::Other(name) => name,
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Transport settings
+///
+/// Contains information returned by the GetTransportSettings operation
+#[derive(Debug, Clone, Default)]
+pub struct TransportSettings {
+    /// Current play mode
+    pub play_mode: PlayMode,
+    /// Current recording quality mode
+    pub rec_quality_mode: String,
+}
+
+impl TransportSettings {
+    /// Parses TransportSettings from HashMap response
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
+        let play_mode = field(map, "PlayMode").ok_or("Missing PlayMode in transport settings")?;
+        Ok(TransportSettings {
+            play_mode: play_mode.parse().unwrap_or_default(),
+            rec_quality_mode: field(map, "RecQualityMode").cloned().unwrap_or_default(),
+        })
+    }
+}
+
+/// Device capabilities information
+///
+/// Contains information returned by the GetDeviceCapabilities operation. The
+/// media lists name storage media (e.g. `NETWORK`, `HDD`), not file formats.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// Storage media the device can play from
+    pub play_media: Vec<String>,
+    /// Storage media the device can record to
+    pub rec_media: Vec<String>,
+    /// Recording quality modes the device supports
+    pub rec_quality_modes: Vec<String>,
+}
+
+impl DeviceCapabilities {
+    /// Parses DeviceCapabilities from HashMap response
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
+        let list = |name: &str| -> Vec<String> {
+            field(map, name)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        if field(map, "PlayMedia").is_none() {
+            return Err("Missing PlayMedia in device capabilities".to_string());
+        }
+        Ok(DeviceCapabilities {
+            play_media: list("PlayMedia"),
+            rec_media: list("RecMedia"),
+            rec_quality_modes: list("RecQualityModes"),
+        })
+    }
+}
+
+/// Media information
+///
+/// Contains information returned by the GetMediaInfo operation about the
+/// media loaded in the device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    /// Number of tracks of the loaded media
+    pub nr_tracks: u32,
+    /// Duration of the loaded media, e.g. `01:30:00`
+    pub media_duration: String,
+    /// URI of the loaded media
+    pub current_uri: String,
+    /// Storage medium the media plays from, e.g. `NETWORK`
+    pub play_medium: String,
+}
+
+impl MediaInfo {
+    /// Parses MediaInfo from HashMap response
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
+        let nr_tracks = field(map, "NrTracks").ok_or("Missing NrTracks in media info")?;
+        let text = |name: &str| field(map, name).cloned().unwrap_or_default();
+        Ok(MediaInfo {
+            nr_tracks: nr_tracks.trim().parse().unwrap_or(0),
+            media_duration: text("MediaDuration"),
+            current_uri: text("CurrentURI"),
+            play_medium: text("PlayMedium"),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_position_info_lenient_garbage_track() {
+        let map = response(&[
+            ("Track", "NOT_IMPLEMENTED"),
+            ("RelTime", "00:01:02"),
+            ("RelCount", "2147483647"),
+            ("AbsCount", "x"),
+        ]);
+        let info = PositionInfo::from_map(&map).unwrap();
+        assert_eq!(info.track, 0);
+        assert_eq!(info.rel_time, "00:01:02");
+        assert_eq!(info.rel_count, i32::MAX);
+        assert_eq!(info.abs_count, -1);
+    }
+
+    #[test]
+    fn test_position_info_strict_garbage_track() {
+        let map = response(&[("Track", "-1"), ("RelTime", "00:01:02")]);
+        assert!(PositionInfo::from_map_strict(&map).is_err());
+        assert_eq!(PositionInfo::from_map(&map).unwrap().track, 0);
+    }
+
+    #[test]
+    fn test_media_info_response() {
+        let map = response(&[
+            ("u:NrTracks", "1"),
+            ("MediaDuration", "01:30:00"),
+            ("CurrentURI", "http://192.168.1.5:9000/video.mkv"),
+            ("PlayMedium", "NETWORK"),
+        ]);
+        let info = MediaInfo::from_map(&map).unwrap();
+        assert_eq!(info.nr_tracks, 1);
+        assert_eq!(info.media_duration, "01:30:00");
+        assert_eq!(info.current_uri, "http://192.168.1.5:9000/video.mkv");
+        assert_eq!(info.play_medium, "NETWORK");
+
+        assert!(MediaInfo::from_map(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_position_info_empty_response() {
+        assert!(PositionInfo::from_map(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_position_info_valid_response() {
+        let map = response(&[
+            ("Track", "3"),
+            ("TrackDuration", "01:00:00"),
+            ("RelTime", "00:30:00"),
+            ("RelCount", "10"),
+            ("AbsCount", "20"),
+        ]);
+        let lenient = PositionInfo::from_map(&map).unwrap();
+        let strict = PositionInfo::from_map_strict(&map).unwrap();
+        for info in [lenient, strict] {
+            assert_eq!(info.track, 3);
+            assert_eq!(info.remaining_ms(), Some(30 * 60 * 1000));
+            assert_eq!((info.rel_count, info.abs_count), (10, 20));
+        }
+    }
+
+    #[test]
+    fn test_namespaced_and_mixed_case_keys() {
+        let map = response(&[
+            ("u:CurrentTransportState", "PLAYING"),
+            ("currenttransportstatus", "OK"),
+            ("avt:CURRENTSPEED", "1"),
+        ]);
+        let info = TransportInfo::from_map(&map).unwrap();
+        assert_eq!(info.transport_state, "PLAYING");
+        assert_eq!(info.transport_status, "OK");
+        assert_eq!(info.speed, "1");
+
+        let map = response(&[
+            ("u:Track", "2"),
+            ("u:TrackDuration", "00:10:00"),
+            ("relTime", "00:04:00"),
+            ("s:RelCount", "7"),
+        ]);
+        let info = PositionInfo::from_map(&map).unwrap();
+        assert_eq!(info.track, 2);
+        assert_eq!(info.remaining_ms(), Some(6 * 60 * 1000));
+        assert_eq!(info.rel_count, 7);
+        assert_eq!(PositionInfo::from_map_strict(&map).unwrap().track, 2);
+    }
+
+    #[test]
+    fn test_transport_info_speed_ratio() {
+        let speed_ratio = |speed: &str| {
+            TransportInfo::from_map(&response(&[("CurrentSpeed", speed)]))
+                .unwrap()
+                .speed_ratio()
+        };
+        assert_eq!(speed_ratio("1"), Some(1.0));
+        assert_eq!(speed_ratio("2"), Some(2.0));
+        assert_eq!(speed_ratio("-2"), Some(-2.0));
+        assert_eq!(speed_ratio("1/2"), Some(0.5));
+        assert_eq!(speed_ratio(""), None);
+        assert_eq!(speed_ratio("1/0"), None);
+        assert_eq!(speed_ratio("fast"), None);
+    }
+
+    #[test]
+    fn test_transport_settings_from_map() {
+        let map = response(&[
+            ("PlayMode", "REPEAT_ALL"),
+            ("RecQualityMode", "NOT_IMPLEMENTED"),
+        ]);
+        let settings = TransportSettings::from_map(&map).unwrap();
+        assert_eq!(settings.play_mode, PlayMode::RepeatAll);
+        assert_eq!(settings.rec_quality_mode, "NOT_IMPLEMENTED");
+
+        let map = response(&[("PlayMode", "VENDOR_LOOP")]);
+        let settings = TransportSettings::from_map(&map).unwrap();
+        assert_eq!(settings.play_mode.to_string(), "VENDOR_LOOP");
+        assert!(TransportSettings::from_map(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_device_capabilities_from_map() {
+        let map = response(&[
+            ("PlayMedia", "NETWORK, HDD,UNKNOWN"),
+            ("RecMedia", "NOT_IMPLEMENTED"),
+            ("RecQualityModes", ""),
+        ]);
+        let capabilities = DeviceCapabilities::from_map(&map).unwrap();
+        assert_eq!(capabilities.play_media, ["NETWORK", "HDD", "UNKNOWN"]);
+        assert_eq!(capabilities.rec_media, ["NOT_IMPLEMENTED"]);
+        assert!(capabilities.rec_quality_modes.is_empty());
+        assert!(DeviceCapabilities::from_map(&HashMap::new()).is_err());
+    }
+}
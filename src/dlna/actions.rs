@@ -5,26 +5,36 @@
 
 use crate::{
     config::{
-        DLNA_ACTION_PAUSE, DLNA_ACTION_PLAY, DLNA_DEFAULT_SPEED, DLNA_INSTANCE_ID,
+        DLNA_ACTION_GET_MUTE, DLNA_ACTION_GET_VOLUME, DLNA_ACTION_NEXT, DLNA_ACTION_PAUSE,
+        DLNA_ACTION_PLAY, DLNA_ACTION_PREVIOUS, DLNA_ACTION_SEEK, DLNA_ACTION_SET_VOLUME,
+        DLNA_ACTION_STOP, DLNA_DEFAULT_SPEED, DLNA_INSTANCE_ID, MAX_VOLUME,
     },
-    devices::Render,
+    devices::{Render, discovery::RENDERING_CONTROL},
     error::{Error, Result},
-    utils::retry_with_backoff,
+    utils::{format_dlna_time, retry_with_backoff},
 };
 use log::info;
+use quick_xml::escape::escape;
 
 /// Builds a DLNA play payload with configurable parameters
-fn build_play_payload(instance_id: u32, speed: u32) -> String {
-    format!(
-        r#"
+///
+/// The `Speed` element is left out without a speed, for renderers faulting on it.
+/// The speed is escaped, as it may come as-is from the command line.
+pub(crate) fn build_play_payload(instance_id: u32, speed: Option<&str>) -> String {
+    match speed {
+        Some(speed) => format!(
+            r#"
     <InstanceID>{instance_id}</InstanceID>
-    <Speed>{speed}</Speed>
-"#
-    )
+    <Speed>{}</Speed>
+"#,
+            escape(speed)
+        ),
+        None => build_instance_payload(instance_id),
+    }
 }
 
-/// Builds a DLNA pause payload
-fn build_pause_payload(instance_id: u32) -> String {
+/// Builds a DLNA payload carrying only the instance ID (Pause, Stop, Next, ...)
+fn build_instance_payload(instance_id: u32) -> String {
     format!(
         r#"
     <InstanceID>{instance_id}</InstanceID>
@@ -34,21 +44,26 @@ fn build_pause_payload(instance_id: u32) -> String {
 
 /// Pauses playback on a DLNA device
 pub async fn pause(render: &Render) -> Result<()> {
-    let pause_payload = build_pause_payload(DLNA_INSTANCE_ID);
+    let pause_payload = build_instance_payload(DLNA_INSTANCE_ID);
+    // Each attempt is timed, so that a retry follows an attempt the device never answered
     retry_with_backoff(
         || async {
             render
-                .service
-                .action(render.device.url(), DLNA_ACTION_PAUSE, &pause_payload)
-                .await
+                .timed(
+                    DLNA_ACTION_PAUSE,
+                    render
+                        .service
+                        .action(render.url(), DLNA_ACTION_PAUSE, &pause_payload),
+                )
+                .await?
+                .map_err(|err| Error::DlnaPlaybackFailed {
+                    source: err,
+                    context: "Failed to pause media playback on render device".to_string(),
+                })
         },
         "Pause",
     )
-    .await
-    .map_err(|err| Error::DlnaPlaybackFailed {
-        source: err,
-        context: "Failed to pause media playback on render device".to_string(),
-    })?;
+    .await?;
 
     info!("Media playback paused");
     Ok(())
@@ -56,26 +71,277 @@ pub async fn pause(render: &Render) -> Result<()> {
 
 /// Resumes playback on a DLNA device
 pub async fn resume(render: &Render) -> Result<()> {
-    let play_payload = build_play_payload(DLNA_INSTANCE_ID, DLNA_DEFAULT_SPEED);
+    let play_payload = build_play_payload(DLNA_INSTANCE_ID, render.play_speed());
     retry_with_backoff(
         || async {
             render
-                .service
-                .action(render.device.url(), DLNA_ACTION_PLAY, &play_payload)
-                .await
+                .timed(
+                    DLNA_ACTION_PLAY,
+                    render
+                        .service
+                        .action(render.url(), DLNA_ACTION_PLAY, &play_payload),
+                )
+                .await?
+                .map_err(|err| Error::DlnaPlaybackFailed {
+                    source: err,
+                    context: "Failed to resume media playback on render device".to_string(),
+                })
         },
         "Resume",
     )
-    .await
-    .map_err(|err| Error::DlnaPlaybackFailed {
-        source: err,
-        context: "Failed to resume media playback on render device".to_string(),
-    })?;
+    .await?;
 
     info!("Media playback resumed");
     Ok(())
 }
 
+/// Builds a DLNA seek payload targeting a position in the current track
+fn build_seek_payload(instance_id: u32, position_ms: u64) -> String {
+    let target = format_dlna_time(position_ms);
+    format!(
+        r#"
+    <InstanceID>{instance_id}</InstanceID>
+    <Unit>REL_TIME</Unit>
+    <Target>{target}</Target>
+"#
+    )
+}
+
+/// Builds a RenderingControl payload for the master channel
+fn build_volume_payload(instance_id: u32, volume: Option<u8>) -> String {
+    let desired_volume = volume
+        .map(|volume| format!("\n    <DesiredVolume>{volume}</DesiredVolume>"))
+        .unwrap_or_default();
+    format!(
+        r#"
+    <InstanceID>{instance_id}</InstanceID>
+    <Channel>Master</Channel>{desired_volume}
+"#
+    )
+}
+
+/// Applies a signed step to a volume, keeping it within `0..=MAX_VOLUME`
+fn apply_volume_delta(volume: u8, delta: i16) -> u8 {
+    (i16::from(volume) + delta).clamp(0, i16::from(MAX_VOLUME)) as u8
+}
+
+/// Runs an AVTransport action whose payload only carries the instance ID
+async fn transport_action(render: &Render, action: &str) -> Result<()> {
+    let payload = build_instance_payload(DLNA_INSTANCE_ID);
+    render
+        .timed(
+            action,
+            render.service.action(render.url(), action, &payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaActionFailed {
+            action: action.to_string(),
+            source: err,
+        })?;
+    Ok(())
+}
+
+/// Stops playback on a DLNA device
+pub async fn stop(render: &Render) -> Result<()> {
+    transport_action(render, DLNA_ACTION_STOP).await?;
+    info!("Media playback stopped");
+    Ok(())
+}
+
+/// Skips to the next track of the device's own queue
+pub async fn next_track(render: &Render) -> Result<()> {
+    transport_action(render, DLNA_ACTION_NEXT).await?;
+    info!("Skipped to next track");
+    Ok(())
+}
+
+/// Skips to the previous track of the device's own queue
+pub async fn previous_track(render: &Render) -> Result<()> {
+    transport_action(render, DLNA_ACTION_PREVIOUS).await?;
+    info!("Skipped to previous track");
+    Ok(())
+}
+
+/// Seeks to an absolute position in the current track
+pub async fn seek(render: &Render, position_ms: u64) -> Result<()> {
+    let payload = build_seek_payload(DLNA_INSTANCE_ID, position_ms);
+    render
+        .timed(
+            DLNA_ACTION_SEEK,
+            render
+                .service
+                .action(render.url(), DLNA_ACTION_SEEK, &payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaActionFailed {
+            action: DLNA_ACTION_SEEK.to_string(),
+            source: err,
+        })?;
+    Ok(())
+}
+
+/// Seeks forward or backward relative to the current position
+///
+/// Returns the position that was sought to, in milliseconds. Seeking before
+/// the start of the track lands on the start.
+pub async fn seek_relative(render: &Render, offset_seconds: i64) -> Result<u64> {
+    let current_ms = render
+        .get_position_info()
+        .await?
+        .rel_time_ms()
+        .ok_or_else(|| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_SEEK.to_string(),
+            error: "Device did not report the current position".to_string(),
+        })?;
+    let target_ms = current_ms.saturating_add_signed(offset_seconds.saturating_mul(1000));
+    seek(render, target_ms).await?;
+    info!("Seeked to {}s", target_ms / 1000);
+    Ok(target_ms)
+}
+
+/// Returns the RenderingControl service of the render device
+fn rendering_control(render: &Render) -> Result<&rupnp::Service> {
+    render
+        .device
+        .find_service(&RENDERING_CONTROL)
+        .ok_or_else(|| Error::DlnaServiceNotAvailable {
+            service: RENDERING_CONTROL.to_string(),
+        })
+}
+
+/// Gets the master volume of a DLNA device
+pub async fn get_volume(render: &Render) -> Result<u8> {
+    let payload = build_volume_payload(DLNA_INSTANCE_ID, None);
+    let response = render
+        .timed(
+            DLNA_ACTION_GET_VOLUME,
+            rendering_control(render)?.action(render.url(), DLNA_ACTION_GET_VOLUME, &payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaActionFailed {
+            action: DLNA_ACTION_GET_VOLUME.to_string(),
+            source: err,
+        })?;
+
+    response
+        .get("CurrentVolume")
+        .and_then(|volume| volume.parse().ok())
+        .ok_or_else(|| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_GET_VOLUME.to_string(),
+            error: "Missing or invalid CurrentVolume".to_string(),
+        })
+}
+
+/// Gets whether the master channel of a DLNA device is muted
+pub async fn get_mute(render: &Render) -> Result<bool> {
+    let payload = build_volume_payload(DLNA_INSTANCE_ID, None);
+    let response = render
+        .timed(
+            DLNA_ACTION_GET_MUTE,
+            rendering_control(render)?.action(render.url(), DLNA_ACTION_GET_MUTE, &payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaActionFailed {
+            action: DLNA_ACTION_GET_MUTE.to_string(),
+            source: err,
+        })?;
+
+    response
+        .get("CurrentMute")
+        .and_then(|mute| parse_mute(mute))
+        .ok_or_else(|| Error::DlnaResponseParseError {
+            action: DLNA_ACTION_GET_MUTE.to_string(),
+            error: "Missing or invalid CurrentMute".to_string(),
+        })
+}
+
+/// Parses a UPnP boolean mute state, `1`/`0` or `true`/`false`
+fn parse_mute(mute: &str) -> Option<bool> {
+    match mute.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        other if other.eq_ignore_ascii_case("true") => Some(true),
+        other if other.eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// Sets the master volume of a DLNA device, capped at `MAX_VOLUME`
+pub async fn set_volume(render: &Render, volume: u8) -> Result<()> {
+    let payload = build_volume_payload(DLNA_INSTANCE_ID, Some(volume.min(MAX_VOLUME)));
+    render
+        .timed(
+            DLNA_ACTION_SET_VOLUME,
+            rendering_control(render)?.action(render.url(), DLNA_ACTION_SET_VOLUME, &payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaActionFailed {
+            action: DLNA_ACTION_SET_VOLUME.to_string(),
+            source: err,
+        })?;
+    Ok(())
+}
+
+/// Raises or lowers the master volume by `delta` and returns the new volume
+pub async fn adjust_volume(render: &Render, delta: i16) -> Result<u8> {
+    let volume = apply_volume_delta(get_volume(render).await?, delta);
+    set_volume(render, volume).await?;
+    info!("Volume set to {volume}");
+    Ok(volume)
+}
+
+/// Checks that a speed is in the format DLNA expects for `TransportPlaySpeed`
+///
+/// Valid speeds are non-zero integers or fractions, optionally negative for
+/// rewind, e.g. `2`, `-2` or `1/2`.
+pub fn validate_speed(speed: &str) -> Result<()> {
+    let invalid = || Error::InvalidPlaybackSpeed {
+        speed: speed.to_string(),
+    };
+    let magnitude = speed.strip_prefix('-').unwrap_or(speed);
+    let (numerator, denominator) = magnitude.split_once('/').unwrap_or((magnitude, "1"));
+
+    for part in [numerator, denominator] {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if part.parse::<u32>().map_err(|_| invalid())? == 0 {
+            return Err(invalid());
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays at a given speed on a DLNA device (fast-forward/rewind)
+///
+/// Many renderers only accept a speed of `1`, so a rejection from the device is
+/// returned as an ordinary error that callers can report and recover from.
+pub async fn play_at_speed(render: &Render, speed: &str) -> Result<()> {
+    validate_speed(speed)?;
+    // Normal speed is sent the way the device was configured for
+    let play_payload = if speed == DLNA_DEFAULT_SPEED.to_string() {
+        build_play_payload(DLNA_INSTANCE_ID, render.play_speed())
+    } else {
+        build_play_payload(DLNA_INSTANCE_ID, Some(speed))
+    };
+    render
+        .timed(
+            DLNA_ACTION_PLAY,
+            render
+                .service
+                .action(render.url(), DLNA_ACTION_PLAY, &play_payload),
+        )
+        .await?
+        .map_err(|err| Error::DlnaPlaybackFailed {
+            source: err,
+            context: format!("Render device rejected playback speed '{speed}'"),
+        })?;
+
+    info!("Playback speed set to {speed}");
+    Ok(())
+}
+
 /// Toggles play/pause state based on current transport state
 pub async fn toggle_play_pause(render: &Render) -> Result<()> {
     let transport_info = render.get_transport_info().await?;
@@ -95,3 +361,70 @@ pub async fn toggle_play_pause(render: &Render) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_speed() {
+        for speed in ["1", "2", "-2", "1/2", "-1/4", "16"] {
+            assert!(validate_speed(speed).is_ok(), "{speed} should be valid");
+        }
+        for speed in ["", "0", "1/0", "x2", "1.5", "--2", "/2", "2/"] {
+            assert!(validate_speed(speed).is_err(), "{speed} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_build_seek_payload() {
+        let payload = build_seek_payload(0, 3_725_900);
+        assert!(payload.contains("<Unit>REL_TIME</Unit>"));
+        assert!(payload.contains("<Target>01:02:05</Target>"));
+    }
+
+    #[test]
+    fn test_build_volume_payload() {
+        let get = build_volume_payload(0, None);
+        assert!(get.contains("<Channel>Master</Channel>"));
+        assert!(!get.contains("DesiredVolume"));
+
+        let set = build_volume_payload(0, Some(42));
+        assert!(set.contains("<DesiredVolume>42</DesiredVolume>"));
+    }
+
+    #[test]
+    fn test_parse_mute() {
+        assert_eq!(parse_mute("1"), Some(true));
+        assert_eq!(parse_mute("0"), Some(false));
+        assert_eq!(parse_mute("True"), Some(true));
+        assert_eq!(parse_mute(" false "), Some(false));
+        assert_eq!(parse_mute("muted"), None);
+    }
+
+    #[test]
+    fn test_apply_volume_delta_clamps() {
+        assert_eq!(apply_volume_delta(50, 5), 55);
+        assert_eq!(apply_volume_delta(3, -5), 0);
+        assert_eq!(apply_volume_delta(98, 5), MAX_VOLUME);
+    }
+
+    #[test]
+    fn test_build_play_payload_speed() {
+        let payload = build_play_payload(0, Some("1/2"));
+        assert!(payload.contains("<Speed>1/2</Speed>"));
+    }
+
+    #[test]
+    fn test_build_play_payload_without_speed() {
+        let payload = build_play_payload(0, None);
+        assert!(payload.contains("<InstanceID>0</InstanceID>"));
+        assert!(!payload.contains("Speed"));
+    }
+
+    #[test]
+    fn test_build_play_payload_escapes_speed() {
+        let payload = build_play_payload(0, Some("1</Speed><Speed>2"));
+        assert!(payload.contains("<Speed>1&lt;/Speed&gt;&lt;Speed&gt;2</Speed>"));
+    }
+}
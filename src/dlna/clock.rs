@@ -0,0 +1,164 @@
+//! Local playback clock for crab-dlna
+//!
+//! Some renderers answer `GetPositionInfo` with `NOT_IMPLEMENTED` instead of
+//! a position. This module provides a clock that follows playback locally, so
+//! that the position can still be estimated on those devices.
+
+use crate::utils::format_dlna_time;
+use std::time::Instant;
+
+/// A clock estimating the playback position from the monotonic system clock
+///
+/// The clock runs while the device plays and stops while it is paused or
+/// stopped. Whenever the device does report a position, the clock is set to
+/// it, so the estimate only drifts while the device stays silent.
+#[derive(Debug, Clone, Default)]
+pub struct PlaybackClock {
+    /// Position at the time the clock was last set, paused or resumed
+    anchor_ms: u64,
+    /// When the clock was last resumed, `None` while paused
+    running_since: Option<Instant>,
+}
+
+impl PlaybackClock {
+    /// Creates a paused clock at position 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a clock at position 0 that is already running, as right after Play
+    pub fn started() -> Self {
+        Self {
+            anchor_ms: 0,
+            running_since: Some(Instant::now()),
+        }
+    }
+
+    /// Returns the estimated position in milliseconds
+    pub fn position_ms(&self) -> u64 {
+        self.position_at(Instant::now())
+    }
+
+    /// Returns the estimated position formatted as a DLNA `rel_time` (HH:MM:SS)
+    pub fn rel_time(&self) -> String {
+        format_dlna_time(self.position_ms())
+    }
+
+    /// Checks whether the clock is running
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    /// Stops the clock at its current position
+    pub fn pause(&mut self) {
+        self.anchor_ms = self.position_ms();
+        self.running_since = None;
+    }
+
+    /// Restarts the clock from its current position
+    pub fn resume(&mut self) {
+        if self.running_since.is_none() {
+            self.running_since = Some(Instant::now());
+        }
+    }
+
+    /// Moves the clock to a position, keeping it running or paused
+    pub fn seek(&mut self, position_ms: u64) {
+        self.anchor_ms = position_ms;
+        if self.running_since.is_some() {
+            self.running_since = Some(Instant::now());
+        }
+    }
+
+    /// Runs or stops the clock according to a device transport state
+    ///
+    /// A stopped device also rewinds the clock, as playback restarts from the
+    /// beginning.
+    pub fn apply_transport_state(&mut self, transport_state: &str) {
+        match transport_state {
+            "PLAYING" => self.resume(),
+            "PAUSED_PLAYBACK" | "PAUSED_RECORDING" => self.pause(),
+            "STOPPED" | "NO_MEDIA_PRESENT" => {
+                self.pause();
+                self.anchor_ms = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Reconciles the clock with the position reported by the device, if any
+    ///
+    /// # Returns
+    /// Returns the device position when available, the estimate otherwise
+    pub fn reconcile(&mut self, device_position_ms: Option<u64>) -> u64 {
+        match device_position_ms {
+            Some(position_ms) => {
+                self.seek(position_ms);
+                position_ms
+            }
+            None => self.position_ms(),
+        }
+    }
+
+    /// Returns the position the clock shows at a given instant
+    fn position_at(&self, now: Instant) -> u64 {
+        match self.running_since {
+            Some(since) => self.anchor_ms + now.saturating_duration_since(since).as_millis() as u64,
+            None => self.anchor_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Creates a clock that has been running for the given time
+    fn running_for(elapsed: Duration) -> PlaybackClock {
+        PlaybackClock {
+            anchor_ms: 0,
+            running_since: Some(Instant::now() - elapsed),
+        }
+    }
+
+    #[test]
+    fn test_clock_advances_while_running() {
+        let clock = running_for(Duration::from_secs(5));
+        let now = Instant::now();
+        assert_eq!(clock.position_at(now) / 1000, 5);
+        assert_eq!(clock.position_at(now + Duration::from_secs(2)) / 1000, 7);
+        assert_eq!(PlaybackClock::new().position_ms(), 0);
+    }
+
+    #[test]
+    fn test_clock_pauses_and_resumes() {
+        let mut clock = running_for(Duration::from_secs(5));
+        clock.apply_transport_state("PAUSED_PLAYBACK");
+        assert!(!clock.is_running());
+        let paused_at = clock.position_ms();
+        assert_eq!(
+            clock.position_at(Instant::now() + Duration::from_secs(60)),
+            paused_at
+        );
+
+        clock.apply_transport_state("PLAYING");
+        assert!(clock.is_running());
+        assert!(clock.position_ms() >= paused_at);
+
+        clock.apply_transport_state("STOPPED");
+        assert_eq!(clock.position_ms(), 0);
+    }
+
+    #[test]
+    fn test_clock_reconciles_with_device() {
+        let mut clock = running_for(Duration::from_secs(5));
+        assert_eq!(clock.reconcile(Some(60_000)), 60_000);
+        assert!(clock.is_running());
+        assert!((60_000..61_000).contains(&clock.reconcile(None)));
+
+        clock.pause();
+        clock.seek(3_723_000);
+        assert_eq!(clock.rel_time(), "01:02:03");
+    }
+}
@@ -0,0 +1,162 @@
+//! Detection of why playback ended for crab-dlna
+//!
+//! Devices report the same STOPPED transport state whether the media played to
+//! its end or was stopped with their remote. This module tells them apart from
+//! the last position the device reported before stopping.
+
+use crate::{config::END_OF_MEDIA_MARGIN_MS, devices::PositionInfo};
+
+/// Why the device stopped playing a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndReason {
+    /// The media, or the clip of it being played, played to its end
+    EndOfMedia,
+    /// The device was stopped well before the end, e.g. with its remote
+    Stopped,
+}
+
+/// Follows the transport state and position of a device to tell why playback ended
+///
+/// Feed it every transport state and device position polled; it reports an
+/// [`EndReason`] once the device stops after it was seen playing. A stop counts
+/// as the end of the media when the last position was within
+/// `END_OF_MEDIA_MARGIN_MS` of the end. Devices that don't report their
+/// position always reach the end, as nothing tells otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct PlaybackEndDetector {
+    /// Whether the device was seen playing since the last stop
+    started: bool,
+    /// Position of the end, instead of the track duration, e.g. of a clip
+    end_ms: Option<u64>,
+    /// Last position reported by the device, in milliseconds
+    position_ms: Option<u64>,
+    /// Last track duration reported by the device, in milliseconds
+    duration_ms: Option<u64>,
+}
+
+impl PlaybackEndDetector {
+    /// Creates a detector, `started` telling the device is known to be playing already
+    pub fn new(started: bool) -> Self {
+        Self {
+            started,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the position playback ends at, when it ends before the track does
+    pub fn with_end(mut self, end_ms: Option<u64>) -> Self {
+        self.end_ms = end_ms;
+        self
+    }
+
+    /// Records a position reported by the device
+    pub fn observe_position(&mut self, position_info: &PositionInfo) {
+        if !self.started {
+            return;
+        }
+        self.position_ms = position_info.rel_time_ms();
+        self.duration_ms = position_info.duration_ms().filter(|ms| *ms > 0);
+    }
+
+    /// Records a transport state, returning why playback ended if it just did
+    pub fn observe_transport_state(&mut self, transport_state: &str) -> Option<EndReason> {
+        match transport_state {
+            "PLAYING" | "PAUSED_PLAYBACK" | "TRANSITIONING" => {
+                self.started = true;
+                None
+            }
+            "STOPPED" | "NO_MEDIA_PRESENT" if self.started => {
+                let reason = self.end_reason();
+                self.started = false;
+                self.position_ms = None;
+                self.duration_ms = None;
+                Some(reason)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether playback is over with the last position seen
+    fn end_reason(&self) -> EndReason {
+        match (self.position_ms, self.end_ms.or(self.duration_ms)) {
+            (Some(position_ms), Some(end_ms)) if position_ms + END_OF_MEDIA_MARGIN_MS < end_ms => {
+                EndReason::Stopped
+            }
+            _ => EndReason::EndOfMedia,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(rel_time: &str, track_duration: &str) -> PositionInfo {
+        PositionInfo {
+            rel_time: rel_time.to_string(),
+            track_duration: track_duration.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn end_reason(mut detector: PlaybackEndDetector, rel_time: &str) -> Option<EndReason> {
+        detector.observe_transport_state("PLAYING");
+        detector.observe_position(&position(rel_time, "00:10:00"));
+        detector.observe_transport_state("STOPPED")
+    }
+
+    #[test]
+    fn test_stop_near_end_is_end_of_media() {
+        let detector = PlaybackEndDetector::new(false);
+        assert_eq!(
+            end_reason(detector.clone(), "00:09:57"),
+            Some(EndReason::EndOfMedia)
+        );
+        assert_eq!(end_reason(detector, "00:04:00"), Some(EndReason::Stopped));
+    }
+
+    #[test]
+    fn test_clip_ends_before_the_track() {
+        let detector = PlaybackEndDetector::new(false).with_end(Some(30_000));
+        assert_eq!(
+            end_reason(detector.clone(), "00:00:29"),
+            Some(EndReason::EndOfMedia)
+        );
+        assert_eq!(end_reason(detector, "00:00:12"), Some(EndReason::Stopped));
+    }
+
+    #[test]
+    fn test_without_position_stop_is_end_of_media() {
+        let mut detector = PlaybackEndDetector::new(false);
+        detector.observe_transport_state("PLAYING");
+        assert_eq!(
+            detector.observe_transport_state("STOPPED"),
+            Some(EndReason::EndOfMedia)
+        );
+
+        let detector = PlaybackEndDetector::new(false);
+        assert_eq!(
+            end_reason(detector, "NOT_IMPLEMENTED"),
+            Some(EndReason::EndOfMedia)
+        );
+    }
+
+    #[test]
+    fn test_end_is_reported_once_after_playing() {
+        let mut detector = PlaybackEndDetector::new(false);
+        assert_eq!(detector.observe_transport_state("STOPPED"), None);
+        detector.observe_position(&position("00:01:00", "00:10:00"));
+        detector.observe_transport_state("PLAYING");
+        assert_eq!(
+            detector.observe_transport_state("STOPPED"),
+            Some(EndReason::EndOfMedia)
+        );
+        assert_eq!(detector.observe_transport_state("STOPPED"), None);
+
+        let mut detector = PlaybackEndDetector::new(true);
+        assert_eq!(
+            detector.observe_transport_state("NO_MEDIA_PRESENT"),
+            Some(EndReason::EndOfMedia)
+        );
+    }
+}
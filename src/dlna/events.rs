@@ -0,0 +1,193 @@
+//! Machine-readable playback events for crab-dlna
+//!
+//! This module emits newline-delimited JSON events on stdout, so that
+//! crab-dlna can be driven by scripts and home-automation tools.
+
+use crate::{config::EVENT_POLL_INTERVAL_MS, devices::Render, error::Error};
+use log::debug;
+use serde::Serialize;
+use std::{
+    io::{Write, stdout},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::interval};
+
+/// A playback event, serialized as one JSON object per line
+///
+/// The `event` field identifies the kind of event. Field names are part of the
+/// command line interface and should be kept stable.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PlaybackEvent {
+    /// Playback of a file was started on the device
+    Playing {
+        /// Path of the local file
+        file: String,
+        /// URI the device streams the file from
+        uri: String,
+    },
+    /// The playback position changed
+    Position {
+        /// Path of the local file
+        file: String,
+        /// Relative time position (format: HH:MM:SS)
+        rel_time: String,
+        /// Total duration of the track (format: HH:MM:SS)
+        track_duration: String,
+    },
+    /// Playback was paused on the device
+    Paused {
+        /// Path of the local file
+        file: String,
+    },
+    /// Playback was resumed on the device
+    Resumed {
+        /// Path of the local file
+        file: String,
+    },
+    /// Playback stopped on the device
+    Stopped {
+        /// Path of the local file
+        file: String,
+    },
+    /// Playback failed
+    Error {
+        /// Path of the local file
+        file: String,
+        /// Description of the failure
+        message: String,
+    },
+    /// A playlist run ended
+    Summary {
+        /// Paths of the files that played successfully, in order
+        played: Vec<String>,
+        /// Files that failed to play, in order
+        failed: Vec<FailedFile>,
+        /// Time spent playing the files, in seconds
+        duration_secs: u64,
+        /// Number of times the whole playlist was played
+        loops: usize,
+    },
+}
+
+/// A file that failed to play, as reported in [`PlaybackEvent::Summary`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedFile {
+    /// Path of the local file
+    pub file: String,
+    /// Description of the failure
+    pub message: String,
+}
+
+impl PlaybackEvent {
+    /// Serializes the event as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|e| {
+            format!(r#"{{"event":"error","file":"","message":"Failed to serialize event: {e}"}}"#)
+        })
+    }
+
+    /// Writes the event to stdout as a single line of JSON
+    pub fn emit(&self) {
+        let mut stdout = stdout().lock();
+        if writeln!(stdout, "{}", self.to_json())
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            debug!("Failed to write playback event to stdout");
+        }
+    }
+}
+
+/// Spawns a task that polls the device and emits position and state events
+pub(crate) fn spawn_event_reporter(render: Render, file: String) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_millis(EVENT_POLL_INTERVAL_MS));
+        let mut last_rel_time = None;
+        let mut last_transport_state: Option<String> = None;
+        let mut position_unavailable = false;
+
+        loop {
+            interval.tick().await;
+
+            if !position_unavailable {
+                match render.get_position_info().await {
+                    Ok(position_info)
+                        if last_rel_time.as_ref() != Some(&position_info.rel_time) =>
+                    {
+                        PlaybackEvent::Position {
+                            file: file.clone(),
+                            rel_time: position_info.rel_time.clone(),
+                            track_duration: position_info.track_duration,
+                        }
+                        .emit();
+                        last_rel_time = Some(position_info.rel_time);
+                    }
+                    Err(Error::DlnaActionNotSupported { .. }) => {
+                        debug!(
+                            "Device does not report the playback position, no longer polling it"
+                        );
+                        position_unavailable = true;
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Ok(transport_info) = render.get_transport_info().await {
+                let state = transport_info.transport_state;
+                if last_transport_state.as_ref() == Some(&state) {
+                    continue;
+                }
+
+                let event = match state.as_str() {
+                    "PAUSED_PLAYBACK" => Some(PlaybackEvent::Paused { file: file.clone() }),
+                    "PLAYING" if last_transport_state.is_some() => {
+                        Some(PlaybackEvent::Resumed { file: file.clone() })
+                    }
+                    "STOPPED" => Some(PlaybackEvent::Stopped { file: file.clone() }),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    event.emit();
+                }
+                last_transport_state = Some(state);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json_format() {
+        let event = PlaybackEvent::Playing {
+            file: "movie.mp4".to_string(),
+            uri: "http://192.168.1.100:9000/movie.mp4".to_string(),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"playing","file":"movie.mp4","uri":"http://192.168.1.100:9000/movie.mp4"}"#
+        );
+
+        let event = PlaybackEvent::Stopped {
+            file: "movie.mp4".to_string(),
+        };
+        assert_eq!(event.to_json(), r#"{"event":"stopped","file":"movie.mp4"}"#);
+
+        let event = PlaybackEvent::Summary {
+            played: vec!["a.mp4".to_string()],
+            failed: vec![FailedFile {
+                file: "b.mp4".to_string(),
+                message: "refused".to_string(),
+            }],
+            duration_secs: 90,
+            loops: 0,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"summary","played":["a.mp4"],"failed":[{"file":"b.mp4","message":"refused"}],"duration_secs":90,"loops":0}"#
+        );
+    }
+}
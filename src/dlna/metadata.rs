@@ -4,14 +4,20 @@
 //! for media files, including subtitle support.
 
 use crate::{
-    config::{DEFAULT_DLNA_VIDEO_TITLE, DLNA_INSTANCE_ID},
-    error::Result,
+    config::{Config, DEFAULT_DLNA_VIDEO_TITLE, DLNA_INSTANCE_ID},
+    error::{Error, Result},
     media::MediaStreamingServer,
+    types::SubtitleType,
+    utils::clean_title_from_filename,
 };
 use askama::Template;
-use quick_xml::escape::escape;
+use log::{debug, info};
+use quick_xml::escape::{escape, unescape};
+use std::path::Path;
 
 /// Template context for DIDL-Lite metadata with subtitles
+///
+/// The first track is also advertised as the subtitle of the video resource.
 #[derive(Template)]
 #[template(path = "didl_lite_with_subtitles.xml")]
 struct DidlLiteWithSubtitlesTemplate {
@@ -20,6 +26,14 @@ struct DidlLiteWithSubtitlesTemplate {
     video_type: String,
     subtitle_uri: String,
     subtitle_type: String,
+    subtitles: Vec<CaptionTrack>,
+}
+
+/// A subtitle track as advertised in DIDL-Lite metadata
+struct CaptionTrack {
+    uri: String,
+    caption_type: String,
+    mime_type: String,
 }
 
 /// Template context for DIDL-Lite metadata without subtitles
@@ -40,20 +54,56 @@ struct SetAvTransportUriTemplate {
     current_uri_metadata: String,
 }
 
+/// Template context for SetNextAVTransportURI payload
+#[derive(Template)]
+#[template(path = "set_next_av_transport_uri.xml")]
+struct SetNextAvTransportUriTemplate {
+    instance_id: u32,
+    next_uri: String,
+    next_uri_metadata: String,
+}
+
+/// Determines the title advertised to the device for a media file
+///
+/// Uses the configured title if any, otherwise derives one from the file name.
+pub fn media_title(path: &Path, config: &Config) -> String {
+    if let Some(title) = &config.title {
+        return title.clone();
+    }
+
+    match path.file_name() {
+        Some(file_name) => {
+            clean_title_from_filename(&file_name.to_string_lossy(), config.clean_title)
+        }
+        None => DEFAULT_DLNA_VIDEO_TITLE.to_string(),
+    }
+}
+
 /// Builds the metadata XML for the media content
-pub fn build_metadata(streaming_server: &MediaStreamingServer) -> Result<String> {
-    let subtitle_uri = streaming_server.subtitle_uri();
+pub fn build_metadata(streaming_server: &MediaStreamingServer, title: &str) -> Result<String> {
+    let subtitles: Vec<CaptionTrack> = streaming_server
+        .subtitle_tracks()
+        .into_iter()
+        .map(|track| {
+            // Unrecognized subtitle files are advertised as SubRip, the most widely supported format
+            let format = track.format.unwrap_or(SubtitleType::Srt);
+            CaptionTrack {
+                uri: track.uri,
+                caption_type: format.extension().to_string(),
+                mime_type: format.protocol_mime_type().to_string(),
+            }
+        })
+        .collect();
 
-    let metadata = match subtitle_uri {
-        Some(subtitle_uri) => {
+    let metadata = match subtitles.first() {
+        Some(first) => {
             let template = DidlLiteWithSubtitlesTemplate {
-                title: DEFAULT_DLNA_VIDEO_TITLE.to_string(),
+                title: title.to_string(),
                 video_uri: streaming_server.video_uri(),
                 video_type: streaming_server.video_type(),
-                subtitle_uri,
-                subtitle_type: streaming_server
-                    .subtitle_type()
-                    .unwrap_or_else(|| "unknown".to_string()),
+                subtitle_uri: first.uri.clone(),
+                subtitle_type: first.caption_type.clone(),
+                subtitles,
             };
             template
                 .render()
@@ -64,7 +114,7 @@ pub fn build_metadata(streaming_server: &MediaStreamingServer) -> Result<String>
         }
         None => {
             let template = DidlLiteWithoutSubtitlesTemplate {
-                title: DEFAULT_DLNA_VIDEO_TITLE.to_string(),
+                title: title.to_string(),
                 video_uri: streaming_server.video_uri(),
                 video_type: streaming_server.video_type(),
             };
@@ -80,15 +130,51 @@ pub fn build_metadata(streaming_server: &MediaStreamingServer) -> Result<String>
     Ok(escape(metadata.as_str()).to_string())
 }
 
+/// Builds the metadata XML for the file pre-loaded after the current one, if any
+pub fn build_next_metadata(
+    streaming_server: &MediaStreamingServer,
+    title: &str,
+) -> Result<Option<String>> {
+    let (Some(next_uri), Some(next_type)) =
+        (streaming_server.next_uri(), streaming_server.next_type())
+    else {
+        return Ok(None);
+    };
+
+    let template = DidlLiteWithoutSubtitlesTemplate {
+        title: title.to_string(),
+        video_uri: next_uri,
+        video_type: next_type,
+    };
+    let metadata = template
+        .render()
+        .map_err(|e| crate::error::Error::TemplateRenderError {
+            template_name: "didl_lite_without_subtitles.xml".to_string(),
+            source: e.into(),
+        })?;
+
+    Ok(Some(escape(metadata.as_str()).to_string()))
+}
+
 /// Builds the SetAVTransportURI payload
 pub fn build_setavtransporturi_payload(
     streaming_server: &MediaStreamingServer,
     metadata: &str,
 ) -> Result<String> {
+    render_setavtransporturi_payload(streaming_server.video_uri(), metadata.to_string())
+}
+
+/// Builds a SetAVTransportURI payload with an empty URI, releasing the device
+pub fn build_clear_setavtransporturi_payload() -> Result<String> {
+    render_setavtransporturi_payload(String::new(), String::new())
+}
+
+/// Renders the SetAVTransportURI template
+fn render_setavtransporturi_payload(current_uri: String, metadata: String) -> Result<String> {
     let template = SetAvTransportUriTemplate {
         instance_id: DLNA_INSTANCE_ID,
-        current_uri: streaming_server.video_uri(),
-        current_uri_metadata: metadata.to_string(),
+        current_uri,
+        current_uri_metadata: metadata,
     };
 
     template
@@ -99,46 +185,251 @@ pub fn build_setavtransporturi_payload(
         })
 }
 
+/// Builds the SetNextAVTransportURI payload
+pub fn build_setnextavtransporturi_payload(next_uri: &str, metadata: &str) -> Result<String> {
+    let template = SetNextAvTransportUriTemplate {
+        instance_id: DLNA_INSTANCE_ID,
+        next_uri: next_uri.to_string(),
+        next_uri_metadata: metadata.to_string(),
+    };
+
+    template
+        .render()
+        .map_err(|e| crate::error::Error::TemplateRenderError {
+            template_name: "set_next_av_transport_uri.xml".to_string(),
+            source: e.into(),
+        })
+}
+
+/// Loads a template overriding a built-in one from a template directory
+///
+/// Returns `None` when no directory is given or it has no such file, in
+/// which case the built-in template applies.
+pub fn load_template_override(
+    template_dir: Option<&Path>,
+    file_name: &str,
+) -> Result<Option<String>> {
+    let Some(template_dir) = template_dir else {
+        return Ok(None);
+    };
+
+    let path = template_dir.join(file_name);
+    match std::fs::read_to_string(&path) {
+        Ok(template) => {
+            info!("Using template {}", path.display());
+            Ok(Some(template))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && template_dir.is_dir() => {
+            debug!(
+                "No {file_name} in {}, using the built-in template",
+                template_dir.display()
+            );
+            Ok(None)
+        }
+        Err(e) => Err(Error::TemplateRenderError {
+            template_name: path.display().to_string(),
+            source: e.into(),
+        }),
+    }
+}
+
+/// Renders a DIDL-Lite metadata template loaded from disk
+///
+/// Placeholders are `{title}`, `{video_uri}`, `{video_type}` and, empty for
+/// media without subtitles, `{subtitle_uri}`, `{subtitle_type}` and
+/// `{subtitle_mime_type}` of the first subtitle track.
+pub fn render_metadata_template(
+    template: &str,
+    streaming_server: &MediaStreamingServer,
+    title: &str,
+) -> String {
+    let subtitle = streaming_server.subtitle_tracks().into_iter().next();
+    let format = subtitle
+        .as_ref()
+        .map(|track| track.format.unwrap_or(SubtitleType::Srt));
+    let metadata = substitute_placeholders(
+        template,
+        &[
+            ("title", escape(title).into_owned()),
+            (
+                "video_uri",
+                escape(streaming_server.video_uri()).into_owned(),
+            ),
+            ("video_type", streaming_server.video_type()),
+            (
+                "subtitle_uri",
+                subtitle
+                    .map(|track| escape(track.uri).into_owned())
+                    .unwrap_or_default(),
+            ),
+            (
+                "subtitle_type",
+                format
+                    .map(|f| f.extension().to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "subtitle_mime_type",
+                format
+                    .map(|f| f.protocol_mime_type().to_string())
+                    .unwrap_or_default(),
+            ),
+        ],
+    );
+
+    escape(metadata.as_str()).to_string()
+}
+
+/// Renders a SetAVTransportURI template loaded from disk
+///
+/// Placeholders are `{instance_id}`, `{current_uri}` and
+/// `{current_uri_metadata}`, the latter already escaped.
+pub fn render_setavtransporturi_template(
+    template: &str,
+    streaming_server: &MediaStreamingServer,
+    metadata: &str,
+) -> String {
+    substitute_placeholders(
+        template,
+        &[
+            ("instance_id", DLNA_INSTANCE_ID.to_string()),
+            (
+                "current_uri",
+                escape(streaming_server.video_uri()).into_owned(),
+            ),
+            ("current_uri_metadata", metadata.to_string()),
+        ],
+    )
+}
+
+/// Replaces the `{name}` placeholders of a template in a single pass
+///
+/// Unknown placeholders are kept as they are, so braces in the XML are safe.
+fn substitute_placeholders(template: &str, values: &[(&str, String)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        output.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest.find('}').and_then(|close| {
+            let name = &rest[1..close];
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                output.push_str(value);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Formats the metadata sent to the device for a debug dump
+///
+/// Holds the DIDL-Lite metadata both unescaped, for reading, and escaped as
+/// sent, since escaping mistakes are a common reason for devices to reject it.
+pub fn format_metadata_dump(metadata: &str, setavtransporturi_payload: &str) -> String {
+    let unescaped = unescape(metadata).map_or_else(
+        |e| format!("<!-- failed to unescape: {e} -->"),
+        |metadata| metadata.into_owned(),
+    );
+    format!(
+        "<!-- DIDL-Lite metadata -->\n{unescaped}\n\n\
+         <!-- DIDL-Lite metadata, escaped as sent in CurrentURIMetaData -->\n{metadata}\n\n\
+         <!-- SetAVTransportURI payload -->\n{setavtransporturi_payload}\n"
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::media::MediaStreamingServer;
-    use std::path::PathBuf;
+    use crate::{
+        config::{DIDL_LITE_TEMPLATE_FILE, SET_AV_TRANSPORT_URI_TEMPLATE_FILE},
+        media::MediaStreamingServer,
+        utils::testing::TestDir,
+    };
+    use quick_xml::events::Event;
 
     /// Create a test MediaStreamingServer for testing
     fn create_test_streaming_server(with_subtitle: bool) -> MediaStreamingServer {
-        let video_path = PathBuf::from("test_video.mp4");
-        let subtitle_path = if with_subtitle {
-            Some(PathBuf::from("test_subtitle.srt"))
-        } else {
-            None
-        };
+        let dir = TestDir::new("crab_dlna_meta_server");
+        let video_path = dir.create_file("test_video.mp4", b"fake video content");
+        let subtitle_path =
+            with_subtitle.then(|| dir.create_file("test_subtitle.srt", b"fake subtitle content"));
         let host_ip = "192.168.1.100".to_string();
         let host_port = 9000;
 
-        // Create a temporary video file for testing
-        std::fs::write(&video_path, b"fake video content").unwrap();
+        MediaStreamingServer::new(&video_path, &subtitle_path, &host_ip, &host_port).unwrap()
+    }
 
-        if with_subtitle {
-            std::fs::write(subtitle_path.as_ref().unwrap(), b"fake subtitle content").unwrap();
-        }
+    #[test]
+    fn test_substitute_placeholders() {
+        let values = [
+            ("title", "{video_uri}".to_string()),
+            ("video_uri", "http://host/video.mp4".to_string()),
+        ];
+        assert_eq!(
+            substitute_placeholders("<a>{title}</a><b>{video_uri}</b>{unknown}{", &values),
+            "<a>{video_uri}</a><b>http://host/video.mp4</b>{unknown}{"
+        );
+    }
 
-        let server =
-            MediaStreamingServer::new(&video_path, &subtitle_path, &host_ip, &host_port).unwrap();
+    #[test]
+    fn test_render_metadata_template() {
+        let streaming_server = create_test_streaming_server(true);
+        let template =
+            "<dc:title>{title}</dc:title><res>{video_uri}</res><sub>{subtitle_type}</sub>";
+        let metadata = render_metadata_template(template, &streaming_server, "Tom & Jerry");
 
-        // Clean up test files
-        std::fs::remove_file(&video_path).ok();
-        if let Some(sub_path) = subtitle_path {
-            std::fs::remove_file(&sub_path).ok();
-        }
+        let metadata = unescape(&metadata).unwrap();
+        assert!(metadata.contains("<dc:title>Tom &amp; Jerry</dc:title>"));
+        assert!(metadata.contains("<res>http://192.168.1.100:9000/"));
+        assert!(metadata.contains("<sub>srt</sub>"));
+    }
+
+    #[test]
+    fn test_load_template_override_falls_back() {
+        let template_dir = TestDir::new("crab_dlna_template_dir");
+        std::fs::write(template_dir.join(DIDL_LITE_TEMPLATE_FILE), "<DIDL-Lite/>").unwrap();
 
-        server
+        let didl_lite = load_template_override(Some(&template_dir), DIDL_LITE_TEMPLATE_FILE);
+        assert_eq!(didl_lite.unwrap().as_deref(), Some("<DIDL-Lite/>"));
+        let payload =
+            load_template_override(Some(&template_dir), SET_AV_TRANSPORT_URI_TEMPLATE_FILE);
+        assert_eq!(payload.unwrap(), None);
+        assert!(
+            load_template_override(None, DIDL_LITE_TEMPLATE_FILE)
+                .unwrap()
+                .is_none()
+        );
+
+        let missing_dir = template_dir.join("missing");
+        assert!(load_template_override(Some(&missing_dir), DIDL_LITE_TEMPLATE_FILE).is_err());
+    }
+
+    #[test]
+    fn test_format_metadata_dump() {
+        let metadata = escape(r#"<dc:title>Tom &amp; Jerry</dc:title>"#).to_string();
+        let dump = format_metadata_dump(&metadata, "<CurrentURIMetaData/>");
+
+        assert!(dump.contains("<dc:title>Tom &amp; Jerry</dc:title>\n"));
+        assert!(dump.contains("&lt;dc:title&gt;Tom &amp;amp; Jerry&lt;/dc:title&gt;"));
+        assert!(dump.ends_with("<!-- SetAVTransportURI payload -->\n<CurrentURIMetaData/>\n"));
     }
 
     #[test]
     fn test_metadata_without_subtitles() {
         let streaming_server = create_test_streaming_server(false);
-        let result = build_metadata(&streaming_server);
+        let result = build_metadata(&streaming_server, "crab-dlna Video");
 
         assert!(result.is_ok());
         let metadata = result.unwrap();
@@ -157,7 +448,7 @@ mod tests {
     #[test]
     fn test_metadata_with_subtitles() {
         let streaming_server = create_test_streaming_server(true);
-        let result = build_metadata(&streaming_server);
+        let result = build_metadata(&streaming_server, "crab-dlna Video");
 
         assert!(result.is_ok());
         let metadata = result.unwrap();
@@ -173,6 +464,117 @@ mod tests {
         assert!(metadata.contains("subtitleFileUri"));
     }
 
+    /// Builds metadata for a video with a subtitle file of the given extension
+    fn build_metadata_with_subtitle_extension(extension: &str) -> String {
+        let dir = TestDir::new("crab_dlna_meta_subtitle_extension");
+        let video_path = dir.create_file("video.mp4", b"fake video content");
+        let subtitle_path =
+            dir.create_file(&format!("video.{extension}"), b"fake subtitle content");
+
+        let server = MediaStreamingServer::new(
+            &video_path,
+            &Some(subtitle_path),
+            &"192.168.1.100".to_string(),
+            &9000,
+        )
+        .unwrap();
+        build_metadata(&server, "crab-dlna Video").unwrap()
+    }
+
+    #[test]
+    fn test_metadata_subtitle_protocol_info_per_type() {
+        let cases = [
+            ("srt", "text/srt", "srt"),
+            ("vtt", "text/vtt", "vtt"),
+            ("ass", "smi/caption", "ass"),
+            ("ssa", "smi/caption", "ssa"),
+        ];
+
+        for (extension, mime_type, caption_type) in cases {
+            let metadata = build_metadata_with_subtitle_extension(extension);
+            assert!(
+                metadata.contains(&format!("http-get:*:{mime_type}:*")),
+                "missing {mime_type} protocolInfo for .{extension}"
+            );
+            assert!(
+                metadata.contains(&format!("sec:type=&quot;{caption_type}&quot;")),
+                "missing {caption_type} caption type for .{extension}"
+            );
+        }
+
+        let metadata = build_metadata_with_subtitle_extension("vtt");
+        assert!(!metadata.contains("text/srt"));
+        assert!(!metadata.contains("smi/caption"));
+    }
+
+    #[test]
+    fn test_metadata_with_multiple_subtitle_tracks() {
+        let dir = TestDir::new("crab_dlna_meta_tracks");
+        let video_path = dir.create_file("video.mp4", b"fake content");
+        let english = dir.create_file("video.en.srt", b"fake content");
+        let french = dir.create_file("video.fr.vtt", b"fake content");
+
+        let server = MediaStreamingServer::new(
+            &video_path,
+            &Some(english.clone()),
+            &"192.168.1.100".to_string(),
+            &9000,
+        )
+        .unwrap()
+        .with_subtitle(&french)
+        .unwrap();
+        let metadata = build_metadata(&server, "crab-dlna Video").unwrap();
+        let tracks = server.subtitle_tracks();
+
+        assert_eq!(metadata.matches("subtitleFileUri").count(), 1);
+        assert_eq!(metadata.matches("sec:CaptionInfoEx ").count(), 2);
+        assert_eq!(metadata.matches("sec:CaptionInfo ").count(), 2);
+        assert!(metadata.contains(&format!(
+            "subtitleFileUri=&quot;{}&quot; pv:subtitleFileType=&quot;srt&quot;",
+            tracks[0].uri
+        )));
+        assert!(metadata.contains("http-get:*:text/srt:*"));
+        assert!(metadata.contains(&format!(
+            "sec:type=&quot;vtt&quot;&gt;{}&lt;",
+            tracks[1].uri
+        )));
+    }
+
+    #[test]
+    fn test_media_title() {
+        let path = Path::new("/videos/test_video.mp4");
+        assert_eq!(media_title(path, &Config::default()), "test video");
+
+        let config = Config::default().with_title(Some("Holiday Movie".to_string()));
+        assert_eq!(media_title(path, &config), "Holiday Movie");
+    }
+
+    #[test]
+    fn test_next_metadata_and_payload() {
+        let streaming_server = create_test_streaming_server(false);
+        assert!(
+            build_next_metadata(&streaming_server, "Next")
+                .unwrap()
+                .is_none()
+        );
+
+        let dir = TestDir::new("crab_dlna_next_episode");
+        let next_path = dir.create_file("next_episode.mkv", b"next");
+        let streaming_server = streaming_server.with_next_file(&next_path);
+        let metadata = build_next_metadata(&streaming_server, "Next Episode")
+            .unwrap()
+            .unwrap();
+        assert!(metadata.contains("Next Episode"));
+        assert!(metadata.contains("video/x-matroska"));
+
+        let payload =
+            build_setnextavtransporturi_payload(&streaming_server.next_uri().unwrap(), &metadata)
+                .unwrap();
+        assert!(payload.contains("<InstanceID>0</InstanceID>"));
+        assert!(payload.contains("<NextURI>http://192.168.1.100:9000/"));
+        assert!(payload.contains("<NextURIMetaData>&lt;DIDL-Lite"));
+    }
+
     #[test]
     fn test_setavtransporturi_payload() {
         let streaming_server = create_test_streaming_server(false);
@@ -187,12 +589,17 @@ mod tests {
         assert!(payload.contains("<CurrentURI>"));
         assert!(payload.contains("192.168.1.100:9000")); // Check for the host/port instead
         assert!(payload.contains("<CurrentURIMetaData>test metadata</CurrentURIMetaData>"));
+
+        // Metadata is escaped once by build_metadata, and must not be escaped again
+        let metadata = build_metadata(&streaming_server, "crab-dlna Video").unwrap();
+        let payload = build_setavtransporturi_payload(&streaming_server, &metadata).unwrap();
+        assert!(payload.contains("<CurrentURIMetaData>&lt;DIDL-Lite"));
     }
 
     #[test]
     fn test_xml_escaping() {
         let streaming_server = create_test_streaming_server(false);
-        let result = build_metadata(&streaming_server);
+        let result = build_metadata(&streaming_server, "crab-dlna Video");
 
         assert!(result.is_ok());
         let metadata = result.unwrap();
@@ -202,4 +609,67 @@ mod tests {
         assert!(!metadata.contains("<DIDL-Lite")); // Should be escaped
         assert!(metadata.contains("&lt;DIDL-Lite")); // Should be escaped
     }
+
+    /// Parses an XML document, returning the text of its `dc:title` element
+    fn parse_title(xml: &str) -> String {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut title: Option<String> = None;
+        let mut in_title = false;
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(start)) => in_title = start.name().as_ref() == b"dc:title",
+                Ok(Event::Text(text)) if in_title => {
+                    title
+                        .get_or_insert_default()
+                        .push_str(&text.decode().unwrap());
+                }
+                Ok(Event::GeneralRef(entity)) if in_title => {
+                    let entity = format!("&{};", entity.decode().unwrap());
+                    title
+                        .get_or_insert_default()
+                        .push_str(&unescape(&entity).unwrap());
+                }
+                Ok(Event::End(_)) => in_title = false,
+                Ok(Event::Eof) => break,
+                Ok(_) => {}
+                Err(e) => panic!("malformed XML ({e}): {xml}"),
+            }
+        }
+        title.expect("no dc:title element")
+    }
+
+    #[test]
+    fn test_special_characters_keep_metadata_well_formed() {
+        let title = r#"Tom & Jerry <"O'Neil">"#;
+        let dir = TestDir::new("crab_dlna_meta_special_chars");
+        // `<`, `>` and `"` can't be in file names on Windows, the title has them
+        let video_path = dir.join("Tom & Jerry's #1 100%.mp4");
+        let subtitle_path = dir.join("Tom & Jerry's #1 100%.srt");
+        std::fs::write(&video_path, b"fake video content").unwrap();
+        std::fs::write(&subtitle_path, b"fake subtitle content").unwrap();
+
+        for subtitle_path in [None, Some(subtitle_path)] {
+            let server = MediaStreamingServer::new(
+                &video_path,
+                &subtitle_path,
+                &"192.168.1.100".to_string(),
+                &9000,
+            )
+            .unwrap();
+            let metadata = build_metadata(&server, title).unwrap();
+
+            let didl_lite = unescape(&metadata).unwrap();
+            assert_eq!(parse_title(&didl_lite), title);
+            let payload = build_setavtransporturi_payload(&server, &metadata).unwrap();
+            let mut reader = quick_xml::Reader::from_str(&payload);
+            while reader.read_event().expect("malformed payload") != Event::Eof {}
+
+            let custom = render_metadata_template(
+                "<dc:title>{title}</dc:title><res>{video_uri}</res>",
+                &server,
+                title,
+            );
+            assert_eq!(parse_title(&unescape(&custom).unwrap()), title);
+        }
+    }
 }
@@ -4,12 +4,23 @@
 //! - Media playback control (play, pause, resume)
 //! - Metadata generation for media files
 //! - Transport state management
+//! - Telling the end of the media from the device being stopped
+//! - A local playback clock for devices that don't report their position
 //! - Subtitle synchronization support
 
 pub mod actions;
+pub mod clock;
+pub mod end_reason;
+pub mod events;
 pub mod metadata;
 pub mod playback;
 
 // Re-export main functions for backward compatibility
-pub use actions::{pause, resume, toggle_play_pause};
-pub use playback::play;
+pub use actions::{
+    adjust_volume, get_mute, get_volume, next_track, pause, play_at_speed, previous_track, resume,
+    seek, seek_relative, set_volume, stop, toggle_play_pause,
+};
+pub use clock::PlaybackClock;
+pub use end_reason::{EndReason, PlaybackEndDetector};
+pub use events::{FailedFile, PlaybackEvent};
+pub use playback::{play, play_until_stopped};
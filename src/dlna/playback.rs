@@ -5,136 +5,514 @@
 
 use crate::{
     config::{
-        Config, DLNA_ACTION_SET_AV_TRANSPORT_URI, LOG_MSG_PLAYING_VIDEO, LOG_MSG_SETTING_VIDEO_URI,
-        MEDIA_PLAYBACK_FAILED_MSG,
+        Config, DIDL_LITE_TEMPLATE_FILE, DLNA_ACTION_SET_AV_TRANSPORT_URI,
+        DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI, LOG_MSG_PLAYING_VIDEO, LOG_MSG_SETTING_VIDEO_URI,
+        MAX_TRANSITION_RETRIES, MEDIA_PLAYBACK_FAILED_MSG, PAUSE_THROTTLE_POLL_INTERVAL_MS,
+        PLAYBACK_END_POLL_INTERVAL_MS, SET_AV_TRANSPORT_URI_TEMPLATE_FILE,
+        TRANSITION_RETRY_DELAY_MS, UPNP_ERROR_TRANSITION_NOT_AVAILABLE,
     },
     devices::Render,
     error::{Error, Result},
-    media::{MediaStreamingServer, SubtitleSyncer},
-    utils::retry_with_backoff,
+    media::{MediaStreamingServer, ReadThrottle, SubtitleSyncer},
+    utils::{
+        format_action_response, format_dlna_time, retry_while, retry_with_backoff,
+        try_time_str_to_milliseconds, upnp_error_code,
+    },
 };
-use log::{debug, info};
+use log::{debug, info, trace, warn};
 use std::time::Duration;
-use tokio::time::interval;
-
-use super::metadata::{build_metadata, build_setavtransporturi_payload};
+use tokio::{task::JoinHandle, time::interval};
 
-/// Builds a DLNA play payload with configurable parameters
-fn build_play_payload(instance_id: u32, speed: u32) -> String {
-    format!(
-        r#"
-    <InstanceID>{instance_id}</InstanceID>
-    <Speed>{speed}</Speed>
-"#
-    )
-}
+use super::{
+    PlaybackClock,
+    actions::{build_play_payload, seek, stop},
+    end_reason::{EndReason, PlaybackEndDetector},
+    events::{PlaybackEvent, spawn_event_reporter},
+    metadata::{
+        build_metadata, build_next_metadata, build_setavtransporturi_payload, format_metadata_dump,
+        load_template_override, media_title, render_metadata_template,
+        render_setavtransporturi_template,
+    },
+};
 
 /// Plays a media file in a DLNA compatible device render, according to the render and media streaming server provided
+///
+/// Returns once the device has stopped playing the file. The streaming server
+/// is shut down then, unless `config.keep_serving` is set, in which case it
+/// keeps serving re-requests until the process is interrupted.
 pub async fn play(
     render: Render,
     streaming_server: MediaStreamingServer,
     subtitle_syncer: Option<SubtitleSyncer>,
     config: &Config,
 ) -> Result<()> {
-    let metadata = build_metadata(&streaming_server)?;
+    play_until_stopped(render, streaming_server, subtitle_syncer, config)
+        .await
+        .map(|_| ())
+}
+
+/// Plays a media file like [`play`], telling why the device stopped playing it
+///
+/// See [`PlaybackEndDetector`] for how the end of the media is told apart
+/// from the device being stopped early.
+pub async fn play_until_stopped(
+    render: Render,
+    streaming_server: MediaStreamingServer,
+    subtitle_syncer: Option<SubtitleSyncer>,
+    config: &Config,
+) -> Result<EndReason> {
+    let render = render.with_play_speed(config.play_speed.clone());
+    let title = media_title(streaming_server.video_file_path(), config);
+    let template_dir = config.template_dir.as_deref();
+    let metadata = match load_template_override(template_dir, DIDL_LITE_TEMPLATE_FILE)? {
+        Some(template) => render_metadata_template(&template, &streaming_server, &title),
+        None => build_metadata(&streaming_server, &title)?,
+    };
+
+    let next_metadata = match streaming_server.next_file_path() {
+        Some(next_path) => build_next_metadata(&streaming_server, &media_title(next_path, config))?,
+        None => None,
+    };
+    let next_uri = streaming_server.next_uri();
     debug!("Metadata: '{metadata}'");
 
-    let setavtransporturi_payload = build_setavtransporturi_payload(&streaming_server, &metadata)?;
+    let setavtransporturi_payload =
+        match load_template_override(template_dir, SET_AV_TRANSPORT_URI_TEMPLATE_FILE)? {
+            Some(template) => {
+                render_setavtransporturi_template(&template, &streaming_server, &metadata)
+            }
+            None => build_setavtransporturi_payload(&streaming_server, &metadata)?,
+        };
     debug!("SetAVTransportURI payload: '{setavtransporturi_payload}'");
+    if let Some(path) = &config.output_metadata {
+        let dump = format_metadata_dump(&metadata, &setavtransporturi_payload);
+        match std::fs::write(path, dump) {
+            Ok(()) => info!("Wrote metadata to {}", path.display()),
+            Err(e) => warn!("Failed to write metadata to {}: {e}", path.display()),
+        }
+    }
 
-    // Get the video URI before moving streaming_server
+    // Get the video URI and path before moving streaming_server
     let video_uri = streaming_server.video_uri();
+    let video_file = streaming_server.video_file_path().display().to_string();
+    let read_throttle = streaming_server.read_throttle().cloned();
 
     info!("Starting media streaming server...");
-    let streaming_server_handle = tokio::spawn(async move { streaming_server.run().await });
+    let listener = streaming_server.bind().await?;
+    let mut streaming_server_handle =
+        tokio::spawn(async move { streaming_server.serve(listener).await });
+
+    // Casting again would restart playback, e.g. when the command is re-run
+    let already_playing = !config.force_recast && is_playing_uri(&render, &video_uri).await;
+    if already_playing {
+        info!(
+            "Device is already playing {video_uri}, not casting it again (use --force to restart)"
+        );
+    } else if let Err(e) = start_playback(&render, &setavtransporturi_payload, &video_uri).await {
+        // A device left with a URI it failed to play may stay on a loading screen
+        render.stop_and_clear().await;
+        streaming_server_handle.abort();
+        return Err(e);
+    }
+
+    if let (Some(next_uri), Some(next_metadata)) = (next_uri, next_metadata) {
+        preload_next_uri(&render, &next_uri, &next_metadata).await;
+    }
+
+    // Play only a clip of the file, if requested
+    let clip_end_handle = match (config.clip_start_ms, config.clip_end_ms) {
+        (None, None) => None,
+        (start_ms, end_ms) => {
+            let start_ms = start_clip(&render, start_ms, end_ms).await;
+            end_ms.map(|end_ms| spawn_clip_end_watcher(render.clone(), start_ms, end_ms))
+        }
+    };
+
+    // Start reporting playback events if enabled
+    let event_reporter_handle = if config.events_json {
+        PlaybackEvent::Playing {
+            file: video_file.clone(),
+            uri: video_uri.clone(),
+        }
+        .emit();
+        Some(spawn_event_reporter(render.clone(), video_file))
+    } else {
+        None
+    };
+
+    // Hold back disk reads while the device is paused, if enabled
+    let pause_throttle_handle =
+        read_throttle.map(|throttle| spawn_pause_throttle(render.clone(), throttle));
+
+    // Start subtitle synchronization task if enabled
+    let subtitle_sync_handle = if let Some(mut syncer) = subtitle_syncer {
+        info!("Starting subtitle synchronization...");
+        syncer.start_clock();
+        let render_clone = render.clone();
+        let sync_interval_ms = config.subtitle_sync_interval_ms;
+        Some(tokio::spawn(async move {
+            let mut interval = interval(Duration::from_millis(sync_interval_ms));
+            let mut position_unavailable = false;
+            loop {
+                interval.tick().await;
+
+                // Get playback position, `None` if the device doesn't report it
+                let device_position_ms = match position_unavailable {
+                    true => None,
+                    false => match render_clone.get_position_info().await {
+                        Ok(position_info) => try_time_str_to_milliseconds(&position_info.rel_time),
+                        Err(Error::DlnaActionNotSupported { .. }) => {
+                            info!(
+                                "Device does not report the playback position, following it with a local clock"
+                            );
+                            position_unavailable = true;
+                            None
+                        }
+                        Err(e) => {
+                            warn!("Failed to get position info: {e}");
+                            None
+                        }
+                    },
+                };
+
+                // Without a device position, the local clock has to follow pauses
+                if device_position_ms.is_none()
+                    && let Ok(transport_info) = render_clone.get_transport_info().await
+                {
+                    syncer.apply_transport_state(&transport_info.transport_state);
+                }
+
+                // Update subtitle content in clipboard and output file
+                if let Some(position_ms) = syncer.position_ms(device_position_ms)
+                    && let Err(e) = syncer.sync(position_ms)
+                {
+                    warn!("Failed to synchronize subtitle: {e}");
+                }
+            }
+        }))
+    } else {
+        None
+    };
+
+    // The server only ends on its own when it fails
+    let detector = PlaybackEndDetector::new(already_playing).with_end(config.clip_end_ms);
+    let mut end_reason = EndReason::EndOfMedia;
+    let server_result = tokio::select! {
+        result = &mut streaming_server_handle => Some(result),
+        reason = wait_for_playback_end(&render, detector) => {
+            end_reason = reason;
+            None
+        }
+    };
+
+    // Cancel subtitle synchronization, event reporting, throttling and clip tasks
+    if let Some(handle) = subtitle_sync_handle {
+        handle.abort();
+    }
+    if let Some(handle) = clip_end_handle {
+        handle.abort();
+    }
+    if let Some(handle) = pause_throttle_handle {
+        handle.abort();
+    }
+    if let Some(handle) = event_reporter_handle {
+        handle.abort();
+    }
 
+    let server_result = match server_result {
+        Some(result) => {
+            // The device can no longer fetch the media, release it
+            render.stop_and_clear().await;
+            result
+        }
+        None if config.keep_serving => {
+            info!("Playback finished, still serving {video_uri} until interrupted");
+            streaming_server_handle.await
+        }
+        None => {
+            info!("Playback finished, stopping media streaming server");
+            streaming_server_handle.abort();
+            // Wait for the server to release its port, so the next file can use it
+            let _ = streaming_server_handle.await;
+            return Ok(end_reason);
+        }
+    };
+    server_result.map_err(|err| Error::StreamingServerError {
+        source: err,
+        context: "Media streaming server encountered an error".to_string(),
+    })??;
+    Ok(end_reason)
+}
+
+/// Whether the device is currently playing the given URI
+///
+/// Devices not reporting their track URI are never considered to play it.
+async fn is_playing_uri(render: &Render, uri: &str) -> bool {
+    let playing = match render.get_transport_info().await {
+        Ok(transport_info) => transport_info.transport_state == "PLAYING",
+        Err(e) => {
+            debug!("Failed to get transport info before casting: {e}");
+            false
+        }
+    };
+    if !playing {
+        return false;
+    }
+
+    match render.get_position_info().await {
+        Ok(position_info) => position_info.track_uri == uri,
+        Err(e) => {
+            debug!("Failed to get position info before casting: {e}");
+            false
+        }
+    }
+}
+
+/// Loads the media URI into the device and starts playing it
+async fn start_playback(
+    render: &Render,
+    setavtransporturi_payload: &str,
+    video_uri: &str,
+) -> Result<()> {
     info!("{LOG_MSG_SETTING_VIDEO_URI}");
-    retry_with_backoff(
+    trace!("SetAVTransportURI request: {setavtransporturi_payload}");
+    // Each attempt is timed, so that a retry follows an attempt the device never answered
+    let response = retry_with_backoff(
         || async {
             render
-                .service
-                .action(
-                    render.device.url(),
+                .timed(
                     DLNA_ACTION_SET_AV_TRANSPORT_URI,
-                    setavtransporturi_payload.as_str(),
+                    render.service.action(
+                        render.url(),
+                        DLNA_ACTION_SET_AV_TRANSPORT_URI,
+                        setavtransporturi_payload,
+                    ),
                 )
-                .await
+                .await?
+                .map_err(|err| Error::DlnaSetTransportUriFailed {
+                    source: err,
+                    uri: video_uri.to_string(),
+                })
         },
         "SetAVTransportURI",
     )
-    .await
-    .map_err(|err| Error::DlnaSetTransportUriFailed {
-        source: err,
-        uri: video_uri.clone(),
-    })?;
+    .await?;
+    trace!(
+        "SetAVTransportURI response: {}",
+        format_action_response(&response)
+    );
 
     info!("{LOG_MSG_PLAYING_VIDEO}");
-    let play_payload = build_play_payload(
-        crate::config::DLNA_INSTANCE_ID,
-        crate::config::DLNA_DEFAULT_SPEED,
-    );
-    retry_with_backoff(
+    let play_payload = build_play_payload(crate::config::DLNA_INSTANCE_ID, render.play_speed());
+    // Renders still processing the new URI reject Play with "transition not
+    // available"; they only need a moment, while other errors won't go away
+    trace!("Play request: {play_payload}");
+    let response = retry_while(
         || async {
             render
-                .service
-                .action(
-                    render.device.url(),
+                .timed(
                     crate::config::DLNA_ACTION_PLAY,
-                    &play_payload,
+                    render.service.action(
+                        render.url(),
+                        crate::config::DLNA_ACTION_PLAY,
+                        &play_payload,
+                    ),
                 )
-                .await
+                .await?
+                .map_err(|err| Error::DlnaPlaybackFailed {
+                    source: err,
+                    context: MEDIA_PLAYBACK_FAILED_MSG.to_string(),
+                })
         },
         "Play",
+        |err| {
+            matches!(err, Error::DlnaPlaybackFailed { source, .. }
+                if upnp_error_code(source) == Some(UPNP_ERROR_TRANSITION_NOT_AVAILABLE))
+        },
+        MAX_TRANSITION_RETRIES,
+        Duration::from_millis(TRANSITION_RETRY_DELAY_MS),
     )
-    .await
-    .map_err(|err| Error::DlnaPlaybackFailed {
-        source: err,
-        context: MEDIA_PLAYBACK_FAILED_MSG.to_string(),
-    })?;
+    .await?;
+    trace!("Play response: {}", format_action_response(&response));
+    Ok(())
+}
 
-    // Start subtitle synchronization task if enabled
-    let subtitle_sync_handle = if let Some(mut syncer) = subtitle_syncer {
-        info!("Starting subtitle synchronization...");
-        let render_clone = render.clone();
-        let sync_interval_ms = config.subtitle_sync_interval_ms;
-        Some(tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(sync_interval_ms));
-            loop {
-                interval.tick().await;
+/// Waits until the device has stopped playing, telling why
+///
+/// Playback is over once the device reports a stopped transport after it was
+/// seen playing, which is either the end of the media or a stop command.
+/// Failed status queries are ignored, as the device may answer again later.
+async fn wait_for_playback_end(render: &Render, mut detector: PlaybackEndDetector) -> EndReason {
+    let mut interval = interval(Duration::from_millis(PLAYBACK_END_POLL_INTERVAL_MS));
+    let mut position_supported = true;
+    loop {
+        interval.tick().await;
+        match render.get_transport_info().await {
+            Ok(transport_info) => {
+                if let Some(reason) =
+                    detector.observe_transport_state(&transport_info.transport_state)
+                {
+                    return reason;
+                }
+            }
+            Err(e) => debug!("Failed to get transport info while waiting for playback end: {e}"),
+        }
 
-                // Get playback position
-                match render_clone.get_position_info().await {
-                    Ok(position_info) => {
-                        // Convert time format to milliseconds
-                        let position_ms =
-                            crate::utils::time_str_to_milliseconds(&position_info.rel_time);
+        if position_supported {
+            match render.get_position_info().await {
+                Ok(position_info) => detector.observe_position(&position_info),
+                Err(Error::DlnaActionNotSupported { .. }) => position_supported = false,
+                Err(e) => debug!("Failed to get position info while waiting for playback end: {e}"),
+            }
+        }
+    }
+}
 
-                        // Update subtitle content in clipboard
-                        if let Err(e) = syncer.update_clipboard(position_ms) {
-                            eprintln!("Failed to update clipboard: {e}");
-                        }
+/// Seeks to the start of a clip, checking the clip against the track duration
+///
+/// Renders still processing the new URI reject the seek with "transition not
+/// available", so it is retried for a moment.
+///
+/// # Returns
+/// Returns the position playback starts from, in milliseconds
+async fn start_clip(render: &Render, start_ms: Option<u64>, end_ms: Option<u64>) -> u64 {
+    let duration_ms = render
+        .get_position_info()
+        .await
+        .ok()
+        .and_then(|position_info| position_info.duration_ms())
+        .filter(|duration_ms| *duration_ms > 0);
+    if let (Some(end_ms), Some(duration_ms)) = (end_ms, duration_ms)
+        && end_ms > duration_ms
+    {
+        warn!(
+            "Clip end {} is past the end of the track ({}), playing to the end",
+            format_dlna_time(end_ms),
+            format_dlna_time(duration_ms)
+        );
+    }
+
+    let Some(start_ms) = start_ms.filter(|start_ms| *start_ms > 0) else {
+        return 0;
+    };
+    if let Some(duration_ms) = duration_ms
+        && start_ms >= duration_ms
+    {
+        warn!(
+            "Clip start {} is past the end of the track ({}), playing from the beginning",
+            format_dlna_time(start_ms),
+            format_dlna_time(duration_ms)
+        );
+        return 0;
+    }
+
+    let mut attempt = 0;
+    loop {
+        match seek(render, start_ms).await {
+            Ok(()) => {
+                info!("Starting clip at {}", format_dlna_time(start_ms));
+                return start_ms;
+            }
+            Err(Error::DlnaActionFailed { source, .. })
+                if upnp_error_code(&source) == Some(UPNP_ERROR_TRANSITION_NOT_AVAILABLE)
+                    && attempt < MAX_TRANSITION_RETRIES =>
+            {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(TRANSITION_RETRY_DELAY_MS)).await;
+            }
+            Err(e) => {
+                warn!("Failed to seek to the start of the clip: {e}");
+                return 0;
+            }
+        }
+    }
+}
+
+/// Stops playback once the end of a clip is reached
+///
+/// The position is polled from the device, falling back to a local clock
+/// started at the clip start on devices that don't report it.
+fn spawn_clip_end_watcher(render: Render, start_ms: u64, end_ms: u64) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut clock = PlaybackClock::started();
+        clock.seek(start_ms);
+        let mut interval = interval(Duration::from_millis(PLAYBACK_END_POLL_INTERVAL_MS));
+        let mut position_unavailable = false;
+        loop {
+            interval.tick().await;
+
+            let device_position_ms = match position_unavailable {
+                true => None,
+                false => match render.get_position_info().await {
+                    Ok(position_info) => position_info.rel_time_ms(),
+                    Err(Error::DlnaActionNotSupported { .. }) => {
+                        position_unavailable = true;
+                        None
                     }
                     Err(e) => {
-                        eprintln!("Failed to get position info: {e}");
+                        debug!("Failed to get position info for the clip end: {e}");
+                        None
                     }
+                },
+            };
+            if device_position_ms.is_none()
+                && let Ok(transport_info) = render.get_transport_info().await
+            {
+                clock.apply_transport_state(&transport_info.transport_state);
+            }
+
+            if clock.reconcile(device_position_ms) >= end_ms {
+                info!(
+                    "Reached the end of the clip at {}",
+                    format_dlna_time(end_ms)
+                );
+                if let Err(e) = stop(&render).await {
+                    warn!("Failed to stop playback at the end of the clip: {e}");
                 }
+                return;
             }
-        }))
-    } else {
-        None
-    };
+        }
+    })
+}
 
-    streaming_server_handle
-        .await
-        .map_err(|err| Error::StreamingServerError {
-            source: err,
-            context: "Media streaming server encountered an error".to_string(),
-        })?;
+/// Holds back the disk reads of the streaming server while the device is paused
+fn spawn_pause_throttle(render: Render, throttle: ReadThrottle) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_millis(PAUSE_THROTTLE_POLL_INTERVAL_MS));
+        loop {
+            interval.tick().await;
+            match render.get_transport_info().await {
+                Ok(transport_info) => {
+                    throttle.set_paused(transport_info.transport_state == "PAUSED_PLAYBACK")
+                }
+                // Reads are never held back on a device whose state is unknown
+                Err(e) => {
+                    debug!("Failed to get transport info for throttling: {e}");
+                    throttle.set_paused(false);
+                }
+            }
+        }
+    })
+}
 
-    // Cancel subtitle synchronization task
-    if let Some(handle) = subtitle_sync_handle {
-        handle.abort();
+/// Pre-loads the next file on devices supporting gapless playback
+///
+/// Devices lacking `SetNextAVTransportURI` keep the regular flow, where the
+/// next file is loaded once the current one has finished.
+async fn preload_next_uri(render: &Render, next_uri: &str, next_metadata: &str) {
+    if !render
+        .supports_action(DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI)
+        .await
+    {
+        debug!("Device does not support {DLNA_ACTION_SET_NEXT_AV_TRANSPORT_URI}, not pre-loading");
+        return;
     }
 
-    Ok(())
+    match render
+        .set_next_av_transport_uri(next_uri, next_metadata)
+        .await
+    {
+        Ok(()) => info!("Pre-loaded next file: {next_uri}"),
+        Err(e) => debug!("Failed to pre-load next file: {e}"),
+    }
 }
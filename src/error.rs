@@ -1,4 +1,10 @@
-use crate::devices::RenderSpec;
+use crate::{
+    config::{
+        EXIT_CODE_DEVICE_NOT_FOUND, EXIT_CODE_FAILURE, EXIT_CODE_INTERRUPTED,
+        EXIT_CODE_MEDIA_NOT_FOUND, EXIT_CODE_NETWORK, EXIT_CODE_PLAYBACK_FAILED,
+    },
+    devices::RenderSpec,
+};
 use std::fmt;
 
 /// Errors that can happen inside crab-dlna
@@ -26,6 +32,13 @@ pub enum Error {
         /// The underlying UPnP error
         source: rupnp::Error,
     },
+    /// Failed to fetch a description document from a device
+    DeviceDescriptionFetchFailed {
+        /// The URL of the document that failed to be fetched
+        url: String,
+        /// The underlying UPnP error
+        source: rupnp::Error,
+    },
     /// The specified render device was not found
     RenderNotFound {
         /// The render specification that was searched for
@@ -33,6 +46,13 @@ pub enum Error {
         /// Additional context about the search
         context: String,
     },
+    /// A device query could not be parsed
+    InvalidDeviceQuery {
+        /// The rejected query
+        query: String,
+        /// The underlying regular expression error
+        source: regex::Error,
+    },
 
     // Streaming and network errors
     /// Failed to parse host or IP address
@@ -41,6 +61,13 @@ pub enum Error {
         address: String,
         /// The reason for the parsing failure
         reason: String,
+        /// The underlying parse error
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The streaming host address cannot be reached by other devices
+    UnreachableStreamingAddress {
+        /// The rejected address
+        address: String,
     },
     /// Media file does not exist or is not accessible
     MediaFileNotFound {
@@ -49,6 +76,20 @@ pub enum Error {
         /// Additional context about the file access attempt
         context: String,
     },
+    /// Media file exists but cannot be opened for reading
+    MediaFileNotReadable {
+        /// Path to the unreadable file
+        path: String,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+    /// Media file is empty or its header does not match its format
+    MediaFileInvalid {
+        /// Path to the invalid file
+        path: String,
+        /// Why the file was rejected
+        reason: String,
+    },
     /// Failed to connect to remote render device
     RenderConnectionFailed {
         /// The host that failed to connect
@@ -63,6 +104,13 @@ pub enum Error {
         /// Additional context about the resolution attempt
         context: String,
     },
+    /// The requested network interface does not exist or has no usable address
+    NetworkInterfaceNotFound {
+        /// The name of the requested interface
+        name: String,
+        /// Additional context about the lookup
+        context: String,
+    },
 
     // DLNA protocol errors
     /// Failed to set AV transport URI on the render
@@ -86,6 +134,28 @@ pub enum Error {
         /// The underlying UPnP error
         source: rupnp::Error,
     },
+    /// The render device does not provide a required UPnP service
+    DlnaServiceNotAvailable {
+        /// The missing service type
+        service: String,
+    },
+    /// The render device did not answer an action in time
+    DlnaActionTimeout {
+        /// The action that timed out
+        action: String,
+        /// How long the device was waited for
+        timeout: std::time::Duration,
+    },
+    /// The render device does not implement an optional UPnP action
+    DlnaActionNotSupported {
+        /// The unsupported action
+        action: String,
+    },
+    /// Playback speed is not in the format expected by DLNA
+    InvalidPlaybackSpeed {
+        /// The rejected speed value
+        speed: String,
+    },
     /// Failed to parse response from DLNA device
     DlnaResponseParseError {
         /// The action that generated the response
@@ -103,6 +173,16 @@ pub enum Error {
         context: String,
     },
 
+    /// The streaming server could not listen on its address
+    StreamingBindError {
+        /// The address the server tried to listen on
+        address: String,
+        /// The underlying I/O error
+        source: std::io::Error,
+        /// Whether another crab-dlna server already answers on the address
+        other_instance: bool,
+    },
+
     // Subtitle synchronization errors
     /// Subtitle synchronization encountered an error
     SubtitleSyncError {
@@ -110,6 +190,29 @@ pub enum Error {
         message: String,
         /// Additional context about the subtitle operation
         context: String,
+        /// The underlying clipboard, I/O or parse error
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// No clipboard could be opened for subtitle synchronization
+    ClipboardUnavailable {
+        /// The underlying clipboard error
+        source: arboard::Error,
+    },
+
+    // Watch mode errors
+    /// A directory could not be watched for new media files
+    DirectoryWatchFailed {
+        /// The directory to watch
+        path: String,
+        /// Why watching failed
+        reason: String,
+    },
+
+    // Generic I/O errors
+    /// An I/O operation failed where no more specific error applies
+    Io {
+        /// The underlying I/O error
+        source: std::io::Error,
     },
 
     // Keyboard input errors
@@ -117,6 +220,8 @@ pub enum Error {
     KeyboardError {
         /// The error message
         message: String,
+        /// The underlying terminal error
+        source: Box<dyn std::error::Error + Send + Sync>,
     },
 
     // Template rendering errors
@@ -127,6 +232,55 @@ pub enum Error {
         /// The underlying template error
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    // Interruption
+    /// Playback was interrupted (Ctrl+C) before it finished
+    Interrupted {
+        /// The file being played when interrupted
+        file: String,
+    },
+}
+
+impl Error {
+    /// Returns the process exit code the CLI reports for this error
+    ///
+    /// Lets scripts tell a missing device from a missing file or a failed
+    /// playback, see the `EXIT_CODE_*` constants.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::DeviceDiscoveryFailed { .. }
+            | Error::DeviceUrlParseError { .. }
+            | Error::DeviceCreationError { .. }
+            | Error::DeviceDescriptionFetchFailed { .. }
+            | Error::RenderNotFound { .. } => EXIT_CODE_DEVICE_NOT_FOUND,
+            Error::MediaFileNotFound { .. }
+            | Error::MediaFileNotReadable { .. }
+            | Error::MediaFileInvalid { .. } => EXIT_CODE_MEDIA_NOT_FOUND,
+            Error::DlnaSetTransportUriFailed { .. }
+            | Error::DlnaPlaybackFailed { .. }
+            | Error::DlnaActionFailed { .. }
+            | Error::DlnaServiceNotAvailable { .. }
+            | Error::DlnaActionTimeout { .. }
+            | Error::DlnaActionNotSupported { .. }
+            | Error::DlnaResponseParseError { .. } => EXIT_CODE_PLAYBACK_FAILED,
+            Error::NetworkAddressParseError { .. }
+            | Error::UnreachableStreamingAddress { .. }
+            | Error::RenderConnectionFailed { .. }
+            | Error::LocalAddressResolutionFailed { .. }
+            | Error::NetworkInterfaceNotFound { .. }
+            | Error::StreamingServerError { .. }
+            | Error::StreamingBindError { .. } => EXIT_CODE_NETWORK,
+            Error::InvalidDeviceQuery { .. }
+            | Error::InvalidPlaybackSpeed { .. }
+            | Error::SubtitleSyncError { .. }
+            | Error::ClipboardUnavailable { .. }
+            | Error::DirectoryWatchFailed { .. }
+            | Error::Io { .. }
+            | Error::KeyboardError { .. }
+            | Error::TemplateRenderError { .. } => EXIT_CODE_FAILURE,
+            Error::Interrupted { .. } => EXIT_CODE_INTERRUPTED,
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -141,6 +295,9 @@ impl fmt::Display for Error {
             Error::DeviceCreationError { url, source } => {
                 write!(f, "Failed to create device from '{url}': {source}")
             }
+            Error::DeviceDescriptionFetchFailed { url, source } => {
+                write!(f, "Failed to fetch device description '{url}': {source}")
+            }
             Error::RenderNotFound { spec, context } => match spec {
                 RenderSpec::Location(device_url) => {
                     write!(f, "No render found at '{device_url}': {context}")
@@ -153,18 +310,46 @@ impl fmt::Display for Error {
                     write!(f, "No render found within {timeout} seconds: {context}")
                 }
             },
-            Error::NetworkAddressParseError { address, reason } => {
-                write!(f, "Failed to parse network address '{address}': {reason}")
+            Error::InvalidDeviceQuery { query, source } => {
+                write!(f, "Invalid device query '{query}': {source}")
+            }
+            Error::NetworkAddressParseError {
+                address,
+                reason,
+                source,
+            } => {
+                write!(
+                    f,
+                    "Failed to parse network address '{address}': {reason}: {source}"
+                )
+            }
+            Error::UnreachableStreamingAddress { address } => {
+                write!(
+                    f,
+                    "Streaming address '{address}' is not reachable by other devices, use --host or --interface to pick a LAN address"
+                )
             }
             Error::MediaFileNotFound { path, context } => {
                 write!(f, "Media file '{path}' not found: {context}")
             }
+            Error::MediaFileNotReadable { path, source } => {
+                write!(
+                    f,
+                    "Media file '{path}' exists but is not readable: {source}"
+                )
+            }
+            Error::MediaFileInvalid { path, reason } => {
+                write!(f, "Media file '{path}' is not playable: {reason}")
+            }
             Error::RenderConnectionFailed { host, source } => {
                 write!(f, "Failed to connect to render '{host}': {source}")
             }
             Error::LocalAddressResolutionFailed { source, context } => {
                 write!(f, "Failed to resolve local address: {source} ({context})")
             }
+            Error::NetworkInterfaceNotFound { name, context } => {
+                write!(f, "Network interface '{name}' not usable: {context}")
+            }
             Error::DlnaSetTransportUriFailed { source, uri } => {
                 write!(f, "Failed to set transport URI '{uri}': {source}")
             }
@@ -174,6 +359,25 @@ impl fmt::Display for Error {
             Error::DlnaActionFailed { action, source } => {
                 write!(f, "Failed to execute DLNA action '{action}': {source}")
             }
+            Error::DlnaServiceNotAvailable { service } => {
+                write!(f, "Render device does not provide the {service} service")
+            }
+            Error::DlnaActionTimeout { action, timeout } => {
+                write!(
+                    f,
+                    "Render device did not answer the {action} action within {}s",
+                    timeout.as_secs_f32()
+                )
+            }
+            Error::DlnaActionNotSupported { action } => {
+                write!(f, "Render device does not support the {action} action")
+            }
+            Error::InvalidPlaybackSpeed { speed } => {
+                write!(
+                    f,
+                    "Invalid playback speed '{speed}': expected an integer or fraction such as '2', '-2' or '1/2'"
+                )
+            }
             Error::DlnaResponseParseError { action, error } => {
                 write!(
                     f,
@@ -183,11 +387,54 @@ impl fmt::Display for Error {
             Error::StreamingServerError { source, context } => {
                 write!(f, "Streaming server error: {source} ({context})")
             }
-            Error::SubtitleSyncError { message, context } => {
-                write!(f, "Subtitle synchronization error: {message} ({context})")
+            Error::StreamingBindError {
+                address,
+                other_instance: true,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Another crab-dlna instance is already serving on {address}, \
+                     stop it or choose another port with --port"
+                )
+            }
+            Error::StreamingBindError {
+                address, source, ..
+            } if source.kind() == std::io::ErrorKind::AddrInUse => {
+                write!(
+                    f,
+                    "Another program is already using {address}, choose another port with --port"
+                )
+            }
+            Error::StreamingBindError {
+                address, source, ..
+            } => {
+                write!(f, "Failed to listen on {address}: {source}")
+            }
+            Error::SubtitleSyncError {
+                message,
+                context,
+                source,
+            } => {
+                write!(
+                    f,
+                    "Subtitle synchronization error: {message}: {source} ({context})"
+                )
+            }
+            Error::ClipboardUnavailable { source } => {
+                write!(
+                    f,
+                    "Clipboard is not available ({source}); use --subtitle-sync-file to write subtitles to a file instead"
+                )
             }
-            Error::KeyboardError { message } => {
-                write!(f, "Keyboard input error: {message}")
+            Error::DirectoryWatchFailed { path, reason } => {
+                write!(f, "Failed to watch directory '{path}': {reason}")
+            }
+            Error::Io { source } => {
+                write!(f, "I/O error: {source}")
+            }
+            Error::KeyboardError { message, source } => {
+                write!(f, "Keyboard input error: {message}: {source}")
             }
             Error::TemplateRenderError {
                 template_name,
@@ -195,6 +442,9 @@ impl fmt::Display for Error {
             } => {
                 write!(f, "Failed to render template '{template_name}': {source}")
             }
+            Error::Interrupted { file } => {
+                write!(f, "Interrupted while playing '{file}'")
+            }
         }
     }
 }
@@ -204,12 +454,21 @@ impl std::error::Error for Error {
         match self {
             Error::DeviceDiscoveryFailed { source, .. } => Some(source),
             Error::DeviceCreationError { source, .. } => Some(source),
+            Error::DeviceDescriptionFetchFailed { source, .. } => Some(source),
+            Error::InvalidDeviceQuery { source, .. } => Some(source),
+            Error::MediaFileNotReadable { source, .. } => Some(source),
             Error::RenderConnectionFailed { source, .. } => Some(source),
             Error::LocalAddressResolutionFailed { source, .. } => Some(source),
             Error::DlnaSetTransportUriFailed { source, .. } => Some(source),
             Error::DlnaPlaybackFailed { source, .. } => Some(source),
             Error::DlnaActionFailed { source, .. } => Some(source),
             Error::StreamingServerError { source, .. } => Some(source),
+            Error::StreamingBindError { source, .. } => Some(source),
+            Error::NetworkAddressParseError { source, .. } => Some(source.as_ref()),
+            Error::SubtitleSyncError { source, .. } => Some(source.as_ref()),
+            Error::ClipboardUnavailable { source } => Some(source),
+            Error::Io { source } => Some(source),
+            Error::KeyboardError { source, .. } => Some(source.as_ref()),
             Error::TemplateRenderError { source, .. } => Some(source.as_ref()),
             _ => None,
         }
@@ -225,6 +484,12 @@ impl From<ssdp_client::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Error::Io { source }
+    }
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[cfg(test)]
@@ -245,7 +510,7 @@ mod tests {
 
     #[test]
     fn test_render_not_found_error() {
-        let spec = RenderSpec::Query(5, "test".to_string());
+        let spec = RenderSpec::Query(5, "test".parse().unwrap());
         let error = Error::RenderNotFound {
             spec,
             context: "test context".to_string(),
@@ -263,6 +528,10 @@ mod tests {
         let error = Error::NetworkAddressParseError {
             address: "invalid:address".to_string(),
             reason: "Invalid format".to_string(),
+            source: "invalid:address"
+                .parse::<std::net::SocketAddr>()
+                .unwrap_err()
+                .into(),
         };
         assert!(
             error
@@ -270,6 +539,7 @@ mod tests {
                 .contains("Failed to parse network address")
         );
         assert!(error.to_string().contains("invalid:address"));
+        assert!(std::error::Error::source(&error).is_some());
     }
 
     #[test]
@@ -277,9 +547,59 @@ mod tests {
         let error = Error::SubtitleSyncError {
             message: "Failed to sync".to_string(),
             context: "test context".to_string(),
+            source: arboard::Error::ContentNotAvailable.into(),
         };
         assert!(error.to_string().contains("Subtitle synchronization error"));
         assert!(error.to_string().contains("Failed to sync"));
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.downcast_ref::<arboard::Error>().is_some());
+    }
+
+    #[test]
+    fn test_keyboard_error_keeps_source() {
+        let error = Error::KeyboardError {
+            message: "Failed to read event".to_string(),
+            source: std::io::Error::other("terminal closed").into(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Keyboard input error: Failed to read event: terminal closed"
+        );
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+    }
+
+    #[test]
+    fn test_clipboard_unavailable_error() {
+        let error = Error::ClipboardUnavailable {
+            source: arboard::Error::ClipboardNotSupported,
+        };
+        assert!(error.to_string().contains("--subtitle-sync-file"));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn test_action_timeout_error() {
+        let error = Error::DlnaActionTimeout {
+            action: "Play".to_string(),
+            timeout: std::time::Duration::from_secs(10),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Render device did not answer the Play action within 10s"
+        );
+    }
+
+    #[test]
+    fn test_io_error_conversion() {
+        fn open_missing() -> Result<std::fs::File> {
+            Ok(std::fs::File::open("/crab-dlna/missing/file")?)
+        }
+
+        let error = open_missing().unwrap_err();
+        assert!(matches!(error, Error::Io { .. }));
+        assert!(error.to_string().starts_with("I/O error:"));
+        assert!(StdError::source(&error).is_some());
     }
 
     #[test]
@@ -291,4 +611,58 @@ mod tests {
         };
         assert!(StdError::source(&error).is_some());
     }
+
+    #[test]
+    fn test_exit_codes() {
+        let not_found = Error::RenderNotFound {
+            spec: RenderSpec::First(5),
+            context: "test context".to_string(),
+        };
+        assert_eq!(not_found.exit_code(), EXIT_CODE_DEVICE_NOT_FOUND);
+
+        let missing_file = Error::MediaFileNotFound {
+            path: "missing.mp4".to_string(),
+            context: "test context".to_string(),
+        };
+        assert_eq!(missing_file.exit_code(), EXIT_CODE_MEDIA_NOT_FOUND);
+
+        let rejected = Error::DlnaPlaybackFailed {
+            source: rupnp::Error::ParseError("test"),
+            context: "test context".to_string(),
+        };
+        assert_eq!(rejected.exit_code(), EXIT_CODE_PLAYBACK_FAILED);
+
+        let unreachable = Error::UnreachableStreamingAddress {
+            address: "127.0.0.1:9000".to_string(),
+        };
+        assert_eq!(unreachable.exit_code(), EXIT_CODE_NETWORK);
+
+        let io = Error::from(std::io::Error::other("test"));
+        assert_eq!(io.exit_code(), EXIT_CODE_FAILURE);
+
+        let interrupted = Error::Interrupted {
+            file: "video.mp4".to_string(),
+        };
+        assert_eq!(interrupted.exit_code(), EXIT_CODE_INTERRUPTED);
+    }
+
+    #[test]
+    fn test_exit_codes_differ_from_usage_errors() {
+        use clap::Parser;
+
+        let usage_code = crate::cli::Cli::try_parse_from(["crab-dlna", "--unknown-flag"])
+            .err()
+            .unwrap()
+            .exit_code();
+        for code in [
+            EXIT_CODE_FAILURE,
+            EXIT_CODE_DEVICE_NOT_FOUND,
+            EXIT_CODE_MEDIA_NOT_FOUND,
+            EXIT_CODE_PLAYBACK_FAILED,
+            EXIT_CODE_NETWORK,
+            EXIT_CODE_INTERRUPTED,
+        ] {
+            assert_ne!(code, usage_code);
+        }
+    }
 }
@@ -3,7 +3,12 @@
 //! This module provides keyboard input handling for controlling media playback,
 //! including play/pause toggle with the space key and other media controls.
 
-use crate::{devices::Render, dlna::toggle_play_pause, error::Result};
+use crate::{
+    config::{SEEK_STEP_SECONDS, VOLUME_STEP},
+    devices::Render,
+    dlna::{adjust_volume, next_track, previous_track, seek_relative, stop, toggle_play_pause},
+    error::Result,
+};
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
     terminal::{disable_raw_mode, enable_raw_mode},
@@ -35,11 +40,12 @@ impl KeyboardHandler {
     /// It will block until the event loop is stopped or an error occurs.
     pub async fn start(&mut self) -> Result<()> {
         info!("Starting keyboard event handler...");
-        info!("Press SPACE to toggle play/pause, 'q' to quit");
+        info!("Press SPACE to toggle play/pause, 'h' for help, 'q' to quit");
 
         // Enable raw mode to capture key events
         enable_raw_mode().map_err(|e| crate::error::Error::KeyboardError {
-            message: format!("Failed to enable raw mode: {e}"),
+            message: "Failed to enable raw mode".to_string(),
+            source: e.into(),
         })?;
 
         self.active = true;
@@ -94,11 +100,13 @@ impl KeyboardHandler {
     async fn read_event(&self) -> Result<bool> {
         if event::poll(Duration::from_millis(50)).map_err(|e| {
             crate::error::Error::KeyboardError {
-                message: format!("Failed to poll for events: {e}"),
+                message: "Failed to poll for events".to_string(),
+                source: e.into(),
             }
         })? {
             match event::read().map_err(|e| crate::error::Error::KeyboardError {
-                message: format!("Failed to read event: {e}"),
+                message: "Failed to read event".to_string(),
+                source: e.into(),
             })? {
                 Event::Key(key_event) => {
                     return self.handle_key_event(key_event).await;
@@ -149,6 +157,36 @@ impl KeyboardHandler {
                     info!("Play/pause toggled successfully");
                 }
             }
+            KeyCode::Char('s') | KeyCode::Char('S') => {
+                debug!("S key pressed - stopping playback");
+                if let Err(e) = stop(&self.render).await {
+                    warn!("Failed to stop playback: {e}");
+                }
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.change_volume(i16::from(VOLUME_STEP)).await;
+            }
+            KeyCode::Char('-') | KeyCode::Char('_') => {
+                self.change_volume(-i16::from(VOLUME_STEP)).await;
+            }
+            KeyCode::Right => {
+                self.seek_by(SEEK_STEP_SECONDS as i64).await;
+            }
+            KeyCode::Left => {
+                self.seek_by(-(SEEK_STEP_SECONDS as i64)).await;
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                debug!("N key pressed - next track");
+                if let Err(e) = next_track(&self.render).await {
+                    warn!("Failed to skip to next track: {e}");
+                }
+            }
+            KeyCode::Char('b') | KeyCode::Char('B') => {
+                debug!("B key pressed - previous track");
+                if let Err(e) = previous_track(&self.render).await {
+                    warn!("Failed to skip to previous track: {e}");
+                }
+            }
             KeyCode::Char('h') | KeyCode::Char('H') | KeyCode::Char('?') => {
                 self.show_help();
             }
@@ -160,10 +198,29 @@ impl KeyboardHandler {
         Ok(true)
     }
 
+    /// Raises or lowers the device volume by `delta`
+    async fn change_volume(&self, delta: i16) {
+        match adjust_volume(&self.render, delta).await {
+            Ok(volume) => info!("Volume: {volume}"),
+            Err(e) => warn!("Failed to change volume: {e}"),
+        }
+    }
+
+    /// Seeks forward or backward by `offset_seconds`
+    async fn seek_by(&self, offset_seconds: i64) {
+        if let Err(e) = seek_relative(&self.render, offset_seconds).await {
+            warn!("Failed to seek: {e}");
+        }
+    }
+
     /// Shows help information for keyboard controls
     fn show_help(&self) {
         println!("\n=== Keyboard Controls ===");
         println!("SPACE / P  : Toggle play/pause");
+        println!("S          : Stop");
+        println!("+ / -      : Volume up/down");
+        println!("LEFT/RIGHT : Seek -/+{SEEK_STEP_SECONDS}s");
+        println!("N / B      : Next/previous track (device queue)");
         println!("Q / ESC    : Quit");
         println!("H / ?      : Show this help");
         println!("========================\n");
@@ -52,7 +52,7 @@ use crab_dlna::{
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let discover_timeout_secs = 5;
-    let render_spec = RenderSpec::Query(discover_timeout_secs, "Kodi".to_string());
+    let render_spec = RenderSpec::Query(discover_timeout_secs, "Kodi".parse()?);
     let render = Render::new(render_spec).await?;
     let host_ip = get_local_ip().await?;
     let host_port = STREAMING_PORT_DEFAULT;
@@ -114,13 +114,24 @@ mod keyboard;
 /// Terminal User Interface
 mod tui;
 
-pub use config::Config;
-pub use devices::{PositionInfo, Render, RenderSpec, TransportInfo};
-pub use dlna::{pause, play, resume, toggle_play_pause};
+pub use config::{Config, DevicePreset};
+pub use devices::{
+    DeviceCapabilities, DeviceIdentity, DeviceMatcher, DidlObject, MediaInfo, MediaServer,
+    PlayMode, PositionInfo, Render, RenderSpec, TransportInfo, TransportSettings,
+};
+pub use dlna::{
+    EndReason, PlaybackClock, PlaybackEndDetector, PlaybackEvent, adjust_volume, get_mute,
+    get_volume, next_track, pause, play, play_at_speed, play_until_stopped, previous_track, resume,
+    seek, seek_relative, set_volume, stop, toggle_play_pause,
+};
 pub use error::Error;
 pub use keyboard::{KeyboardHandler, start_interactive_control};
+#[cfg(feature = "watch")]
+pub use media::DirectoryWatcher;
 pub use media::{
-    MediaStreamingServer, Playlist, STREAMING_PORT_DEFAULT, SubtitleSyncer, get_local_ip,
+    ClipboardMode, ClipboardOutput, FileOutput, InterfaceAddress, MediaStreamingServer, Playlist,
+    ReadThrottle, STREAMING_PORT_DEFAULT, SubtitleOutput, SubtitleSyncer, SubtitleTrack,
+    get_interface_ip, get_local_ip, list_interface_addresses,
 };
-pub use tui::start_tui;
-pub use utils::infer_subtitle_from_video;
+pub use tui::{start_tui, start_tui_with_config, start_tui_with_spec};
+pub use utils::{compute_file_uri, infer_subtitle_from_video};
@@ -5,6 +5,6 @@ use std::process::exit;
 async fn main() {
     if let Err(e) = crab_dlna::cli::run().await {
         error!("{e}");
-        exit(1);
+        exit(e.exit_code());
     }
 }
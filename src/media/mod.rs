@@ -4,12 +4,24 @@
 //! - Media file streaming over HTTP
 //! - Playlist management for multiple files
 //! - Subtitle synchronization and display
+//! - A "now playing" on-screen display served as subtitles
+//! - Watching a directory for new media files (`watch` feature)
 
+pub mod osd;
 pub mod playlist;
 pub mod streaming;
 pub mod subtitle_sync;
+#[cfg(feature = "watch")]
+pub mod watch;
 
 // Re-export main types and functions for backward compatibility
 pub use playlist::Playlist;
-pub use streaming::{MediaStreamingServer, STREAMING_PORT_DEFAULT, get_local_ip};
-pub use subtitle_sync::SubtitleSyncer;
+pub use streaming::{
+    InterfaceAddress, MediaStreamingServer, ReadThrottle, STREAMING_PORT_DEFAULT, SubtitleTrack,
+    get_interface_ip, get_local_ip, list_interface_addresses,
+};
+pub use subtitle_sync::{
+    ClipboardMode, ClipboardOutput, FileOutput, SubtitleOutput, SubtitleSyncer,
+};
+#[cfg(feature = "watch")]
+pub use watch::DirectoryWatcher;
@@ -0,0 +1,147 @@
+//! "Now playing" on-screen display for crab-dlna
+//!
+//! Renderers show a subtitle track without any further interaction, so the
+//! on-screen display is a generated WebVTT file: a short cue with the title
+//! and elapsed time at the start of playback and at regular intervals. It is
+//! then served like any other subtitle file.
+//!
+//! The files are written to a temporary directory, removed once the
+//! [`OsdDirectory`] guarding it is dropped.
+
+use crate::{
+    config::{OSD_CUE_DURATION_SECS, OSD_HORIZON_SECS, OSD_INTERVAL_SECS},
+    error::Result,
+    utils::format_milliseconds,
+};
+use log::debug;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Builds the WebVTT document holding the on-screen display cues
+pub fn build_osd_vtt(title: &str) -> String {
+    let title = escape_cue_text(title);
+    let mut vtt = String::from("WEBVTT\n");
+    for start in (0..OSD_HORIZON_SECS).step_by(OSD_INTERVAL_SECS as usize) {
+        let _ = write!(
+            vtt,
+            "\n{} --> {}\n{title} · {}\n",
+            cue_timestamp(start),
+            cue_timestamp(start + OSD_CUE_DURATION_SECS),
+            format_milliseconds(start * 1000)
+        );
+    }
+    vtt
+}
+
+/// Per-process directory holding the on-screen display files
+///
+/// The directory is created on the first write and removed, with the files
+/// in it, when the guard is dropped at the end of playback.
+#[derive(Debug)]
+pub struct OsdDirectory {
+    path: PathBuf,
+}
+
+impl OsdDirectory {
+    /// Creates the guard of the directory for this process inside `parent`
+    pub fn new(parent: &Path) -> Self {
+        Self {
+            path: parent.join(format!("crab-dlna-osd-{}", std::process::id())),
+        }
+    }
+
+    /// Writes the on-screen display for a media file and returns its path
+    ///
+    /// The file is named after the media file, as some renderers only pick up
+    /// subtitles sharing its name.
+    pub fn write_subtitle(&self, video_path: &Path, title: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.path)?;
+
+        let mut file_name = video_path
+            .file_stem()
+            .unwrap_or_else(|| "osd".as_ref())
+            .to_os_string();
+        file_name.push(".vtt");
+        let path = self.path.join(file_name);
+        std::fs::write(&path, build_osd_vtt(title))?;
+        Ok(path)
+    }
+}
+
+impl Drop for OsdDirectory {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            debug!(
+                "Failed to remove on-screen display directory {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Formats seconds as a WebVTT cue timestamp
+fn cue_timestamp(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.000",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+/// Escapes text so it is shown verbatim in a WebVTT cue
+fn escape_cue_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+
+    #[test]
+    fn test_build_osd_vtt() {
+        let vtt = build_osd_vtt("Tom & Jerry");
+        assert!(
+            vtt.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:05.000\nTom &amp; Jerry · 00:00\n")
+        );
+        assert!(vtt.contains("\n00:05:00.000 --> 00:05:05.000\nTom &amp; Jerry · 05:00\n"));
+        assert!(vtt.contains("01:00:00.000 --> 01:00:05.000\nTom &amp; Jerry · 1:00:00\n"));
+        assert_eq!(
+            vtt.matches(" --> ").count() as u64,
+            OSD_HORIZON_SECS / OSD_INTERVAL_SECS
+        );
+    }
+
+    #[test]
+    fn test_escape_cue_text() {
+        assert_eq!(escape_cue_text("a --> <b>\nc"), "a --&gt; &lt;b&gt; c");
+    }
+
+    #[test]
+    fn test_osd_directory_is_removed_on_drop() {
+        let parent = TestDir::new("crab_dlna_osd_directory");
+
+        let directory = OsdDirectory::new(&parent);
+        let path = directory
+            .write_subtitle(Path::new("/videos/Some Movie.mkv"), "Some Movie")
+            .unwrap();
+        assert_eq!(path.file_name().unwrap(), "Some Movie.vtt");
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .starts_with("WEBVTT")
+        );
+
+        drop(directory);
+        assert!(!path.exists());
+        assert!(!path.parent().unwrap().exists());
+    }
+}
@@ -4,15 +4,22 @@
 //! of media files, including support for playing entire folders.
 
 use crate::{
+    config::Config,
     error::{Error, Result},
-    utils::is_supported_media_file,
+    utils::{check_file_readable, is_supported_media_file},
 };
-use log::{debug, info};
+use log::{debug, info, warn};
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
 use std::{
-    collections::VecDeque,
-    path::{Path, PathBuf},
+    collections::{HashSet, VecDeque},
+    path::{Component, Path, PathBuf},
 };
 
+/// Number of files listed from the start of a long playlist when displayed
+const DISPLAY_HEAD_FILES: usize = 5;
+/// Number of files listed from the end of a long playlist when displayed
+const DISPLAY_TAIL_FILES: usize = 3;
+
 /// Represents a playlist of media files
 #[derive(Debug, Clone, Default)]
 pub struct Playlist {
@@ -22,6 +29,8 @@ pub struct Playlist {
     current_index: Option<usize>,
     /// Whether to loop the playlist
     loop_playlist: bool,
+    /// Order of the files before shuffling, if the playlist is shuffled
+    unshuffled_files: Option<VecDeque<PathBuf>>,
 }
 
 impl Playlist {
@@ -29,12 +38,7 @@ impl Playlist {
     pub fn from_file<P: AsRef<Path>>(file_path: P) -> Result<Self> {
         let path = file_path.as_ref().to_path_buf();
 
-        if !path.exists() {
-            return Err(Error::MediaFileNotFound {
-                path: path.display().to_string(),
-                context: "File does not exist".to_string(),
-            });
-        }
+        check_file_readable(&path, "File does not exist")?;
 
         if !is_supported_media_file(&path) {
             return Err(Error::MediaFileNotFound {
@@ -50,6 +54,14 @@ impl Playlist {
 
     /// Creates a playlist from a directory, scanning for supported media files
     pub fn from_directory<P: AsRef<Path>>(dir_path: P) -> Result<Self> {
+        Self::from_directory_with_config(dir_path, &Config::default())
+    }
+
+    /// Creates a playlist from a directory with configurable scan options
+    pub fn from_directory_with_config<P: AsRef<Path>>(
+        dir_path: P,
+        config: &Config,
+    ) -> Result<Self> {
         let path = dir_path.as_ref();
 
         if !path.exists() {
@@ -67,7 +79,7 @@ impl Playlist {
         }
 
         let mut playlist = Self::default();
-        playlist.scan_directory(path)?;
+        playlist.scan_directory(path, config)?;
 
         if playlist.is_empty() {
             return Err(Error::MediaFileNotFound {
@@ -80,40 +92,25 @@ impl Playlist {
     }
 
     /// Scans a directory for supported media files and adds them to the playlist
-    fn scan_directory(&mut self, dir_path: &Path) -> Result<()> {
+    fn scan_directory(&mut self, dir_path: &Path, config: &Config) -> Result<()> {
         info!("Scanning directory for media files: {}", dir_path.display());
 
-        let entries = std::fs::read_dir(dir_path).map_err(|e| Error::MediaFileNotFound {
-            path: dir_path.display().to_string(),
-            context: format!("Failed to read directory: {e}"),
-        })?;
-
-        let mut media_files = Vec::new();
-
-        for entry in entries {
-            let entry = entry.map_err(|e| Error::MediaFileNotFound {
-                path: dir_path.display().to_string(),
-                context: format!("Failed to read directory entry: {e}"),
-            })?;
-
-            let path = entry.path();
+        let mut scanner = DirectoryScanner::new(config);
+        scanner.scan(dir_path)?;
+        let mut media_files = scanner.media_files;
 
-            if path.is_file() && is_supported_media_file(&path) {
-                debug!("Found media file: {}", path.display());
-                media_files.push(path);
-            } else if path.is_dir() {
-                debug!("Skipping subdirectory: {}", path.display());
-            } else {
-                debug!("Skipping unsupported file: {}", path.display());
-            }
+        if scanner.limit_reached {
+            warn!(
+                "Stopped scanning after {} media files, remaining files were skipped (use --max-files to raise the limit)",
+                config.max_files
+            );
         }
 
         // Sort files for consistent ordering
         media_files.sort();
 
-        for file in media_files {
-            self.add_file(file);
-        }
+        // Symlinked files can point at the same media, which is played once
+        self.extend_from_paths(media_files);
 
         info!("Found {} media files in directory", self.files.len());
         Ok(())
@@ -121,7 +118,92 @@ impl Playlist {
 
     /// Adds a file to the playlist
     pub fn add_file<P: Into<PathBuf>>(&mut self, file_path: P) {
-        self.files.push_back(file_path.into());
+        let file_path = file_path.into();
+        if let Some(unshuffled_files) = &mut self.unshuffled_files {
+            unshuffled_files.push_back(file_path.clone());
+        }
+        self.files.push_back(file_path);
+    }
+
+    /// Adds a file to the playlist unless it is already in it
+    ///
+    /// Returns whether the file was added. See [`Playlist::contains`] for how
+    /// paths are compared.
+    pub fn add_file_unique<P: Into<PathBuf>>(&mut self, file_path: P) -> bool {
+        let file_path = file_path.into();
+        if self.contains(&file_path) {
+            debug!("Skipping duplicate playlist entry: {}", file_path.display());
+            return false;
+        }
+        self.add_file(file_path);
+        true
+    }
+
+    /// Appends the files of another playlist, keeping the current file
+    ///
+    /// Files already in the playlist are skipped, as for directory scans.
+    /// Only the files are taken from `other`, not its position or options.
+    pub fn append(&mut self, other: Playlist) {
+        self.extend_from_paths(other.files);
+    }
+
+    /// Appends files to the playlist, keeping the current file
+    ///
+    /// Files already in the playlist are skipped, as for directory scans.
+    pub fn extend_from_paths(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        let mut seen: HashSet<PathBuf> =
+            self.files.iter().map(|file| normalize_path(file)).collect();
+        for file in paths {
+            if seen.insert(normalize_path(&file)) {
+                self.add_file(file);
+            } else {
+                debug!("Skipping duplicate playlist entry: {}", file.display());
+            }
+        }
+    }
+
+    /// Removes the file at the given index from the playlist
+    ///
+    /// The current index keeps pointing at the same file. Removing the
+    /// current file leaves the playlist without a current file.
+    pub fn remove(&mut self, index: usize) -> Option<PathBuf> {
+        let removed = self.files.remove(index)?;
+        if let Some(unshuffled_files) = &mut self.unshuffled_files
+            && let Some(position) = unshuffled_files.iter().position(|file| *file == removed)
+        {
+            unshuffled_files.remove(position);
+        }
+        self.current_index = match self.current_index {
+            Some(current) if current == index => None,
+            Some(current) if current > index => Some(current - 1),
+            current => current,
+        };
+        Some(removed)
+    }
+
+    /// Keeps only the first `len` files, returning how many were removed
+    pub fn truncate(&mut self, len: usize) -> usize {
+        let removed = self.files.len().saturating_sub(len);
+        for index in (len..self.files.len()).rev() {
+            self.remove(index);
+        }
+        removed
+    }
+
+    /// Checks whether a file is in the playlist
+    ///
+    /// Paths are normalized before comparing, so `./a.mp4` and `a.mp4` are
+    /// the same file.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.index_of(path).is_some()
+    }
+
+    /// Returns the position of a file in the playlist, comparing normalized paths
+    pub fn index_of(&self, path: &Path) -> Option<usize> {
+        let path = normalize_path(path);
+        self.files
+            .iter()
+            .position(|file| normalize_path(file) == path)
     }
 
     /// Gets the current file in the playlist
@@ -156,6 +238,17 @@ impl Playlist {
         self.current_file()
     }
 
+    /// Gets the file [`Playlist::next_file`] would move to, without moving
+    pub fn peek_next_file(&self) -> Option<&PathBuf> {
+        let next_index = match self.current_index {
+            None => 0,
+            Some(index) if index + 1 < self.files.len() => index + 1,
+            Some(_) if self.loop_playlist => 0,
+            Some(_) => return None,
+        };
+        self.files.get(next_index)
+    }
+
     /// Moves to the previous file in the playlist
     pub fn previous_file(&mut self) -> Option<&PathBuf> {
         if self.files.is_empty() {
@@ -207,6 +300,66 @@ impl Playlist {
         self.loop_playlist
     }
 
+    /// Sets whether to shuffle the playlist
+    ///
+    /// Shuffling uses a randomly seeded generator; use
+    /// [`Playlist::set_shuffle_seeded`] for a reproducible order. Turning
+    /// shuffle off restores the original order. The current file is kept.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        match shuffle {
+            true => self.shuffle_with(&mut rand::rng()),
+            false => self.unshuffle(),
+        }
+    }
+
+    /// Shuffles the playlist in an order determined by the given seed
+    ///
+    /// The same seed always produces the same order for the same files.
+    pub fn set_shuffle_seeded(&mut self, seed: u64) {
+        self.shuffle_with(&mut StdRng::seed_from_u64(seed));
+    }
+
+    /// Returns whether the playlist is shuffled
+    pub fn is_shuffled(&self) -> bool {
+        self.unshuffled_files.is_some()
+    }
+
+    /// Shuffles the files, always starting from their original order
+    fn shuffle_with<R: rand::Rng>(&mut self, rng: &mut R) {
+        let current_file = self.current_file().cloned();
+        let unshuffled_files = self
+            .unshuffled_files
+            .get_or_insert_with(|| self.files.clone());
+
+        let mut files: Vec<PathBuf> = unshuffled_files.iter().cloned().collect();
+        files.shuffle(rng);
+        self.files = files.into();
+        self.restore_current_file(current_file);
+    }
+
+    /// Restores the order the files had before shuffling
+    fn unshuffle(&mut self) {
+        if let Some(unshuffled_files) = self.unshuffled_files.take() {
+            let current_file = self.current_file().cloned();
+            self.files = unshuffled_files;
+            self.restore_current_file(current_file);
+        }
+    }
+
+    /// Points the current index at the given file after the files were reordered
+    fn restore_current_file(&mut self, current_file: Option<PathBuf>) {
+        if let Some(current_file) = current_file {
+            self.current_index = self.files.iter().position(|file| *file == current_file);
+        }
+    }
+
+    /// Iterates over all files in playlist order, without moving the current file
+    ///
+    /// Use [`Playlist::next_file`] to advance playback instead.
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, PathBuf> {
+        self.files.iter()
+    }
+
     /// Gets all files in the playlist
     pub fn files(&self) -> &VecDeque<PathBuf> {
         &self.files
@@ -223,10 +376,414 @@ impl Playlist {
     }
 }
 
-impl Iterator for Playlist {
-    type Item = PathBuf;
+impl std::fmt::Display for Playlist {
+    /// Summarizes the playlist, listing only the first and last files of long playlists
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let count = self.files.len();
+        write!(f, "{count} file{}", if count == 1 { "" } else { "s" })?;
+        let mut modes = Vec::new();
+        if self.loop_playlist {
+            modes.push("loop");
+        }
+        if self.is_shuffled() {
+            modes.push("shuffled");
+        }
+        if !modes.is_empty() {
+            write!(f, " ({})", modes.join(", "))?;
+        }
+        if let Some(index) = self.current_index {
+            write!(f, ", current: {}", index + 1)?;
+        }
+
+        let truncated = count > DISPLAY_HEAD_FILES + DISPLAY_TAIL_FILES + 1;
+        for (index, file) in self.iter().enumerate() {
+            if truncated && index == DISPLAY_HEAD_FILES {
+                write!(
+                    f,
+                    "\n  ... {} more",
+                    count - DISPLAY_HEAD_FILES - DISPLAY_TAIL_FILES
+                )?;
+            }
+            if truncated && (DISPLAY_HEAD_FILES..count - DISPLAY_TAIL_FILES).contains(&index) {
+                continue;
+            }
+            let marker = if Some(index) == self.current_index {
+                '*'
+            } else {
+                ' '
+            };
+            write!(f, "\n {marker}{}: {}", index + 1, file.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalizes a path for comparison
+///
+/// Existing files are canonicalized. Other paths only lose their `.` components.
+fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        path.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect()
+    })
+}
+
+/// Collects supported media files from a directory tree
+///
+/// Symlinked entries are skipped unless `Config::follow_symlinks` is set. When
+/// followed, directories are tracked by their canonical path, so a symlink
+/// pointing back into the scanned tree is only traversed once. FIFOs, sockets,
+/// devices, broken symlinks and entries that can't be read are skipped, and
+/// collection stops at `Config::max_files`.
+struct DirectoryScanner<'a> {
+    /// Scan options
+    config: &'a Config,
+    /// Canonical paths of the directories already scanned
+    visited_dirs: HashSet<PathBuf>,
+    /// Media files collected so far
+    media_files: Vec<PathBuf>,
+    /// Whether collection stopped because of the file limit
+    limit_reached: bool,
+}
+
+impl<'a> DirectoryScanner<'a> {
+    /// Creates a new scanner with the given options
+    fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            visited_dirs: HashSet::new(),
+            media_files: Vec::new(),
+            limit_reached: false,
+        }
+    }
+
+    /// Scans a directory, recursing into subdirectories if enabled
+    fn scan(&mut self, dir_path: &Path) -> Result<()> {
+        let canonical_dir = dir_path
+            .canonicalize()
+            .unwrap_or_else(|_| dir_path.to_path_buf());
+        if !self.visited_dirs.insert(canonical_dir) {
+            debug!("Skipping already visited directory: {}", dir_path.display());
+            return Ok(());
+        }
+
+        let entries = std::fs::read_dir(dir_path).map_err(|e| Error::MediaFileNotFound {
+            path: dir_path.display().to_string(),
+            context: format!("Failed to read directory: {e}"),
+        })?;
+
+        for entry in entries {
+            if self.limit_reached {
+                break;
+            }
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping unreadable entry in {}: {e}", dir_path.display());
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            if !self.config.follow_symlinks {
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_symlink() => {
+                        debug!("Skipping symlink: {}", path.display());
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Skipping entry {}: {e}", path.display());
+                        continue;
+                    }
+                }
+            }
+
+            // Follows symlinks, so a broken link fails here and is skipped
+            let file_type = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(e) => {
+                    debug!("Skipping inaccessible entry {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            if file_type.is_file() {
+                if is_supported_media_file(&path) {
+                    self.add_media_file(path);
+                } else {
+                    debug!("Skipping unsupported file: {}", path.display());
+                }
+            } else if file_type.is_dir() {
+                if self.config.recursive_scan {
+                    if let Err(e) = self.scan(&path) {
+                        warn!("Skipping subdirectory {}: {e}", path.display());
+                    }
+                } else {
+                    debug!("Skipping subdirectory: {}", path.display());
+                }
+            } else {
+                debug!("Skipping special file: {}", path.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a media file, flagging the limit once it is exceeded
+    fn add_media_file(&mut self, path: PathBuf) {
+        if self.media_files.len() >= self.config.max_files {
+            self.limit_reached = true;
+            return;
+        }
+        debug!("Found media file: {}", path.display());
+        self.media_files.push(path);
+    }
+}
+
+impl<'a> IntoIterator for &'a Playlist {
+    type Item = &'a PathBuf;
+    type IntoIter = std::collections::vec_deque::Iter<'a, PathBuf>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+
+    #[test]
+    fn test_add_file_unique() {
+        let dir = TestDir::new("crab_dlna_playlist_unique");
+        let file = dir.join("a.mp4");
+        std::fs::write(&file, b"a").unwrap();
+
+        let mut playlist = Playlist::default();
+        assert!(playlist.add_file_unique(&file));
+        assert!(!playlist.add_file_unique(dir.join(".").join("a.mp4")));
+        assert_eq!(playlist.len(), 1);
+        assert_eq!(playlist.index_of(&file), Some(0));
+    }
+
+    #[test]
+    fn test_append_keeps_order_and_current_file() {
+        let mut first = Playlist::default();
+        first.extend_from_paths(["a.mp4", "b.mp4"].map(PathBuf::from));
+        first.next_file();
+        let mut second = Playlist::default();
+        second.extend_from_paths(["c.mp4", "./a.mp4", "d.mp4"].map(PathBuf::from));
+
+        first.append(second);
+        assert_eq!(first.len(), 4);
+        assert_eq!(
+            first.iter().collect::<Vec<_>>(),
+            ["a.mp4", "b.mp4", "c.mp4", "d.mp4"]
+                .map(PathBuf::from)
+                .iter()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(first.current_file(), Some(&PathBuf::from("a.mp4")));
+        assert_eq!(first.next_file(), Some(&PathBuf::from("b.mp4")));
+    }
+
+    #[test]
+    fn test_contains_normalizes_missing_paths() {
+        let mut playlist = Playlist::default();
+        playlist.add_file("missing_dir/b.mp4");
+        assert!(playlist.contains(Path::new("./missing_dir/./b.mp4")));
+        assert!(!playlist.contains(Path::new("missing_dir/c.mp4")));
+        assert_eq!(playlist.index_of(Path::new("c.mp4")), None);
+    }
+
+    #[test]
+    fn test_from_directory_skips_subdirectories() {
+        let dir = TestDir::new("crab_dlna_scan_flat");
+        std::fs::create_dir(dir.join("season1")).unwrap();
+        std::fs::write(dir.join("a.mp4"), b"a").unwrap();
+        std::fs::write(dir.join("season1").join("b.mp4"), b"b").unwrap();
+
+        let playlist = Playlist::from_directory(&dir).unwrap();
+        assert_eq!(playlist.len(), 1);
+
+        let config = Config::new().with_recursive_scan(true);
+        let playlist = Playlist::from_directory_with_config(&dir, &config).unwrap();
+        assert_eq!(playlist.len(), 2);
+    }
+
+    #[test]
+    fn test_from_directory_respects_max_files() {
+        let dir = TestDir::new("crab_dlna_scan_limit");
+        for name in ["a.mp4", "b.mp4", "c.mp4"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+
+        let config = Config::new().with_max_files(2);
+        let playlist = Playlist::from_directory_with_config(&dir, &config).unwrap();
+        assert_eq!(playlist.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_scan_terminates_on_symlink_cycle() {
+        let dir = TestDir::new("crab_dlna_scan_cycle");
+        std::fs::create_dir(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("nested").join("clip.mp4"), b"clip").unwrap();
+        std::os::unix::fs::symlink(&*dir, dir.join("nested").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing.mp4"), dir.join("broken.mp4")).unwrap();
+
+        let config = Config::new()
+            .with_recursive_scan(true)
+            .with_follow_symlinks(true);
+        let playlist = Playlist::from_directory_with_config(&dir, &config).unwrap();
+        assert_eq!(playlist.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_follows_symlinks_only_when_enabled() {
+        let dir = TestDir::new("crab_dlna_scan_symlinks");
+        let target = TestDir::new("crab_dlna_scan_symlinks_target");
+        std::fs::create_dir(target.join("season1")).unwrap();
+        std::fs::write(target.join("linked.mp4"), b"linked").unwrap();
+        std::fs::write(target.join("season1").join("episode.mp4"), b"episode").unwrap();
+        std::fs::write(dir.join("local.mp4"), b"local").unwrap();
+        std::os::unix::fs::symlink(target.join("linked.mp4"), dir.join("linked.mp4")).unwrap();
+        std::os::unix::fs::symlink(target.join("season1"), dir.join("season1")).unwrap();
+
+        let config = Config::new().with_recursive_scan(true);
+        let playlist = Playlist::from_directory_with_config(&dir, &config).unwrap();
+        assert_eq!(playlist.len(), 1);
+        assert!(playlist.contains(&dir.join("local.mp4")));
+
+        let config = config.with_follow_symlinks(true);
+        let playlist = Playlist::from_directory_with_config(&dir, &config).unwrap();
+        assert_eq!(playlist.len(), 3);
+        assert!(playlist.contains(&dir.join("linked.mp4")));
+        assert!(playlist.contains(&dir.join("season1").join("episode.mp4")));
+    }
+
+    #[test]
+    fn test_remove_keeps_current_file() {
+        let mut playlist = Playlist::default();
+        for file in ["a.mp4", "b.mp4", "c.mp4"] {
+            playlist.add_file(file);
+        }
+        playlist.next_file();
+        playlist.next_file();
+
+        assert_eq!(playlist.remove(0), Some(PathBuf::from("a.mp4")));
+        assert_eq!(playlist.current_file(), Some(&PathBuf::from("b.mp4")));
+        assert_eq!(playlist.remove(0), Some(PathBuf::from("b.mp4")));
+        assert_eq!(playlist.current_index(), None);
+        assert_eq!(playlist.remove(5), None);
+        assert_eq!(playlist.len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_applies_to_appended_playlists() {
+        let mut playlist = Playlist::default();
+        for paths in [["a.mp4", "b.mp4"], ["c.mp4", "d.mp4"]] {
+            let mut other = Playlist::default();
+            other.extend_from_paths(paths.map(PathBuf::from));
+            playlist.append(other);
+        }
+
+        assert_eq!(playlist.truncate(3), 1);
+        assert_eq!(playlist.len(), 3);
+        assert!(!playlist.contains(Path::new("d.mp4")));
+        assert_eq!(playlist.truncate(3), 0);
+    }
+
+    #[test]
+    fn test_display_short_playlist() {
+        let mut playlist = create_test_playlist(2);
+        playlist.set_loop(true);
+        playlist.next_file();
+        assert_eq!(
+            playlist.to_string(),
+            "2 files (loop), current: 1\n *1: 00.mp4\n  2: 01.mp4"
+        );
+        assert_eq!(Playlist::default().to_string(), "0 files");
+    }
+
+    #[test]
+    fn test_display_truncates_long_playlist() {
+        let summary = create_test_playlist(20).to_string();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 1 + DISPLAY_HEAD_FILES + 1 + DISPLAY_TAIL_FILES);
+        assert_eq!(lines[0], "20 files");
+        assert_eq!(lines[5], "  5: 04.mp4");
+        assert_eq!(lines[6], "  ... 12 more");
+        assert_eq!(lines[7], "  18: 17.mp4");
+        assert_eq!(lines[9], "  20: 19.mp4");
+    }
+
+    #[test]
+    fn test_iter_keeps_current_file() {
+        let mut playlist = create_test_playlist(3);
+        playlist.set_loop(true);
+        playlist.next_file();
+
+        let files: Vec<&PathBuf> = playlist.iter().collect();
+        assert_eq!(files.len(), 3);
+        assert_eq!((&playlist).into_iter().count(), 3);
+        assert_eq!(playlist.current_index(), Some(0));
+    }
+
+    #[test]
+    fn test_peek_next_file() {
+        let mut playlist = create_test_playlist(2);
+        assert_eq!(playlist.peek_next_file(), Some(&PathBuf::from("00.mp4")));
+        playlist.next_file();
+        assert_eq!(playlist.peek_next_file(), Some(&PathBuf::from("01.mp4")));
+        playlist.next_file();
+        assert_eq!(playlist.peek_next_file(), None);
+        playlist.set_loop(true);
+        assert_eq!(playlist.peek_next_file(), Some(&PathBuf::from("00.mp4")));
+        assert_eq!(playlist.current_index(), Some(1));
+    }
+
+    /// Builds an in-memory playlist with the given number of files
+    fn create_test_playlist(count: usize) -> Playlist {
+        let mut playlist = Playlist::default();
+        for index in 0..count {
+            playlist.add_file(format!("{index:02}.mp4"));
+        }
+        playlist
+    }
+
+    #[test]
+    fn test_shuffle_seeded_is_reproducible() {
+        let mut first = create_test_playlist(20);
+        let mut second = create_test_playlist(20);
+        first.set_shuffle_seeded(42);
+        second.set_shuffle_seeded(42);
+        assert_eq!(first.files(), second.files());
+        assert_ne!(first.files(), create_test_playlist(20).files());
+
+        second.set_shuffle_seeded(7);
+        assert_ne!(first.files(), second.files());
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next_file().cloned()
+    #[test]
+    fn test_unshuffle_restores_order_and_current_file() {
+        let mut playlist = create_test_playlist(10);
+        playlist.next_file();
+        playlist.next_file();
+        let current_file = playlist.current_file().cloned();
+
+        playlist.set_shuffle_seeded(1);
+        assert!(playlist.is_shuffled());
+        assert_eq!(playlist.current_file().cloned(), current_file);
+
+        playlist.set_shuffle(false);
+        assert!(!playlist.is_shuffled());
+        assert_eq!(playlist.files(), create_test_playlist(10).files());
+        assert_eq!(playlist.current_index(), Some(1));
     }
 }
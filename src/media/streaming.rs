@@ -4,23 +4,56 @@
 //! to DLNA devices, including video and subtitle file streaming.
 
 use crate::{
-    config::{DEFAULT_STREAMING_PORT, INVALID_SOCKET_ADDRESS_MSG},
+    config::{
+        DEFAULT_STREAMING_CHUNK_SIZE, DEFAULT_STREAMING_PORT, INVALID_SOCKET_ADDRESS_MSG,
+        STREAMING_FILE_HANDLE_CACHE_SIZE, SUBTITLE_COMPRESSION_MIN_BYTES, USER_AGENT,
+        clamp_chunk_size,
+    },
     error::{Error, Result},
-    utils::{detect_subtitle_type, sanitize_filename_for_url},
+    types::SubtitleType,
+    utils::{
+        URL_SEGMENT_UNRESERVED, check_file_readable, detect_subtitle_type, file_url_path, host_url,
+        http_server_header,
+    },
 };
 use axum::{
     Router,
-    http::{StatusCode, header},
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, Uri, header},
+    middleware::{Next, from_fn, map_response},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{MethodRouter, get},
 };
-use local_ip_address::local_ip;
-use log::debug;
-use std::net::SocketAddr;
-use tokio::net::TcpListener;
+use futures_util::{Stream, StreamExt, stream};
+use local_ip_address::{list_afinet_netifas, local_ip};
+use log::{debug, trace, warn};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::{
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    net::{IpAddr, SocketAddr},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, ReadBuf},
+    net::TcpListener,
+    sync::{Notify, mpsc},
+};
+use tokio_util::io::ReaderStream;
+use tower::ServiceExt;
+use tower_http::compression::{CompressionLayer, predicate::SizeAbove};
 
 /// Default port to use for the streaming server
-pub const STREAMING_PORT_DEFAULT: u32 = DEFAULT_STREAMING_PORT;
+pub const STREAMING_PORT_DEFAULT: u16 = DEFAULT_STREAMING_PORT;
 
 /// A media file to stream
 #[derive(Debug, Clone)]
@@ -30,6 +63,54 @@ pub struct MediaFile {
     file_uri: String,
 }
 
+impl MediaFile {
+    /// Creates a media file served by the server at `server_addr`
+    fn new(file_path: &Path, server_addr: SocketAddr) -> Self {
+        Self {
+            file_path: file_path.to_path_buf(),
+            host_uri: host_url(server_addr),
+            file_uri: file_url_path(file_path),
+        }
+    }
+
+    /// Gives the file a URL path distinct from the other served files
+    ///
+    /// Two different files only get the same URL path on a token collision. A
+    /// numeric suffix is then added, so that a route never serves the wrong
+    /// file. The same file served twice keeps its URL path.
+    fn disambiguated(mut self, served: &[&MediaFile]) -> Self {
+        let base_uri = self.file_uri.clone();
+        let mut suffix = 0;
+        while served
+            .iter()
+            .any(|other| other.file_uri == self.file_uri && !other.is_same_file(&self))
+        {
+            suffix += 1;
+            self.file_uri = match base_uri.split_once('/') {
+                Some((token, filename)) => format!("{token}-{suffix}/{filename}"),
+                None => format!("{base_uri}-{suffix}"),
+            };
+            warn!(
+                "URL collision for {}, serving it at /{}",
+                self.file_path.display(),
+                self.file_uri
+            );
+        }
+        self
+    }
+
+    /// Checks whether both entries point at the same file on disk
+    fn is_same_file(&self, other: &MediaFile) -> bool {
+        match (
+            self.file_path.canonicalize(),
+            other.file_path.canonicalize(),
+        ) {
+            (Ok(path), Ok(other_path)) => path == other_path,
+            _ => self.file_path == other.file_path,
+        }
+    }
+}
+
 impl std::fmt::Display for MediaFile {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
@@ -42,12 +123,91 @@ impl std::fmt::Display for MediaFile {
     }
 }
 
+/// A subtitle track served next to the video
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleTrack {
+    /// URI the subtitle is served at
+    pub uri: String,
+    /// Path to the subtitle file
+    pub path: PathBuf,
+    /// Format of the subtitle, if recognized
+    pub format: Option<SubtitleType>,
+}
+
+impl SubtitleTrack {
+    /// Gets the MIME type the subtitle is served with
+    pub fn mime_type(&self) -> String {
+        match self.format {
+            Some(format) => format.mime_type().to_string(),
+            None => "text/plain".to_string(),
+        }
+    }
+}
+
 /// A media streaming server
 #[derive(Debug, Clone)]
 pub struct MediaStreamingServer {
     video_file: MediaFile,
-    subtitle_file: Option<MediaFile>,
+    subtitle_files: Vec<MediaFile>,
+    next_file: Option<MediaFile>,
     server_addr: SocketAddr,
+    server_header: String,
+    chunk_size: usize,
+    read_ahead: usize,
+    read_throttle: Option<ReadThrottle>,
+    file_handles: Arc<FileHandleCache>,
+}
+
+/// Holds back disk reads of streamed files, e.g. while playback is paused
+///
+/// Cloned handles share the same state, so the playback loop can pause reads
+/// of responses that are already being streamed.
+#[derive(Debug, Clone, Default)]
+pub struct ReadThrottle {
+    inner: Arc<ReadThrottleState>,
+}
+
+#[derive(Debug, Default)]
+struct ReadThrottleState {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl ReadThrottle {
+    /// Creates a throttle that lets reads through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds back or lets through reads
+    pub fn set_paused(&self, paused: bool) {
+        if self.inner.paused.swap(paused, Ordering::SeqCst) != paused {
+            debug!(
+                "{} disk reads for streamed files",
+                if paused { "Pausing" } else { "Resuming" }
+            );
+        }
+        if !paused {
+            self.inner.resumed.notify_waiters();
+        }
+    }
+
+    /// Returns whether reads are held back
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+
+    /// Waits until reads are let through
+    async fn wait_while_paused(&self) {
+        loop {
+            // Registered before checking the flag, so a resume can't be missed
+            let resumed = self.inner.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
 }
 
 impl MediaStreamingServer {
@@ -56,74 +216,186 @@ impl MediaStreamingServer {
         video_path: &std::path::Path,
         subtitle_path: &Option<std::path::PathBuf>,
         host_ip: &String,
-        host_port: &u32,
+        host_port: &u16,
     ) -> Result<Self> {
         let server_addr_str = format!("{host_ip}:{host_port}");
         let server_addr: SocketAddr =
             server_addr_str
-                .parse()
+                .parse::<SocketAddr>()
                 .map_err(|e| Error::NetworkAddressParseError {
                     address: server_addr_str.clone(),
-                    reason: format!("{INVALID_SOCKET_ADDRESS_MSG}: {e}"),
+                    reason: INVALID_SOCKET_ADDRESS_MSG.to_string(),
+                    source: e.into(),
                 })?;
 
+        // A renderer can't reach a loopback or unspecified address, playback would silently fail
+        let ip = server_addr.ip();
+        if ip.is_loopback() || ip.is_unspecified() {
+            return Err(Error::UnreachableStreamingAddress {
+                address: server_addr.to_string(),
+            });
+        }
+
+        check_file_readable(video_path, "Media file does not exist")?;
+
         debug!("Creating video file route in streaming server");
-        let video_file = MediaFile {
-            file_path: video_path.to_path_buf(),
-            host_uri: format!("http://{server_addr}"),
-            file_uri: sanitize_filename_for_url(&video_path.display().to_string()),
-        };
+        let video_file = MediaFile::new(video_path, server_addr);
 
         debug!("Creating subtitle file route in streaming server");
-        let subtitle_file = match subtitle_path {
-            Some(subtitle_path) => match subtitle_path.exists() {
-                true => Some(MediaFile {
-                    file_path: subtitle_path.clone(),
-                    host_uri: format!("http://{server_addr}"),
-                    file_uri: sanitize_filename_for_url(&subtitle_path.display().to_string()),
-                }),
-                false => {
-                    return Err(Error::MediaFileNotFound {
-                        path: subtitle_path.display().to_string(),
-                        context: "Subtitle file does not exist or is not accessible".to_string(),
-                    });
-                }
-            },
-            None => None,
+        let subtitle_files = match subtitle_path {
+            Some(subtitle_path) => {
+                check_file_readable(subtitle_path, "Subtitle file does not exist")?;
+                vec![MediaFile::new(subtitle_path, server_addr).disambiguated(&[&video_file])]
+            }
+            None => Vec::new(),
         };
 
         Ok(Self {
             video_file,
-            subtitle_file,
+            subtitle_files,
+            next_file: None,
             server_addr,
+            server_header: USER_AGENT.to_string(),
+            chunk_size: DEFAULT_STREAMING_CHUNK_SIZE,
+            read_ahead: 0,
+            read_throttle: None,
+            file_handles: Arc::default(),
         })
     }
 
+    /// Sets the size of the chunks read from disk when streaming a file
+    ///
+    /// Sizes outside `MIN_STREAMING_CHUNK_SIZE..=MAX_STREAMING_CHUNK_SIZE` are
+    /// brought back within.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = clamp_chunk_size(chunk_size);
+        self
+    }
+
+    /// Reads up to `read_ahead` bytes from disk ahead of the connection, 0 to disable
+    ///
+    /// Each response then buffers up to that many bytes in memory, which
+    /// smooths out slow storage for high-bitrate files.
+    pub fn with_read_ahead(mut self, read_ahead: usize) -> Self {
+        self.read_ahead = read_ahead;
+        self
+    }
+
+    /// Holds back disk reads while the given throttle is paused, `None` to never hold them back
+    pub fn with_read_throttle(mut self, read_throttle: Option<ReadThrottle>) -> Self {
+        self.read_throttle = read_throttle;
+        self
+    }
+
+    /// Gets the throttle holding back disk reads, if any
+    pub fn read_throttle(&self) -> Option<&ReadThrottle> {
+        self.read_throttle.as_ref()
+    }
+
+    /// Also serves another subtitle track, e.g. in a different language
+    ///
+    /// The subtitle given to [`MediaStreamingServer::new`] stays the first
+    /// track, the one the accessors like [`MediaStreamingServer::subtitle_uri`]
+    /// refer to. A file that is already served is not added again.
+    pub fn with_subtitle(mut self, subtitle_path: &Path) -> Result<Self> {
+        check_file_readable(subtitle_path, "Subtitle file does not exist")?;
+        let subtitle_file = MediaFile::new(subtitle_path, self.server_addr);
+        if self
+            .subtitle_files
+            .iter()
+            .any(|served| served.is_same_file(&subtitle_file))
+        {
+            return Ok(self);
+        }
+
+        debug!("Creating subtitle file route in streaming server");
+        let served: Vec<&MediaFile> = std::iter::once(&self.video_file)
+            .chain(&self.subtitle_files)
+            .collect();
+        let subtitle_file = subtitle_file.disambiguated(&served);
+        self.subtitle_files.push(subtitle_file);
+        Ok(self)
+    }
+
+    /// Also serves the file played after the current one, so it can be pre-loaded for gapless playback
+    pub fn with_next_file(mut self, next_path: &std::path::Path) -> Self {
+        debug!("Creating next file route in streaming server");
+        let served: Vec<&MediaFile> = std::iter::once(&self.video_file)
+            .chain(&self.subtitle_files)
+            .collect();
+        let next_file = MediaFile::new(next_path, self.server_addr).disambiguated(&served);
+        self.next_file = Some(next_file);
+        self
+    }
+
+    /// Sets the value of the `Server` header sent with every response
+    pub fn with_server_header(mut self, server_header: &str) -> Self {
+        self.server_header = server_header.to_string();
+        self
+    }
+
+    /// Gets the value of the `Server` header sent with every response
+    pub fn server_header(&self) -> &str {
+        &self.server_header
+    }
+
     /// Gets the video URI
     #[doc(hidden)]
     pub fn video_uri(&self) -> String {
         format!("{}/{}", self.video_file.host_uri, self.video_file.file_uri)
     }
 
-    /// Gets the subtitle URI if available
+    /// Gets the URI of the first subtitle track if available
     pub fn subtitle_uri(&self) -> Option<String> {
-        self.subtitle_file
-            .as_ref()
-            .map(|subtitle| format!("{}/{}", subtitle.host_uri, subtitle.file_uri))
+        self.subtitle_tracks()
+            .into_iter()
+            .next()
+            .map(|track| track.uri)
     }
 
-    /// Gets the subtitle file path if available
+    /// Gets the file path of the first subtitle track if available
     pub fn subtitle_file_path(&self) -> Option<&std::path::Path> {
-        self.subtitle_file
-            .as_ref()
+        self.subtitle_files
+            .first()
             .map(|subtitle| subtitle.file_path.as_path())
     }
 
+    /// Gets all subtitle tracks, in the order they were added
+    pub fn subtitle_tracks(&self) -> Vec<SubtitleTrack> {
+        self.subtitle_files
+            .iter()
+            .map(|subtitle| SubtitleTrack {
+                uri: format!("{}/{}", subtitle.host_uri, subtitle.file_uri),
+                path: subtitle.file_path.clone(),
+                format: detect_subtitle_type(&subtitle.file_path),
+            })
+            .collect()
+    }
+
     /// Gets the video file path
     pub fn video_file_path(&self) -> &std::path::Path {
         &self.video_file.file_path
     }
 
+    /// Gets the URI of the file played after the current one, if any
+    pub fn next_uri(&self) -> Option<String> {
+        self.next_file
+            .as_ref()
+            .map(|next| format!("{}/{}", next.host_uri, next.file_uri))
+    }
+
+    /// Gets the path of the file played after the current one, if any
+    pub fn next_file_path(&self) -> Option<&std::path::Path> {
+        self.next_file.as_ref().map(|next| next.file_path.as_path())
+    }
+
+    /// Gets the MIME type of the file played after the current one, if any
+    pub fn next_type(&self) -> Option<String> {
+        self.next_file
+            .as_ref()
+            .map(|next| get_mime_type_from_path(&next.file_path))
+    }
+
     /// Gets the server address
     pub fn server_addr(&self) -> SocketAddr {
         self.server_addr
@@ -134,36 +406,123 @@ impl MediaStreamingServer {
         get_mime_type_from_path(&self.video_file.file_path)
     }
 
-    /// Gets the subtitle file type/MIME type if available
+    /// Gets the file type/MIME type of the first subtitle track if available
     pub fn subtitle_type(&self) -> Option<String> {
-        self.subtitle_file.as_ref().map(|subtitle| {
-            let subtitle_type = detect_subtitle_type(&subtitle.file_path);
-            match subtitle_type {
-                Some(sub_type) => sub_type.mime_type().to_string(),
-                None => "text/plain".to_string(),
-            }
-        })
+        self.subtitle_tracks().first().map(SubtitleTrack::mime_type)
+    }
+
+    /// Gets the format of the first subtitle track if available and recognized
+    pub fn subtitle_format(&self) -> Option<SubtitleType> {
+        self.subtitle_tracks()
+            .first()
+            .and_then(|track| track.format)
     }
 
     /// Creates the axum router for serving media files
     fn get_routes(self) -> Router {
         let video_file_path = self.video_file.file_path.clone();
         let video_file_uri = self.video_file.file_uri.clone();
+        let video_type = self.video_type();
+
+        let buffering = Buffering {
+            chunk_size: self.chunk_size,
+            read_ahead: self.read_ahead,
+            throttle: self.read_throttle.clone(),
+            file_handles: self.file_handles.clone(),
+        };
 
-        Router::new().route(
+        let router = Router::new().route(
             &format!("/{video_file_uri}"),
-            get(move || serve_video_file(video_file_path)),
-        )
+            file_handler(video_file_path, video_type, buffering.clone()),
+        );
+
+        let router = self.subtitle_files.iter().zip(self.subtitle_tracks()).fold(
+            router,
+            |router, (subtitle_file, track)| {
+                debug!("Serving subtitle file: {subtitle_file}");
+                router.route(
+                    &format!("/{}", subtitle_file.file_uri),
+                    file_handler(track.path.clone(), track.mime_type(), buffering.clone())
+                        .layer(subtitle_compression()),
+                )
+            },
+        );
+
+        let router = match &self.next_file {
+            Some(next_file) if next_file.file_uri != video_file_uri => {
+                debug!("Serving next file: {next_file}");
+                let next_file_path = next_file.file_path.clone();
+                let next_type = get_mime_type_from_path(&next_file_path);
+                router.route(
+                    &format!("/{}", next_file.file_uri),
+                    file_handler(next_file_path, next_type, buffering),
+                )
+            }
+            _ => router,
+        };
+
+        let server_header = HeaderValue::from_str(&self.server_header).unwrap_or_else(|_| {
+            warn!(
+                "Invalid Server header '{}', using '{USER_AGENT}'",
+                self.server_header
+            );
+            HeaderValue::from_static(USER_AGENT)
+        });
+        let router = router
+            .layer(map_response(move |mut response: Response| {
+                let server_header = server_header.clone();
+                async move {
+                    response.headers_mut().insert(header::SERVER, server_header);
+                    response
+                }
+            }))
+            .layer(from_fn(log_request));
+
+        // Routing happens after the path is rewritten, so the rewrite wraps the router
+        Router::new().fallback_service(router.map_request(canonical_request_path))
+    }
+
+    /// Listens on the address of the server, without serving yet.
+    ///
+    /// When the address is already in use, the error tells whether it is
+    /// taken by another crab-dlna server, found by its `Server` header.
+    pub async fn bind(&self) -> Result<TcpListener> {
+        let source = match TcpListener::bind(self.server_addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(source) => source,
+        };
+        let other_instance = source.kind() == std::io::ErrorKind::AddrInUse
+            && is_crab_dlna_server(self.server_addr).await;
+        Err(Error::StreamingBindError {
+            address: self.server_addr.to_string(),
+            source,
+            other_instance,
+        })
+    }
+
+    /// Serves the media files on a listener from [`bind`](Self::bind).
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        axum::serve(listener, self.get_routes()).await?;
+        Ok(())
     }
 
     /// Start the media streaming server.
-    pub async fn run(self) {
-        let app = self.clone().get_routes();
-        let listener = TcpListener::bind(self.server_addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+    pub async fn run(self) -> Result<()> {
+        let listener = self.bind().await?;
+        self.serve(listener).await
     }
 }
 
+/// Whether the HTTP server listening on an address is a crab-dlna server
+async fn is_crab_dlna_server(address: SocketAddr) -> bool {
+    let Ok(url) = format!("http://{address}/").parse::<Uri>() else {
+        return false;
+    };
+    http_server_header(&url)
+        .await
+        .is_some_and(|server| server.contains("crab-dlna/"))
+}
+
 /// Identifies the local serve IP address.
 pub async fn get_local_ip() -> Result<String> {
     debug!("Identifying local IP address of host");
@@ -175,8 +534,142 @@ pub async fn get_local_ip() -> Result<String> {
         .to_string())
 }
 
+/// Identifies the IP address of the named network interface.
+///
+/// IPv4 addresses are preferred; IPv6 addresses are returned in brackets so
+/// they can be combined with a port.
+pub fn get_interface_ip(name: &str) -> Result<String> {
+    debug!("Identifying IP address of network interface '{name}'");
+    let interfaces = list_afinet_netifas().map_err(|err| Error::LocalAddressResolutionFailed {
+        source: err,
+        context: "Failed to list network interfaces".to_string(),
+    })?;
+
+    let addresses: Vec<IpAddr> = interfaces
+        .into_iter()
+        .filter(|(interface, _)| interface == name)
+        .map(|(_, address)| address)
+        .collect();
+
+    if addresses.is_empty() {
+        return Err(Error::NetworkInterfaceNotFound {
+            name: name.to_string(),
+            context: "No interface with this name exists".to_string(),
+        });
+    }
+
+    addresses
+        .iter()
+        .find(|address| address.is_ipv4())
+        .or_else(|| addresses.iter().find(|address| address.is_ipv6()))
+        .map(|address| match address {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{address}]"),
+        })
+        .ok_or_else(|| Error::NetworkInterfaceNotFound {
+            name: name.to_string(),
+            context: "Interface has no usable IP address".to_string(),
+        })
+}
+
+/// An address of a local network interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    /// Name of the interface, e.g. `eth0`
+    pub name: String,
+    /// One of the addresses of the interface
+    pub address: IpAddr,
+    /// Whether files are served from this address when no host is given
+    pub is_default: bool,
+}
+
+impl std::fmt::Display for InterfaceAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let marker = if self.is_default { '*' } else { ' ' };
+        write!(f, "{marker} {:<16} {}", self.name, self.address)
+    }
+}
+
+/// Lists the addresses of all local network interfaces
+///
+/// The address `get_local_ip` would pick is marked as the default one.
+pub fn list_interface_addresses() -> Result<Vec<InterfaceAddress>> {
+    let interfaces = list_afinet_netifas().map_err(|err| Error::LocalAddressResolutionFailed {
+        source: err,
+        context: "Failed to list network interfaces".to_string(),
+    })?;
+    let default_address = local_ip().ok();
+
+    Ok(interfaces
+        .into_iter()
+        .map(|(name, address)| InterfaceAddress {
+            is_default: Some(address) == default_address,
+            name,
+            address,
+        })
+        .collect())
+}
+
+/// Rewrites the request path to the encoding of the advertised file URLs
+///
+/// Renderers don't always send a URL back exactly as advertised: some decode
+/// characters such as `(`, others use lowercase hex digits. Each segment is
+/// decoded and encoded again like `file_url_path` does, so that equivalent
+/// paths all match the route of the file.
+fn canonical_request_path(mut request: Request) -> Request {
+    let path = request.uri().path();
+    let canonical_path = path
+        .split('/')
+        .map(|segment| {
+            utf8_percent_encode(
+                &percent_decode_str(segment).decode_utf8_lossy(),
+                URL_SEGMENT_UNRESERVED,
+            )
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if canonical_path == path {
+        return request;
+    }
+
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{canonical_path}?{query}"),
+        None => canonical_path,
+    };
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = path_and_query.parse().ok();
+    match Uri::from_parts(parts) {
+        Ok(uri) => *request.uri_mut() = uri,
+        Err(e) => debug!("Failed to rewrite request path '{path_and_query}': {e}"),
+    }
+    request
+}
+
+/// Builds the `Content-Disposition` header value naming the served file
+///
+/// Characters that can't appear in a quoted ASCII header value are replaced.
+fn content_disposition(path: &Path) -> String {
+    let filename: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("inline; filename=\"{filename}\"")
+}
+
 /// Gets MIME type from file path extension
+///
+/// Subtitle extensions resolve to the MIME type of their [`SubtitleType`].
 fn get_mime_type_from_path(path: &std::path::Path) -> String {
+    if let Some(subtitle_type) = detect_subtitle_type(path) {
+        return subtitle_type.mime_type().to_string();
+    }
     if let Some(extension) = path.extension() {
         if let Some(ext_str) = extension.to_str() {
             match ext_str.to_lowercase().as_str() {
@@ -206,20 +699,1037 @@ fn get_mime_type_from_path(path: &std::path::Path) -> String {
     .to_string()
 }
 
-/// Serves a video file using axum
-async fn serve_video_file(file_path: std::path::PathBuf) -> Response {
-    debug!("Serving video file: {}", file_path.display());
+/// Logs each request with its range and the response it got
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let range = header_str(request.headers(), header::RANGE).unwrap_or("none".to_string());
+    debug!("{method} {path} (range: {range})");
+
+    let response = next.run(request).await;
+
+    let served = header_str(response.headers(), header::CONTENT_RANGE)
+        .or_else(|| {
+            header_str(response.headers(), header::CONTENT_LENGTH)
+                .map(|length| format!("{length} bytes"))
+        })
+        .unwrap_or("no body".to_string());
+    debug!("{method} {path} -> {} ({served})", response.status());
+    response
+}
+
+/// Gets a header value as a string, if present and valid
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// A body stream counting the bytes handed to the connection
+///
+/// The count is logged when the stream is dropped, which also happens when a
+/// renderer closes the connection early, e.g. to seek. Dropping the stream
+/// drops the file reader with it, so no more of the file is read.
+struct CountingStream<S> {
+    inner: S,
+    path: PathBuf,
+    sent: u64,
+    expected: u64,
+}
+
+impl<S> Stream for CountingStream<S>
+where
+    S: Stream<Item = std::io::Result<bytes::Bytes>> + Unpin,
+{
+    type Item = std::io::Result<bytes::Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.sent += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl<S> Drop for CountingStream<S> {
+    fn drop(&mut self) {
+        if self.sent < self.expected {
+            debug!(
+                "Transfer of {} aborted by the client after {}/{} bytes",
+                self.path.display(),
+                self.sent,
+                self.expected
+            );
+        } else {
+            trace!(
+                "Sent {}/{} bytes of {}",
+                self.sent,
+                self.expected,
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// A parsed `Range` request header
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// No usable range was requested, the whole file is served
+    Full,
+    /// An inclusive range of bytes within the file
+    Partial(u64, u64),
+    /// The requested range lies outside of the file
+    Unsatisfiable,
+}
+
+/// Parses a single-range `Range` header value against the file size
+///
+/// Malformed and multi-range values are ignored, as allowed by RFC 9110.
+fn parse_byte_range(value: &str, file_size: u64) -> ByteRange {
+    let Some(range) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if range.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = range.split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => (
+                file_size.saturating_sub(suffix),
+                file_size.saturating_sub(1),
+            ),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, file_size.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(file_size.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        },
+    };
+
+    if start >= file_size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end)
+}
+
+/// How files are read from disk while being streamed
+#[derive(Debug, Clone)]
+struct Buffering {
+    /// Size of each read, in bytes
+    chunk_size: usize,
+    /// Bytes read ahead of the connection, 0 to disable
+    read_ahead: usize,
+    /// Holds back reads while paused
+    throttle: Option<ReadThrottle>,
+    /// Handles left open by previous responses
+    file_handles: Arc<FileHandleCache>,
+}
+
+/// Idle file handles kept open between responses, least recently used first
+///
+/// Renderers often fire many short range requests while seeking or probing a
+/// file, so reusing a handle saves reopening the file for each of them.
+#[derive(Debug, Default)]
+struct FileHandleCache {
+    idle: Mutex<VecDeque<(PathBuf, std::fs::File)>>,
+    /// Number of times an idle handle was reused
+    reuses: AtomicUsize,
+}
+
+impl FileHandleCache {
+    /// Takes an idle handle on `path` out of the cache, or opens a new one
+    async fn open(self: &Arc<Self>, path: &Path) -> io::Result<CachedFile> {
+        let idle = {
+            let mut handles = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+            handles
+                .iter()
+                .rposition(|(handle_path, _)| handle_path == path)
+                .and_then(|index| handles.remove(index))
+        };
+        let file = match idle {
+            Some((_, file)) => {
+                self.reuses.fetch_add(1, Ordering::Relaxed);
+                trace!("Reusing open handle of {}", path.display());
+                File::from_std(file)
+            }
+            None => File::open(path).await?,
+        };
+        Ok(CachedFile {
+            file: Some(file),
+            path: path.to_path_buf(),
+            cache: self.clone(),
+        })
+    }
 
-    match tokio::fs::read(&file_path).await {
-        Ok(contents) => {
-            let mime_type = get_mime_type_from_path(&file_path);
-            (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, mime_type)],
-                contents,
+    /// Puts an idle handle back, closing the least recently used one when full
+    fn release(&self, path: PathBuf, file: std::fs::File) {
+        let mut handles = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        handles.push_back((path, file));
+        if handles.len() > STREAMING_FILE_HANDLE_CACHE_SIZE {
+            handles.pop_front();
+        }
+    }
+
+    /// Number of idle handles in the cache
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// Number of times an idle handle was reused
+    #[cfg(test)]
+    fn reuses(&self) -> usize {
+        self.reuses.load(Ordering::Relaxed)
+    }
+}
+
+/// A file handle given back to its cache once dropped
+///
+/// Handles dropped in the middle of a read, e.g. when the renderer closes the
+/// connection, are closed instead.
+struct CachedFile {
+    file: Option<File>,
+    path: PathBuf,
+    cache: Arc<FileHandleCache>,
+}
+
+impl Deref for CachedFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        self.file.as_ref().expect("file is only taken on drop")
+    }
+}
+
+impl DerefMut for CachedFile {
+    fn deref_mut(&mut self) -> &mut File {
+        self.file.as_mut().expect("file is only taken on drop")
+    }
+}
+
+impl AsyncRead for CachedFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl Drop for CachedFile {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take()
+            && let Ok(file) = file.try_into_std()
+        {
+            self.cache.release(std::mem::take(&mut self.path), file);
+        }
+    }
+}
+
+/// Creates the route handler serving a file
+fn file_handler(file_path: PathBuf, mime_type: String, buffering: Buffering) -> MethodRouter {
+    get(move |headers: HeaderMap| serve_file(file_path, mime_type, buffering, headers))
+}
+
+/// Compresses subtitle responses for clients accepting gzip or deflate
+///
+/// Only full responses above `SUBTITLE_COMPRESSION_MIN_BYTES` are compressed,
+/// range responses are sent as-is. Media files are never compressed, as their
+/// codecs already are.
+fn subtitle_compression() -> CompressionLayer<SizeAbove> {
+    CompressionLayer::new()
+        .gzip(true)
+        .deflate(true)
+        .compress_when(SizeAbove::new(SUBTITLE_COMPRESSION_MIN_BYTES))
+}
+
+/// Waits for `throttle` to let reads through before each read from `stream`
+fn throttled<S>(stream: S, throttle: ReadThrottle) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + Unpin + 'static,
+    S::Item: Send,
+{
+    stream::unfold((stream, throttle), |(mut stream, throttle)| async move {
+        throttle.wait_while_paused().await;
+        let chunk = stream.next().await?;
+        Some((chunk, (stream, throttle)))
+    })
+}
+
+/// Reads chunks from `stream` in a background task, keeping up to `chunks` of them buffered
+///
+/// The task stops once the returned stream is dropped, e.g. when the renderer
+/// closes the connection, even while it waits for a read.
+fn read_ahead<S>(mut stream: S, chunks: usize) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + Unpin + 'static,
+    S::Item: Send,
+{
+    let (sender, receiver) = mpsc::channel(chunks.max(1));
+    tokio::spawn(async move {
+        loop {
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                () = sender.closed() => break,
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+/// Serves a media file using axum, streaming it from disk in chunks
+///
+/// Single-range `Range` requests are answered with `206 Partial Content`, so
+/// renderers can seek without downloading the whole file.
+async fn serve_file(
+    file_path: PathBuf,
+    mime_type: String,
+    buffering: Buffering,
+    headers: HeaderMap,
+) -> Response {
+    debug!("Serving file: {}", file_path.display());
+
+    let mut file = match buffering.file_handles.open(&file_path).await {
+        Ok(file) => file,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let file_size = match file.metadata().await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(ByteRange::Full, |value| parse_byte_range(value, file_size));
+
+    let (status, start, length) = match range {
+        ByteRange::Full => (StatusCode::OK, 0, file_size),
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        ByteRange::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{file_size}"))],
             )
-                .into_response()
+                .into_response();
         }
-        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let disposition = content_disposition(&file_path);
+    // Always seeking, as a reused handle is left where the previous response stopped
+    if file.seek(SeekFrom::Start(start)).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let stream = ReaderStream::with_capacity(file.take(length), buffering.chunk_size);
+    // Throttled before reading ahead, so that the disk reads are the ones held back
+    let stream = match buffering.throttle {
+        Some(throttle) => throttled(stream, throttle).boxed(),
+        None => stream.boxed(),
+    };
+    let stream = match buffering.read_ahead {
+        0 => stream.boxed(),
+        bytes => read_ahead(stream, bytes / buffering.chunk_size).boxed(),
+    };
+    let body = Body::from_stream(CountingStream {
+        inner: stream,
+        path: file_path,
+        sent: 0,
+        expected: length,
+    });
+
+    let mut response = (
+        status,
+        [
+            (header::CONTENT_TYPE, mime_type),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+            (header::CONTENT_LENGTH, length.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response();
+    if status == StatusCode::PARTIAL_CONTENT
+        && let Ok(content_range) =
+            HeaderValue::from_str(&format!("bytes {start}-{}/{file_size}", start + length - 1))
+    {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_RANGE, content_range);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{compute_file_uri, testing::TestDir};
+    use axum::body::to_bytes;
+    use axum::http::Request;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    /// Issues a GET request for the given path against the server routes
+    async fn get(server: &MediaStreamingServer, path: &str) -> Response {
+        server
+            .clone()
+            .get_routes()
+            .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Extracts the path component of a served URI
+    fn uri_path(uri: &str) -> String {
+        uri.parse::<axum::http::Uri>().unwrap().path().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_video_route_serves_file() {
+        let dir = TestDir::new("crab_dlna_video_route_serves_file");
+        let video_path = dir.create_file("crab_dlna_route_video.mp4", b"fake video content");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+
+        let response = get(&server, &uri_path(&server.video_uri())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "video/mp4");
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"crab_dlna_route_video.mp4\""
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"fake video content");
+    }
+
+    #[tokio::test]
+    async fn test_subtitle_route_serves_file() {
+        let dir = TestDir::new("crab_dlna_subtitle_route_serves_file");
+        let video_path = dir.create_file("crab_dlna_route_sub_video.mp4", b"fake video");
+        let subtitle_path = dir.create_file(
+            "crab_dlna_route_sub_video.srt",
+            b"1\n00:00:01,000 --> 00:00:02,000\nHello\n",
+        );
+        let server = MediaStreamingServer::new(
+            &video_path,
+            &Some(subtitle_path.clone()),
+            &"192.168.1.100".to_string(),
+            &9000,
+        )
+        .unwrap();
+
+        let response = get(&server, &uri_path(&server.subtitle_uri().unwrap())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/srt");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.starts_with(b"1\n00:00:01,000"));
+    }
+
+    #[tokio::test]
+    async fn test_large_subtitles_are_compressed_when_accepted() {
+        use std::io::Read;
+
+        let dir = TestDir::new("crab_dlna_large_subtitles_are_compressed_when_accepted");
+        let video_path = dir.create_file("crab_dlna_route_gzip_video.mp4", b"fake video");
+        let cues: String = (1..=100)
+            .map(|i| {
+                format!(
+                    "{i}\n00:00:{:02},000 --> 00:00:{:02},500\nLine {i}\n\n",
+                    i % 60,
+                    i % 60
+                )
+            })
+            .collect();
+        let subtitle_path = dir.create_file("crab_dlna_route_gzip_video.srt", cues.as_bytes());
+        let server = MediaStreamingServer::new(
+            &video_path,
+            &Some(subtitle_path.clone()),
+            &"192.168.1.100".to_string(),
+            &9000,
+        )
+        .unwrap();
+        let request = |path: &str, accept_encoding: &str| {
+            Request::builder()
+                .uri(path)
+                .header(header::ACCEPT_ENCODING, accept_encoding)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let subtitle_path_uri = uri_path(&server.subtitle_uri().unwrap());
+        let response = server
+            .clone()
+            .get_routes()
+            .oneshot(request(&subtitle_path_uri, "gzip"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.len() < cues.len());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, cues);
+
+        // Without support advertised, and never for the video
+        let response = get(&server, &subtitle_path_uri).await;
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        let video_path_uri = uri_path(&server.video_uri());
+        let response = server
+            .clone()
+            .get_routes()
+            .oneshot(request(&video_path_uri, "gzip"))
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn test_subtitle_route_content_type_matches_subtitle_type() {
+        let dir = TestDir::new("crab_dlna_subtitle_route_content_type_matches_subtitle_type");
+        let video_path = dir.create_file("crab_dlna_route_sub_types.mp4", b"fake video");
+        for subtitle_type in SubtitleType::all() {
+            let subtitle_path = dir.create_file(
+                &format!("crab_dlna_route_sub_types.{}", subtitle_type.extension()),
+                b"subtitle",
+            );
+            let server = MediaStreamingServer::new(
+                &video_path,
+                &Some(subtitle_path.clone()),
+                &"192.168.1.100".to_string(),
+                &9000,
+            )
+            .unwrap();
+
+            let response = get(&server, &uri_path(&server.subtitle_uri().unwrap())).await;
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                subtitle_type.mime_type()
+            );
+            assert_eq!(
+                get_mime_type_from_path(&subtitle_path),
+                subtitle_type.mime_type()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_additional_subtitle_tracks_are_served() {
+        let dir = TestDir::new("crab_dlna_additional_subtitle_tracks_are_served");
+        let video_path = dir.create_file("crab_dlna_route_tracks.mp4", b"fake video");
+        let english = dir.create_file("crab_dlna_route_tracks.en.srt", b"english");
+        let french = dir.create_file("crab_dlna_route_tracks.fr.vtt", b"WEBVTT");
+        let server = MediaStreamingServer::new(
+            &video_path,
+            &Some(english.clone()),
+            &"192.168.1.100".to_string(),
+            &9000,
+        )
+        .unwrap()
+        .with_subtitle(&french)
+        .unwrap()
+        .with_subtitle(&english)
+        .unwrap();
+
+        let tracks = server.subtitle_tracks();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(server.subtitle_file_path(), Some(english.as_path()));
+        assert_eq!(tracks[1].format, Some(SubtitleType::Vtt));
+
+        let response = get(&server, &uri_path(&tracks[1].uri)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/vtt");
+    }
+
+    #[tokio::test]
+    async fn test_server_header() {
+        let dir = TestDir::new("crab_dlna_server_header");
+        let video_path = dir.create_file("crab_dlna_server_header.mp4", b"video");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+        let response = get(&server, "/missing").await;
+        assert_eq!(response.headers()[header::SERVER], USER_AGENT);
+
+        let server = server.with_server_header("Samsung AllShare/1.0");
+        let path = uri_path(&server.video_uri());
+        let response = get(&server, &path).await;
+        assert_eq!(response.headers()[header::SERVER], "Samsung AllShare/1.0");
+    }
+
+    #[tokio::test]
+    async fn test_next_file_route_serves_file() {
+        let dir = TestDir::new("crab_dlna_next_file_route_serves_file");
+        let video_path = dir.create_file("crab_dlna_route_current.mp4", b"current");
+        let next_path = dir.create_file("crab_dlna_route_next.mp4", b"next");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap()
+                .with_next_file(&next_path);
+
+        let response = get(&server, &uri_path(&server.next_uri().unwrap())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"next");
+    }
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(
+            parse_byte_range("bytes=0-99", 1000),
+            ByteRange::Partial(0, 99)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=500-", 1000),
+            ByteRange::Partial(500, 999)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=-100", 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=900-5000", 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(
+            parse_byte_range("bytes=1000-", 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_byte_range("bytes=0-1,5-9", 1000), ByteRange::Full);
+        assert_eq!(parse_byte_range("items=0-1", 1000), ByteRange::Full);
+        assert_eq!(parse_byte_range("bytes=9-1", 1000), ByteRange::Full);
+    }
+
+    #[tokio::test]
+    async fn test_range_request_serves_partial_content() {
+        let dir = TestDir::new("crab_dlna_range_request_serves_partial_content");
+        let video_path = dir.create_file("crab_dlna_route_range.mp4", b"0123456789");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+
+        let request = Request::builder()
+            .uri(uri_path(&server.video_uri()))
+            .header(header::RANGE, "bytes=2-7")
+            .body(Body::empty())
+            .unwrap();
+        let response = server.clone().get_routes().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-7/10");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "6");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"234567");
+
+        let request = Request::builder()
+            .uri(uri_path(&server.video_uri()))
+            .header(header::RANGE, "bytes=20-")
+            .body(Body::empty())
+            .unwrap();
+        let response = server.get_routes().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_range_requests_share_handles() {
+        let dir = TestDir::new("crab_dlna_overlapping_range_requests_share_handles");
+        let contents: Vec<u8> = (0..=250).cycle().take(200_000).collect();
+        let video_path = dir.create_file("crab_dlna_route_overlapping.mp4", &contents);
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap()
+                .with_chunk_size(4096);
+        let path = uri_path(&server.video_uri());
+        let file_handles = server.file_handles.clone();
+        let router = server.get_routes();
+
+        for round in 0..3 {
+            let requests = (0..32u64).map(|i| {
+                let start = (i * 6_151 + round * 997) % 190_000;
+                let end = start + 1_000 + i * 257;
+                let request = Request::builder()
+                    .uri(&path)
+                    .header(header::RANGE, format!("bytes={start}-{end}"))
+                    .body(Body::empty())
+                    .unwrap();
+                let router = router.clone();
+                async move {
+                    let response = router.oneshot(request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+                    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                    (start as usize, end as usize, body)
+                }
+            });
+            for (start, end, body) in futures_util::future::join_all(requests).await {
+                assert_eq!(&body[..], &contents[start..=end.min(contents.len() - 1)]);
+            }
+        }
+        // Later rounds are served from the handles the earlier ones left open
+        assert!(file_handles.reuses() >= STREAMING_FILE_HANDLE_CACHE_SIZE);
+        assert!(file_handles.len() <= STREAMING_FILE_HANDLE_CACHE_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_file_handle_cache_reuses_and_evicts_handles() {
+        let dir = TestDir::new("crab_dlna_file_handle_cache_reuses_and_evicts_handles");
+        let path = dir.create_file("crab_dlna_handle_cache.mp4", b"0123456789");
+        let cache = Arc::new(FileHandleCache::default());
+
+        let mut file = cache.open(&path).await.unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await.unwrap();
+        drop(file);
+        assert_eq!(cache.len(), 1);
+
+        // The reused handle is left at the end of the file
+        let mut file = cache.open(&path).await.unwrap();
+        assert_eq!(cache.len(), 0);
+        file.seek(SeekFrom::Start(4)).await.unwrap();
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "456789");
+        drop(file);
+
+        let files = futures_util::future::try_join_all(
+            (0..STREAMING_FILE_HANDLE_CACHE_SIZE + 2).map(|_| cache.open(&path)),
+        )
+        .await
+        .unwrap();
+        drop(files);
+        assert_eq!(cache.len(), STREAMING_FILE_HANDLE_CACHE_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_serves_whole_file() {
+        let dir = TestDir::new("crab_dlna_read_ahead_serves_whole_file");
+        let contents: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        let video_path = dir.create_file("crab_dlna_route_read_ahead.mp4", &contents);
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap()
+                .with_chunk_size(4096)
+                .with_read_ahead(16 * 1024);
+
+        let response = get(&server, &uri_path(&server.video_uri())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], &contents[..]);
+    }
+
+    #[tokio::test]
+    async fn test_zero_chunk_size_still_serves_file() {
+        let dir = TestDir::new("crab_dlna_zero_chunk_size");
+        let contents: Vec<u8> = (0..=255).cycle().take(20_000).collect();
+        let video_path = dir.create_file("video.mp4", &contents);
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap()
+                .with_chunk_size(0)
+                .with_read_ahead(16 * 1024);
+
+        let response = get(&server, &uri_path(&server.video_uri())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], &contents[..]);
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_keeps_order() {
+        let chunks = futures_util::stream::iter((0..10).map(Ok::<u32, std::io::Error>));
+        let read: Vec<u32> = read_ahead(chunks, 3)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(read, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_stops_when_dropped() {
+        let (dropped_sender, dropped) = tokio::sync::oneshot::channel::<()>();
+        // Never yields, like a read held back while paused
+        let chunks = stream::pending::<u32>().map(move |chunk| {
+            let _ = &dropped_sender;
+            chunk
+        });
+
+        drop(read_ahead(chunks, 3));
+        let stopped = tokio::time::timeout(Duration::from_secs(1), dropped).await;
+        assert!(stopped.is_ok());
+    }
+
+    /// Chunks read from a disk stalling on every fourth read, like slow storage
+    fn slow_disk(chunks: u32) -> impl Stream<Item = u32> + Send + Unpin + 'static {
+        Box::pin(stream::iter(0..chunks).then(|chunk| async move {
+            let read_ms = if chunk % 4 == 3 { 40 } else { 2 };
+            tokio::time::sleep(Duration::from_millis(read_ms)).await;
+            chunk
+        }))
+    }
+
+    /// Plays a chunk every 15 ms, counting the chunks that weren't read in time
+    async fn count_underruns(mut chunks: impl Stream<Item = u32> + Unpin) -> usize {
+        let period = Duration::from_millis(15);
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut underruns = 0;
+        loop {
+            interval.tick().await;
+            let waiting_since = tokio::time::Instant::now();
+            if chunks.next().await.is_none() {
+                return underruns;
+            }
+            if waiting_since.elapsed() > period {
+                underruns += 1;
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_ahead_reduces_underruns_on_slow_disk() {
+        // The disk keeps up on average (11 ms per chunk), but not on every read
+        let direct = count_underruns(slow_disk(64)).await;
+        let buffered = count_underruns(read_ahead(slow_disk(64), 8).boxed()).await;
+
+        assert_eq!(direct, 16);
+        assert_eq!(buffered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_waits_while_paused() {
+        let throttle = ReadThrottle::new();
+        let mut chunks = Box::pin(throttled(stream::iter([1, 2]), throttle.clone()));
+        assert_eq!(chunks.next().await, Some(1));
+
+        throttle.set_paused(true);
+        let held_back =
+            tokio::time::timeout(std::time::Duration::from_millis(50), chunks.next()).await;
+        assert!(held_back.is_err());
+
+        throttle.set_paused(false);
+        assert_eq!(chunks.next().await, Some(2));
+        assert_eq!(chunks.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_counting_stream_counts_sent_bytes() {
+        use futures_util::StreamExt;
+
+        let chunks = futures_util::stream::iter(vec![
+            Ok(bytes::Bytes::from_static(b"abc")),
+            Ok(bytes::Bytes::from_static(b"de")),
+        ]);
+        let mut stream = CountingStream {
+            inner: chunks,
+            path: PathBuf::from("clip.mp4"),
+            sent: 0,
+            expected: 10,
+        };
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), "abc");
+        assert_eq!(stream.sent, 3);
+        while stream.next().await.is_some() {}
+        assert_eq!(stream.sent, 5);
+    }
+
+    #[test]
+    fn test_get_interface_ip_unknown_interface() {
+        let result = get_interface_ip("crab-dlna-missing0");
+        assert!(matches!(
+            result,
+            Err(Error::NetworkInterfaceNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_list_interface_addresses() {
+        let addresses = list_interface_addresses().unwrap();
+        assert!(
+            addresses
+                .iter()
+                .filter(|address| address.is_default)
+                .count()
+                <= 1
+        );
+
+        let loopback = InterfaceAddress {
+            name: "lo".to_string(),
+            address: IpAddr::from([127, 0, 0, 1]),
+            is_default: false,
+        };
+        assert_eq!(loopback.to_string(), "  lo               127.0.0.1");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_get_interface_ip_loopback() {
+        assert_eq!(get_interface_ip("lo").unwrap(), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_loopback_host_rejected() {
+        let dir = TestDir::new("crab_dlna_loopback_host_rejected");
+        let video_path = dir.create_file("crab_dlna_loopback.mp4", b"video");
+        for host in ["127.0.0.1", "0.0.0.0", "[::1]"] {
+            let result = MediaStreamingServer::new(&video_path, &None, &host.to_string(), &9000);
+            assert!(matches!(
+                result,
+                Err(Error::UnreachableStreamingAddress { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_video_uri_matches_computed_uri() {
+        let dir = TestDir::new("crab_dlna_video_uri_matches_computed_uri");
+        let video_path = dir.create_file("crab_dlna_computed_uri.mp4", b"video");
+        for host in ["192.168.1.100", "[fe80::1]"] {
+            let server =
+                MediaStreamingServer::new(&video_path, &None, &host.to_string(), &9000).unwrap();
+            assert_eq!(
+                server.video_uri(),
+                compute_file_uri(host, 9000, &video_path)
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_uri_is_opaque() {
+        let dir = TestDir::new("crab_dlna_private_dir");
+        let uri = file_url_path(&dir.join("My Movie.MKV"));
+        assert!(uri.ends_with("/My%20Movie.MKV"));
+        assert!(!uri.contains("private"));
+        assert_eq!(uri, file_url_path(&dir.join("My Movie.MKV")));
+        assert_ne!(uri, file_url_path(&dir.join("other").join("My Movie.MKV")));
+    }
+
+    #[test]
+    fn test_same_named_files_get_distinct_uris() {
+        let dir = TestDir::new("crab_dlna_collision");
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        let first = dir.join("a").join("clip.mp4");
+        let second = dir.join("b").join("clip.mp4");
+        std::fs::write(&first, b"first").unwrap();
+        std::fs::write(&second, b"second").unwrap();
+
+        let server = MediaStreamingServer::new(&first, &None, &"192.168.1.100".to_string(), &9000)
+            .unwrap()
+            .with_next_file(&second);
+        assert_ne!(server.video_uri(), server.next_uri().unwrap());
+    }
+
+    #[test]
+    fn test_disambiguated_on_token_collision() {
+        let addr: SocketAddr = "192.168.1.100:9000".parse().unwrap();
+        let video = MediaFile::new(Path::new("a/clip.mp4"), addr);
+        let mut colliding = MediaFile::new(Path::new("b/clip.mp4"), addr);
+        colliding.file_uri = video.file_uri.clone();
+
+        let colliding = colliding.disambiguated(&[&video]);
+        assert_ne!(colliding.file_uri, video.file_uri);
+        assert!(colliding.file_uri.ends_with("-1/clip.mp4"));
+
+        let same = MediaFile::new(Path::new("a/clip.mp4"), addr).disambiguated(&[&video]);
+        assert_eq!(same.file_uri, video.file_uri);
+    }
+
+    #[tokio::test]
+    async fn test_file_name_is_percent_encoded() {
+        let dir = TestDir::new("crab_dlna_file_name_is_percent_encoded");
+        let video_path = dir.create_file("Some Movie (2020).mkv", b"fake video");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+
+        let path = uri_path(&server.video_uri());
+        let segment = path.rsplit('/').next().unwrap();
+        assert_eq!(segment, "Some%20Movie%20%282020%29.mkv");
+        assert_eq!(
+            percent_encoding::percent_decode_str(segment)
+                .decode_utf8()
+                .unwrap(),
+            "Some Movie (2020).mkv"
+        );
+
+        let response = get(&server, &path).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"fake video");
+    }
+
+    #[tokio::test]
+    async fn test_unicode_file_name_round_trips() {
+        let dir = TestDir::new("crab_dlna_unicode_file_name_round_trips");
+        let video_path = dir.create_file("映画 (2020).mkv", b"fake video");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+
+        let path = uri_path(&server.video_uri());
+        let segment = path.rsplit('/').next().unwrap();
+        assert_eq!(segment, "%E6%98%A0%E7%94%BB%20%282020%29.mkv");
+        assert_eq!(
+            percent_encoding::percent_decode_str(segment)
+                .decode_utf8()
+                .unwrap(),
+            "映画 (2020).mkv"
+        );
+
+        // Equivalent spellings of the advertised path reach the same route
+        let token = path.trim_end_matches(segment);
+        for variant in [
+            path.clone(),
+            format!("{token}%e6%98%a0%e7%94%bb%20(2020).mkv"),
+        ] {
+            let response = get(&server, &variant).await;
+            assert_eq!(response.status(), StatusCode::OK, "{variant}");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(&body[..], b"fake video");
+        }
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition(Path::new("/videos/Film \"2023\".mp4")),
+            "inline; filename=\"Film _2023_.mp4\""
+        );
+        assert_eq!(
+            content_disposition(Path::new("Amélie.mkv")),
+            "inline; filename=\"Am_lie.mkv\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_not_found() {
+        let dir = TestDir::new("crab_dlna_unknown_route_not_found");
+        let video_path = dir.create_file("crab_dlna_route_unknown.mp4", b"fake video");
+        let server =
+            MediaStreamingServer::new(&video_path, &None, &"192.168.1.100".to_string(), &9000)
+                .unwrap();
+
+        let response = get(&server, "/does.not.exist.mp4").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
@@ -1,12 +1,21 @@
 //! Subtitle synchronization module
 //!
 //! This module provides subtitle synchronization functionality, including parsing subtitle files,
-//! retrieving current subtitle content based on playback time, and copying subtitle content to clipboard.
+//! retrieving current subtitle content based on playback time, and handing it to outputs such as
+//! the clipboard or a file.
 
-use crate::error::{Error, Result};
+use crate::{
+    dlna::PlaybackClock,
+    error::{Error, Result},
+};
 use arboard::Clipboard;
-use aspasia::{Subtitle, TimedEventInterface, TimedSubtitleFile};
-use std::path::Path;
+use aspasia::{
+    AssSubtitle, Format, SsaSubtitle, SubRipSubtitle, Subtitle, TimedEventInterface,
+    TimedMicroDvdSubtitle, TimedSubtitleFile, WebVttSubtitle, detect_format_with_encoding,
+};
+use encoding_rs::Encoding;
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
 
 /// Subtitle entry containing timing and text information
 #[derive(Debug, Clone)]
@@ -19,12 +28,94 @@ pub struct SubtitleEntry {
     pub text: String,
 }
 
-/// Subtitle synchronizer for managing subtitle display and clipboard integration
+/// A destination the current subtitle text is handed to
+pub trait SubtitleOutput: Send {
+    /// Shows the text of the current subtitle, empty between subtitles
+    fn show(&mut self, text: &str) -> Result<()>;
+}
+
+/// Copies subtitles to the system clipboard
+///
+/// The clipboard keeps the last subtitle between subtitles.
+pub struct ClipboardOutput {
+    clipboard: Clipboard,
+}
+
+impl ClipboardOutput {
+    /// Opens the system clipboard
+    ///
+    /// Fails with [`Error::ClipboardUnavailable`] where there is none, e.g. on
+    /// a headless machine.
+    pub fn new() -> Result<Self> {
+        let clipboard =
+            Clipboard::new().map_err(|source| Error::ClipboardUnavailable { source })?;
+        Ok(Self { clipboard })
+    }
+}
+
+impl SubtitleOutput for ClipboardOutput {
+    fn show(&mut self, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.clipboard
+            .set_text(text.to_string())
+            .map_err(|e| Error::SubtitleSyncError {
+                message: "Failed to copy subtitle to clipboard".to_string(),
+                context: "Updating clipboard".to_string(),
+                source: e.into(),
+            })
+    }
+}
+
+/// Writes subtitles to a file
+///
+/// The file is truncated on each subtitle transition and left empty between
+/// subtitles, so other tools (e.g. streaming overlays) can watch it.
+pub struct FileOutput {
+    path: PathBuf,
+}
+
+impl FileOutput {
+    /// Creates an output writing to the given file
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SubtitleOutput for FileOutput {
+    fn show(&mut self, text: &str) -> Result<()> {
+        std::fs::write(&self.path, text).map_err(|e| Error::SubtitleSyncError {
+            message: "Failed to write subtitle file".to_string(),
+            context: format!("Writing file: {}", self.path.display()),
+            source: e.into(),
+        })
+    }
+}
+
+/// Subtitle synchronizer following playback and handing subtitles to outputs
 pub struct SubtitleSyncer {
     /// List of parsed subtitle entries
     entries: Vec<SubtitleEntry>,
-    /// Clipboard instance for copying subtitle text
-    clipboard: Option<Clipboard>,
+    /// Destinations of the current subtitle text
+    outputs: Vec<Box<dyn SubtitleOutput>>,
+    /// Subtitle text last handed to the outputs
+    last_text: Option<String>,
+    /// Local playback clock, followed when the device reports no position
+    clock: Option<PlaybackClock>,
+    /// Whether the local clock is being followed
+    following_local_clock: bool,
+}
+
+/// Whether subtitles are copied to the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardMode {
+    /// Copy subtitles if a clipboard can be opened, warn otherwise
+    Optional,
+    /// Fail with [`Error::ClipboardUnavailable`] if no clipboard can be opened
+    Required,
+    /// Leave the clipboard alone
+    Disabled,
 }
 
 impl SubtitleSyncer {
@@ -36,19 +127,76 @@ impl SubtitleSyncer {
     /// # Returns
     /// Returns a new subtitle synchronizer instance
     pub fn new(subtitle_path: &Path) -> Result<Self> {
-        // Parse subtitle file
-        let entries = parse_subtitle_file(subtitle_path)?;
-
-        // Initialize clipboard
-        let clipboard = match Clipboard::new() {
-            Ok(clipboard) => Some(clipboard),
-            Err(e) => {
-                eprintln!("Warning: Failed to initialize clipboard: {e}");
-                None
-            }
+        Self::open(subtitle_path, ClipboardMode::Optional, None)
+    }
+
+    /// Creates a new subtitle synchronizer that fails without a clipboard
+    ///
+    /// Unlike [`SubtitleSyncer::new`], an unavailable clipboard (e.g. on a
+    /// headless machine) is reported as [`Error::ClipboardUnavailable`], for
+    /// when the clipboard is the only place subtitles are sent to.
+    pub fn with_required_clipboard(subtitle_path: &Path) -> Result<Self> {
+        Self::open(subtitle_path, ClipboardMode::Required, None)
+    }
+
+    /// Creates a new subtitle synchronizer that leaves the clipboard alone
+    ///
+    /// Useful together with [`SubtitleSyncer::with_output_file`] on headless
+    /// machines, where no clipboard is available.
+    pub fn without_clipboard(subtitle_path: &Path) -> Result<Self> {
+        Self::open(subtitle_path, ClipboardMode::Disabled, None)
+    }
+
+    /// Creates a new subtitle synchronizer
+    ///
+    /// # Arguments
+    /// * `subtitle_path` - Path to the subtitle file
+    /// * `clipboard_mode` - Whether subtitles are copied to the clipboard
+    /// * `encoding` - Encoding of the subtitle file, detected if `None`
+    ///
+    /// # Returns
+    /// Returns a new subtitle synchronizer instance
+    pub fn open(
+        subtitle_path: &Path,
+        clipboard_mode: ClipboardMode,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<Self> {
+        let entries = parse_subtitle_file(subtitle_path, encoding)?;
+
+        let clipboard = match clipboard_mode {
+            ClipboardMode::Optional => match ClipboardOutput::new() {
+                Ok(clipboard) => Some(clipboard),
+                Err(e) => {
+                    warn!("Failed to initialize clipboard: {e}");
+                    None
+                }
+            },
+            ClipboardMode::Required => Some(ClipboardOutput::new()?),
+            ClipboardMode::Disabled => None,
+        };
+
+        let syncer = SubtitleSyncer {
+            entries,
+            outputs: Vec::new(),
+            last_text: None,
+            clock: None,
+            following_local_clock: false,
         };
+        Ok(match clipboard {
+            Some(clipboard) => syncer.with_output(clipboard),
+            None => syncer,
+        })
+    }
 
-        Ok(SubtitleSyncer { entries, clipboard })
+    /// Also hands the current subtitle text to the given output
+    pub fn with_output(mut self, output: impl SubtitleOutput + 'static) -> Self {
+        self.outputs.push(Box::new(output));
+        self
+    }
+
+    /// Also writes the current subtitle text to a file, see [`FileOutput`]
+    pub fn with_output_file<P: Into<PathBuf>>(self, output_file: P) -> Self {
+        self.with_output(FileOutput::new(output_file))
     }
 
     /// Gets the current subtitle text for the given time
@@ -67,29 +215,29 @@ impl SubtitleSyncer {
         None
     }
 
-    /// Copies the current subtitle text to clipboard
+    /// Hands the current subtitle text to the outputs, e.g. the clipboard
     ///
     /// # Arguments
     /// * `current_time_ms` - Current playback time in milliseconds
     ///
     /// # Returns
-    /// Returns true if subtitle was copied to clipboard, false otherwise
+    /// Returns true if a subtitle was handed to at least one output, false otherwise
     pub fn copy_current_subtitle_to_clipboard(&mut self, current_time_ms: u64) -> bool {
-        if let Some(subtitle_text) = self.get_current_subtitle(current_time_ms) {
-            let subtitle_text = subtitle_text.to_string(); // Clone the text to avoid borrow issues
-            if let Some(ref mut clipboard) = self.clipboard {
-                match clipboard.set_text(subtitle_text.clone()) {
-                    Ok(_) => {
-                        println!("Copied to clipboard: {subtitle_text}");
-                        return true;
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to copy subtitle to clipboard: {e}");
-                    }
-                }
+        let Some(subtitle_text) = self.get_current_subtitle(current_time_ms) else {
+            return false;
+        };
+        let subtitle_text = subtitle_text.to_string(); // Clone the text to avoid borrow issues
+        let mut shown = false;
+        for output in &mut self.outputs {
+            match output.show(&subtitle_text) {
+                Ok(()) => shown = true,
+                Err(e) => warn!("{e}"),
             }
         }
-        false
+        if shown {
+            debug!("Copied subtitle: {subtitle_text}");
+        }
+        shown
     }
 
     /// Updates clipboard with current subtitle (alias for copy_current_subtitle_to_clipboard)
@@ -107,6 +255,81 @@ impl SubtitleSyncer {
         }
     }
 
+    /// Marks the start of playback, starting the local clock
+    pub fn start_clock(&mut self) {
+        self.clock = Some(PlaybackClock::started());
+    }
+
+    /// Runs or stops the local clock according to a device transport state
+    pub fn apply_transport_state(&mut self, transport_state: &str) {
+        if let Some(clock) = &mut self.clock {
+            clock.apply_transport_state(transport_state);
+        }
+    }
+
+    /// Checks whether the local clock is being followed
+    pub fn is_following_local_clock(&self) -> bool {
+        self.following_local_clock
+    }
+
+    /// Resolves the playback position to synchronize subtitles with
+    ///
+    /// Devices that report no position (e.g. `NOT_IMPLEMENTED`) are followed
+    /// with a local clock started by [`SubtitleSyncer::start_clock`] instead,
+    /// kept in step with pauses through
+    /// [`SubtitleSyncer::apply_transport_state`].
+    ///
+    /// # Arguments
+    /// * `device_position_ms` - Position reported by the device, if any
+    ///
+    /// # Returns
+    /// Returns the position in milliseconds, or None if neither is available
+    pub fn position_ms(&mut self, device_position_ms: Option<u64>) -> Option<u64> {
+        match (device_position_ms, self.following_local_clock) {
+            (Some(_), true) => {
+                info!("Device reports its playback position again");
+                self.following_local_clock = false;
+            }
+            (None, false) if self.clock.is_some() => {
+                warn!("Device does not report its playback position, following a local clock");
+                self.following_local_clock = true;
+            }
+            _ => {}
+        }
+
+        match &mut self.clock {
+            Some(clock) => Some(clock.reconcile(device_position_ms)),
+            None => device_position_ms,
+        }
+    }
+
+    /// Hands the current subtitle to the outputs
+    ///
+    /// Nothing is rewritten while the subtitle text doesn't change.
+    ///
+    /// # Arguments
+    /// * `current_time_ms` - Current playback time in milliseconds
+    ///
+    /// # Returns
+    /// Returns Ok(true) if the subtitle changed and was written, Ok(false) if unchanged
+    pub fn sync(&mut self, current_time_ms: u64) -> Result<bool> {
+        let text = self
+            .get_current_subtitle(current_time_ms)
+            .unwrap_or_default()
+            .to_string();
+        if self.last_text.as_ref() == Some(&text) {
+            return Ok(false);
+        }
+
+        for output in &mut self.outputs {
+            output.show(&text)?;
+        }
+
+        debug!("Synchronized subtitle: {text}");
+        self.last_text = Some(text);
+        Ok(true)
+    }
+
     /// Gets all subtitle entries
     pub fn entries(&self) -> &[SubtitleEntry] {
         &self.entries
@@ -123,20 +346,59 @@ impl SubtitleSyncer {
     }
 }
 
+/// Opens a subtitle file decoded with the given encoding
+fn open_with_encoding(
+    subtitle_path: &Path,
+    encoding: &'static Encoding,
+) -> std::result::Result<TimedSubtitleFile, aspasia::Error> {
+    let encoding = Some(encoding);
+    Ok(
+        match detect_format_with_encoding(subtitle_path, encoding)? {
+            Format::Ass => TimedSubtitleFile::Ass(AssSubtitle::from_path_with_encoding(
+                subtitle_path,
+                encoding,
+            )?),
+            Format::MicroDvd => TimedSubtitleFile::MicroDvd(
+                TimedMicroDvdSubtitle::from_path_with_encoding(subtitle_path, encoding)?,
+            ),
+            Format::Ssa => TimedSubtitleFile::Ssa(SsaSubtitle::from_path_with_encoding(
+                subtitle_path,
+                encoding,
+            )?),
+            Format::SubRip => TimedSubtitleFile::SubRip(SubRipSubtitle::from_path_with_encoding(
+                subtitle_path,
+                encoding,
+            )?),
+            Format::WebVtt => TimedSubtitleFile::WebVtt(WebVttSubtitle::from_path_with_encoding(
+                subtitle_path,
+                encoding,
+            )?),
+        },
+    )
+}
+
 /// Parses a subtitle file and returns a list of subtitle entries
 ///
 /// # Arguments
 /// * `subtitle_path` - Path to the subtitle file
+/// * `encoding` - Encoding of the subtitle file, detected if `None`
 ///
 /// # Returns
 /// Returns a list of parsed subtitle entries
-fn parse_subtitle_file(subtitle_path: &Path) -> Result<Vec<SubtitleEntry>> {
+fn parse_subtitle_file(
+    subtitle_path: &Path,
+    encoding: Option<&'static Encoding>,
+) -> Result<Vec<SubtitleEntry>> {
     // Parse subtitle file using aspasia
-    let subtitle_file =
-        TimedSubtitleFile::new(subtitle_path).map_err(|e| Error::SubtitleSyncError {
-            message: format!("Failed to parse subtitle file: {e}"),
-            context: format!("Parsing file: {}", subtitle_path.display()),
-        })?;
+    let subtitle_file = match encoding {
+        Some(encoding) => open_with_encoding(subtitle_path, encoding),
+        None => TimedSubtitleFile::new(subtitle_path),
+    }
+    .map_err(|e| Error::SubtitleSyncError {
+        message: "Failed to parse subtitle file".to_string(),
+        context: format!("Parsing file: {}", subtitle_path.display()),
+        source: e.into(),
+    })?;
 
     // Convert to unified subtitle entry format
     let mut entries = Vec::new();
@@ -225,3 +487,95 @@ fn clean_subtitle_text(text: &str) -> String {
     // Remove extra whitespace
     cleaned.trim().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+
+    /// Records the texts handed to it
+    #[derive(Clone, Default)]
+    struct RecordingOutput(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl SubtitleOutput for RecordingOutput {
+        fn show(&mut self, text: &str) -> Result<()> {
+            self.0.lock().unwrap().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    /// Creates a synchronizer with the given cues and no clipboard
+    fn create_test_syncer(output_file: &Path) -> SubtitleSyncer {
+        SubtitleSyncer {
+            entries: vec![
+                SubtitleEntry {
+                    start_time: 1000,
+                    end_time: 2000,
+                    text: "Hello".to_string(),
+                },
+                SubtitleEntry {
+                    start_time: 3000,
+                    end_time: 4000,
+                    text: "World".to_string(),
+                },
+            ],
+            outputs: Vec::new(),
+            last_text: None,
+            clock: None,
+            following_local_clock: false,
+        }
+        .with_output_file(output_file)
+    }
+
+    #[test]
+    fn test_sync_writes_output_file_on_transitions() {
+        let dir = TestDir::new("crab_dlna_subtitle_sync");
+        let output_file = dir.join("subtitle.txt");
+        let mut syncer = create_test_syncer(&output_file);
+
+        assert!(syncer.sync(1500).unwrap());
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "Hello");
+
+        // Unchanged cue: the file is not rewritten
+        std::fs::write(&output_file, "touched").unwrap();
+        assert!(!syncer.sync(1800).unwrap());
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "touched");
+
+        // Between cues the file is emptied
+        assert!(syncer.sync(2500).unwrap());
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "");
+
+        assert!(syncer.sync(3500).unwrap());
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "World");
+    }
+
+    #[test]
+    fn test_sync_hands_transitions_to_every_output() {
+        let dir = TestDir::new("crab_dlna_subtitle_outputs");
+        let output_file = dir.join("subtitle.txt");
+        let recorded = RecordingOutput::default();
+        let mut syncer = create_test_syncer(&output_file).with_output(recorded.clone());
+
+        for position in [1500, 1800, 2500, 3500, 3600] {
+            syncer.sync(position).unwrap();
+        }
+        assert_eq!(*recorded.0.lock().unwrap(), ["Hello", "", "World"]);
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "World");
+    }
+
+    #[test]
+    fn test_position_falls_back_to_local_clock() {
+        let dir = TestDir::new("crab_dlna_clock");
+        let mut syncer = create_test_syncer(&dir.join("subtitle.txt"));
+        assert_eq!(syncer.position_ms(None), None);
+
+        syncer.start_clock();
+        assert!(syncer.position_ms(None).unwrap() < 1000);
+        assert!(syncer.is_following_local_clock());
+
+        assert_eq!(syncer.position_ms(Some(60_000)), Some(60_000));
+        assert!(!syncer.is_following_local_clock());
+        syncer.apply_transport_state("PAUSED_PLAYBACK");
+        assert!((60_000..61_000).contains(&syncer.position_ms(None).unwrap()));
+    }
+}
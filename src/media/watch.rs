@@ -0,0 +1,188 @@
+//! Watching a directory for new media files
+//!
+//! Backs `play --watch`: files created in (or moved into) the directory are
+//! reported once they are fully written, so they can be queued for playback.
+
+use crate::{
+    config::WATCH_SETTLE_INTERVAL_MS,
+    error::{Error, Result},
+    utils::is_supported_media_file,
+};
+use log::{debug, warn};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{CreateKind, ModifyKind, RenameMode},
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Reports the media files that appear in a directory
+pub struct DirectoryWatcher {
+    // Notifications stop when the watcher is dropped
+    _watcher: RecommendedWatcher,
+    ready: mpsc::UnboundedReceiver<PathBuf>,
+}
+
+impl DirectoryWatcher {
+    /// Starts watching a directory, files already in it are not reported
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn new(dir: &Path) -> Result<Self> {
+        let watch_error = |e: notify::Error| Error::DirectoryWatchFailed {
+            path: dir.display().to_string(),
+            reason: e.to_string(),
+        };
+
+        let (created_sender, mut created) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) if is_new_file_event(&event.kind) => {
+                    for path in event.paths {
+                        let _ = created_sender.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Error while watching for new files: {e}"),
+            })
+            .map_err(watch_error)?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+
+        // Each new file is reported on its own once written, so a large copy
+        // doesn't hold back the files after it
+        let (ready_sender, ready) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(path) = created.recv().await {
+                if !is_supported_media_file(&path) {
+                    continue;
+                }
+                debug!("New media file in watched directory: {}", path.display());
+                let ready_sender = ready_sender.clone();
+                tokio::spawn(async move {
+                    let interval = Duration::from_millis(WATCH_SETTLE_INTERVAL_MS);
+                    if wait_until_written(&path, interval).await {
+                        let _ = ready_sender.send(path);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            _watcher: watcher,
+            ready,
+        })
+    }
+
+    /// Waits for the next new media file, once it is fully written
+    pub async fn next_file(&mut self) -> Option<PathBuf> {
+        self.ready.recv().await
+    }
+
+    /// Takes a new media file that is already fully written, without waiting
+    pub fn try_next_file(&mut self) -> Option<PathBuf> {
+        self.ready.try_recv().ok()
+    }
+}
+
+/// Whether an event may bring a new file into the directory
+fn is_new_file_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(CreateKind::File | CreateKind::Any)
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Any))
+    )
+}
+
+/// Waits until the size of a file stops changing over an interval
+///
+/// Returns `false` if the file disappears meanwhile, e.g. a temporary file
+/// renamed once complete, in which case the rename is reported on its own.
+async fn wait_until_written(path: &Path, interval: Duration) -> bool {
+    let mut last_size = None;
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Some(written) = check_written(path, &mut last_size) {
+            return written;
+        }
+    }
+}
+
+/// Compares the size of a file with the one of the previous check
+///
+/// Returns whether the file is fully written once that is known, `None` while
+/// it may still be growing.
+fn check_written(path: &Path, last_size: &mut Option<u64>) -> Option<bool> {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Some(false);
+    };
+    if !metadata.is_file() {
+        return Some(false);
+    }
+    let size = metadata.len();
+    if size > 0 && *last_size == Some(size) {
+        return Some(true);
+    }
+    *last_size = Some(size);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+    use std::io::Write;
+
+    #[test]
+    fn test_check_written_waits_for_writes_to_stop() {
+        let dir = TestDir::new("crab_dlna_watch_settle");
+        let path = dir.join("growing.mp4");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut last_size = None;
+
+        // Empty files are still being created
+        assert_eq!(check_written(&path, &mut last_size), None);
+        assert_eq!(check_written(&path, &mut last_size), None);
+        for _ in 0..3 {
+            file.write_all(&[0; 1024]).unwrap();
+            assert_eq!(check_written(&path, &mut last_size), None);
+        }
+        assert_eq!(check_written(&path, &mut last_size), Some(true));
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(check_written(&path, &mut last_size), Some(false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_until_written_checks_twice() {
+        let dir = TestDir::new("crab_dlna_watch_wait");
+        let path = dir.join("complete.mp4");
+        std::fs::write(&path, b"video").unwrap();
+
+        let started = tokio::time::Instant::now();
+        assert!(wait_until_written(&path, Duration::from_secs(1)).await);
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!wait_until_written(&path, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reports_new_media_files_only() {
+        let dir = TestDir::new("crab_dlna_watch_new_files");
+        std::fs::write(dir.join("existing.mp4"), b"video").unwrap();
+        let mut watcher = DirectoryWatcher::new(&dir).unwrap();
+
+        std::fs::write(dir.join("notes.txt"), b"text").unwrap();
+        std::fs::write(dir.join("new.mp4"), b"video").unwrap();
+
+        let file = tokio::time::timeout(Duration::from_secs(10), watcher.next_file())
+            .await
+            .unwrap();
+        assert_eq!(file, Some(dir.join("new.mp4")));
+        assert_eq!(watcher.try_next_file(), None);
+    }
+}
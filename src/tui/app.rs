@@ -4,11 +4,82 @@
 //! functionality for the TUI interface.
 
 use crate::{
-    devices::{PositionInfo, Render, TransportInfo},
+    config::{
+        DEFAULT_TUI_POLL_INTERVAL_MS, DLNA_DEFAULT_SPEED, DLNA_PLAYBACK_SPEEDS,
+        TUI_MAX_RECONNECT_ATTEMPTS, TUI_RECONNECT_BASE_DELAY_SECS, TUI_RECONNECT_FAILURE_THRESHOLD,
+    },
+    devices::{PositionInfo, Render, RenderSpec, TransportInfo, TransportSettings},
+    dlna::{EndReason, PlaybackClock, PlaybackEndDetector},
+    error::{Error, Result},
     media::Playlist,
 };
-use log::{debug, warn};
-use std::{path::PathBuf, time::Instant};
+use log::{debug, info, warn};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// A user interaction, applied synchronously by [`AppState::tick`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiAction {
+    /// Quit the application, or dismiss the quit confirmation
+    Quit,
+    /// Answer yes to the quit confirmation
+    Accept,
+    /// Answer no to the quit confirmation
+    Decline,
+    /// Show or hide the help dialog
+    ToggleHelp,
+    /// Show or hide the device info dialog
+    ToggleDeviceInfo,
+    /// Move the playlist selection down
+    SelectNext,
+    /// Move the playlist selection up
+    SelectPrevious,
+    /// Play the selected item, or close the open dialog
+    Confirm,
+    /// Remove the selected item from the playlist
+    RemoveSelected,
+    /// Toggle play/pause, or close the open dialog
+    TogglePlayPause,
+    /// Switch to the next faster playback speed
+    SpeedUp,
+    /// Switch to the next slower playback speed
+    SpeedDown,
+    /// Stop playback
+    Stop,
+    /// Refresh the device status
+    Refresh,
+    /// Start typing a playlist filter
+    StartFilter,
+    /// Add a character to the playlist filter
+    FilterInput(char),
+    /// Remove the last character of the playlist filter
+    FilterBackspace,
+    /// Stop typing the playlist filter, keeping it applied
+    FinishFilter,
+    /// Remove the playlist filter
+    ClearFilter,
+}
+
+/// Device work requested by [`AppState::tick`], carried out by the event handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SideEffect {
+    /// Play the playlist item at the given index
+    NeedsPlay(usize),
+    /// Toggle play/pause on the device
+    TogglePlayPause,
+    /// Change the playback speed of the device
+    SetSpeed(&'static str),
+    /// Stop playback on the device
+    Stop,
+    /// Stop playback on the device, then quit
+    StopAndQuit,
+    /// Query the device status
+    Refresh,
+    /// Query the device play mode, shown in the device info dialog
+    FetchTransportSettings,
+}
 
 /// Application state for the TUI
 #[derive(Debug, Clone)]
@@ -23,8 +94,28 @@ pub struct AppState {
     pub transport_info: Option<TransportInfo>,
     /// Position information
     pub position_info: Option<PositionInfo>,
+    /// Whether the device doesn't support position queries, which are then no longer made
+    pub position_unavailable: bool,
+    /// Local playback clock, standing in for positions the device doesn't report
+    pub clock: PlaybackClock,
+    /// Why the device last stopped playing, telling the end of the media from a stop
+    pub end_reason: Option<EndReason>,
+    /// Follows the polled status to tell why the device stops playing
+    end_detector: PlaybackEndDetector,
+    /// Transport settings, fetched when the device info dialog is opened
+    pub transport_settings: Option<std::result::Result<TransportSettings, String>>,
     /// DLNA render device
     pub render: Render,
+    /// Specification used to resolve the render, kept for reconnecting
+    pub render_spec: RenderSpec,
+    /// Number of consecutive failed status queries
+    pub status_failures: u32,
+    /// Number of reconnect attempts made since the device stopped responding
+    pub reconnect_attempts: u32,
+    /// Earliest time at which the next reconnect attempt may be made
+    pub next_reconnect_at: Option<Instant>,
+    /// Whether reconnecting was given up after too many attempts
+    pub connection_lost: bool,
     /// Whether the app should quit
     pub should_quit: bool,
     /// Status message to display
@@ -35,29 +126,218 @@ pub struct AppState {
     pub last_update: Instant,
     /// Selected playlist item
     pub selected_playlist_item: usize,
+    /// First playlist item shown, kept across frames so the view scrolls smoothly
+    pub playlist_offset: usize,
+    /// Text the shown playlist items are filtered by, matched against file names
+    pub filter_query: Option<String>,
+    /// Whether keys are typed into the playlist filter
+    pub filter_editing: bool,
     /// Whether help dialog is shown
     pub show_help: bool,
     /// Whether device info dialog is shown
     pub show_device_info: bool,
+    /// Whether the quit confirmation dialog is shown
+    pub show_quit_dialog: bool,
+    /// Playback speed last requested from the device
+    pub playback_speed: String,
+    /// Interval between status queries, `None` to only query on manual refresh
+    poll_interval: Option<Duration>,
+    /// Whether quitting asks for confirmation while something is playing
+    confirm_quit: bool,
 }
 
 impl AppState {
     /// Creates a new application state
-    pub fn new(render: Render, playlist: Playlist) -> Self {
+    pub fn new(render: Render, render_spec: RenderSpec, playlist: Playlist) -> Self {
         Self {
             playlist,
             current_file_index: None,
             current_file: None,
             transport_info: None,
             position_info: None,
+            position_unavailable: false,
+            clock: PlaybackClock::new(),
+            end_reason: None,
+            end_detector: PlaybackEndDetector::default(),
+            transport_settings: None,
             render,
+            render_spec,
+            status_failures: 0,
+            reconnect_attempts: 0,
+            next_reconnect_at: None,
+            connection_lost: false,
             should_quit: false,
             status_message: "Ready".to_string(),
             error_message: None,
             last_update: Instant::now(),
             selected_playlist_item: 0,
+            playlist_offset: 0,
+            filter_query: None,
+            filter_editing: false,
             show_help: false,
             show_device_info: false,
+            show_quit_dialog: false,
+            playback_speed: DLNA_DEFAULT_SPEED.to_string(),
+            poll_interval: Some(Duration::from_millis(DEFAULT_TUI_POLL_INTERVAL_MS)),
+            confirm_quit: true,
+        }
+    }
+
+    /// Sets the interval between status queries, `None` to only query on manual refresh
+    pub fn with_poll_interval(mut self, poll_interval: Option<Duration>) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets whether quitting asks for confirmation while something is playing
+    pub fn with_confirm_quit(mut self, confirm_quit: bool) -> Self {
+        self.confirm_quit = confirm_quit;
+        self
+    }
+
+    /// Returns the effective interval between status queries, if polling is enabled
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+
+    /// Applies a user interaction to the state
+    ///
+    /// State changes happen immediately, without talking to the device. Any
+    /// device work the interaction needs is returned for the caller to run.
+    pub fn tick(&mut self, action: UiAction) -> Option<SideEffect> {
+        // The quit confirmation takes precedence over everything else
+        if self.show_quit_dialog {
+            self.show_quit_dialog = false;
+            return match action {
+                UiAction::Accept => {
+                    self.quit();
+                    None
+                }
+                UiAction::Stop => {
+                    self.set_status_message("Stopping playback...".to_string());
+                    Some(SideEffect::StopAndQuit)
+                }
+                _ => None,
+            };
+        }
+
+        // Global actions work whatever is shown
+        match action {
+            UiAction::Quit => {
+                if self.confirm_quit && self.is_playing() {
+                    self.close_dialogs();
+                    self.show_quit_dialog = true;
+                } else {
+                    self.quit();
+                }
+                return None;
+            }
+            UiAction::ToggleHelp => {
+                self.toggle_help();
+                return None;
+            }
+            UiAction::ToggleDeviceInfo => {
+                self.toggle_device_info();
+                return self
+                    .show_device_info
+                    .then_some(SideEffect::FetchTransportSettings);
+            }
+            _ => {}
+        }
+
+        // While a dialog is shown, only closing it is possible
+        if self.show_help || self.show_device_info {
+            if matches!(action, UiAction::Confirm | UiAction::TogglePlayPause) {
+                self.close_dialogs();
+            }
+            return None;
+        }
+
+        match action {
+            UiAction::SelectNext => {
+                self.next_playlist_item();
+                None
+            }
+            UiAction::SelectPrevious => {
+                self.previous_playlist_item();
+                None
+            }
+            UiAction::Confirm => {
+                let selected_file = self.get_selected_file().cloned()?;
+                let index = self.selected_playlist_item;
+                self.set_status_message(format!("Playing: {}", selected_file.display()));
+                self.set_current_file(selected_file, index);
+                Some(SideEffect::NeedsPlay(index))
+            }
+            UiAction::RemoveSelected => {
+                self.get_selected_file()?;
+                if let Some(removed) = self.remove_playlist_item(self.selected_playlist_item) {
+                    self.set_status_message(format!("Removed: {}", removed.display()));
+                }
+                None
+            }
+            UiAction::StartFilter => {
+                self.filter_editing = true;
+                self.filter_query.get_or_insert_with(String::new);
+                None
+            }
+            UiAction::FilterInput(c) => {
+                self.filter_query.get_or_insert_with(String::new).push(c);
+                self.select_first_match();
+                None
+            }
+            UiAction::FilterBackspace => {
+                if let Some(query) = &mut self.filter_query {
+                    query.pop();
+                }
+                self.select_first_match();
+                None
+            }
+            UiAction::FinishFilter => {
+                self.filter_editing = false;
+                if self.filter_query.as_deref() == Some("") {
+                    self.filter_query = None;
+                }
+                None
+            }
+            UiAction::ClearFilter => {
+                self.filter_editing = false;
+                self.filter_query = None;
+                None
+            }
+            UiAction::TogglePlayPause => {
+                self.set_status_message("Toggling play/pause...".to_string());
+                Some(SideEffect::TogglePlayPause)
+            }
+            UiAction::SpeedUp | UiAction::SpeedDown => {
+                let faster = action == UiAction::SpeedUp;
+                let Some(speed) = self.adjacent_playback_speed(faster) else {
+                    self.set_status_message(format!(
+                        "Already at {} speed",
+                        if faster { "maximum" } else { "minimum" }
+                    ));
+                    return None;
+                };
+                self.set_status_message(format!("Changing speed to {speed}x..."));
+                Some(SideEffect::SetSpeed(speed))
+            }
+            UiAction::Stop => {
+                self.set_status_message("Stopping playback...".to_string());
+                Some(SideEffect::Stop)
+            }
+            UiAction::Refresh => {
+                self.set_status_message("Refreshing status...".to_string());
+                if self.connection_lost {
+                    // A manual refresh after giving up grants a fresh round of reconnect attempts
+                    self.reset_reconnect_state();
+                }
+                Some(SideEffect::Refresh)
+            }
+            UiAction::Quit
+            | UiAction::Accept
+            | UiAction::Decline
+            | UiAction::ToggleHelp
+            | UiAction::ToggleDeviceInfo => None,
         }
     }
 
@@ -66,48 +346,254 @@ impl AppState {
         // Update transport info
         match self.render.get_transport_info().await {
             Ok(info) => {
+                self.clock.apply_transport_state(&info.transport_state);
+                if let Some(reason) = self
+                    .end_detector
+                    .observe_transport_state(&info.transport_state)
+                {
+                    self.set_status_message(match reason {
+                        EndReason::EndOfMedia => "Playback finished".to_string(),
+                        EndReason::Stopped => "Playback stopped before the end".to_string(),
+                    });
+                    self.end_reason = Some(reason);
+                }
                 self.transport_info = Some(info);
                 self.error_message = None;
+                if self.status_failures > 0 || self.connection_lost {
+                    self.reset_reconnect_state();
+                    self.set_status_message("Device is responding again".to_string());
+                }
             }
             Err(e) => {
                 warn!("Failed to get transport info: {e}");
-                self.error_message = Some(format!("Transport error: {e}"));
+                self.status_failures += 1;
+                if !self.connection_lost {
+                    self.error_message = Some(format!("Transport error: {e}"));
+                }
             }
         }
 
-        // Update position info
-        match self.render.get_position_info().await {
-            Ok(info) => {
-                self.position_info = Some(info);
+        // Update position info, unless the device is known not to report it
+        if !self.position_unavailable {
+            match self.render.get_position_info().await {
+                Ok(mut info) => {
+                    self.end_detector.observe_position(&info);
+                    let device_position_ms = info.rel_time_ms();
+                    self.clock.reconcile(device_position_ms);
+                    if device_position_ms.is_none() {
+                        info.rel_time = self.clock.rel_time();
+                    }
+                    self.position_info = Some(info);
+                }
+                Err(Error::DlnaActionNotSupported { .. }) => {
+                    info!("Device does not report the playback position, no longer querying it");
+                    self.position_unavailable = true;
+                    self.position_info = None;
+                }
+                Err(e) => {
+                    debug!("Failed to get position info: {e}");
+                }
+            }
+        }
+
+        self.last_update = Instant::now();
+    }
+
+    /// Returns the render specification to reconnect with, if a reconnect is due
+    ///
+    /// A reconnect is due once status queries have failed repeatedly and the
+    /// backoff delay since the previous attempt has elapsed. After the maximum
+    /// number of attempts the connection is marked as lost and no further
+    /// attempts are requested.
+    pub fn take_reconnect_request(&mut self) -> Option<RenderSpec> {
+        if self.connection_lost || self.status_failures < TUI_RECONNECT_FAILURE_THRESHOLD {
+            return None;
+        }
+        if self
+            .next_reconnect_at
+            .is_some_and(|next_reconnect_at| Instant::now() < next_reconnect_at)
+        {
+            return None;
+        }
+        if self.reconnect_attempts >= TUI_MAX_RECONNECT_ATTEMPTS {
+            warn!(
+                "Giving up reconnecting after {} attempts",
+                self.reconnect_attempts
+            );
+            self.connection_lost = true;
+            self.set_status_message("Disconnected".to_string());
+            self.set_error_message(Some(format!(
+                "Device unreachable after {} reconnect attempts, press R to retry",
+                self.reconnect_attempts
+            )));
+            return None;
+        }
+
+        self.reconnect_attempts += 1;
+        self.set_status_message(format!(
+            "Reconnecting… (attempt {}/{})",
+            self.reconnect_attempts, TUI_MAX_RECONNECT_ATTEMPTS
+        ));
+        Some(self.render_spec.clone())
+    }
+
+    /// Applies the outcome of a reconnect attempt
+    pub fn handle_reconnect_result(&mut self, result: Result<Render>) {
+        match result {
+            Ok(render) => {
+                info!("Reconnected to {render}");
+                self.set_status_message(format!("Reconnected to {}", render.friendly_name()));
+                self.render = render
+                    .with_action_timeout(self.render.action_timeout())
+                    .with_play_speed(self.render.play_speed().map(str::to_string));
+                self.reset_reconnect_state();
+                self.set_error_message(None);
             }
             Err(e) => {
-                debug!("Failed to get position info: {e}");
+                warn!("Reconnect attempt {} failed: {e}", self.reconnect_attempts);
+                let delay_secs = TUI_RECONNECT_BASE_DELAY_SECS << self.reconnect_attempts.min(4);
+                self.next_reconnect_at = Some(Instant::now() + Duration::from_secs(delay_secs));
+                self.set_error_message(Some(format!("Reconnect failed: {e}")));
             }
         }
+    }
 
-        self.last_update = Instant::now();
+    /// Clears all reconnect bookkeeping
+    pub fn reset_reconnect_state(&mut self) {
+        self.status_failures = 0;
+        self.reconnect_attempts = 0;
+        self.next_reconnect_at = None;
+        self.connection_lost = false;
     }
 
-    /// Moves to the next playlist item
+    /// Moves to the next shown playlist item
     pub fn next_playlist_item(&mut self) {
-        if !self.playlist.is_empty() {
-            self.selected_playlist_item = (self.selected_playlist_item + 1) % self.playlist.len();
+        let shown = self.shown_playlist_indices();
+        if shown.is_empty() {
+            return;
         }
+        self.selected_playlist_item = match self.selected_position(&shown) {
+            Some(position) => shown[(position + 1) % shown.len()],
+            None => shown[0],
+        };
     }
 
-    /// Moves to the previous playlist item
+    /// Moves to the previous shown playlist item
     pub fn previous_playlist_item(&mut self) {
-        if !self.playlist.is_empty() {
-            self.selected_playlist_item = if self.selected_playlist_item == 0 {
-                self.playlist.len() - 1
-            } else {
-                self.selected_playlist_item - 1
-            };
+        let shown = self.shown_playlist_indices();
+        if shown.is_empty() {
+            return;
         }
+        self.selected_playlist_item = match self.selected_position(&shown) {
+            Some(0) | None => shown[shown.len() - 1],
+            Some(position) => shown[position - 1],
+        };
+    }
+
+    /// Playlist indices of the items shown, those whose file name matches the filter
+    ///
+    /// Matching ignores case. Without a filter, every item is shown.
+    pub fn shown_playlist_indices(&self) -> Vec<usize> {
+        let Some(query) = self
+            .filter_query
+            .as_deref()
+            .filter(|query| !query.is_empty())
+        else {
+            return (0..self.playlist.len()).collect();
+        };
+        let query = query.to_lowercase();
+        self.playlist
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| {
+                file.file_name()
+                    .is_some_and(|name| name.to_string_lossy().to_lowercase().contains(&query))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Position of the selection among the shown playlist indices, if it is shown
+    pub fn selected_position(&self, shown: &[usize]) -> Option<usize> {
+        shown
+            .iter()
+            .position(|index| *index == self.selected_playlist_item)
+    }
+
+    /// Moves the selection to the first item matching the filter, if any
+    fn select_first_match(&mut self) {
+        if let Some(first) = self.shown_playlist_indices().first() {
+            self.selected_playlist_item = *first;
+        }
+    }
+
+    /// Scrolls the playlist view just enough for the selection to be visible
+    ///
+    /// The view only moves once the selection would leave it, and never
+    /// leaves empty rows below the last item. Returns the new offset.
+    pub fn scroll_playlist_to_selection(&mut self, visible_rows: usize) -> usize {
+        let shown = self.shown_playlist_indices();
+        let len = shown.len();
+        let selected = self.selected_position(&shown).unwrap_or(0);
+        let offset = if selected < self.playlist_offset {
+            selected
+        } else if selected >= self.playlist_offset + visible_rows {
+            (selected + 1).saturating_sub(visible_rows)
+        } else {
+            self.playlist_offset
+        };
+        self.playlist_offset = offset.min(len.saturating_sub(visible_rows));
+        self.playlist_offset
+    }
+
+    /// Returns the speed one step faster or slower than the current one
+    ///
+    /// Returns `None` when already at the fastest or slowest speed.
+    pub fn adjacent_playback_speed(&self, faster: bool) -> Option<&'static str> {
+        let current = DLNA_PLAYBACK_SPEEDS
+            .iter()
+            .position(|speed| *speed == self.playback_speed)
+            .unwrap_or_else(|| {
+                let default_speed = DLNA_DEFAULT_SPEED.to_string();
+                DLNA_PLAYBACK_SPEEDS
+                    .iter()
+                    .position(|speed| *speed == default_speed)
+                    .unwrap_or(0)
+            });
+
+        let next = if faster {
+            current.checked_add(1)
+        } else {
+            current.checked_sub(1)
+        }?;
+        DLNA_PLAYBACK_SPEEDS.get(next).copied()
+    }
+
+    /// Removes a playlist item, keeping the selection in range
+    pub fn remove_playlist_item(&mut self, index: usize) -> Option<PathBuf> {
+        let removed = self.playlist.remove(index)?;
+        self.clamp_playlist_selection();
+        Some(removed)
     }
 
-    /// Gets the currently selected playlist file
+    /// Brings the selection and current file index back in range
+    ///
+    /// Must be called whenever the playlist changes. The selection moves to
+    /// the last item if it was past the end, and the current file index
+    /// follows the current file, or is cleared if it is no longer listed.
+    pub fn clamp_playlist_selection(&mut self) {
+        self.selected_playlist_item = self
+            .selected_playlist_item
+            .min(self.playlist.len().saturating_sub(1));
+        self.current_file_index = self
+            .current_file
+            .as_deref()
+            .and_then(|file| self.playlist.index_of(file));
+    }
+
+    /// Gets the currently selected playlist file, if it is shown
     pub fn get_selected_file(&self) -> Option<&PathBuf> {
+        self.selected_position(&self.shown_playlist_indices())?;
         self.playlist.get_file(self.selected_playlist_item)
     }
 
@@ -115,6 +601,7 @@ impl AppState {
     pub fn set_current_file(&mut self, file_path: PathBuf, index: usize) {
         self.current_file = Some(file_path);
         self.current_file_index = Some(index);
+        self.clock = PlaybackClock::new();
     }
 
     /// Clears the current playing file
@@ -149,6 +636,16 @@ impl AppState {
         self.show_device_info = false;
     }
 
+    /// Whether the device has media loaded that is playing or paused
+    pub fn is_playing(&self) -> bool {
+        self.transport_info.as_ref().is_some_and(|info| {
+            matches!(
+                info.transport_state.as_str(),
+                "PLAYING" | "PAUSED_PLAYBACK" | "TRANSITIONING"
+            )
+        })
+    }
+
     /// Marks the app for quitting
     pub fn quit(&mut self) {
         self.should_quit = true;
@@ -174,3 +671,330 @@ pub fn parse_time_string(time_str: &str) -> f64 {
         _ => 0.0,
     }
 }
+
+/// Describes the transport state, showing trick play when playing at another speed
+///
+/// E.g. `▶▶ 2x` when fast-forwarding, `◀◀ 2x` when rewinding or `▶ 0.5x` in
+/// slow motion. Other states are shown as reported.
+pub fn transport_state_display(transport_info: &TransportInfo) -> String {
+    let state = transport_info.transport_state.as_str();
+    match transport_info.speed_ratio() {
+        Some(ratio) if state == "PLAYING" && ratio != 1.0 => {
+            let symbol = if ratio < 0.0 {
+                "◀◀"
+            } else if ratio > 1.0 {
+                "▶▶"
+            } else {
+                "▶"
+            };
+            format!("{symbol} {}x", ratio.abs())
+        }
+        _ => state.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        routing::{get, post},
+    };
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    const DESCRIPTION: &str = include_str!("../../tests/fixtures/renderer_description.xml");
+
+    /// Answers any AVTransport action with an "Invalid Action" UPnP error
+    const INVALID_ACTION_FAULT: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+  <s:Body>
+    <s:Fault>
+      <faultcode>s:Client</faultcode>
+      <faultstring>UPnPError</faultstring>
+      <detail>
+        <UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+          <errorCode>401</errorCode>
+          <errorDescription>Invalid Action</errorDescription>
+        </UPnPError>
+      </detail>
+    </s:Fault>
+  </s:Body>
+</s:Envelope>"#;
+
+    /// Builds a state whose render is served from a local description document
+    async fn test_state(file_count: usize) -> AppState {
+        serve_test_state(Router::new(), file_count).await
+    }
+
+    /// Builds a state whose render is served from a local description document and `routes`
+    async fn serve_test_state(routes: Router, file_count: usize) -> AppState {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let location = format!("http://{}/description.xml", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/description.xml", get(|| async { DESCRIPTION }))
+            .merge(routes);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let render_spec = RenderSpec::Location(location);
+        let render = Render::new(render_spec.clone()).await.unwrap();
+        let mut playlist = Playlist::default();
+        for i in 0..file_count {
+            playlist.add_file(format!("video{i}.mp4"));
+        }
+        AppState::new(render, render_spec, playlist)
+    }
+
+    #[tokio::test]
+    async fn test_position_unavailable_stops_position_queries() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let control_requests = requests.clone();
+        let routes = Router::new().route(
+            "/avt/control",
+            post(move || {
+                control_requests.fetch_add(1, Ordering::SeqCst);
+                async { INVALID_ACTION_FAULT }
+            }),
+        );
+        let mut state = serve_test_state(routes, 1).await;
+
+        state.update_status().await;
+        assert!(state.position_unavailable);
+        assert!(state.position_info.is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Only the transport state is queried from now on
+        state.update_status().await;
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_tick_selection_wraps() {
+        let mut state = test_state(3).await;
+        assert_eq!(state.tick(UiAction::SelectPrevious), None);
+        assert_eq!(state.selected_playlist_item, 2);
+        state.tick(UiAction::SelectNext);
+        assert_eq!(state.selected_playlist_item, 0);
+    }
+
+    #[tokio::test]
+    async fn test_playlist_scrolls_to_selection() {
+        let mut state = test_state(100).await;
+        assert_eq!(state.scroll_playlist_to_selection(10), 0);
+
+        // Moving within the view keeps it still
+        state.selected_playlist_item = 9;
+        assert_eq!(state.scroll_playlist_to_selection(10), 0);
+        state.selected_playlist_item = 10;
+        assert_eq!(state.scroll_playlist_to_selection(10), 1);
+        state.selected_playlist_item = 5;
+        assert_eq!(state.scroll_playlist_to_selection(10), 1);
+        state.selected_playlist_item = 0;
+        assert_eq!(state.scroll_playlist_to_selection(10), 0);
+
+        // Wrapping to the bottom shows the last full page
+        state.tick(UiAction::SelectPrevious);
+        assert_eq!(state.scroll_playlist_to_selection(10), 90);
+
+        // A taller view after a resize leaves no empty rows
+        assert_eq!(state.scroll_playlist_to_selection(40), 60);
+        assert_eq!(state.scroll_playlist_to_selection(200), 0);
+    }
+
+    #[tokio::test]
+    async fn test_filter_selects_first_match() {
+        let mut state = test_state(3).await;
+        state.playlist.add_file("Holiday.MP4");
+        state.playlist.add_file("holiday2.mp4");
+        state.tick(UiAction::StartFilter);
+        for c in "HOLI".chars() {
+            state.tick(UiAction::FilterInput(c));
+        }
+        assert_eq!(state.shown_playlist_indices(), [3, 4]);
+        assert_eq!(state.selected_playlist_item, 3);
+
+        // Navigation stays among the matches, and plays the real playlist index
+        state.tick(UiAction::SelectNext);
+        assert_eq!(state.selected_playlist_item, 4);
+        state.tick(UiAction::SelectNext);
+        assert_eq!(state.selected_playlist_item, 3);
+        state.tick(UiAction::FinishFilter);
+        assert_eq!(
+            state.tick(UiAction::Confirm),
+            Some(SideEffect::NeedsPlay(3))
+        );
+
+        state.tick(UiAction::ClearFilter);
+        assert_eq!(state.shown_playlist_indices().len(), 5);
+        assert_eq!(state.selected_playlist_item, 3);
+    }
+
+    #[tokio::test]
+    async fn test_filter_without_match_selects_nothing() {
+        let mut state = test_state(3).await;
+        state.tick(UiAction::StartFilter);
+        state.tick(UiAction::FilterInput('x'));
+        assert!(state.shown_playlist_indices().is_empty());
+        assert_eq!(state.get_selected_file(), None);
+        assert_eq!(state.tick(UiAction::Confirm), None);
+        state.tick(UiAction::RemoveSelected);
+        assert_eq!(state.playlist.len(), 3);
+
+        state.tick(UiAction::FilterBackspace);
+        state.tick(UiAction::FinishFilter);
+        assert_eq!(state.filter_query, None);
+    }
+
+    #[test]
+    fn test_transport_state_display() {
+        let display = |transport_state: &str, speed: &str| {
+            transport_state_display(&TransportInfo {
+                transport_state: transport_state.to_string(),
+                speed: speed.to_string(),
+                ..Default::default()
+            })
+        };
+        assert_eq!(display("PLAYING", "1"), "PLAYING");
+        assert_eq!(display("PLAYING", ""), "PLAYING");
+        assert_eq!(display("PLAYING", "2"), "▶▶ 2x");
+        assert_eq!(display("PLAYING", "-4"), "◀◀ 4x");
+        assert_eq!(display("PLAYING", "1/2"), "▶ 0.5x");
+        assert_eq!(display("PAUSED_PLAYBACK", "2"), "PAUSED_PLAYBACK");
+    }
+
+    #[tokio::test]
+    async fn test_remove_selected_last_item() {
+        let mut state = test_state(3).await;
+        state.tick(UiAction::SelectPrevious);
+        state.tick(UiAction::Confirm);
+        assert_eq!(state.current_file_index, Some(2));
+
+        assert_eq!(
+            state.remove_playlist_item(2),
+            Some(PathBuf::from("video2.mp4"))
+        );
+        assert_eq!(state.selected_playlist_item, 1);
+        assert_eq!(state.current_file_index, None);
+        assert_eq!(
+            state.get_selected_file(),
+            Some(&PathBuf::from("video1.mp4"))
+        );
+
+        state.tick(UiAction::SelectNext);
+        assert_eq!(state.selected_playlist_item, 0);
+    }
+
+    #[tokio::test]
+    async fn test_remove_keeps_current_file_index() {
+        let mut state = test_state(3).await;
+        state.tick(UiAction::SelectPrevious);
+        state.tick(UiAction::Confirm);
+
+        state.remove_playlist_item(0);
+        assert_eq!(state.current_file_index, Some(1));
+
+        state.remove_playlist_item(0);
+        state.tick(UiAction::RemoveSelected);
+        assert!(state.playlist.is_empty());
+        assert_eq!(state.selected_playlist_item, 0);
+        assert_eq!(state.current_file_index, None);
+        assert_eq!(state.get_selected_file(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tick_confirm_requests_play() {
+        let mut state = test_state(2).await;
+        state.tick(UiAction::SelectNext);
+        assert_eq!(
+            state.tick(UiAction::Confirm),
+            Some(SideEffect::NeedsPlay(1))
+        );
+        assert_eq!(state.current_file, Some(PathBuf::from("video1.mp4")));
+        assert_eq!(state.current_file_index, Some(1));
+
+        let mut empty = test_state(0).await;
+        assert_eq!(empty.tick(UiAction::Confirm), None);
+    }
+
+    #[tokio::test]
+    async fn test_tick_dialogs_block_other_actions() {
+        let mut state = test_state(2).await;
+        state.tick(UiAction::ToggleHelp);
+        assert!(state.show_help);
+
+        assert_eq!(state.tick(UiAction::SelectNext), None);
+        assert_eq!(state.selected_playlist_item, 0);
+        assert_eq!(state.tick(UiAction::Stop), None);
+
+        assert_eq!(state.tick(UiAction::TogglePlayPause), None);
+        assert!(!state.show_help);
+        assert_eq!(
+            state.tick(UiAction::TogglePlayPause),
+            Some(SideEffect::TogglePlayPause)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tick_quit_confirmation() {
+        let mut state = test_state(1).await;
+        state.transport_info = Some(TransportInfo {
+            transport_state: "PLAYING".to_string(),
+            transport_status: "OK".to_string(),
+            speed: "1".to_string(),
+        });
+
+        assert_eq!(state.tick(UiAction::Quit), None);
+        assert!(state.show_quit_dialog);
+        assert!(!state.should_quit);
+
+        // Any other key keeps playing
+        assert_eq!(state.tick(UiAction::Decline), None);
+        assert!(!state.show_quit_dialog);
+        assert!(!state.should_quit);
+
+        state.tick(UiAction::Quit);
+        assert_eq!(state.tick(UiAction::Stop), Some(SideEffect::StopAndQuit));
+        assert!(!state.show_quit_dialog);
+
+        state.tick(UiAction::Quit);
+        assert_eq!(state.tick(UiAction::Accept), None);
+        assert!(state.should_quit);
+
+        let mut unconfirmed = test_state(1).await.with_confirm_quit(false);
+        unconfirmed.transport_info = state.transport_info.clone();
+        unconfirmed.tick(UiAction::Quit);
+        assert!(unconfirmed.should_quit);
+    }
+
+    #[tokio::test]
+    async fn test_tick_speed_limits() {
+        let mut state = test_state(1).await;
+        assert_eq!(
+            state.tick(UiAction::SpeedUp),
+            Some(SideEffect::SetSpeed("2"))
+        );
+
+        state.playback_speed = "4".to_string();
+        assert_eq!(state.tick(UiAction::SpeedUp), None);
+        assert_eq!(state.status_message, "Already at maximum speed");
+    }
+
+    #[tokio::test]
+    async fn test_tick_quit_and_refresh() {
+        let mut state = test_state(1).await;
+        state.connection_lost = true;
+        assert_eq!(state.tick(UiAction::Refresh), Some(SideEffect::Refresh));
+        assert!(!state.connection_lost);
+
+        assert_eq!(
+            state.tick(UiAction::ToggleDeviceInfo),
+            Some(SideEffect::FetchTransportSettings)
+        );
+        assert_eq!(state.tick(UiAction::ToggleDeviceInfo), None);
+        state.tick(UiAction::ToggleDeviceInfo);
+        assert_eq!(state.tick(UiAction::Quit), None);
+        assert!(state.should_quit);
+    }
+}
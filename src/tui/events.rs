@@ -1,108 +1,159 @@
 //! Event handling for the TUI interface
 //!
 //! This module handles keyboard input and other events for the TUI application.
+//! Keys are translated into [`UiAction`]s applied by [`AppState::tick`], and the
+//! device work those return is carried out here.
 
-use super::app::AppState;
+use super::app::{AppState, SideEffect, UiAction};
 use crate::{
-    dlna::{pause, toggle_play_pause},
+    dlna::{pause, play_at_speed, stop, toggle_play_pause},
     error::Result,
 };
 use crossterm::event::KeyCode;
-use log::info;
+use log::{info, warn};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-/// Handles keyboard input events
-pub async fn handle_key_event(state_arc: Arc<Mutex<AppState>>, key_code: KeyCode) -> Result<()> {
-    let mut state = state_arc.lock().await;
+/// Maps a key to the interaction it triggers, if any
+pub fn ui_action(key_code: KeyCode) -> Option<UiAction> {
+    match key_code {
+        KeyCode::Char('q') | KeyCode::Esc => Some(UiAction::Quit),
+        KeyCode::Char('h') | KeyCode::F(1) => Some(UiAction::ToggleHelp),
+        KeyCode::Char('d') => Some(UiAction::ToggleDeviceInfo),
+        KeyCode::Up | KeyCode::Char('k') => Some(UiAction::SelectPrevious),
+        KeyCode::Down | KeyCode::Char('j') => Some(UiAction::SelectNext),
+        KeyCode::Enter => Some(UiAction::Confirm),
+        KeyCode::Delete => Some(UiAction::RemoveSelected),
+        KeyCode::Char(' ') | KeyCode::Char('p') => Some(UiAction::TogglePlayPause),
+        KeyCode::Char('}') => Some(UiAction::SpeedUp),
+        KeyCode::Char('{') => Some(UiAction::SpeedDown),
+        KeyCode::Char('s') => Some(UiAction::Stop),
+        KeyCode::Char('r') => Some(UiAction::Refresh),
+        KeyCode::Char('y') => Some(UiAction::Accept),
+        KeyCode::Char('n') => Some(UiAction::Decline),
+        KeyCode::Char('/') => Some(UiAction::StartFilter),
+        _ => None,
+    }
+}
 
-    // Handle global keys first
+/// Maps a key typed into the playlist filter to the interaction it triggers
+pub fn filter_action(key_code: KeyCode) -> Option<UiAction> {
     match key_code {
-        KeyCode::Char('q') | KeyCode::Esc => {
-            state.quit();
-            return Ok(());
-        }
-        KeyCode::Char('h') | KeyCode::F(1) => {
-            state.toggle_help();
-            return Ok(());
-        }
-        KeyCode::Char('d') => {
-            state.toggle_device_info();
-            return Ok(());
-        }
-        _ => {}
+        KeyCode::Char(c) => Some(UiAction::FilterInput(c)),
+        KeyCode::Backspace => Some(UiAction::FilterBackspace),
+        KeyCode::Enter => Some(UiAction::FinishFilter),
+        KeyCode::Esc => Some(UiAction::ClearFilter),
+        KeyCode::Up => Some(UiAction::SelectPrevious),
+        KeyCode::Down => Some(UiAction::SelectNext),
+        _ => None,
     }
+}
 
-    // If help or device info is shown, handle those keys
-    if state.show_help || state.show_device_info {
-        match key_code {
-            KeyCode::Enter | KeyCode::Char(' ') => {
-                state.close_dialogs();
-            }
-            _ => {}
-        }
-        return Ok(());
+/// Handles keyboard input events
+pub async fn handle_key_event(state_arc: Arc<Mutex<AppState>>, key_code: KeyCode) -> Result<()> {
+    let side_effect = {
+        let mut state = state_arc.lock().await;
+        // Keys go to the filter while typing it, and ESC clears an applied filter before quitting
+        let action = match (state.filter_editing, key_code) {
+            (true, _) => filter_action(key_code),
+            (false, KeyCode::Esc) if state.filter_query.is_some() => Some(UiAction::ClearFilter),
+            (false, _) => ui_action(key_code),
+        };
+        let Some(action) = action else {
+            return Ok(());
+        };
+        state.tick(action)
+    };
+    if let Some(side_effect) = side_effect {
+        run_side_effect(state_arc, side_effect).await;
     }
 
-    // Handle main interface keys
-    match key_code {
-        KeyCode::Up | KeyCode::Char('k') => {
-            state.previous_playlist_item();
-        }
-        KeyCode::Down | KeyCode::Char('j') => {
-            state.next_playlist_item();
+    Ok(())
+}
+
+/// Carries out device work requested by [`AppState::tick`]
+///
+/// The state lock is released while talking to the device, so that status
+/// updates keep flowing.
+async fn run_side_effect(state_arc: Arc<Mutex<AppState>>, side_effect: SideEffect) {
+    let render = state_arc.lock().await.render.clone();
+
+    match side_effect {
+        SideEffect::NeedsPlay(index) => {
+            let state = state_arc.lock().await;
+            if let Some(file) = state.playlist.get_file(index) {
+                info!("Selected file for playback: {}", file.display());
+            }
         }
-        KeyCode::Enter => {
-            if let Some(selected_file) = state.get_selected_file().cloned() {
-                let index = state.selected_playlist_item;
-                state.set_current_file(selected_file.clone(), index);
-                state.set_status_message(format!("Playing: {}", selected_file.display()));
-                info!("Selected file for playback: {}", selected_file.display());
+        SideEffect::TogglePlayPause => {
+            let result = toggle_play_pause(&render).await;
+            let mut state = state_arc.lock().await;
+            match result {
+                Ok(_) => state.set_status_message("Play/pause toggled".to_string()),
+                Err(e) => {
+                    state.set_error_message(Some(format!("Failed to toggle play/pause: {e}")))
+                }
             }
         }
-        KeyCode::Char(' ') | KeyCode::Char('p') => {
-            state.set_status_message("Toggling play/pause...".to_string());
-            let render = state.render.clone();
-            drop(state);
-
-            match toggle_play_pause(&render).await {
+        SideEffect::SetSpeed(speed) => {
+            let result = play_at_speed(&render, speed).await;
+            let mut state = state_arc.lock().await;
+            match result {
                 Ok(_) => {
-                    let mut state = state_arc.lock().await;
-                    state.set_status_message("Play/pause toggled".to_string());
-                }
-                Err(e) => {
-                    let mut state = state_arc.lock().await;
-                    state.set_error_message(Some(format!("Failed to toggle play/pause: {e}")));
+                    state.playback_speed = speed.to_string();
+                    state.set_status_message(format!("Playback speed: {speed}x"));
                 }
+                Err(e) => state.set_error_message(Some(format!("Speed change not supported: {e}"))),
             }
         }
-        KeyCode::Char('s') => {
-            state.set_status_message("Stopping playback...".to_string());
-            let render = state.render.clone();
-            drop(state);
-
-            match pause(&render).await {
+        SideEffect::Stop => {
+            let result = pause(&render).await;
+            let mut state = state_arc.lock().await;
+            match result {
                 Ok(_) => {
-                    let mut state = state_arc.lock().await;
                     state.set_status_message("Playback stopped".to_string());
                     state.clear_current_file();
                 }
-                Err(e) => {
-                    let mut state = state_arc.lock().await;
-                    state.set_error_message(Some(format!("Failed to stop playback: {e}")));
-                }
+                Err(e) => state.set_error_message(Some(format!("Failed to stop playback: {e}"))),
             }
         }
-        KeyCode::Char('r') => {
-            state.set_status_message("Refreshing status...".to_string());
-            drop(state);
-
+        SideEffect::StopAndQuit => {
+            if let Err(e) = stop(&render).await {
+                warn!("Failed to stop playback before quitting: {e}");
+            }
+            state_arc.lock().await.quit();
+        }
+        SideEffect::FetchTransportSettings => {
+            let result = render.get_transport_settings().await;
+            state_arc.lock().await.transport_settings = Some(result.map_err(|e| e.to_string()));
+        }
+        SideEffect::Refresh => {
             let mut state = state_arc.lock().await;
             state.update_status().await;
             state.set_status_message("Status refreshed".to_string());
         }
-        _ => {}
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ui_action_mapping() {
+        assert_eq!(ui_action(KeyCode::Esc), Some(UiAction::Quit));
+        assert_eq!(ui_action(KeyCode::F(1)), Some(UiAction::ToggleHelp));
+        assert_eq!(ui_action(KeyCode::Char('j')), Some(UiAction::SelectNext));
+        assert_eq!(ui_action(KeyCode::Up), Some(UiAction::SelectPrevious));
+        assert_eq!(ui_action(KeyCode::Char('}')), Some(UiAction::SpeedUp));
+        assert_eq!(ui_action(KeyCode::Delete), Some(UiAction::RemoveSelected));
+        assert_eq!(ui_action(KeyCode::Char('y')), Some(UiAction::Accept));
+        assert_eq!(ui_action(KeyCode::Char('x')), None);
+        assert_eq!(ui_action(KeyCode::Char('/')), Some(UiAction::StartFilter));
+        assert_eq!(
+            filter_action(KeyCode::Char('q')),
+            Some(UiAction::FilterInput('q'))
+        );
+        assert_eq!(filter_action(KeyCode::Esc), Some(UiAction::ClearFilter));
+    }
 }
@@ -12,7 +12,8 @@ use events::handle_key_event;
 use ui::draw_ui;
 
 use crate::{
-    devices::Render,
+    config::Config,
+    devices::{Render, RenderSpec},
     error::{Error, Result},
     media::Playlist,
 };
@@ -35,26 +36,29 @@ pub struct TuiApp {
 }
 
 impl TuiApp {
-    /// Creates a new TUI application
-    pub fn new(render: Render, playlist: Playlist) -> Result<Self> {
+    /// Creates a new TUI application from its initial state
+    pub fn new(state: AppState) -> Result<Self> {
         // Setup terminal
         enable_raw_mode().map_err(|e| Error::KeyboardError {
-            message: format!("Failed to enable raw mode: {e}"),
+            message: "Failed to enable raw mode".to_string(),
+            source: e.into(),
         })?;
 
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen, EnableMouseCapture).map_err(|e| {
             Error::KeyboardError {
-                message: format!("Failed to setup terminal: {e}"),
+                message: "Failed to setup terminal".to_string(),
+                source: e.into(),
             }
         })?;
 
         let backend = CrosstermBackend::new(stdout);
         let terminal = Terminal::new(backend).map_err(|e| Error::KeyboardError {
-            message: format!("Failed to create terminal: {e}"),
+            message: "Failed to create terminal".to_string(),
+            source: e.into(),
         })?;
 
-        let state = Arc::new(Mutex::new(AppState::new(render, playlist)));
+        let state = Arc::new(Mutex::new(state));
 
         Ok(Self { state, terminal })
     }
@@ -65,15 +69,31 @@ impl TuiApp {
 
         // Start status update task
         let state_clone = Arc::clone(&self.state);
+        let poll_interval = self.state.lock().await.poll_interval();
         let update_handle = tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(1000));
+            let Some(poll_interval) = poll_interval else {
+                // Without polling, the status is only queried once and on manual refresh
+                state_clone.lock().await.update_status().await;
+                return;
+            };
+            let mut interval = interval(poll_interval);
             loop {
                 interval.tick().await;
-                if let Ok(mut state) = state_clone.try_lock() {
-                    if state.should_quit {
-                        break;
+                let reconnect_spec = match state_clone.try_lock() {
+                    Ok(mut state) => {
+                        if state.should_quit {
+                            break;
+                        }
+                        state.update_status().await;
+                        state.take_reconnect_request()
                     }
-                    state.update_status().await;
+                    Err(_) => None,
+                };
+
+                // Resolve the device without holding the lock, since discovery can be slow
+                if let Some(render_spec) = reconnect_spec {
+                    let result = Render::new(render_spec).await;
+                    state_clone.lock().await.handle_reconnect_result(result);
                 }
             }
         });
@@ -99,25 +119,28 @@ impl TuiApp {
                 }
             }
 
-            // Draw the UI
-            let state = self.state.lock().await.clone();
-            self.terminal
-                .draw(|f| draw_ui(f, &state))
-                .map_err(|e| Error::KeyboardError {
-                    message: format!("Failed to draw UI: {e}"),
-                })?;
+            // Draw the UI on the shared state, which keeps the playlist scroll offset
+            {
+                let mut state = self.state.lock().await;
+                self.terminal
+                    .draw(|f| draw_ui(f, &mut state))
+                    .map_err(|e| Error::KeyboardError {
+                        message: "Failed to draw UI".to_string(),
+                        source: e.into(),
+                    })?;
+            }
 
             // Handle events
             if event::poll(Duration::from_millis(50)).map_err(|e| Error::KeyboardError {
-                message: format!("Failed to poll for events: {e}"),
+                message: "Failed to poll for events".to_string(),
+                source: e.into(),
             })? {
                 match event::read().map_err(|e| Error::KeyboardError {
-                    message: format!("Failed to read event: {e}"),
+                    message: "Failed to read event".to_string(),
+                    source: e.into(),
                 })? {
-                    Event::Key(key_event) => {
-                        if key_event.kind == KeyEventKind::Press {
-                            handle_key_event(Arc::clone(&self.state), key_event.code).await?;
-                        }
+                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                        handle_key_event(Arc::clone(&self.state), key_event.code).await?;
                     }
                     Event::Resize(_, _) => {
                         // Terminal was resized, will be handled on next draw
@@ -133,7 +156,8 @@ impl TuiApp {
     /// Cleanup terminal state
     fn cleanup(&mut self) -> Result<()> {
         disable_raw_mode().map_err(|e| Error::KeyboardError {
-            message: format!("Failed to disable raw mode: {e}"),
+            message: "Failed to disable raw mode".to_string(),
+            source: e.into(),
         })?;
 
         execute!(
@@ -142,13 +166,15 @@ impl TuiApp {
             DisableMouseCapture
         )
         .map_err(|e| Error::KeyboardError {
-            message: format!("Failed to cleanup terminal: {e}"),
+            message: "Failed to cleanup terminal".to_string(),
+            source: e.into(),
         })?;
 
         self.terminal
             .show_cursor()
             .map_err(|e| Error::KeyboardError {
-                message: format!("Failed to show cursor: {e}"),
+                message: "Failed to show cursor".to_string(),
+                source: e.into(),
             })?;
 
         Ok(())
@@ -156,7 +182,33 @@ impl TuiApp {
 }
 
 /// Starts the TUI application
+///
+/// If the device stops responding, the TUI reconnects to it by its location.
 pub async fn start_tui(render: Render, playlist: Playlist) -> Result<()> {
-    let mut app = TuiApp::new(render, playlist)?;
+    let render_spec = RenderSpec::Location(render.url().to_string());
+    start_tui_with_spec(render, render_spec, playlist).await
+}
+
+/// Starts the TUI application, reconnecting through the given render specification
+pub async fn start_tui_with_spec(
+    render: Render,
+    render_spec: RenderSpec,
+    playlist: Playlist,
+) -> Result<()> {
+    start_tui_with_config(render, render_spec, playlist, &Config::default()).await
+}
+
+/// Starts the TUI application with the status polling and quit confirmation of the given configuration
+pub async fn start_tui_with_config(
+    render: Render,
+    render_spec: RenderSpec,
+    playlist: Playlist,
+    config: &Config,
+) -> Result<()> {
+    let poll_interval = config.tui_poll_interval_ms.map(Duration::from_millis);
+    let state = AppState::new(render, render_spec, playlist)
+        .with_poll_interval(poll_interval)
+        .with_confirm_quit(config.tui_confirm_quit);
+    let mut app = TuiApp::new(state)?;
     app.run().await
 }
@@ -4,19 +4,25 @@
 //! playlist, and info panels.
 
 use super::layout::create_info_panel_layout;
-use crate::tui::app::{AppState, parse_time_string};
+use crate::{
+    tui::app::{AppState, parse_time_string, transport_state_display},
+    utils::format_milliseconds,
+};
 use ratatui::{
     Frame,
-    layout::{Alignment, Rect},
+    layout::{Alignment, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Wrap,
+    },
 };
 
 /// Draws the header with device info and status
 pub fn draw_header(f: &mut Frame, area: Rect, state: &AppState) {
-    let device_name = state.render.device.friendly_name();
-    let device_url = state.render.device.url().to_string();
+    let device_name = state.render.friendly_name();
+    let device_url = state.render.url().to_string();
 
     let header_text = format!("🎵 crab-dlna TUI - Device: {device_name} ({device_url})");
 
@@ -37,12 +43,18 @@ pub fn draw_header(f: &mut Frame, area: Rect, state: &AppState) {
 }
 
 /// Draws the playlist panel
-pub fn draw_playlist(f: &mut Frame, area: Rect, state: &AppState) {
-    let files: Vec<ListItem> = state
-        .playlist
-        .files()
+///
+/// Long playlists scroll to keep the selection visible, with a scrollbar and
+/// the range of shown items on the bottom border.
+pub fn draw_playlist(f: &mut Frame, area: Rect, state: &mut AppState) {
+    // Rows inside the borders
+    let visible_rows = usize::from(area.height.saturating_sub(2));
+    let offset = state.scroll_playlist_to_selection(visible_rows);
+
+    let shown = state.shown_playlist_indices();
+    let files: Vec<ListItem> = shown
         .iter()
-        .enumerate()
+        .filter_map(|i| Some((*i, state.playlist.get_file(*i)?)))
         .map(|(i, file)| {
             let filename = file
                 .file_name()
@@ -67,19 +79,46 @@ pub fn draw_playlist(f: &mut Frame, area: Rect, state: &AppState) {
         })
         .collect();
 
-    let mut list_state = ListState::default();
-    list_state.select(Some(state.selected_playlist_item));
+    let mut list_state = ListState::default().with_offset(offset);
+    let selected_position = state.selected_position(&shown);
+    list_state.select(selected_position);
+    let position = selected_position.map_or(0, |position| position + 1);
+
+    let len = shown.len();
+    let scrolls = len > visible_rows && visible_rows > 0;
+    let title = match &state.filter_query {
+        Some(query) => format!(
+            "Playlist ({position}/{len} of {}) /{query}{}",
+            state.playlist.len(),
+            if state.filter_editing { "_" } else { "" }
+        ),
+        None => format!("Playlist ({position}/{len})"),
+    };
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if scrolls {
+        let last = offset + visible_rows;
+        block = block
+            .title_bottom(Line::from(format!("{}–{last} of {len}", offset + 1)).right_aligned());
+    }
 
     let playlist = List::new(files)
-        .block(Block::default().borders(Borders::ALL).title(format!(
-            "Playlist ({}/{})",
-            state.selected_playlist_item + 1,
-            state.playlist.len()
-        )))
+        .block(block)
         .highlight_style(Style::default().bg(Color::DarkGray))
         .highlight_symbol("► ");
 
     f.render_stateful_widget(playlist, area, &mut list_state);
+
+    if scrolls {
+        let mut scrollbar_state = ScrollbarState::new(len).position(position.saturating_sub(1));
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight),
+            area.inner(Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut scrollbar_state,
+        );
+    }
 }
 
 /// Draws the info panel with playback status and controls
@@ -115,6 +154,10 @@ pub fn draw_current_track_info(f: &mut Frame, area: Rect, state: &AppState) {
         .as_ref()
         .map(|info| info.transport_state.as_str())
         .unwrap_or("Unknown");
+    let transport_state_text = state
+        .transport_info
+        .as_ref()
+        .map_or_else(|| "Unknown".to_string(), transport_state_display);
 
     let track_info = vec![
         Line::from(vec![
@@ -125,7 +168,7 @@ pub fn draw_current_track_info(f: &mut Frame, area: Rect, state: &AppState) {
         Line::from(vec![
             Span::styled("State: ", Style::default().add_modifier(Modifier::BOLD)),
             Span::styled(
-                transport_state,
+                transport_state_text,
                 match transport_state {
                     "PLAYING" => Style::default().fg(Color::Green),
                     "PAUSED_PLAYBACK" => Style::default().fg(Color::Yellow),
@@ -134,6 +177,10 @@ pub fn draw_current_track_info(f: &mut Frame, area: Rect, state: &AppState) {
                 },
             ),
         ]),
+        Line::from(vec![
+            Span::styled("Speed: ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!("{}x", state.playback_speed)),
+        ]),
         Line::from(""),
         Line::from(vec![
             Span::styled("Updated: ", Style::default().add_modifier(Modifier::BOLD)),
@@ -167,11 +214,21 @@ pub fn draw_progress_bar(f: &mut Frame, area: Rect, state: &AppState) {
             0
         };
 
-        let label = format!(
-            "{} / {}",
-            position_info.rel_time, position_info.track_duration
-        );
+        let label = match position_info.remaining_ms() {
+            Some(remaining_ms) => format!(
+                "{} / {}  (-{} remaining)",
+                position_info.rel_time,
+                position_info.track_duration,
+                format_milliseconds(remaining_ms)
+            ),
+            None => format!(
+                "{} / {}",
+                position_info.rel_time, position_info.track_duration
+            ),
+        };
         (progress, label)
+    } else if state.position_unavailable {
+        (0, "Position unavailable".to_string())
     } else {
         (0, "-- / --".to_string())
     };
@@ -189,7 +246,7 @@ pub fn draw_progress_bar(f: &mut Frame, area: Rect, state: &AppState) {
 pub fn draw_transport_controls(f: &mut Frame, area: Rect, _state: &AppState) {
     let controls_text = vec![
         Line::from("Controls:"),
-        Line::from("SPACE/P: Play/Pause  S: Stop"),
+        Line::from("SPACE/P: Play/Pause  S: Stop  {/}: Speed"),
         Line::from("↑/↓: Navigate  ENTER: Play Selected"),
         Line::from("R: Refresh  H: Help  D: Device Info"),
     ];
@@ -228,7 +285,7 @@ pub fn draw_status_messages(f: &mut Frame, area: Rect, state: &AppState) {
 
 /// Draws the footer with keyboard shortcuts
 pub fn draw_footer(f: &mut Frame, area: Rect, _state: &AppState) {
-    let footer_text = "Q/ESC: Quit | H/F1: Help | D: Device Info | SPACE/P: Play/Pause | ↑/↓: Navigate | R: Refresh";
+    let footer_text = "Q/ESC: Quit | H/F1: Help | D: Device Info | SPACE/P: Play/Pause | ↑/↓: Navigate | /: Filter | R: Refresh";
 
     let footer = Paragraph::new(footer_text)
         .style(Style::default().fg(Color::Gray))
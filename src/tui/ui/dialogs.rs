@@ -1,6 +1,6 @@
 //! Dialog components for the TUI interface
 //!
-//! This module contains dialog boxes like help and device info dialogs.
+//! This module contains dialog boxes like help, device info and quit confirmation dialogs.
 
 use super::layout::centered_rect;
 use crate::tui::app::AppState;
@@ -26,17 +26,20 @@ pub fn draw_help_dialog(f: &mut Frame) {
         Line::from("Playback Controls:"),
         Line::from("  SPACE / P    - Toggle play/pause"),
         Line::from("  S            - Stop playback"),
+        Line::from("  { / }        - Slower / faster playback speed"),
         Line::from("  R            - Refresh status"),
         Line::from(""),
         Line::from("Navigation:"),
         Line::from("  ↑ / K        - Previous item"),
         Line::from("  ↓ / J        - Next item"),
         Line::from("  ENTER        - Play selected item"),
+        Line::from("  DELETE       - Remove selected item"),
+        Line::from("  /            - Filter by file name (ENTER keeps it, ESC clears it)"),
         Line::from(""),
         Line::from("Interface:"),
         Line::from("  H / F1       - Toggle this help"),
         Line::from("  D            - Show device info"),
-        Line::from("  Q / ESC      - Quit application"),
+        Line::from("  Q / ESC      - Quit application (asks first while playing)"),
         Line::from(""),
         Line::from("Press any key to close this help..."),
     ];
@@ -55,13 +58,44 @@ pub fn draw_help_dialog(f: &mut Frame) {
     f.render_widget(help_paragraph, area);
 }
 
+/// Draws the quit confirmation dialog
+pub fn draw_quit_dialog(f: &mut Frame) {
+    let area = centered_rect(40, 30, f.area());
+
+    f.render_widget(Clear, area);
+
+    let quit_text = vec![
+        Line::from(vec![Span::styled(
+            "Quit while playing?",
+            Style::default().add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(""),
+        Line::from("  Y            - Quit, the device keeps playing"),
+        Line::from("  S            - Stop playback and quit"),
+        Line::from("  N / ESC      - Cancel"),
+    ];
+
+    let quit_paragraph = Paragraph::new(quit_text)
+        .block(
+            Block::default()
+                .title("Quit")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        )
+        .style(Style::default().fg(Color::White))
+        .alignment(ratatui::layout::Alignment::Left)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(quit_paragraph, area);
+}
+
 /// Draws the device info dialog
 pub fn draw_device_info_dialog(f: &mut Frame, state: &AppState) {
     let area = centered_rect(70, 60, f.area());
 
     f.render_widget(Clear, area);
 
-    let device = &state.render.device;
+    let render = &state.render;
     let device_info = vec![
         Line::from(vec![Span::styled(
             "Device Information",
@@ -70,15 +104,18 @@ pub fn draw_device_info_dialog(f: &mut Frame, state: &AppState) {
         Line::from(""),
         Line::from(vec![
             Span::styled("Name: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(device.friendly_name()),
+            Span::raw(render.friendly_name()),
         ]),
         Line::from(vec![
-            Span::styled("URL: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(device.url().to_string()),
+            Span::styled(
+                "Description URL: ",
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(render.description_url().to_string()),
         ]),
         Line::from(vec![
             Span::styled("Type: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(device.device_type().to_string()),
+            Span::raw(render.device_type().to_string()),
         ]),
         Line::from(""),
         Line::from(vec![Span::styled(
@@ -90,14 +127,22 @@ pub fn draw_device_info_dialog(f: &mut Frame, state: &AppState) {
                 "Service Type: ",
                 Style::default().add_modifier(Modifier::BOLD),
             ),
-            Span::raw(state.render.service.service_type().to_string()),
+            Span::raw(render.service_type().to_string()),
         ]),
         Line::from(vec![
             Span::styled(
                 "Service ID: ",
                 Style::default().add_modifier(Modifier::BOLD),
             ),
-            Span::raw(state.render.service.service_id()),
+            Span::raw(render.service_id()),
+        ]),
+        Line::from(vec![
+            Span::styled("Play Mode: ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(match &state.transport_settings {
+                Some(Ok(settings)) => settings.play_mode.to_string(),
+                Some(Err(e)) => e.clone(),
+                None => "Loading...".to_string(),
+            }),
         ]),
         Line::from(""),
         Line::from("Press any key to close this dialog..."),
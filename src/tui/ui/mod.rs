@@ -15,7 +15,7 @@ use super::app::AppState;
 use ratatui::Frame;
 
 /// Draws the main UI
-pub fn draw_ui(f: &mut Frame, state: &AppState) {
+pub fn draw_ui(f: &mut Frame, state: &mut AppState) {
     // Create main layout
     let chunks = create_main_layout(f.area());
 
@@ -37,4 +37,7 @@ pub fn draw_ui(f: &mut Frame, state: &AppState) {
     if state.show_device_info {
         draw_device_info_dialog(f, state);
     }
+    if state.show_quit_dialog {
+        draw_quit_dialog(f);
+    }
 }
@@ -9,6 +9,8 @@ pub enum SubtitleType {
     Ass,
     /// SubStation Alpha subtitle format
     Ssa,
+    /// WebVTT subtitle format
+    Vtt,
 }
 
 impl SubtitleType {
@@ -18,6 +20,7 @@ impl SubtitleType {
             SubtitleType::Srt => "srt",
             SubtitleType::Ass => "ass",
             SubtitleType::Ssa => "ssa",
+            SubtitleType::Vtt => "vtt",
         }
     }
 
@@ -27,12 +30,30 @@ impl SubtitleType {
             SubtitleType::Srt => "text/srt",
             SubtitleType::Ass => "text/x-ass",
             SubtitleType::Ssa => "text/x-ssa",
+            SubtitleType::Vtt => "text/vtt",
+        }
+    }
+
+    /// Returns the MIME type advertised to renderers in DIDL-Lite `protocolInfo`
+    ///
+    /// Renderers generally don't understand the ASS/SSA MIME types, so those
+    /// formats are advertised as `smi/caption`.
+    pub fn protocol_mime_type(&self) -> &'static str {
+        match self {
+            SubtitleType::Srt => "text/srt",
+            SubtitleType::Vtt => "text/vtt",
+            SubtitleType::Ass | SubtitleType::Ssa => "smi/caption",
         }
     }
 
     /// Returns all supported subtitle types in order of preference
     pub fn all() -> Vec<SubtitleType> {
-        vec![SubtitleType::Srt, SubtitleType::Ass, SubtitleType::Ssa]
+        vec![
+            SubtitleType::Srt,
+            SubtitleType::Ass,
+            SubtitleType::Ssa,
+            SubtitleType::Vtt,
+        ]
     }
 }
 
@@ -51,6 +72,15 @@ mod tests {
         assert_eq!(SubtitleType::Srt.extension(), "srt");
         assert_eq!(SubtitleType::Ass.extension(), "ass");
         assert_eq!(SubtitleType::Ssa.extension(), "ssa");
+        assert_eq!(SubtitleType::Vtt.extension(), "vtt");
+    }
+
+    #[test]
+    fn test_subtitle_type_protocol_mime_type() {
+        assert_eq!(SubtitleType::Srt.protocol_mime_type(), "text/srt");
+        assert_eq!(SubtitleType::Vtt.protocol_mime_type(), "text/vtt");
+        assert_eq!(SubtitleType::Ass.protocol_mime_type(), "smi/caption");
+        assert_eq!(SubtitleType::Ssa.protocol_mime_type(), "smi/caption");
     }
 
     #[test]
@@ -63,10 +93,11 @@ mod tests {
     #[test]
     fn test_subtitle_type_all() {
         let all_types = SubtitleType::all();
-        assert_eq!(all_types.len(), 3);
+        assert_eq!(all_types.len(), 4);
         assert_eq!(all_types[0], SubtitleType::Srt);
         assert_eq!(all_types[1], SubtitleType::Ass);
         assert_eq!(all_types[2], SubtitleType::Ssa);
+        assert_eq!(all_types[3], SubtitleType::Vtt);
     }
 
     #[test]
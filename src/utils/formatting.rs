@@ -3,6 +3,8 @@
 //! This module provides functions for formatting text and display strings,
 //! particularly for device information and user interface elements.
 
+use std::collections::HashMap;
+
 /// Formats a device description for display
 ///
 /// # Arguments
@@ -32,7 +34,187 @@ pub fn format_device_with_service_description(
     friendly_name: &str,
     url: &str,
 ) -> String {
-    format!(
-        "[{device_type}][{service_type}] {friendly_name} @ {url}"
-    )
+    format!("[{device_type}][{service_type}] {friendly_name} @ {url}")
+}
+
+/// Formats the arguments returned by a UPnP action for logging
+///
+/// Arguments are sorted by name, so that responses read the same from one
+/// run to the next.
+///
+/// # Arguments
+/// * `response` - The output arguments of the action
+///
+/// # Returns
+/// Returns the arguments as `Name="value"` pairs
+pub fn format_action_response(response: &HashMap<String, String>) -> String {
+    let mut arguments: Vec<_> = response.iter().collect();
+    arguments.sort();
+    let arguments: Vec<String> = arguments
+        .into_iter()
+        .map(|(name, value)| format!("{name}={value:?}"))
+        .collect();
+    format!("{{{}}}", arguments.join(", "))
+}
+
+/// Release tags commonly found in file names that aren't part of the title
+///
+/// Words that also make up titles, like `web`, `proper` or `extended`, are
+/// left out, as cutting the title there would lose part of it.
+const RELEASE_TAGS: &[&str] = &[
+    "480p", "576p", "720p", "1080p", "1080i", "2160p", "x264", "x265", "h264", "h265", "hevc",
+    "xvid", "divx", "av1", "bluray", "blu-ray", "brrip", "bdrip", "webrip", "web-dl", "webdl",
+    "hdtv", "dvdrip", "hdrip", "remux", "hdr", "hdr10", "10bit", "aac", "ac3", "dts", "ddp5",
+    "dd5", "atmos", "repack", "unrated",
+];
+
+/// Derives a human-readable title from a media file name
+///
+/// Strips the extension and replaces dots and underscores with spaces. When
+/// `strip_release_tags` is set, bracketed tags and everything from the first
+/// release tag (resolution, source, codec, ...) onwards are removed as well.
+///
+/// # Arguments
+/// * `filename` - The file name, with or without leading directories
+/// * `strip_release_tags` - Whether to remove common release tags
+///
+/// # Returns
+/// Returns the cleaned title, or the file name itself if nothing is left after cleaning
+pub fn clean_title_from_filename(filename: &str, strip_release_tags: bool) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| filename.to_string());
+
+    let mut title = stem.replace(['.', '_'], " ");
+
+    if strip_release_tags {
+        title = remove_bracketed_tags(&title);
+        title = title
+            .split_whitespace()
+            .take_while(|word| !is_release_tag(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        title = title.trim_end_matches([' ', '-']).to_string();
+    }
+
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    match title.is_empty() {
+        true => stem,
+        false => title,
+    }
+}
+
+/// Removes `[...]` and `{...}` groups, and `(...)` groups that only contain release tags
+fn remove_bracketed_tags(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['[', '{', '(']) {
+        let close = match rest.as_bytes()[start] {
+            b'[' => ']',
+            b'{' => '}',
+            _ => ')',
+        };
+        let Some(length) = rest[start..].find(close) else {
+            break;
+        };
+
+        let inner = &rest[start + 1..start + length];
+        let keep = close == ')' && !inner.split_whitespace().all(is_release_tag);
+        result.push_str(&rest[..start]);
+        if keep {
+            result.push_str(&rest[start..=start + length]);
+        }
+        result.push(' ');
+        rest = &rest[start + length + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Checks whether a word is a release tag, ignoring a trailing `-GROUP` suffix
+fn is_release_tag(word: &str) -> bool {
+    let word = word.to_lowercase();
+    RELEASE_TAGS.contains(&word.as_str())
+        || word
+            .split_once('-')
+            .is_some_and(|(tag, _)| RELEASE_TAGS.contains(&tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_action_response() {
+        let response = HashMap::from([
+            ("TrackDuration".to_string(), "00:01:00".to_string()),
+            ("Track".to_string(), "1".to_string()),
+        ]);
+        assert_eq!(
+            format_action_response(&response),
+            r#"{Track="1", TrackDuration="00:01:00"}"#
+        );
+        assert_eq!(format_action_response(&HashMap::new()), "{}");
+    }
+
+    #[test]
+    fn test_clean_title_dotted_name() {
+        assert_eq!(
+            clean_title_from_filename("My.Movie.2021.1080p.BluRay.x264.mkv", false),
+            "My Movie 2021 1080p BluRay x264"
+        );
+        assert_eq!(
+            clean_title_from_filename("My.Movie.2021.1080p.BluRay.x264.mkv", true),
+            "My Movie 2021"
+        );
+        assert_eq!(
+            clean_title_from_filename("/videos/Another_Movie.WEB-DL.x265-GROUP.mp4", true),
+            "Another Movie"
+        );
+    }
+
+    #[test]
+    fn test_clean_title_bracketed_tags() {
+        assert_eq!(
+            clean_title_from_filename("[Group] Show_Name_-_01_[1080p].mkv", true),
+            "Show Name - 01"
+        );
+        assert_eq!(
+            clean_title_from_filename("Some Movie (2019) (1080p x264).mp4", true),
+            "Some Movie (2019)"
+        );
+    }
+
+    #[test]
+    fn test_clean_title_keeps_words_that_look_like_tags() {
+        assert_eq!(
+            clean_title_from_filename("Charlotte's.Web.2006.1080p.mkv", true),
+            "Charlotte's Web 2006"
+        );
+        assert_eq!(
+            clean_title_from_filename("The.Proper.Way.2020.WEB-DL.mkv", true),
+            "The Proper Way 2020"
+        );
+        assert_eq!(
+            clean_title_from_filename("Extended.Family.S01E01.720p.mkv", true),
+            "Extended Family S01E01"
+        );
+        assert_eq!(
+            clean_title_from_filename("UHD.4K.AVC.Demo.2160p.mkv", true),
+            "UHD 4K AVC Demo"
+        );
+    }
+
+    #[test]
+    fn test_clean_title_falls_back_to_file_name() {
+        assert_eq!(clean_title_from_filename("1080p.mkv", true), "1080p");
+        assert_eq!(
+            clean_title_from_filename("Plain Title.mp4", true),
+            "Plain Title"
+        );
+    }
 }
@@ -0,0 +1,170 @@
+//! Play history log for crab-dlna
+//!
+//! With `play --history`, each file played is appended to a log of JSON lines,
+//! kept to its most recent `HISTORY_MAX_ENTRIES` entries.
+
+use crate::{
+    config::HISTORY_FILE_NAME,
+    error::{Error, Result},
+};
+use serde::Serialize;
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A file played, as written to the history log
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// Path of the local file
+    pub file: String,
+    /// Friendly name of the device the file was played on
+    pub device: String,
+    /// When playback started, in seconds since the Unix epoch
+    pub started_at: u64,
+    /// How playback ended
+    pub outcome: HistoryOutcome,
+    /// Description of the failure, for failed playbacks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How the playback of a file in the history log ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOutcome {
+    /// The file played to its end
+    Completed,
+    /// The device was stopped before the end, e.g. with its remote
+    Stopped,
+    /// Playback failed
+    Failed,
+    /// Playback was interrupted with Ctrl-C
+    Interrupted,
+}
+
+/// Current time in seconds since the Unix epoch, as recorded in history entries
+pub fn unix_time_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Default location of the history log, in the crab-dlna configuration directory
+///
+/// That is `$XDG_CONFIG_HOME/crab-dlna` or `~/.config/crab-dlna` on Linux,
+/// `~/Library/Application Support/crab-dlna` on macOS and
+/// `%APPDATA%\crab-dlna` on Windows. `None` if the directory can't be found.
+pub fn default_history_path() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let config_dir = if cfg!(windows) {
+        non_empty("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        non_empty("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("crab-dlna").join(HISTORY_FILE_NAME))
+}
+
+/// Appends an entry to a history log, dropping the oldest ones beyond `max_entries`
+///
+/// The log and its directory are created if missing. The entry is appended to
+/// the log, which is only rewritten when it grows beyond `max_entries`.
+pub fn append_history(path: &Path, entry: &HistoryEntry, max_entries: usize) -> Result<()> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(source) => return Err(Error::Io { source }),
+    };
+    let line = serde_json::to_string(entry).map_err(|e| Error::Io { source: e.into() })?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|source| Error::Io { source })?;
+    }
+    let mut lines: Vec<&str> = existing.lines().filter(|line| !line.is_empty()).collect();
+    if lines.len() >= max_entries {
+        lines.push(&line);
+        let kept = &lines[lines.len().saturating_sub(max_entries)..];
+        return std::fs::write(path, kept.join("\n") + "\n").map_err(|source| Error::Io { source });
+    }
+
+    // A log cut short, e.g. by a crash, may miss its last line break
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{separator}{line}"))
+        .map_err(|source| Error::Io { source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+
+    fn entry(file: &str, outcome: HistoryOutcome) -> HistoryEntry {
+        HistoryEntry {
+            file: file.to_string(),
+            device: "Living Room TV".to_string(),
+            started_at: 1_700_000_000,
+            outcome,
+            error: (outcome == HistoryOutcome::Failed).then(|| "Render refused".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_history_entry_json() {
+        assert_eq!(
+            serde_json::to_string(&entry("a.mkv", HistoryOutcome::Completed)).unwrap(),
+            r#"{"file":"a.mkv","device":"Living Room TV","started_at":1700000000,"outcome":"completed"}"#
+        );
+        assert!(
+            serde_json::to_string(&entry("a.mkv", HistoryOutcome::Failed))
+                .unwrap()
+                .ends_with(r#""outcome":"failed","error":"Render refused"}"#)
+        );
+    }
+
+    #[test]
+    fn test_append_history_keeps_most_recent_entries() {
+        let dir = TestDir::new("crab_dlna_history");
+        let path = dir.join("nested").join(HISTORY_FILE_NAME);
+
+        for file in ["1.mkv", "2.mkv", "3.mkv"] {
+            append_history(&path, &entry(file, HistoryOutcome::Completed), 2).unwrap();
+        }
+        append_history(&path, &entry("4.mkv", HistoryOutcome::Interrupted), 2).unwrap();
+
+        let history = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = history.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""file":"3.mkv""#));
+        assert!(lines[1].contains(r#""outcome":"interrupted""#));
+    }
+
+    #[test]
+    fn test_append_history_keeps_earlier_lines_as_is() {
+        let dir = TestDir::new("crab_dlna_history_append");
+        let path = dir.join(HISTORY_FILE_NAME);
+        std::fs::write(&path, r#"{"file":"0.mkv"}"#).unwrap();
+
+        append_history(&path, &entry("1.mkv", HistoryOutcome::Stopped), 10).unwrap();
+
+        let history = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = history.lines().collect();
+        assert_eq!(lines[0], r#"{"file":"0.mkv"}"#);
+        assert!(lines[1].contains(r#""outcome":"stopped""#));
+        assert!(history.ends_with('\n'));
+    }
+}
@@ -3,8 +3,11 @@
 //! This module provides functions for working with media files,
 //! including subtitle detection and file format validation.
 
-use crate::types::SubtitleType;
-use std::path::Path;
+use crate::{
+    error::{Error, Result},
+    types::SubtitleType,
+};
+use std::path::{Path, PathBuf};
 
 /// Infers subtitle file path from video file path
 ///
@@ -16,7 +19,7 @@ use std::path::Path;
 ///
 /// # Returns
 /// Returns the path to the subtitle file if found, None otherwise
-pub fn infer_subtitle_from_video(video_path: &Path) -> Option<std::path::PathBuf> {
+pub fn infer_subtitle_from_video(video_path: &Path) -> Option<PathBuf> {
     // Try different subtitle formats in order of preference using SubtitleType enum
     for subtitle_type in SubtitleType::all() {
         let inferred_subtitle_path = video_path.with_extension(subtitle_type.extension());
@@ -29,6 +32,69 @@ pub fn infer_subtitle_from_video(video_path: &Path) -> Option<std::path::PathBuf
     None
 }
 
+/// Infers all subtitle files of a video file
+///
+/// The subtitle found by [`infer_subtitle_from_video`] comes first, followed
+/// by sidecars named like `<stem>.<anything>.<ext>`, e.g. `movie.en.srt`
+/// and `movie.fr.srt` for `movie.mp4`, in name order.
+///
+/// # Arguments
+/// * `video_path` - Path to the video file
+///
+/// # Returns
+/// Returns the paths to the subtitle files found
+pub fn infer_subtitles_from_video(video_path: &Path) -> Vec<PathBuf> {
+    let mut subtitles: Vec<PathBuf> = infer_subtitle_from_video(video_path).into_iter().collect();
+
+    let (Some(video_dir), Some(stem)) = (video_path.parent(), video_path.file_stem()) else {
+        return subtitles;
+    };
+    let video_dir = match video_dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => video_dir,
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let Ok(entries) = std::fs::read_dir(video_dir) else {
+        return subtitles;
+    };
+
+    let mut sidecars: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            name.strip_prefix(&prefix).is_some_and(|rest| {
+                rest.contains('.') && detect_subtitle_type(&path).is_some() && path.is_file()
+            })
+        })
+        .map(|entry| video_path.with_file_name(entry.file_name()))
+        .collect();
+    sidecars.sort();
+    subtitles.extend(sidecars);
+    subtitles
+}
+
+/// Resolves a subtitle path given on the command line
+///
+/// Relative paths are looked up next to the video file first, and only then
+/// in the current working directory.
+///
+/// # Arguments
+/// * `subtitle_path` - Path to the subtitle file as given by the user
+/// * `video_path` - Path to the video file
+///
+/// # Returns
+/// Returns the path to the subtitle file
+pub fn resolve_subtitle_path(subtitle_path: &Path, video_path: &Path) -> PathBuf {
+    if subtitle_path.is_absolute() {
+        return subtitle_path.to_path_buf();
+    }
+    match video_path.parent() {
+        Some(video_dir) if video_dir.join(subtitle_path).is_file() => video_dir.join(subtitle_path),
+        _ => subtitle_path.to_path_buf(),
+    }
+}
+
 /// Detects subtitle type from file extension
 ///
 /// # Arguments
@@ -43,7 +109,46 @@ pub fn detect_subtitle_type(path: &Path) -> Option<SubtitleType> {
         .unwrap_or("")
         .to_lowercase();
 
-    SubtitleType::all().into_iter().find(|&subtitle_type| subtitle_type.extension() == extension)
+    SubtitleType::all()
+        .into_iter()
+        .find(|&subtitle_type| subtitle_type.extension() == extension)
+}
+
+/// Checks whether a subtitle file is large enough for renderers to drop or truncate it
+///
+/// Cues are counted for SubRip, WebVTT and SSA/ASS files. A limit of 0 is
+/// never exceeded.
+///
+/// # Arguments
+/// * `path` - Path to the subtitle file
+/// * `max_bytes` - File size above which a warning is returned
+/// * `max_cues` - Number of cues above which a warning is returned
+///
+/// # Returns
+/// Returns a warning with guidance if a limit is exceeded, None otherwise
+pub fn subtitle_size_warning(path: &Path, max_bytes: u64, max_cues: usize) -> Option<String> {
+    let contents = std::fs::read(path).ok()?;
+    let size = contents.len() as u64;
+    if max_bytes > 0 && size > max_bytes {
+        return Some(format!(
+            "Subtitle {} is {} KiB, some renderers drop subtitles larger than {} KiB; try splitting it if it doesn't show up",
+            path.display(),
+            size / 1024,
+            max_bytes / 1024
+        ));
+    }
+
+    let cues = String::from_utf8_lossy(&contents)
+        .lines()
+        .filter(|line| line.contains("-->") || line.trim_start().starts_with("Dialogue:"))
+        .count();
+    if max_cues > 0 && cues > max_cues {
+        return Some(format!(
+            "Subtitle {} has {cues} cues, some renderers truncate subtitles with more than {max_cues}; try splitting it if it stops showing up",
+            path.display()
+        ));
+    }
+    None
 }
 
 /// Validates if a file path has a supported video extension
@@ -54,11 +159,11 @@ pub fn detect_subtitle_type(path: &Path) -> Option<SubtitleType> {
 /// # Returns
 /// Returns true if the file has a supported video extension
 pub fn is_supported_video_file(path: &Path) -> bool {
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            let ext_lower = ext_str.to_lowercase();
-            return crate::config::SUPPORTED_VIDEO_EXTENSIONS.contains(&ext_lower.as_str());
-        }
+    if let Some(extension) = path.extension()
+        && let Some(ext_str) = extension.to_str()
+    {
+        let ext_lower = ext_str.to_lowercase();
+        return crate::config::SUPPORTED_VIDEO_EXTENSIONS.contains(&ext_lower.as_str());
     }
     false
 }
@@ -71,11 +176,11 @@ pub fn is_supported_video_file(path: &Path) -> bool {
 /// # Returns
 /// Returns true if the file has a supported audio extension
 pub fn is_supported_audio_file(path: &Path) -> bool {
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            let ext_lower = ext_str.to_lowercase();
-            return crate::config::SUPPORTED_AUDIO_EXTENSIONS.contains(&ext_lower.as_str());
-        }
+    if let Some(extension) = path.extension()
+        && let Some(ext_str) = extension.to_str()
+    {
+        let ext_lower = ext_str.to_lowercase();
+        return crate::config::SUPPORTED_AUDIO_EXTENSIONS.contains(&ext_lower.as_str());
     }
     false
 }
@@ -91,22 +196,128 @@ pub fn is_supported_media_file(path: &Path) -> bool {
     is_supported_video_file(path) || is_supported_audio_file(path)
 }
 
-/// Sanitizes a filename for use in URLs
+/// Checks that a file exists and can be opened for reading
+///
+/// Distinguishes missing files from files that exist but can't be read, e.g.
+/// because of permissions on a mounted network share.
 ///
 /// # Arguments
-/// * `filename` - The filename to sanitize
+/// * `path` - Path to the file
+/// * `context` - Context reported if the file does not exist
 ///
 /// # Returns
-/// Returns a URL-safe version of the filename
-pub fn sanitize_filename_for_url(filename: &str) -> String {
-    use slugify::slugify;
-    slugify!(filename, separator = ".")
+/// Returns an error describing why the file can't be read, Ok otherwise
+pub fn check_file_readable(path: &Path, context: &str) -> Result<()> {
+    if !path.exists() {
+        return Err(Error::MediaFileNotFound {
+            path: path.display().to_string(),
+            context: context.to_string(),
+        });
+    }
+
+    std::fs::File::open(path).map_err(|source| Error::MediaFileNotReadable {
+        path: path.display().to_string(),
+        source,
+    })?;
+    Ok(())
+}
+
+/// Number of leading bytes read to recognize a media container
+const MEDIA_HEADER_LEN: usize = 8192;
+
+/// Extensions sharing a container, so a header recognized as one fits the others
+const MEDIA_CONTAINER_FAMILIES: &[&[&str]] = &[
+    &["mp4", "m4v", "m4a", "mov", "3gp"],
+    &["mkv", "webm"],
+    &["ogg", "ogv", "opus"],
+    &["mpg", "mpeg"],
+    &["ts", "m2ts"],
+];
+
+/// Checks that a media file is not empty and starts with the header of its format
+///
+/// Catches truncated downloads before a device is contacted. Only the first
+/// bytes are read, so a file with a valid header can still fail to play.
+///
+/// # Arguments
+/// * `path` - Path to the media file
+///
+/// # Returns
+/// Returns an error describing why the file can't be played, Ok otherwise
+pub fn verify_media_file(path: &Path) -> Result<()> {
+    use std::io::Read;
+
+    check_file_readable(path, "File does not exist")?;
+    let invalid = |reason: &str| Error::MediaFileInvalid {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    };
+    let not_readable = |source| Error::MediaFileNotReadable {
+        path: path.display().to_string(),
+        source,
+    };
+
+    let file = std::fs::File::open(path).map_err(not_readable)?;
+    let mut header = Vec::with_capacity(MEDIA_HEADER_LEN);
+    file.take(MEDIA_HEADER_LEN as u64)
+        .read_to_end(&mut header)
+        .map_err(not_readable)?;
+
+    if header.is_empty() {
+        return Err(invalid("file is empty"));
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let extension = extension.as_str();
+    let family = MEDIA_CONTAINER_FAMILIES
+        .iter()
+        .find(|family| family.contains(&extension))
+        .copied()
+        .unwrap_or(std::slice::from_ref(&extension));
+    let media_types = media_type_matchers();
+    if !family.iter().any(|ext| media_types.is_supported(ext)) {
+        return Ok(());
+    }
+
+    match media_types.get(&header) {
+        Some(kind) if family.contains(&kind.extension()) => Ok(()),
+        Some(kind) => Err(invalid(&format!(
+            "header looks like {}, not a .{extension} file",
+            kind.mime_type()
+        ))),
+        None => Err(invalid(&format!(
+            "header does not look like a .{extension} file"
+        ))),
+    }
+}
+
+/// Builds the `infer` matchers with the formats it doesn't know about
+///
+/// Adds MPEG transport streams and MP3 files that start with a bare frame
+/// instead of an ID3 tag.
+fn media_type_matchers() -> infer::Infer {
+    let mut media_types = infer::Infer::new();
+    media_types.add("video/mp2t", "ts", |buf| {
+        buf.len() > 188 && buf[0] == 0x47 && buf[188] == 0x47
+    });
+    media_types.add("video/mp2t", "m2ts", |buf| {
+        buf.len() > 196 && buf[4] == 0x47 && buf[196] == 0x47
+    });
+    media_types.add("audio/mpeg", "mp3", |buf| {
+        // Layer bits of 0 are AAC, which `infer` already recognizes
+        buf.len() > 1 && buf[0] == 0xFF && buf[1] & 0xE0 == 0xE0 && buf[1] & 0x06 != 0
+    });
+    media_types
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use crate::utils::testing::TestDir;
 
     #[test]
     fn test_is_supported_video_file() {
@@ -116,14 +327,165 @@ mod tests {
     }
 
     #[test]
-    fn test_sanitize_filename_for_url() {
+    fn test_verify_media_file() {
+        let dir = TestDir::new("crab_dlna_verify_media");
+
+        let valid = dir.join("valid.mkv");
+        std::fs::write(&valid, [0x1A, 0x45, 0xDF, 0xA3, 0x01, 0x00]).unwrap();
+        assert!(verify_media_file(&valid).is_ok());
+
+        let mp4 = dir.join("valid.mp4");
+        std::fs::write(&mp4, b"\0\0\0\x18ftypmp42").unwrap();
+        assert!(verify_media_file(&mp4).is_ok());
+
+        let empty = dir.join("empty.mp4");
+        std::fs::write(&empty, b"").unwrap();
+        assert!(matches!(
+            verify_media_file(&empty),
+            Err(Error::MediaFileInvalid { reason, .. }) if reason == "file is empty"
+        ));
+
+        let corrupt = dir.join("corrupt.avi");
+        std::fs::write(&corrupt, b"<html>not found</html>").unwrap();
+        assert!(matches!(
+            verify_media_file(&corrupt),
+            Err(Error::MediaFileInvalid { .. })
+        ));
+
+        assert!(matches!(
+            verify_media_file(&dir.join("missing.mp4")),
+            Err(Error::MediaFileNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_media_file_formats() {
+        let dir = TestDir::new("crab_dlna_verify_media_formats");
+        let mut ts = vec![0u8; 376];
+        ts[0] = 0x47;
+        ts[188] = 0x47;
+
+        for (name, contents) in [
+            ("movie.mov", b"\0\0\0\x14ftypqt  \0\0\0\0".to_vec()),
+            ("movie.m4v", b"\0\0\0\x18ftypisom".to_vec()),
+            ("movie.mpg", vec![0x00, 0x00, 0x01, 0xBA, 0x44]),
+            ("movie.ts", ts),
+            ("song.mp3", vec![0xFF, 0xF3, 0x64, 0xC4]),
+            ("song.aac", vec![0xFF, 0xF1, 0x50, 0x80]),
+            ("song.flac", b"fLaC\0\0\0\x22".to_vec()),
+        ] {
+            let path = dir.create_file(name, &contents);
+            assert!(verify_media_file(&path).is_ok(), "{name} was rejected");
+        }
+
+        let flac = dir.create_file("id3.flac", b"ID3\x04\0\0\0\0\0\0");
+        assert!(matches!(
+            verify_media_file(&flac),
+            Err(Error::MediaFileInvalid { reason, .. }) if reason == "header looks like audio/mpeg, not a .flac file"
+        ));
+
+        let ts = dir.create_file("broken.ts", &[0u8; 376]);
+        assert!(verify_media_file(&ts).is_err());
+
+        let unknown = dir.create_file("movie.xyz", b"anything");
+        assert!(verify_media_file(&unknown).is_ok());
+    }
+
+    #[test]
+    fn test_infer_subtitles_from_video() {
+        let video_dir = TestDir::new("crab_dlna_infer_subtitles");
+        let video_path = video_dir.join("movie.mp4");
+        for name in [
+            "movie.mp4",
+            "movie.srt",
+            "movie.fr.srt",
+            "movie.en.vtt",
+            "movie.en.txt",
+            "other.en.srt",
+        ] {
+            std::fs::write(video_dir.join(name), b"1").unwrap();
+        }
+
         assert_eq!(
-            sanitize_filename_for_url("My Video File.mp4"),
-            "my.video.file.mp4"
+            infer_subtitles_from_video(&video_path),
+            vec![
+                video_dir.join("movie.srt"),
+                video_dir.join("movie.en.vtt"),
+                video_dir.join("movie.fr.srt"),
+            ]
         );
+    }
+
+    #[test]
+    fn test_resolve_subtitle_path_next_to_video() {
+        let video_dir = TestDir::new("crab_dlna_resolve_subtitle");
+        let video_path = video_dir.join("movie.mp4");
+        std::fs::write(video_dir.join("movie.en.srt"), b"1").unwrap();
+
         assert_eq!(
-            sanitize_filename_for_url("Test (2023).avi"),
-            "test.2023.avi"
+            resolve_subtitle_path(Path::new("movie.en.srt"), &video_path),
+            video_dir.join("movie.en.srt")
         );
+        // Missing next to the video, left to the current working directory
+        assert_eq!(
+            resolve_subtitle_path(Path::new("other.srt"), &video_path),
+            PathBuf::from("other.srt")
+        );
+        let absolute = video_dir.join("elsewhere").join("movie.en.srt");
+        assert_eq!(resolve_subtitle_path(&absolute, &video_path), absolute);
+    }
+
+    #[test]
+    fn test_subtitle_size_warning() {
+        let dir = TestDir::new("crab_dlna_large_subtitle");
+        let path = dir.join("large.srt");
+        let cue = "1\n00:00:01,000 --> 00:00:02,000\nHello\n\n";
+        std::fs::write(&path, cue.repeat(10)).unwrap();
+
+        assert_eq!(subtitle_size_warning(&path, 0, 0), None);
+        assert_eq!(subtitle_size_warning(&path, 1024, 10), None);
+        assert!(
+            subtitle_size_warning(&path, 1024, 9)
+                .unwrap()
+                .contains("has 10 cues")
+        );
+        assert!(
+            subtitle_size_warning(&path, 100, 0)
+                .unwrap()
+                .contains("KiB")
+        );
+    }
+
+    #[test]
+    fn test_check_file_readable() {
+        let dir = TestDir::new("crab_dlna_check_file_readable");
+        let missing = dir.join("missing.mp4");
+        assert!(matches!(
+            check_file_readable(&missing, "File does not exist"),
+            Err(Error::MediaFileNotFound { .. })
+        ));
+
+        let readable = dir.join("readable.mp4");
+        std::fs::write(&readable, b"video").unwrap();
+        assert!(check_file_readable(&readable, "File does not exist").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_file_readable_without_permission() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TestDir::new("crab_dlna_unreadable_file");
+        let path = dir.join("unreadable.mp4");
+        std::fs::write(&path, b"video").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Privileged users can read the file regardless of its permissions
+        if std::fs::File::open(&path).is_err() {
+            assert!(matches!(
+                check_file_readable(&path, "File does not exist"),
+                Err(Error::MediaFileNotReadable { .. })
+            ));
+        }
     }
 }
@@ -5,17 +5,36 @@
 //! - Media file handling and validation
 //! - Network operations and retry mechanisms
 //! - Text formatting and display utilities
+//! - The play history log
 
 pub mod formatting;
+pub mod history;
 pub mod media;
 pub mod network;
+#[cfg(feature = "probe")]
+pub mod probe;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod time;
 
 // Re-export commonly used functions for backward compatibility
-pub use formatting::{format_device_description, format_device_with_service_description};
+pub use formatting::{
+    clean_title_from_filename, format_action_response, format_device_description,
+    format_device_with_service_description,
+};
+pub use history::{
+    HistoryEntry, HistoryOutcome, append_history, default_history_path, unix_time_secs,
+};
 pub use media::{
-    detect_subtitle_type, infer_subtitle_from_video,
-    is_supported_media_file, sanitize_filename_for_url,
+    check_file_readable, detect_subtitle_type, infer_subtitle_from_video,
+    infer_subtitles_from_video, is_supported_media_file, resolve_subtitle_path,
+    subtitle_size_warning, verify_media_file,
+};
+pub(crate) use network::{URL_SEGMENT_UNRESERVED, file_url_path, host_url};
+pub use network::{
+    compute_file_uri, http_get_text, http_server_header, retry_while, retry_with_backoff,
+    upnp_error_code,
 };
-pub use network::retry_with_backoff;
-pub use time::time_str_to_milliseconds;
+#[cfg(feature = "probe")]
+pub use probe::probe_video;
+pub use time::{format_dlna_time, format_milliseconds, try_time_str_to_milliseconds};
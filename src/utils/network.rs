@@ -3,9 +3,18 @@
 //! This module provides network-related utility functions,
 //! including retry mechanisms and error handling.
 
-use crate::config::MAX_NETWORK_RETRIES;
+use crate::config::{MAX_NETWORK_RETRIES, SERVER_PROBE_TIMEOUT_MS, USER_AGENT};
+use bytes::Bytes;
+use http::{Request, Uri, header};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
 use log::{debug, warn};
-use std::time::Duration;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Component, Path},
+    time::Duration,
+};
 use tokio::time::sleep;
 
 /// Retries an async operation with exponential backoff
@@ -43,9 +52,7 @@ where
                     );
                     sleep(delay).await;
                 } else {
-                    warn!(
-                        "{operation_name} failed on final attempt {attempt} ({error})"
-                    );
+                    warn!("{operation_name} failed on final attempt {attempt} ({error})");
                 }
                 last_error = Some(error);
             }
@@ -54,3 +61,330 @@ where
 
     Err(last_error.unwrap())
 }
+
+/// Retries an async operation at a fixed delay, only while its error is transient
+///
+/// Errors for which `is_transient` returns false are returned at once, as
+/// retrying them would only delay the failure.
+///
+/// # Arguments
+/// * `operation` - The async operation to retry
+/// * `operation_name` - Name of the operation for logging
+/// * `is_transient` - Whether an error is worth another attempt
+/// * `attempts` - Maximum number of attempts
+/// * `delay` - Delay between attempts
+pub async fn retry_while<F, Fut, T, E>(
+    mut operation: F,
+    operation_name: &str,
+    is_transient: impl Fn(&E) -> bool,
+    attempts: u32,
+    delay: Duration,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(error) if attempt < attempts && is_transient(&error) => {
+                debug!(
+                    "{operation_name} failed on attempt {attempt} ({error}), retrying in {delay:?}"
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns the UPnP error code of a failed action, if the device sent one
+pub fn upnp_error_code(error: &rupnp::Error) -> Option<u16> {
+    match error {
+        rupnp::Error::UPnPError(upnp_error) => Some(upnp_error.err_code()),
+        _ => None,
+    }
+}
+
+/// Fetches a document from a device over HTTP and returns it as text
+///
+/// The body is returned even if it is not valid XML, so malformed device
+/// documents can still be inspected and reported.
+///
+/// # Arguments
+/// * `url` - The URL of the document to fetch
+///
+/// # Returns
+/// Returns the response body decoded as (lossy) UTF-8
+pub async fn http_get_text(url: &Uri) -> Result<String, rupnp::Error> {
+    debug!("Fetching {url}");
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+    let request = Request::get(url.clone())
+        .header(header::USER_AGENT, USER_AGENT)
+        .body(Empty::new())
+        .map_err(rupnp::Error::invalid_response)?;
+
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        return Err(rupnp::Error::HttpErrorCode(response.status()));
+    }
+
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Asks an HTTP server for its `Server` header
+///
+/// Used to tell what is already listening on a port, so the answer is
+/// best effort: any failure, or no answer within a second, gives `None`.
+///
+/// # Arguments
+/// * `url` - The URL to send a `HEAD` request to
+pub async fn http_server_header(url: &Uri) -> Option<String> {
+    debug!("Probing the server at {url}");
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+    let request = Request::head(url.clone())
+        .header(header::USER_AGENT, USER_AGENT)
+        .body(Empty::new())
+        .ok()?;
+
+    let timeout = Duration::from_millis(SERVER_PROBE_TIMEOUT_MS);
+    let response = tokio::time::timeout(timeout, client.request(request))
+        .await
+        .ok()?
+        .ok()?;
+    let server = response.headers().get(header::SERVER)?.to_str().ok()?;
+    Some(server.to_string())
+}
+
+/// Characters left as-is in a served file name: the RFC 3986 unreserved set
+pub(crate) const URL_SEGMENT_UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Offset basis of the 64-bit FNV-1a hash
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Prime of the 64-bit FNV-1a hash
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hashes a path into the token of the URL it is served under
+///
+/// FNV-1a over the path components, so a path always gets the same token, in
+/// every run and on every platform. `.` components are ignored.
+fn path_token(path: &Path) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    for component in path.components() {
+        match component {
+            Component::CurDir => continue,
+            Component::RootDir => write(b"/"),
+            other => write(other.as_os_str().as_encoded_bytes()),
+        }
+        write(&[0]);
+    }
+    hash
+}
+
+/// Builds the URL path a file is served under
+///
+/// The path starts with an opaque token hashed from the file's path, so URLs
+/// don't show the local directory layout and files with the same name in
+/// different directories don't collide. The token is the same in every run,
+/// so a renderer still playing a file from an earlier run is recognized. It
+/// ends with the percent-encoded file name, which decodes back to the original
+/// name, as some renderers rely on the extension to detect the media type.
+pub(crate) fn file_url_path(path: &Path) -> String {
+    let token = format!("{:016x}", path_token(path));
+
+    match path.file_name() {
+        Some(filename) => format!(
+            "{token}/{}",
+            utf8_percent_encode(&filename.to_string_lossy(), URL_SEGMENT_UNRESERVED)
+        ),
+        None => token,
+    }
+}
+
+/// Builds the base URL of a server listening on `addr`
+pub(crate) fn host_url(addr: SocketAddr) -> String {
+    format!("http://{addr}")
+}
+
+/// Computes the URL a file is served at by a streaming server on `host_ip` and `port`
+///
+/// This is the URL [`MediaStreamingServer::video_uri`] reports for a server
+/// created with the same arguments, so it can be shown (e.g. as a QR code)
+/// before the server is started. IPv6 addresses get brackets, host names are
+/// kept as given.
+///
+/// The URL only depends on the arguments: the file system isn't read, so the
+/// file needn't exist yet. Paths are taken as given, though, so the same file
+/// reached through a relative path, an absolute path or a symbolic link gets
+/// different URLs; pass the path the server is created with.
+///
+/// [`MediaStreamingServer::video_uri`]: crate::MediaStreamingServer::video_uri
+pub fn compute_file_uri(host_ip: &str, port: u16, path: &Path) -> String {
+    let host = host_ip.trim_start_matches('[').trim_end_matches(']');
+    let host_url = match host.parse::<IpAddr>() {
+        Ok(ip) => host_url(SocketAddr::new(ip, port)),
+        Err(_) => format!("http://{host_ip}:{port}"),
+    };
+    format!("{host_url}/{}", file_url_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn test_retry_while_transient() {
+        let calls = Cell::new(0);
+        let result: Result<u32, String> = retry_while(
+            || {
+                calls.set(calls.get() + 1);
+                let call = calls.get();
+                async move {
+                    match call {
+                        3 => Ok(call),
+                        _ => Err("701".to_string()),
+                    }
+                }
+            },
+            "Test",
+            |error| error == "701",
+            5,
+            Duration::ZERO,
+        )
+        .await;
+        assert_eq!(result, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn test_retry_while_fails_fast() {
+        let calls = Cell::new(0);
+        let result: Result<(), String> = retry_while(
+            || {
+                calls.set(calls.get() + 1);
+                async { Err("501".to_string()) }
+            },
+            "Test",
+            |error| error == "701",
+            5,
+            Duration::ZERO,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_while_gives_up() {
+        let calls = Cell::new(0);
+        let result: Result<(), String> = retry_while(
+            || {
+                calls.set(calls.get() + 1);
+                async { Err("701".to_string()) }
+            },
+            "Test",
+            |_| true,
+            3,
+            Duration::ZERO,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_http_server_header() {
+        use axum::{Router, http::HeaderValue, routing::get};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Uri = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let app = Router::new().route(
+            "/",
+            get(|| async { ([(header::SERVER, HeaderValue::from_static(USER_AGENT))], "") }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        assert_eq!(http_server_header(&url).await.as_deref(), Some(USER_AGENT));
+    }
+
+    #[tokio::test]
+    async fn test_http_server_header_without_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: Uri = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        drop(listener);
+
+        assert_eq!(http_server_header(&url).await, None);
+    }
+
+    #[test]
+    fn test_compute_file_uri_hosts() {
+        let path = Path::new("/media/movie.mp4");
+        let file_path = file_url_path(path);
+
+        assert_eq!(
+            compute_file_uri("192.168.1.100", 9000, path),
+            format!("http://192.168.1.100:9000/{file_path}")
+        );
+        for host in ["fe80::1", "[fe80::1]"] {
+            assert_eq!(
+                compute_file_uri(host, 9000, path),
+                format!("http://[fe80::1]:9000/{file_path}")
+            );
+        }
+        assert_eq!(
+            compute_file_uri("media.local", 8080, path),
+            format!("http://media.local:8080/{file_path}")
+        );
+    }
+
+    #[test]
+    fn test_compute_file_uri_encodes_unicode_filename() {
+        let uri = compute_file_uri("192.168.1.100", 9000, Path::new("/media/Amélie 東京.mkv"));
+        assert!(uri.ends_with("/Am%C3%A9lie%20%E6%9D%B1%E4%BA%AC.mkv"));
+        assert!(uri.is_ascii());
+    }
+
+    #[test]
+    fn test_compute_file_uri_does_not_read_the_file_system() {
+        let dir = TestDir::new("crab_dlna_pure_uri");
+        let path = dir.join("clip.mp4");
+        let before = compute_file_uri("192.168.1.100", 9000, &path);
+        dir.create_file("clip.mp4", b"clip");
+        let after = compute_file_uri("192.168.1.100", 9000, &path);
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn test_file_uri_is_the_same_across_runs() {
+        // Nothing of the process goes into the token: a later run builds the
+        // URI a renderer may still be playing
+        let path = Path::new("/media/My Movie.mkv");
+        assert_eq!(
+            compute_file_uri("192.168.1.100", 9000, path),
+            "http://192.168.1.100:9000/d426d01d4b622a40/My%20Movie.mkv"
+        );
+        assert_eq!(
+            compute_file_uri("192.168.1.100", 9000, Path::new("/media/./My Movie.mkv")),
+            compute_file_uri("192.168.1.100", 9000, path)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Video stream probing for crab-dlna
+//!
+//! This module reads the resolution, codec and dynamic range of the video
+//! track from MP4 and Matroska container headers with the `mp4` and
+//! `matroska` crates, without decoding anything, to warn about files that
+//! common renderers are likely unable to play.
+
+use matroska::{Matroska, Settings};
+use mp4::{MediaType, Mp4Reader, TrackType};
+use std::{fmt, fs::File, io::BufReader, path::Path};
+
+/// Widest video most renderers decode
+const COMMON_MAX_WIDTH: u32 = 1920;
+
+/// Tallest video most renderers decode
+const COMMON_MAX_HEIGHT: u32 = 1080;
+
+/// Transfer characteristics of HDR video, PQ (HDR10, Dolby Vision) and HLG
+const HDR_TRANSFER_CHARACTERISTICS: [u8; 2] = [16, 18];
+
+/// Properties of the video track of a media file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoInfo {
+    /// Width of the video, in pixels
+    pub width: u32,
+    /// Height of the video, in pixels
+    pub height: u32,
+    /// Name of the video codec, e.g. `HEVC`
+    pub codec: String,
+    /// Whether the video uses an HDR transfer function
+    pub hdr: bool,
+}
+
+impl fmt::Display for VideoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {}", self.width, self.height, self.codec)?;
+        if self.hdr {
+            write!(f, " HDR")?;
+        }
+        Ok(())
+    }
+}
+
+impl VideoInfo {
+    /// Describes why common renderers may be unable to play the video, if they may
+    pub fn compatibility_warning(&self) -> Option<String> {
+        let mut reasons = Vec::new();
+        if self.width > COMMON_MAX_WIDTH || self.height > COMMON_MAX_HEIGHT {
+            reasons.push("above 1080p");
+        }
+        if self.hdr {
+            reasons.push("HDR");
+        }
+        if reasons.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Video is {self} ({}), which many renderers can't play. If the device shows \
+             nothing or a green screen, transcode it to 1080p H.264 (e.g. with ffmpeg)",
+            reasons.join(", ")
+        ))
+    }
+}
+
+/// Reads the properties of the video track of an MP4 or Matroska file
+///
+/// Only the container header is read. Returns None for other formats, files
+/// without a video track and headers that can't be parsed. The dynamic range
+/// is only known for VP9 tracks of MP4 files, the only ones whose colour
+/// metadata the parsers expose.
+pub fn probe_video(path: &Path) -> Option<VideoInfo> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let file = File::open(path).ok()?;
+    match extension.as_str() {
+        "mp4" | "m4v" | "mov" | "3gp" => probe_mp4(file),
+        "mkv" | "webm" => probe_matroska(file),
+        _ => None,
+    }
+}
+
+/// Reads the first video track of an MP4 file
+fn probe_mp4(file: File) -> Option<VideoInfo> {
+    let size = file.metadata().ok()?.len();
+    let mp4 = Mp4Reader::read_header(BufReader::new(file), size).ok()?;
+    let track = mp4
+        .tracks()
+        .values()
+        .filter(|track| matches!(track.track_type(), Ok(TrackType::Video)))
+        .min_by_key(|track| track.track_id())?;
+
+    let transfer = track
+        .trak
+        .mdia
+        .minf
+        .stbl
+        .stsd
+        .vp09
+        .as_ref()
+        .map(|vp09| vp09.vpcc.transfer_characteristics);
+    Some(VideoInfo {
+        width: track.width().into(),
+        height: track.height().into(),
+        codec: match track.media_type() {
+            Ok(MediaType::H264) => "H.264".to_string(),
+            Ok(MediaType::H265) => "HEVC".to_string(),
+            Ok(MediaType::VP9) => "VP9".to_string(),
+            _ => track
+                .box_type()
+                .map_or("unknown codec".to_string(), |format| format.to_string()),
+        },
+        hdr: transfer.is_some_and(|t| HDR_TRANSFER_CHARACTERISTICS.contains(&t)),
+    })
+}
+
+/// Reads the first video track of a Matroska file
+fn probe_matroska(file: File) -> Option<VideoInfo> {
+    let matroska = Matroska::open(BufReader::new(file)).ok()?;
+    matroska
+        .video_tracks()
+        .find_map(|track| match &track.settings {
+            Settings::Video(video) => Some(VideoInfo {
+                width: u32::try_from(video.pixel_width).ok()?,
+                height: u32::try_from(video.pixel_height).ok()?,
+                codec: matroska_codec_name(&track.codec_id),
+                hdr: false,
+            }),
+            _ => None,
+        })
+}
+
+/// Names the codec of a Matroska codec ID
+fn matroska_codec_name(codec_id: &str) -> String {
+    match codec_id.trim_end_matches('\0') {
+        "V_MPEG4/ISO/AVC" => "H.264".to_string(),
+        "V_MPEGH/ISO/HEVC" => "HEVC".to_string(),
+        "V_VP9" => "VP9".to_string(),
+        "V_AV1" => "AV1".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::TestDir;
+
+    /// Builds an EBML element with an 8-byte size
+    fn ebml(id: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.push(0x01);
+        data.extend_from_slice(&(contents.len() as u64).to_be_bytes()[1..]);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    #[test]
+    fn test_probe_mp4_4k() {
+        let config = mp4::Mp4Config {
+            major_brand: "isom".parse().unwrap(),
+            minor_version: 512,
+            compatible_brands: vec!["isom".parse().unwrap()],
+            timescale: 1000,
+        };
+        let mut writer =
+            mp4::Mp4Writer::write_start(std::io::Cursor::new(Vec::new()), &config).unwrap();
+        writer
+            .add_track(&mp4::TrackConfig {
+                track_type: TrackType::Video,
+                timescale: 1000,
+                language: "und".to_string(),
+                media_conf: mp4::MediaConfig::AvcConfig(mp4::AvcConfig {
+                    width: 3840,
+                    height: 2160,
+                    seq_param_set: vec![0x67, 0x64, 0x00, 0x33],
+                    pic_param_set: vec![0x68, 0xEE, 0x3C, 0x80],
+                }),
+            })
+            .unwrap();
+        writer.write_end().unwrap();
+        let dir = TestDir::new("crab_dlna_probe_4k");
+        let path = dir.create_file("video.mp4", &writer.into_writer().into_inner());
+
+        let info = probe_video(&path).unwrap();
+        assert_eq!(info.to_string(), "3840x2160 H.264");
+        let warning = info.compatibility_warning().unwrap();
+        assert!(warning.contains("(above 1080p)"));
+    }
+
+    #[test]
+    fn test_hdr_compatibility_warning() {
+        let info = VideoInfo {
+            width: 3840,
+            height: 2160,
+            codec: "HEVC".to_string(),
+            hdr: true,
+        };
+        assert_eq!(info.to_string(), "3840x2160 HEVC HDR");
+        assert!(
+            info.compatibility_warning()
+                .unwrap()
+                .contains("above 1080p, HDR")
+        );
+    }
+
+    #[test]
+    fn test_probe_matroska_1080p() {
+        let video = [
+            ebml(&[0xB0], &1920u16.to_be_bytes()),
+            ebml(&[0xBA], &1080u16.to_be_bytes()),
+        ]
+        .concat();
+        let entry = [
+            ebml(&[0x83], &[1]),
+            ebml(&[0x86], b"V_MPEG4/ISO/AVC"),
+            ebml(&[0xE0], &video),
+        ]
+        .concat();
+        let audio = [ebml(&[0x83], &[2]), ebml(&[0x86], b"A_AAC")].concat();
+        let tracks = [ebml(&[0xAE], &audio), ebml(&[0xAE], &entry)].concat();
+        let segment = [
+            ebml(
+                &[0x15, 0x49, 0xA9, 0x66],
+                &ebml(&[0x2A, 0xD7, 0xB1], &1_000_000u32.to_be_bytes()),
+            ),
+            ebml(&[0x16, 0x54, 0xAE, 0x6B], &tracks),
+        ]
+        .concat();
+        let file = [
+            ebml(&[0x1A, 0x45, 0xDF, 0xA3], &[0; 8]),
+            ebml(&[0x18, 0x53, 0x80, 0x67], &segment),
+        ]
+        .concat();
+        let dir = TestDir::new("crab_dlna_probe_1080p");
+        let path = dir.create_file("video.mkv", &file);
+
+        let info = probe_video(&path).unwrap();
+        assert_eq!(info.to_string(), "1920x1080 H.264");
+        assert_eq!(info.compatibility_warning(), None);
+    }
+
+    #[test]
+    fn test_probe_ignores_unparseable_files() {
+        let dir = TestDir::new("crab_dlna_probe_garbage");
+        let path = dir.create_file("video.mkv", b"not a matroska file");
+        assert_eq!(probe_video(&path), None);
+    }
+}
@@ -9,24 +9,59 @@
 /// - HH:MM:SS (for DLNA position info)
 /// - HH:MM:SS,mmm (for subtitle timestamps)
 ///
+/// Values such as `NOT_IMPLEMENTED` or empty strings are parse failures,
+/// rather than a position of 0.
+///
 /// # Arguments
 /// * `time_str` - Time string to convert
 ///
 /// # Returns
-/// Returns time in milliseconds, or 0 if parsing fails
-pub fn time_str_to_milliseconds(time_str: &str) -> u64 {
+/// Returns time in milliseconds, or None if parsing fails
+pub fn try_time_str_to_milliseconds(time_str: &str) -> Option<u64> {
     // Try HH:MM:SS format first (DLNA format)
     if let Ok(ms) = parse_dlna_time_format(time_str) {
-        return ms;
+        return Some(ms);
     }
 
     // Try HH:MM:SS,mmm format (subtitle format)
-    if let Ok(ms) = parse_subtitle_time_format(time_str) {
-        return ms;
+    parse_subtitle_time_format(time_str).ok()
+}
+
+/// Formats milliseconds as a compact clock string
+///
+/// # Arguments
+/// * `milliseconds` - Duration in milliseconds
+///
+/// # Returns
+/// Returns `MM:SS`, or `H:MM:SS` for durations of an hour or more
+pub fn format_milliseconds(milliseconds: u64) -> String {
+    let total_seconds = milliseconds / 1000;
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
     }
+}
 
-    // Return 0 if both formats fail
-    0
+/// Formats milliseconds in the DLNA time format
+///
+/// # Arguments
+/// * `milliseconds` - Position in milliseconds
+///
+/// # Returns
+/// Returns `HH:MM:SS`, as used by `rel_time` and seek targets
+pub fn format_dlna_time(milliseconds: u64) -> String {
+    let total_seconds = milliseconds / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        total_seconds / 3600,
+        (total_seconds % 3600) / 60,
+        total_seconds % 60
+    )
 }
 
 /// Parses DLNA time format (HH:MM:SS or HH:MM:SS.mmm)
@@ -66,20 +101,34 @@ mod tests {
 
     #[test]
     fn test_time_str_to_milliseconds_dlna_format() {
-        assert_eq!(time_str_to_milliseconds("01:30:45"), 5445000);
-        assert_eq!(time_str_to_milliseconds("00:00:30"), 30000);
-        assert_eq!(time_str_to_milliseconds("02:15:30.5"), 8130500);
+        assert_eq!(try_time_str_to_milliseconds("01:30:45"), Some(5445000));
+        assert_eq!(try_time_str_to_milliseconds("00:00:30"), Some(30000));
+        assert_eq!(try_time_str_to_milliseconds("02:15:30.5"), Some(8130500));
     }
 
     #[test]
     fn test_time_str_to_milliseconds_subtitle_format() {
-        assert_eq!(time_str_to_milliseconds("01:30:45,123"), 5445123);
-        assert_eq!(time_str_to_milliseconds("00:00:30,000"), 30000);
+        assert_eq!(try_time_str_to_milliseconds("01:30:45,123"), Some(5445123));
+        assert_eq!(try_time_str_to_milliseconds("00:00:30,000"), Some(30000));
     }
 
     #[test]
     fn test_time_str_to_milliseconds_invalid() {
-        assert_eq!(time_str_to_milliseconds("invalid"), 0);
-        assert_eq!(time_str_to_milliseconds("1:2"), 0);
+        assert_eq!(try_time_str_to_milliseconds("invalid"), None);
+        assert_eq!(try_time_str_to_milliseconds("1:2"), None);
+    }
+
+    #[test]
+    fn test_try_time_str_to_milliseconds() {
+        assert_eq!(try_time_str_to_milliseconds("00:01:05"), Some(65000));
+        assert_eq!(try_time_str_to_milliseconds("NOT_IMPLEMENTED"), None);
+        assert_eq!(try_time_str_to_milliseconds(""), None);
+    }
+
+    #[test]
+    fn test_format_milliseconds() {
+        assert_eq!(format_milliseconds(65000), "01:05");
+        assert_eq!(format_milliseconds(0), "00:00");
+        assert_eq!(format_milliseconds(5445000), "1:30:45");
     }
 }
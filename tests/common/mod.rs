@@ -0,0 +1,323 @@
+//! A fake UPnP media renderer for integration tests
+//!
+//! The renderer serves a device description, an AVTransport SCPD and a SOAP
+//! control endpoint on a local port. Actions are recorded and answered with
+//! canned responses, so the whole DLNA path can be tested without hardware.
+
+#![allow(dead_code)]
+
+use axum::{
+    Router,
+    http::{HeaderMap, header},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use crab_dlna::{Render, RenderSpec};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::net::TcpListener;
+
+const AVTRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+
+const DESCRIPTION: &str = include_str!("../fixtures/renderer_description.xml");
+
+const SCPD: &str = r#"<?xml version="1.0"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action><name>SetAVTransportURI</name></action>
+    <action><name>Play</name></action>
+    <action><name>Pause</name></action>
+    <action><name>Stop</name></action>
+    <action><name>Seek</name></action>
+    <action><name>GetTransportInfo</name></action>
+    <action><name>GetPositionInfo</name></action>
+    <action><name>GetDeviceCapabilities</name></action>
+  </actionList>
+</scpd>"#;
+
+/// An action received by the fake renderer
+#[derive(Debug, Clone)]
+pub struct RecordedAction {
+    /// Name of the action, e.g. `Play`
+    pub name: String,
+    /// The SOAP request body
+    pub body: String,
+}
+
+impl RecordedAction {
+    /// Gets the value of an argument of the action
+    pub fn argument(&self, name: &str) -> Option<String> {
+        let start = self.body.find(&format!("<{name}>"))? + name.len() + 2;
+        let end = start + self.body[start..].find(&format!("</{name}>"))?;
+        Some(unescape(&self.body[start..end]))
+    }
+}
+
+/// State shared between the fake renderer and the test
+#[derive(Debug)]
+struct RendererState {
+    actions: Vec<RecordedAction>,
+    transport_state: &'static str,
+    current_uri: String,
+    rel_time: &'static str,
+}
+
+/// A fake media renderer served on a local port
+#[derive(Clone)]
+pub struct FakeRenderer {
+    location: String,
+    state: Arc<Mutex<RendererState>>,
+}
+
+impl FakeRenderer {
+    /// Starts serving the fake renderer
+    pub async fn spawn() -> Self {
+        Self::spawn_on(0).await
+    }
+
+    /// Starts serving the fake renderer on a given local port, 0 for any free one
+    pub async fn spawn_on(port: u16) -> Self {
+        let state = Arc::new(Mutex::new(RendererState {
+            actions: Vec::new(),
+            transport_state: "NO_MEDIA_PRESENT",
+            current_uri: String::new(),
+            rel_time: "00:01:00",
+        }));
+
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let location = format!("http://{}/description.xml", listener.local_addr().unwrap());
+        let control_state = state.clone();
+        let app = Router::new()
+            .route("/description.xml", get(|| async { xml(DESCRIPTION) }))
+            .route("/avt.xml", get(|| async { xml(SCPD) }))
+            .route(
+                "/avt/control",
+                post(move |headers: HeaderMap, body: String| {
+                    let state = control_state.clone();
+                    async move { xml(&control(&state, &headers, body)) }
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { location, state }
+    }
+
+    /// Location of the device description
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Connects a render to the fake renderer
+    pub async fn render(&self) -> Render {
+        Render::new(RenderSpec::Location(self.location.clone()))
+            .await
+            .unwrap()
+    }
+
+    /// Puts the renderer in the state of playing a URI, without recording actions
+    pub fn set_playing(&self, uri: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.transport_state = "PLAYING";
+        state.current_uri = uri.to_string();
+    }
+
+    /// Sets the playback position reported by the renderer, of a ten-minute track
+    pub fn set_position(&self, rel_time: &'static str) {
+        self.state.lock().unwrap().rel_time = rel_time;
+    }
+
+    /// Actions received so far, in order
+    pub fn actions(&self) -> Vec<RecordedAction> {
+        self.state.lock().unwrap().actions.clone()
+    }
+
+    /// Names of the actions received so far, in order
+    pub fn action_names(&self) -> Vec<String> {
+        self.actions()
+            .into_iter()
+            .map(|action| action.name)
+            .collect()
+    }
+
+    /// Waits until the renderer received the given action
+    pub async fn wait_for_action(&self, name: &str) -> RecordedAction {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(action) = self.actions().into_iter().find(|a| a.name == name) {
+                    return action;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("the renderer never received {name}"))
+    }
+}
+
+/// Records an action and builds the SOAP response to it
+fn control(state: &Mutex<RendererState>, headers: &HeaderMap, body: String) -> String {
+    let name = headers
+        .get("SOAPAction")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim_matches('"').rsplit_once('#'))
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_default();
+
+    let mut state = state.lock().unwrap();
+    let action = RecordedAction {
+        name: name.clone(),
+        body,
+    };
+    if name == "SetAVTransportURI" {
+        state.current_uri = action.argument("CurrentURI").unwrap_or_default();
+    }
+    state.actions.push(action);
+
+    let arguments = match name.as_str() {
+        "SetAVTransportURI" | "Stop" => {
+            state.transport_state = "STOPPED";
+            String::new()
+        }
+        "Play" => {
+            state.transport_state = "PLAYING";
+            String::new()
+        }
+        "Pause" => {
+            state.transport_state = "PAUSED_PLAYBACK";
+            String::new()
+        }
+        "Seek" => String::new(),
+        "GetTransportInfo" => format!(
+            "<CurrentTransportState>{}</CurrentTransportState>\
+             <CurrentTransportStatus>OK</CurrentTransportStatus>\
+             <CurrentSpeed>1</CurrentSpeed>",
+            state.transport_state
+        ),
+        "GetPositionInfo" => format!(
+            "<Track>1</Track>\
+             <TrackDuration>00:10:00</TrackDuration>\
+             <TrackMetaData>NOT_IMPLEMENTED</TrackMetaData>\
+             <TrackURI>{}</TrackURI>\
+             <RelTime>{rel_time}</RelTime>\
+             <AbsTime>{rel_time}</AbsTime>\
+             <RelCount>0</RelCount>\
+             <AbsCount>0</AbsCount>",
+            escape(&state.current_uri),
+            rel_time = state.rel_time
+        ),
+        "GetDeviceCapabilities" => "<PlayMedia>NETWORK,UNKNOWN</PlayMedia>\
+             <RecMedia>NOT_IMPLEMENTED</RecMedia>\
+             <RecQualityModes>NOT_IMPLEMENTED</RecQualityModes>"
+            .to_string(),
+        _ => return fault(401, "Invalid Action"),
+    };
+    envelope(&format!(
+        r#"<u:{name}Response xmlns:u="{AVTRANSPORT}">{arguments}</u:{name}Response>"#
+    ))
+}
+
+/// Builds a UPnP error response
+///
+/// Sent with status 200, as rupnp only decodes faults of successful responses.
+fn fault(code: u16, description: &str) -> String {
+    envelope(&format!(
+        "<s:Fault>\
+           <faultcode>s:Client</faultcode>\
+           <faultstring>UPnPError</faultstring>\
+           <detail>\
+             <UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">\
+               <errorCode>{code}</errorCode>\
+               <errorDescription>{description}</errorDescription>\
+             </UPnPError>\
+           </detail>\
+         </s:Fault>"
+    ))
+}
+
+/// Wraps a SOAP body into an envelope
+fn envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>{body}</s:Body>
+</s:Envelope>"#
+    )
+}
+
+/// Answers with an XML document
+fn xml(body: &str) -> impl IntoResponse + use<> {
+    (
+        [(header::CONTENT_TYPE, "text/xml; charset=\"utf-8\"")],
+        body.to_string(),
+    )
+}
+
+/// Escapes a value for an XML element
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Reverts the XML escaping of an argument value
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A directory of scratch files, removed with its contents once dropped
+///
+/// Named after the test, the process id and a counter, so that neither tests
+/// running in parallel nor concurrent test runs collide.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates an empty directory in the temporary directory
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "{name}_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Writes a file with the given contents into the directory
+    pub fn create_file(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.path.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
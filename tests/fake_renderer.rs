@@ -0,0 +1,394 @@
+//! End-to-end tests of the DLNA path against a fake renderer
+
+mod common;
+
+use common::{FakeRenderer, TestDir};
+use crab_dlna::{
+    Config, EndReason, Error, MediaStreamingServer, Render, RenderSpec, get_local_ip, pause, play,
+    play_at_speed, play_until_stopped, resume, stop,
+};
+use std::{
+    net::TcpListener,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Writes a small video file into a scratch directory
+fn create_test_video(dir: &TestDir, name: &str) -> PathBuf {
+    dir.create_file(name, b"not really a video")
+}
+
+/// Builds a streaming server on a free port of the LAN address, `None` without one
+async fn streaming_server(video_path: &Path) -> Option<MediaStreamingServer> {
+    let Ok(host_ip) = get_local_ip().await else {
+        eprintln!("Skipping, no LAN address to stream from");
+        return None;
+    };
+    let host_port = TcpListener::bind((host_ip.as_str(), 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    Some(MediaStreamingServer::new(video_path, &None, &host_ip, &host_port).unwrap())
+}
+
+#[tokio::test]
+async fn test_render_from_location() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    assert_eq!(render.friendly_name(), "Fake Renderer");
+    assert_eq!(render.description_url().to_string(), renderer.location());
+}
+
+#[tokio::test]
+async fn test_service_scpd_is_returned_raw() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    let scpd = render.service_scpd().await.unwrap();
+    assert!(scpd.starts_with("<?xml"));
+    assert!(scpd.contains("<name>SetAVTransportURI</name>"));
+}
+
+#[tokio::test]
+async fn test_play_sets_uri_then_plays() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_play");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_play.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+    let video_uri = server.video_uri();
+
+    let config = Config::default();
+    let playback = {
+        let render = render.clone();
+        tokio::spawn(async move { play(render, server, None, &config).await })
+    };
+    renderer.wait_for_action("Play").await;
+
+    // Preceded by a check of what the device is playing, and followed by
+    // transport state polling to detect the end of playback
+    assert_eq!(
+        renderer.action_names()[..3],
+        ["GetTransportInfo", "SetAVTransportURI", "Play"]
+    );
+    let set_uri = &renderer.actions()[1];
+    assert_eq!(set_uri.argument("CurrentURI"), Some(video_uri));
+    assert!(
+        set_uri
+            .argument("CurrentURIMetaData")
+            .unwrap()
+            .contains("crab_dlna_fake_renderer_play")
+    );
+    assert_eq!(
+        render.get_transport_info().await.unwrap().transport_state,
+        "PLAYING"
+    );
+
+    playback.abort();
+}
+
+#[tokio::test]
+async fn test_play_returns_once_stopped() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_stop");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_stop.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+
+    let config = Config::default();
+    let playback = {
+        let render = render.clone();
+        tokio::spawn(async move { play(render, server, None, &config).await })
+    };
+    renderer.wait_for_action("Play").await;
+    renderer.wait_for_action("GetTransportInfo").await;
+    stop(&render).await.unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(5), playback).await;
+    assert!(matches!(result, Ok(Ok(Ok(())))));
+}
+
+#[tokio::test]
+async fn test_play_skips_cast_when_already_playing() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_already_playing");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_already_playing.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+    renderer.set_playing(&server.video_uri());
+
+    let config = Config::default();
+    let playback = {
+        let render = render.clone();
+        tokio::spawn(async move { play(render, server, None, &config).await })
+    };
+    renderer.wait_for_action("GetPositionInfo").await;
+    stop(&render).await.unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(5), playback).await;
+    assert!(matches!(result, Ok(Ok(Ok(())))));
+    let action_names = renderer.action_names();
+    assert!(!action_names.iter().any(|name| name == "SetAVTransportURI"));
+    assert!(!action_names.iter().any(|name| name == "Play"));
+}
+
+#[tokio::test]
+async fn test_stop_before_end_of_media_is_told_apart() {
+    for (position, reason) in [
+        ("00:01:00", EndReason::Stopped),
+        ("00:09:58", EndReason::EndOfMedia),
+    ] {
+        let renderer = FakeRenderer::spawn().await;
+        let render = renderer.render().await;
+        let dir = TestDir::new("crab_dlna_fake_renderer_end_of_media");
+        let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_end_of_media.mp4");
+        let Some(server) = streaming_server(&video_path).await else {
+            return;
+        };
+        renderer.set_position(position);
+
+        let config = Config::default();
+        let playback = {
+            let render = render.clone();
+            tokio::spawn(async move { play_until_stopped(render, server, None, &config).await })
+        };
+        renderer.wait_for_action("Play").await;
+        renderer.wait_for_action("GetPositionInfo").await;
+        stop(&render).await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), playback).await;
+        assert_eq!(result.unwrap().unwrap().unwrap(), reason, "at {position}");
+    }
+}
+
+#[tokio::test]
+async fn test_force_recasts_when_already_playing() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_force");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_force.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+    renderer.set_playing(&server.video_uri());
+
+    let config = Config::default().with_force_recast(true);
+    let playback = tokio::spawn(async move { play(render, server, None, &config).await });
+    renderer.wait_for_action("Play").await;
+
+    assert_eq!(renderer.action_names()[..2], ["SetAVTransportURI", "Play"]);
+    playback.abort();
+}
+
+#[tokio::test]
+async fn test_clip_seeks_to_start_and_stops_at_end() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_clip");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_clip.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+
+    // The fake renderer always reports a position of one minute
+    let config = Config::default().with_clip(Some(10_000), Some(30_000));
+    let playback = tokio::spawn(async move { play(render, server, None, &config).await });
+
+    let result = tokio::time::timeout(Duration::from_secs(5), playback).await;
+    assert!(matches!(result, Ok(Ok(Ok(())))));
+    let seek = renderer.wait_for_action("Seek").await;
+    assert_eq!(seek.argument("Target").as_deref(), Some("00:00:10"));
+    renderer.wait_for_action("Stop").await;
+}
+
+#[tokio::test]
+async fn test_keep_serving_outlives_playback() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+    let dir = TestDir::new("crab_dlna_fake_renderer_keep_serving");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_keep_serving.mp4");
+    let Some(server) = streaming_server(&video_path).await else {
+        return;
+    };
+
+    let config = Config::default().with_keep_serving(true);
+    let mut playback = {
+        let render = render.clone();
+        tokio::spawn(async move { play(render, server, None, &config).await })
+    };
+    renderer.wait_for_action("Play").await;
+    renderer.wait_for_action("GetTransportInfo").await;
+    stop(&render).await.unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(2), &mut playback).await;
+    assert!(result.is_err());
+    playback.abort();
+}
+
+#[tokio::test]
+async fn test_second_instance_on_port_is_detected() {
+    let dir = TestDir::new("crab_dlna_fake_renderer_bind");
+    let video_path = create_test_video(&dir, "crab_dlna_fake_renderer_bind.mp4");
+    let Ok(host_ip) = get_local_ip().await else {
+        eprintln!("Skipping, no LAN address to stream from");
+        return;
+    };
+    let host_port = TcpListener::bind((host_ip.as_str(), 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let first = MediaStreamingServer::new(&video_path, &None, &host_ip, &host_port).unwrap();
+    let second = MediaStreamingServer::new(&video_path, &None, &host_ip, &host_port).unwrap();
+
+    let listener = first.bind().await.unwrap();
+    let serving = tokio::spawn(first.serve(listener));
+
+    let error = second.bind().await.unwrap_err();
+    assert!(matches!(
+        error,
+        Error::StreamingBindError {
+            other_instance: true,
+            ..
+        }
+    ));
+    assert!(error.to_string().contains("--port"));
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn test_stop_and_clear_releases_device() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    render.stop_and_clear().await;
+    assert_eq!(renderer.action_names(), ["Stop", "SetAVTransportURI"]);
+    let clear = &renderer.actions()[1];
+    assert_eq!(clear.argument("CurrentURI").as_deref(), Some(""));
+}
+
+#[tokio::test]
+async fn test_transport_info_follows_pause() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    pause(&render).await.unwrap();
+    let transport_info = render.get_transport_info().await.unwrap();
+    assert_eq!(transport_info.transport_state, "PAUSED_PLAYBACK");
+    assert_eq!(renderer.action_names(), ["Pause", "GetTransportInfo"]);
+}
+
+#[tokio::test]
+async fn test_play_actions_follow_configured_speed() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await.with_play_speed(None);
+
+    resume(&render).await.unwrap();
+    play_at_speed(&render, "1").await.unwrap();
+    play_at_speed(&render, "2").await.unwrap();
+    let speeds: Vec<_> = renderer
+        .actions()
+        .iter()
+        .map(|action| action.argument("Speed"))
+        .collect();
+    assert_eq!(speeds, [None, None, Some("2".to_string())]);
+
+    let render = render.with_play_speed(Some("1.0".to_string()));
+    resume(&render).await.unwrap();
+    assert_eq!(
+        renderer.actions().last().unwrap().argument("Speed"),
+        Some("1.0".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_position_info() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    let position_info = render.get_position_info().await.unwrap();
+    assert_eq!(position_info.rel_time, "00:01:00");
+    assert_eq!(position_info.track_duration, "00:10:00");
+}
+
+#[tokio::test]
+async fn test_device_capabilities() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    let capabilities = render.get_device_capabilities().await.unwrap();
+    assert_eq!(capabilities.play_media, ["NETWORK", "UNKNOWN"]);
+    assert_eq!(capabilities.rec_media, ["NOT_IMPLEMENTED"]);
+}
+
+#[tokio::test]
+async fn test_render_from_location_without_description_path() {
+    let renderer = FakeRenderer::spawn().await;
+    let base_url = renderer.location().trim_end_matches("/description.xml");
+
+    for location in [base_url.to_string(), format!("{}/", renderer.location())] {
+        let render = Render::new(RenderSpec::Location(location)).await.unwrap();
+        assert_eq!(render.description_url().to_string(), renderer.location());
+    }
+}
+
+#[tokio::test]
+async fn test_wait_for_device_retries_until_it_answers() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let location = format!("http://127.0.0.1:{port}/description.xml");
+
+    // The device comes up while the first attempts fail
+    let booting = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        FakeRenderer::spawn_on(port).await
+    });
+    let render = Render::wait_for(RenderSpec::Location(location), Duration::from_secs(20))
+        .await
+        .unwrap();
+
+    assert_eq!(render.friendly_name(), "Fake Renderer");
+    let renderer = booting.await.unwrap();
+    assert_eq!(renderer.action_names(), ["GetTransportInfo"]);
+}
+
+#[tokio::test]
+async fn test_wait_for_device_gives_up_after_wait() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let location = format!("http://127.0.0.1:{port}/description.xml");
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(10),
+        Render::wait_for(RenderSpec::Location(location), Duration::from_secs(2)),
+    )
+    .await
+    .unwrap();
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_unsupported_action() {
+    let renderer = FakeRenderer::spawn().await;
+    let render = renderer.render().await;
+
+    assert!(matches!(
+        render.get_transport_settings().await,
+        Err(Error::DlnaActionNotSupported { .. })
+    ));
+}